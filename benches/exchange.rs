@@ -0,0 +1,59 @@
+//! Compares how evenly the redexes of a balanced and an unbalanced program are
+//! distributed among the threads of a parallel reduction; see
+//! [`ExchangeStats`](hvmc::run::ExchangeStats).
+//!
+//! Run with `cargo bench --bench exchange`; pass a number to change how many
+//! threads are used.
+
+use std::{env, fmt::Write, time::Instant};
+
+use hvmc::{ast::Book, run, stdlib::create_host};
+
+const LOOP: &str = "@loop = (?<(#0 @loop) a> a)";
+
+// many independent countdowns, which are split evenly among the threads
+fn balanced() -> String {
+  let mut main = String::new();
+  for i in 0 .. 64 {
+    write!(main, "(a{i} ").unwrap();
+  }
+  main += &format!("*{}", ")".repeat(64));
+  for i in 0 .. 64 {
+    write!(main, " & @loop ~ (#100000 a{i})").unwrap();
+  }
+  format!("{LOOP}\n@main = {main}")
+}
+
+// a single countdown, which only one thread can reduce
+fn unbalanced() -> String {
+  format!("{LOOP}\n@main = a & @loop ~ (#2000000 a)")
+}
+
+fn main() {
+  let threads = env::args().skip(1).find_map(|arg| arg.parse().ok()).unwrap_or(4);
+  let heap = run::Heap::new(None).expect("memory allocation failed");
+  let pool = run::ThreadPool::new(threads);
+
+  println!("{:<10} {:>10} {:>10} {:>10} {:>24} {:>10}", "PROGRAM", "TIME", "XCHG", "EMPTY", "BAGS", "IMBAL");
+  for (name, program) in [("balanced", balanced()), ("unbalanced", unbalanced())] {
+    let book: Book = program.parse().unwrap();
+    let host = create_host(&book);
+    let host = host.lock();
+    let mut net = run::Net::<run::Strict>::new(&heap);
+    net.exchange = Some(Default::default());
+    net.boot(&host.defs["main"]);
+    let start = Instant::now();
+    net.parallel_normal_on(&pool);
+    let elapsed = start.elapsed();
+    let exchange = net.exchange.take().unwrap();
+    let [min, mean, max] = exchange.bags();
+    println!(
+      "{name:<10} {:>10} {:>10} {:>10} {:>24} {:>10.3}",
+      format!("{elapsed:.3?}"),
+      exchange.exchanges,
+      exchange.empty,
+      format!("{min:.1}/{mean:.1}/{max:.1}"),
+      exchange.imbalance()
+    );
+  }
+}
@@ -0,0 +1,68 @@
+//! Compares reducing many independent expressions one at a time, each in
+//! parallel (as `hvmc reduce` does by default), with reducing them
+//! concurrently, each sequentially on its own heap (as `hvmc reduce --jobs`
+//! does).
+//!
+//! Run with `cargo bench --bench jobs`; pass a number to change how many jobs
+//! are used.
+
+use std::{
+  env,
+  sync::atomic::{AtomicUsize, Ordering},
+  thread,
+  time::Instant,
+};
+
+use hvmc::{
+  ast::{Book, Net},
+  run::{self, Trg},
+  stdlib::create_host,
+};
+
+const PROGRAM: &str = "@loop = (?<(#0 @loop) a> a)";
+const EXPRS: usize = 100;
+// expressions use little memory, so the heaps need not be large
+const MEMORY: usize = 1 << 24;
+
+fn main() {
+  let jobs = env::args().skip(1).find_map(|arg| arg.parse().ok()).unwrap_or(4);
+  let book: Book = PROGRAM.parse().unwrap();
+  let host = create_host(&book);
+  let exprs: Vec<Net> = (0 .. EXPRS).map(|i| format!("a & @loop ~ (#{} a)", 100_000 + i).parse().unwrap()).collect();
+  let reduce = |heap: &run::Heap, expr: &Net, parallel: Option<&run::ThreadPool>| {
+    let mut net = run::Net::<run::Strict>::new(heap);
+    let root = Trg::port(run::Port::new_var(net.root.addr()));
+    host.lock().encode_net(&mut net, root, expr);
+    match parallel {
+      Some(pool) => net.parallel_normal_on(pool),
+      None => net.normal(),
+    }
+    net.rwts.total()
+  };
+
+  println!("{:<12} {:>10} {:>12}", "MODE", "TIME", "RWTS");
+  let heap = run::Heap::new(Some(MEMORY)).expect("memory allocation failed");
+  let pool = run::ThreadPool::with_available_parallelism();
+  let start = Instant::now();
+  let rwts: u64 = exprs.iter().map(|expr| reduce(&heap, expr, Some(&pool))).sum();
+  println!("{:<12} {:>10} {rwts:>12}", "sequential", format!("{:.3?}", start.elapsed()));
+
+  let next = AtomicUsize::new(0);
+  let start = Instant::now();
+  let rwts: u64 = thread::scope(|s| {
+    let workers: Vec<_> = (0 .. jobs)
+      .map(|_| {
+        s.spawn(|| {
+          let heap = run::Heap::new(Some(MEMORY)).expect("memory allocation failed");
+          let mut rwts = 0;
+          while let Some(expr) = exprs.get(next.fetch_add(1, Ordering::Relaxed)) {
+            rwts += reduce(&heap, expr, None);
+          }
+          rwts
+        })
+      })
+      .collect();
+    workers.into_iter().map(|worker| worker.join().unwrap()).sum()
+  });
+  println!("{:<12} {:>10} {rwts:>12}", format!("{jobs} jobs"), format!("{:.3?}", start.elapsed()));
+}
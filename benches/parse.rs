@@ -0,0 +1,20 @@
+//! Times parsing many small expressions, half of which fail to parse, to check
+//! that parse errors are cheap to construct.
+//!
+//! Run with `cargo bench --bench parse`.
+
+use std::time::Instant;
+
+use hvmc::ast::Net;
+
+const EXPRS: usize = 100_000;
+
+fn main() {
+  let exprs: Vec<_> =
+    (0 .. EXPRS).map(|i| if i % 2 == 0 { format!("(a <+ #{i} a>)") } else { format!("(a <+ #{i} a)") }).collect();
+  let start = Instant::now();
+  let parsed = exprs.iter().filter(|expr| expr.parse::<Net>().is_ok()).count();
+  let elapsed = start.elapsed();
+  assert_eq!(parsed, EXPRS / 2);
+  println!("parsed {EXPRS} expressions ({} invalid) in {elapsed:.3?}", EXPRS - parsed);
+}
@@ -0,0 +1,63 @@
+//! Compares the variance of parallel reduction with and without
+//! [`Linker::pin_threads`](hvmc::run::Linker::pin_threads).
+//!
+//! Run with `cargo bench --bench pin_threads`; pass a number to change how
+//! many times each configuration is measured.
+
+use std::{env, time::Instant};
+
+use hvmc::{ast::Book, run, stdlib::create_host};
+
+// `tests/programs/stress_tests/sum_tree.hvmc`, with a smaller tree
+const PROGRAM: &str = "
+  @Leaf = (a ((a b) (* b)))
+  @Node = (a (b (* ((a (b c)) c))))
+  @add = (<+ a b> (a b))
+  @gen = (?<(a b) d> d)
+  & (a b) ~ (c @gen$S0)
+  & @Leaf ~ (#1 c)
+  @gen$S0 = ({3 a b} c)
+  & @Node ~ (d (e c))
+  & @gen ~ (b e)
+  & @gen ~ (a d)
+  @main = a
+  & @sum ~ (b a)
+  & @gen ~ (#20 b)
+  @sum = (((a a) (@sum$S0 b)) b)
+  @sum$S0 = (a (b c))
+  & @add ~ (d (e c))
+  & @sum ~ (b e)
+  & @sum ~ (a d)
+";
+const WARMUP: usize = 2;
+
+fn main() {
+  let runs = env::args().skip(1).find_map(|arg| arg.parse().ok()).unwrap_or(20);
+  let book: Book = PROGRAM.parse().unwrap();
+  let host = create_host(&book);
+  let host = host.lock();
+  let main = &host.defs["main"];
+  let heap = run::Heap::new(None).expect("memory allocation failed");
+
+  println!("{:<10} {:>12} {:>12} {:>12}", "RPS (M)", "mean", "stddev", "cv");
+  for pin_threads in [false, true] {
+    // a fresh pool, as the workers of a pool stay pinned
+    let pool = run::ThreadPool::with_available_parallelism();
+    let mut rps = vec![];
+    for i in 0 .. WARMUP + runs {
+      let mut net = run::Net::<run::Strict>::new(&heap);
+      net.pin_threads = pin_threads;
+      net.boot(main);
+      let start = Instant::now();
+      net.parallel_normal_on(&pool);
+      let elapsed = start.elapsed().as_secs_f64();
+      if i >= WARMUP {
+        rps.push(net.rwts.total() as f64 / elapsed / 1_000_000.0);
+      }
+    }
+    let mean = rps.iter().sum::<f64>() / runs as f64;
+    let stddev = (rps.iter().map(|x| (x - mean).powi(2)).sum::<f64>() / runs as f64).sqrt();
+    let name = if pin_threads { "pinned" } else { "unpinned" };
+    println!("{name:<10} {mean:>12.3} {stddev:>12.3} {:>11.2}%", stddev / mean * 100.0);
+  }
+}
@@ -0,0 +1,47 @@
+//! Compares reducing many small nets in parallel on threads spawned for each
+//! reduction ([`Net::parallel_normal_with_threads`]) with reducing them on a
+//! persistent [`ThreadPool`] ([`Net::parallel_normal_on`]).
+//!
+//! Run with `cargo bench --bench pool`; pass numbers to change how many
+//! threads are used (by default, 1 and 4).
+//!
+//! [`Net::parallel_normal_with_threads`]: hvmc::run::Net::parallel_normal_with_threads
+//! [`Net::parallel_normal_on`]: hvmc::run::Net::parallel_normal_on
+//! [`ThreadPool`]: hvmc::run::ThreadPool
+
+use std::{env, time::Instant};
+
+use hvmc::{ast::Book, run, stdlib::create_host};
+
+const PROGRAM: &str = "@loop = (?<(#0 @loop) a> a)\n@main = a & @loop ~ (#100 a)";
+const NETS: usize = 1000;
+
+fn main() {
+  let threads: Vec<usize> = env::args().skip(1).filter_map(|arg| arg.parse().ok()).collect();
+  let threads = if threads.is_empty() { vec![1, 4] } else { threads };
+  let book: Book = PROGRAM.parse().unwrap();
+  let host = create_host(&book);
+  let host = host.lock();
+  let main = &host.defs["main"];
+  let heap = run::Heap::new(Some(1 << 24)).expect("memory allocation failed");
+
+  println!("{:<8} {:>12} {:>12}", "THREADS", "SPAWNED", "POOL");
+  for threads in threads {
+    let start = Instant::now();
+    for _ in 0 .. NETS {
+      let mut net = run::Net::<run::Strict>::new(&heap);
+      net.boot(main);
+      net.parallel_normal_with_threads(threads);
+    }
+    let spawned = start.elapsed();
+    let pool = run::ThreadPool::new(threads);
+    let start = Instant::now();
+    for _ in 0 .. NETS {
+      let mut net = run::Net::<run::Strict>::new(&heap);
+      net.boot(main);
+      net.parallel_normal_on(&pool);
+    }
+    let pooled = start.elapsed();
+    println!("{threads:<8} {:>12} {:>12}", format!("{spawned:.3?}"), format!("{pooled:.3?}"));
+  }
+}
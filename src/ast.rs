@@ -11,25 +11,49 @@
 //! [interaction calculus]: https://en.wikipedia.org/wiki/Interaction_nets#Interaction_calculus
 
 use crate::{
-  ops::TypedOp as Op,
+  ops::{ExtOps, Ty, TypedOp as Op},
   prelude::*,
-  run::Lab,
-  util::{array_vec, deref, maybe_grow},
+  run::{Lab, Port, Tag},
+  util::{array_vec, create_var, deref, maybe_grow},
 };
 
-use alloc::collections::BTreeMap;
+use alloc::{borrow::Cow, collections::BTreeMap};
 use arrayvec::ArrayVec;
 use core::str::FromStr;
 use ordered_float::OrderedFloat;
-use TSPL::{new_parser, Parser};
 
 /// The top level AST node, representing a collection of named nets.
 ///
-/// This is a newtype wrapper around a `BTreeMap<String, Net>`, and is
-/// dereferencable to such.
+/// This is a wrapper around a `BTreeMap<String, Net>`, and is dereferencable
+/// to such.
 #[derive(Clone, Hash, PartialEq, Eq, Debug, Default)]
 pub struct Book {
   pub nets: BTreeMap<String, Net>,
+  /// The doc comments (`/// ...` lines) preceding each def, without the
+  /// leading slashes.
+  ///
+  /// Transformations keep the docs of the defs they only tweak, and drop the
+  /// docs of the defs they rewrite or remove.
+  pub docs: BTreeMap<String, String>,
+  /// The paths in the `import "path"` directives of the book, in order.
+  ///
+  /// These are not resolved by the parser; the CLI loads the imported books
+  /// (relative to the importing file) and merges them into a single book,
+  /// which has no imports.
+  pub imports: Vec<String>,
+  /// The names declared for labels by `label Name = lab` directives, which
+  /// can then be written in place of the labels, e.g. `{Name a b}`.
+  ///
+  /// The parser resolves the names as it goes, so the nets of the book only
+  /// contain numeric labels; the book is displayed with the declarations,
+  /// and with the names in place of the labels they were declared for.
+  pub labels: BTreeMap<String, Lab>,
+  /// The expressions in the `#reduce Net` directives of the book, in order.
+  ///
+  /// These let a book carry the expressions to reduce with it, e.g. in a
+  /// self-contained test case; the CLI's `reduce` command reduces them when
+  /// no expressions are given on the command line.
+  pub reduce: Vec<Net>,
 }
 
 deref!(Book => self.nets: BTreeMap<String, Net>);
@@ -61,7 +85,15 @@ pub enum Tree {
   /// A nilary eraser node.
   Era,
   /// A native 60-bit integer.
-  Int { val: i64 },
+  ///
+  /// `ty` is the integer type given by the literal's suffix (e.g. `#3u8`), if
+  /// any. Numbers are untyped at runtime, so the type only affects parsing: an
+  /// operation with no explicit type (i.e. a `u60` operation) whose operand is
+  /// written as a typed literal, as in `<+ #1u8 x>` or `#1u8 ~ <+ x y>`, is
+  /// given the literal's type. It is not propagated through variables or
+  /// definitions, and it is lost when a net is encoded, so e.g. readback and
+  /// the flat format always produce untyped ints.
+  Int { val: i64, ty: Option<Ty> },
   /// A native 32-bit float.
   F32 { val: OrderedFloat<f32> },
   /// A nilary node, referencing a named net.
@@ -85,6 +117,9 @@ pub enum Tree {
   /// A binary node representing an operation on native integers.
   ///
   /// The principal port connects to the left operand.
+  ///
+  /// An operator section `<op rhs>` is sugar for `(<op rhs x> x)`, a function
+  /// taking the left operand and returning the result.
   Op {
     /// The operation associated with this node.
     op: Op,
@@ -105,6 +140,30 @@ pub enum Tree {
     /// An auxiliary port; connects to the output.
     out: Box<Tree>,
   },
+  /// A binary node representing a match on the label of a combinator.
+  ///
+  /// The principal port connects to the combinator to be matched on. A
+  /// combinator with label `lab + i` selects the `i`th arm, which is linked to
+  /// `(x (y out))`, where `x` and `y` are the auxiliary ports of the
+  /// combinator, and the other arms are erased. If there is no `i`th arm, the
+  /// arms, the ports of the combinator, and the output are all erased.
+  ///
+  /// Combinators with labels less than `lab` are not matched on; they commute
+  /// with the node, as with any other binary node.
+  ///
+  /// At runtime, this is a [`Tag::Mat`](crate::run::Tag::Mat) node with a
+  /// nonzero label, whose first auxiliary port connects to `(arm0 (arm1 ...
+  /// *))`.
+  Switch {
+    /// The label of the first arm. Must be nonzero.
+    lab: Lab,
+    /// The arms of the match.
+    ///
+    /// Must have a length less than [`MAX_ARITY`].
+    arms: Vec<Tree>,
+    /// An auxiliary port; connects to the output.
+    out: Box<Tree>,
+  },
   /// An Scott-encoded ADT node.
   ///
   /// This is always equivalent to:
@@ -157,6 +216,54 @@ impl Net {
   pub fn trees_mut(&mut self) -> impl Iterator<Item = &mut Tree> {
     iter::once(&mut self.root).chain(self.redexes.iter_mut().flat_map(|(x, y)| [x, y]))
   }
+
+  /// Renames the variables of this net to `a`, `b`, `c`, etc., in order of
+  /// first occurrence (in the root, then in the redexes), so that nets that
+  /// only differ in the names of their variables are displayed identically.
+  pub fn canonicalize(&mut self) {
+    fn rename(tree: &mut Tree, vars: &mut Map<String, String>) {
+      maybe_grow(|| match tree {
+        Tree::Var { nam } => {
+          let len = vars.len();
+          *nam = vars.entry(mem::take(nam)).or_insert_with(|| create_var(len)).clone();
+        }
+        _ => tree.children_mut().for_each(|tree| rename(tree, vars)),
+      })
+    }
+    let mut vars = Map::default();
+    self.trees_mut().for_each(|tree| rename(tree, &mut vars));
+  }
+
+  /// Checks that every variable of this net appears exactly twice, as each
+  /// variable stands for a wire between two ports.
+  ///
+  /// Otherwise, returns a description of each variable that doesn't, in order
+  /// of first occurrence: a variable that appears once is a dangling wire,
+  /// and one that appears three or more times is a wire with too many ends.
+  /// (A variable can't appear zero times, as it only exists by appearing.)
+  pub fn check_linearity(&self) -> Result<(), Vec<String>> {
+    fn count<'t>(tree: &'t Tree, vars: &mut Vec<(&'t str, usize)>, index: &mut Map<&'t str, usize>) {
+      maybe_grow(|| match tree {
+        Tree::Var { nam } => {
+          let i = *index.entry(nam).or_insert_with(|| {
+            vars.push((nam, 0));
+            vars.len() - 1
+          });
+          vars[i].1 += 1;
+        }
+        _ => tree.children().for_each(|tree| count(tree, vars, index)),
+      })
+    }
+    let mut vars = Vec::new();
+    let mut index = Map::default();
+    self.trees().for_each(|tree| count(tree, &mut vars, &mut index));
+    let errors: Vec<_> = vars
+      .into_iter()
+      .filter(|&(_, n)| n != 2)
+      .map(|(nam, n)| if n == 1 { format!("`{nam}` appears once") } else { format!("`{nam}` appears {n} times") })
+      .collect();
+    if errors.is_empty() { Ok(()) } else { Err(errors) }
+  }
 }
 
 impl Tree {
@@ -169,6 +276,7 @@ impl Tree {
       Tree::Ctr { ports, .. } => array_vec::from_iter(ports),
       Tree::Op { rhs, out, .. } => array_vec::from_array([rhs, out]),
       Tree::Mat { zero, succ, out } => array_vec::from_array([zero, succ, out]),
+      Tree::Switch { arms, out, .. } => array_vec::from_iter(arms.iter().chain([&**out])),
       Tree::Adt { fields, .. } => array_vec::from_iter(fields),
     })
   }
@@ -182,6 +290,7 @@ impl Tree {
       Tree::Ctr { ports, .. } => array_vec::from_iter(ports),
       Tree::Op { rhs, out, .. } => array_vec::from_array([rhs, out]),
       Tree::Mat { zero, succ, out } => array_vec::from_array([zero, succ, out]),
+      Tree::Switch { arms, out, .. } => array_vec::from_iter(arms.iter_mut().chain([&mut **out])),
       Tree::Adt { fields, .. } => array_vec::from_iter(fields),
     })
   }
@@ -194,6 +303,35 @@ impl Tree {
     }
   }
 
+  /// If `(self ret)` is an operator section, `<op rhs>`, returns the operator
+  /// and the right operand.
+  fn as_section(&self, ret: &Tree) -> Option<(&Op, &Tree)> {
+    match (self, ret) {
+      (Tree::Op { op, rhs, out }, Tree::Var { nam }) if matches!(&**out, Tree::Var { nam: out } if out == nam) => {
+        Some((op, rhs))
+      }
+      _ => None,
+    }
+  }
+
+  /// Builds a [`Tree::Switch`] from its runtime representation, where `arms`
+  /// is `(arm0 (arm1 ... *))`.
+  pub fn switch(lab: Lab, mut arms: Tree, out: Tree) -> Option<Tree> {
+    let mut list = vec![];
+    loop {
+      match &mut arms {
+        Tree::Era => break,
+        Tree::Ctr { lab: 0, ports } if ports.len() >= 2 => {
+          let rest = ports.pop().unwrap();
+          list.append(ports);
+          arms = rest;
+        }
+        _ => None?,
+      }
+    }
+    (list.len() < MAX_ARITY).then(|| Tree::Switch { lab, arms: list, out: Box::new(out) })
+  }
+
   pub fn legacy_mat(mut arms: Tree, out: Tree) -> Option<Tree> {
     let Tree::Ctr { lab: 0, ports } = &mut arms else { None? };
     let ports = mem::take(ports);
@@ -204,52 +342,175 @@ impl Tree {
   }
 }
 
-new_parser!(HvmcParser);
+pub struct HvmcParser<'i> {
+  input: &'i str,
+  index: usize,
+  /// The label names declared so far; see [`Book::labels`].
+  labels: BTreeMap<String, Lab>,
+  /// The constants declared so far, by `const Name = Tree` directives; each
+  /// use of one, as `$Name` or `@Name`, is replaced with a copy of its tree.
+  consts: BTreeMap<String, Tree>,
+  /// The number of trees being parsed, each nested in the previous one.
+  depth: usize,
+  /// See [`Book::parse_with_max_depth`].
+  max_depth: usize,
+}
 
 impl<'i> HvmcParser<'i> {
-  /// Book = ("@" Name "=" Net)*
-  fn parse_book(&mut self) -> Result<Book, String> {
+  fn new(input: &'i str) -> Self {
+    HvmcParser { input, index: 0, labels: BTreeMap::new(), consts: BTreeMap::new(), depth: 0, max_depth: usize::MAX }
+  }
+
+  /// Book = ("import" Quoted | "label" Name "=" Int | "const" Name "=" Tree
+  ///   | "#reduce" Net | Doc "@" DefName "=" Net)*
+  fn parse_book(&mut self) -> Result<Book, ParseError> {
     maybe_grow(move || {
-      let mut book = BTreeMap::new();
-      while self.consume("@").is_ok() {
-        let name = self.parse_name()?;
+      let mut book = Book::default();
+      loop {
+        let doc = self.parse_doc();
+        if self.try_consume("import") {
+          self.skip_trivia();
+          book.imports.push(self.parse_quoted(ParseErrorKind::UnterminatedPath)?);
+          continue;
+        }
+        if self.try_consume("label") {
+          self.skip_trivia();
+          let start = self.index;
+          let name = self.parse_name()?;
+          if name.starts_with(|c: char| c.is_ascii_digit()) {
+            Err(ParseError::new(start, ParseErrorKind::Expected("label name")))?;
+          }
+          self.consume("=")?;
+          let lab = self.parse_u64()? as Lab;
+          self.labels.insert(name.clone(), lab);
+          book.labels.insert(name, lab);
+          continue;
+        }
+        if self.try_consume("#reduce") {
+          book.reduce.push(self.parse_net()?);
+          continue;
+        }
+        if self.try_consume("const") {
+          self.skip_trivia();
+          let start = self.index;
+          let name = self.parse_name()?;
+          if book.nets.contains_key(&name) {
+            Err(ParseError::new(start, ParseErrorKind::ConstNamesDef))?;
+          }
+          self.consume("=")?;
+          let tree = self.parse_tree()?;
+          self.consts.insert(name, tree);
+          continue;
+        }
+        if !self.try_consume("@") {
+          break;
+        }
+        let start = self.index;
+        let name = self.parse_def_name()?;
+        // otherwise, `@Name` would refer to the constant rather than the def
+        if self.consts.contains_key(&name) {
+          Err(ParseError::new(start, ParseErrorKind::ConstNamesDef))?;
+        }
         self.consume("=")?;
         let net = self.parse_net()?;
-        book.insert(name, net);
+        if let Some(doc) = doc {
+          book.docs.insert(name.clone(), doc);
+        }
+        book.nets.insert(name, net);
       }
-      Ok(Book { nets: book })
+      Ok(book)
     })
   }
 
-  /// Net = Tree ("&" Tree "~" Tree)*
-  fn parse_net(&mut self) -> Result<Net, String> {
+  /// Doc = ("///" /[^\n]*/)*
+  ///
+  /// Skips trivia, collecting the doc comments within it; ordinary `//`
+  /// comments are skipped as usual.
+  fn parse_doc(&mut self) -> Option<String> {
+    let mut doc: Option<String> = None;
+    loop {
+      self.skip_spaces();
+      if !self.starts_with("//") {
+        return doc;
+      }
+      let line = self.take_while(|c| c != '\n');
+      if let Some(line) = line.strip_prefix("///").filter(|line| !line.starts_with('/')) {
+        let line = line.strip_prefix(' ').unwrap_or(line);
+        match &mut doc {
+          Some(doc) => {
+            doc.push('\n');
+            doc.push_str(line);
+          }
+          None => doc = Some(line.to_owned()),
+        }
+      }
+    }
+  }
+
+  /// Net = Tree ("&" Redex ("," Redex)*)*
+  ///
+  /// Redex = "erase" Tree | Tree ["~"] Tree
+  ///
+  /// The `~` may be omitted, and several redexes may follow one `&`, separated
+  /// by commas; `a & b c, d ~ e` is shorthand for `a & b ~ c & d ~ e`.
+  ///
+  /// `erase a` is shorthand for `* ~ a`, which erases the subnet `a`; a
+  /// variable named `erase` may still start a redex if it is followed by `~`.
+  fn parse_net(&mut self) -> Result<Net, ParseError> {
     let mut redexes = Vec::new();
     let root = self.parse_tree()?;
-    while self.consume("&").is_ok() {
-      let tree1 = self.parse_tree()?;
-      self.consume("~")?;
-      let tree2 = self.parse_tree()?;
-      redexes.push((tree1, tree2));
+    while self.try_consume("&") {
+      loop {
+        let mut tree1 = self.parse_tree()?;
+        let erase = matches!(&tree1, Tree::Var { nam } if nam == "erase");
+        if !self.try_consume("~") && erase {
+          tree1 = Tree::Era;
+        }
+        let mut tree2 = self.parse_tree()?;
+        if let (Tree::Int { ty, .. }, Tree::Op { op, .. }) | (Tree::Op { op, .. }, Tree::Int { ty, .. }) =
+          (&mut tree1, &mut tree2)
+        {
+          infer_op_ty(op, *ty);
+        }
+        redexes.push((tree1, tree2));
+        if !self.try_consume(",") {
+          break;
+        }
+      }
     }
-    Ok(Net { root, redexes })
+    let mut net = Net { root, redexes };
+    net.rename_sections();
+    Ok(net)
   }
 
-  fn parse_tree(&mut self) -> Result<Tree, String> {
+  fn parse_tree(&mut self) -> Result<Tree, ParseError> {
+    if self.depth == self.max_depth {
+      self.skip_trivia();
+      return Err(ParseError::new(self.index, ParseErrorKind::TooDeep));
+    }
+    self.depth += 1;
+    let tree = self.parse_tree_inner();
+    self.depth -= 1;
+    tree
+  }
+
+  fn parse_tree_inner(&mut self) -> Result<Tree, ParseError> {
     maybe_grow(move || {
       self.skip_trivia();
+      let start = self.index;
       match self.peek_one() {
         // Era = "*"
         Some('*') => {
           self.advance_one();
           Ok(Tree::Era)
         }
-        // Ctr = "(" Tree Tree ")" | "[" Tree Tree "]" | "{" Int Tree Tree "}"
+        // Ctr = "(" Tree Tree ")" | "[" Tree Tree "]" | "{" Label Tree Tree "}"
         Some(char @ ('(' | '[' | '{')) => {
           self.advance_one();
           let lab = match char {
             '(' => 0,
             '[' => 1,
-            '{' => self.parse_u64()? as Lab,
+            '{' => self.parse_label()?,
             _ => unreachable!(),
           };
           let close = match char {
@@ -272,18 +533,18 @@ impl<'i> HvmcParser<'i> {
             }
             self.advance_one();
             if variant_count == 0 {
-              Err("variant count cannot be zero".to_owned())?;
+              Err(ParseError::new(start, ParseErrorKind::ZeroVariants))?;
             }
             if variant_count > (MAX_ADT_VARIANTS as u64) {
-              Err("adt has too many variants".to_owned())?;
+              Err(ParseError::new(start, ParseErrorKind::TooManyVariants))?;
             }
             if variant_index >= variant_count {
-              Err("variant index out of range".to_owned())?;
+              Err(ParseError::new(start, ParseErrorKind::VariantIndexOutOfRange))?;
             }
             let variant_index = variant_index as usize;
             let variant_count = variant_count as usize;
             if fields.len() > MAX_ADT_FIELDS {
-              Err("adt has too many fields".to_owned())?;
+              Err(ParseError::new(start, ParseErrorKind::TooManyFields))?;
             }
             Ok(Tree::Adt { lab, variant_index, variant_count, fields })
           } else {
@@ -295,58 +556,111 @@ impl<'i> HvmcParser<'i> {
             }
             self.advance_one();
             if ports.len() > MAX_ARITY {
-              Err("ctr has too many ports".to_owned())?;
+              Err(ParseError::new(start, ParseErrorKind::TooManyPorts))?;
             }
             Ok(Tree::Ctr { lab, ports })
           }
         }
-        // Ref = "@" Name
+        // Ref = "@" DefName
+        //
+        // Names of constants are expanded, rather than referenced.
         Some('@') => {
           self.advance_one();
           self.skip_trivia();
+          let nam = self.parse_def_name()?;
+          Ok(self.expand_const(start, &nam).unwrap_or(Tree::Ref { nam }))
+        }
+        // Const = "$" Name
+        //
+        // This takes precedence over variables whose names start with `$`.
+        Some('$') => {
+          self.advance_one();
           let nam = self.parse_name()?;
-          Ok(Tree::Ref { nam })
+          self.expand_const(start, &nam).ok_or(ParseError::new(start, ParseErrorKind::UnknownConst))
         }
-        // Int = "#" [-] Int
+        // Int = "#" [-] Int [Ty]
         // F32 = "#" [-] ( Int "." Int | "NaN" | "inf" )
         Some('#') => {
           self.advance_one();
-          let is_neg = self.consume("-").is_ok();
+          let is_neg = self.try_consume("-");
+          let num_start = self.index;
           let num = self.take_while(|c| c.is_alphanumeric() || c == '.');
+          let invalid_number = || ParseError::new(num_start, ParseErrorKind::InvalidNumber);
 
           if num.contains('.') || num.contains("NaN") || num.contains("inf") {
-            let mut val: f32 = num.parse().map_err(|err| format!("{err:?}"))?;
+            let mut val: f32 = num.parse().map_err(|_| invalid_number())?;
             if is_neg {
               val = -val;
             }
             Ok(Tree::F32 { val: val.into() })
           } else {
-            let mut val: i64 = parse_int(num)? as i64;
+            let (num, ty) = match num.find(['u', 'i']) {
+              Some(idx) => {
+                let ty = num[idx ..].parse::<Ty>().ok().filter(Ty::is_int);
+                (&num[.. idx], Some(ty.ok_or(ParseError::new(num_start + idx, ParseErrorKind::InvalidType))?))
+              }
+              None => (num, None),
+            };
+            let mut val: i64 = parse_int(num).ok_or_else(invalid_number)? as i64;
             if is_neg {
               val = -val;
             }
-            Ok(Tree::Int { val })
+            if let Some(ty) = ty {
+              if !ty.contains(val) {
+                return Err(ParseError::new(start, ParseErrorKind::IntOutOfRange(ty)));
+              }
+            }
+            Ok(Tree::Int { val, ty })
           }
         }
-        // Op = "<" Op Tree Tree ">"
+        // Op = "<" Op Tree Tree ">" | "<" Op Tree ">"
         Some('<') => {
           self.advance_one();
-          let op = self.parse_op()?;
+          let mut op = self.parse_op()?;
           let rhs = Box::new(self.parse_tree()?);
+          if let Tree::Int { ty, .. } = &*rhs {
+            infer_op_ty(&mut op, *ty);
+          }
+          self.skip_trivia();
+          if self.peek_one() == Some('>') {
+            self.advance_one();
+            let nam = format!("{SECTION_VAR}{}", self.index);
+            let out = Box::new(Tree::Var { nam: nam.clone() });
+            return Ok(Tree::Ctr { lab: 0, ports: vec![Tree::Op { op, rhs, out }, Tree::Var { nam }] });
+          }
           let out = Box::new(self.parse_tree()?);
           self.consume(">")?;
           Ok(Tree::Op { op, rhs, out })
         }
+        // Switch = "?{" Label Tree* Tree "}"
         // Mat = "?<" Tree Tree ">"
         Some('?') => {
           self.advance_one();
+          if self.try_consume("{") {
+            let lab = self.parse_label()?;
+            if lab == 0 {
+              Err(ParseError::new(start, ParseErrorKind::ZeroSwitchLabel))?;
+            }
+            let mut arms = Vec::new();
+            self.skip_trivia();
+            while self.peek_one() != Some('}') {
+              arms.push(self.parse_tree()?);
+              self.skip_trivia();
+            }
+            self.advance_one();
+            let out = Box::new(arms.pop().ok_or(ParseError::new(start, ParseErrorKind::EmptySwitch))?);
+            if arms.len() >= MAX_ARITY {
+              Err(ParseError::new(start, ParseErrorKind::TooManyArms))?;
+            }
+            return Ok(Tree::Switch { lab, arms, out });
+          }
           self.consume("<")?;
           let zero = self.parse_tree()?;
           let succ = self.parse_tree()?;
           self.skip_trivia();
           if self.peek_one() == Some('>') {
             self.advance_one();
-            Tree::legacy_mat(zero, succ).ok_or_else(|| "invalid legacy match".to_owned())
+            Tree::legacy_mat(zero, succ).ok_or(ParseError::new(start, ParseErrorKind::InvalidLegacyMatch))
           } else {
             let zero = Box::new(zero);
             let succ = Box::new(succ);
@@ -361,150 +675,737 @@ impl<'i> HvmcParser<'i> {
     })
   }
 
+  /// Returns the next character in the input, without consuming it.
+  fn peek_one(&self) -> Option<char> {
+    self.input[self.index ..].chars().next()
+  }
+
+  /// Consumes the next character in the input.
+  fn advance_one(&mut self) -> Option<char> {
+    let c = self.peek_one()?;
+    self.index += c.len_utf8();
+    Some(c)
+  }
+
+  /// Returns whether `text` is next in the input.
+  fn starts_with(&self, text: &str) -> bool {
+    self.input[self.index ..].starts_with(text)
+  }
+
+  /// Consumes the longest run of characters matching `f`, returning it.
+  fn take_while(&mut self, mut f: impl FnMut(char) -> bool) -> &'i str {
+    let start = self.index;
+    while self.peek_one().is_some_and(&mut f) {
+      self.advance_one();
+    }
+    &self.input[start .. self.index]
+  }
+
+  /// Skips whitespace.
+  fn skip_spaces(&mut self) {
+    self.take_while(|c| c.is_ascii_whitespace());
+  }
+
+  /// Skips whitespace and `//` comments.
+  fn skip_trivia(&mut self) {
+    loop {
+      self.skip_spaces();
+      if !self.starts_with("//") {
+        break;
+      }
+      self.take_while(|c| c != '\n');
+    }
+  }
+
+  /// Consumes `text` if it is next in the input (after trivia), returning
+  /// whether it was found.
+  ///
+  /// Unlike `consume(text).is_ok()`, this doesn't build an error on failure.
+  fn try_consume(&mut self, text: &str) -> bool {
+    self.skip_trivia();
+    let found = self.starts_with(text);
+    if found {
+      self.index += text.len();
+    }
+    found
+  }
+
+  /// Consumes `text`, which must be next in the input (after trivia).
+  fn consume(&mut self, text: &'static str) -> Result<(), ParseError> {
+    if self.try_consume(text) { Ok(()) } else { Err(self.expected(text)) }
+  }
+
+  /// Returns an error at the current position, for when `what` was expected.
+  fn expected(&mut self, what: &'static str) -> ParseError {
+    ParseError::new(self.index, ParseErrorKind::Expected(what))
+  }
+
+  /// Int = /[0-9]+/ | "0x" /[0-9a-fA-F]+/ | "0b" /[01]+/
+  fn parse_u64(&mut self) -> Result<u64, ParseError> {
+    self.skip_trivia();
+    let start = self.index;
+    let radix = if self.try_consume("0x") {
+      16
+    } else if self.try_consume("0b") {
+      2
+    } else {
+      10
+    };
+    let num = self.take_while(|c| c.is_digit(radix) || c == '_');
+    if num.is_empty() {
+      return Err(self.expected("numeric digit"));
+    }
+    u64::from_str_radix(num, radix).map_err(|_| ParseError::new(start, ParseErrorKind::InvalidNumber))
+  }
+
   /// Name = /[a-zA-Z0-9_.$]+/
-  fn parse_name(&mut self) -> Result<String, String> {
+  fn parse_name(&mut self) -> Result<String, ParseError> {
     let name = self.take_while(|c| c.is_alphanumeric() || c == '_' || c == '.' || c == '$');
     if name.is_empty() {
-      return self.expected("name");
+      return Err(self.expected("name"));
     }
     Ok(name.to_owned())
   }
 
+  /// Label = Int | Name
+  ///
+  /// Names are resolved with the `label` declarations that precede them.
+  fn parse_label(&mut self) -> Result<Lab, ParseError> {
+    self.skip_trivia();
+    if self.peek_one().is_some_and(|c| c.is_ascii_digit()) {
+      return Ok(self.parse_u64()? as Lab);
+    }
+    let start = self.index;
+    let name = self.parse_name()?;
+    self.labels.get(&name).copied().ok_or(ParseError::new(start, ParseErrorKind::UnknownLabel))
+  }
+
+  /// Returns a copy of the tree of the constant `nam`, for a use of it at
+  /// `start`, if it has been declared.
+  ///
+  /// The variables of the copy are renamed apart from those of every other
+  /// copy, like the variables of operator sections (see [`SECTION_VAR`]), so
+  /// that a constant can be used several times in the same net.
+  fn expand_const(&self, start: usize, nam: &str) -> Option<Tree> {
+    let mut tree = self.consts.get(nam)?.clone();
+    visit_vars(&mut tree, &mut |var| *var = format!("{SECTION_VAR}{start}:{var}"));
+    Some(tree)
+  }
+
+  /// DefName = Name | "\"" (/[^"\\]/ | "\\" /./)* "\""
+  ///
+  /// The quoted form allows any name; see [`DefName`].
+  fn parse_def_name(&mut self) -> Result<String, ParseError> {
+    if self.peek_one() != Some('"') {
+      return self.parse_name();
+    }
+    self.parse_quoted(ParseErrorKind::UnterminatedName)
+  }
+
+  /// Quoted = "\"" (/[^"\\]/ | "\\" /./)* "\""
+  fn parse_quoted(&mut self, unterminated: ParseErrorKind) -> Result<String, ParseError> {
+    let start = self.index;
+    self.consume("\"")?;
+    let mut str = String::new();
+    let unterminated = ParseError::new(start, unterminated);
+    loop {
+      match self.advance_one() {
+        Some('"') => return Ok(str),
+        Some('\\') => str.push(self.advance_one().ok_or(unterminated)?),
+        Some(c) => str.push(c),
+        None => Err(unterminated)?,
+      }
+    }
+  }
+
   /// See `ops.rs` for the available operators.
-  fn parse_op(&mut self) -> Result<Op, String> {
-    let op = self.take_while(|c| c.is_alphanumeric() || ".+-=*/%<>|&^!?$".contains(c));
-    op.parse().map_err(|_| format!("Unknown operator: {op:?}"))
+  fn parse_op(&mut self) -> Result<Op, ParseError> {
+    let start = self.index;
+    let op = self.take_while(|c| c.is_alphanumeric() || ".+-=*/%<>|&^!?$:".contains(c));
+    op.parse().map_err(|_| ParseError::new(start, ParseErrorKind::UnknownOperator))
+  }
+}
+
+/// Gives an operation with no explicit type the type of a typed integer
+/// literal it is applied to, so that e.g. `<+ #1u8 x>` is a `u8` addition.
+///
+/// This is only called on the literals that are syntactically an operand of the
+/// operation, i.e. its right operand or the other side of its redex; an
+/// operation only connected to a typed literal through a variable stays
+/// untyped.
+fn infer_op_ty(op: &mut Op, ty: Option<Ty>) {
+  if let Some(ty) = ty {
+    if op.ty == Ty::U60 {
+      op.ty = ty;
+    }
   }
 }
 
 /// Parses an unsigned integer with an optional radix prefix.
-fn parse_int(input: &str) -> Result<u64, String> {
+fn parse_int(input: &str) -> Option<u64> {
   if let Some(rest) = input.strip_prefix("0x") {
-    u64::from_str_radix(rest, 16).map_err(|err| format!("{err:?}"))
+    u64::from_str_radix(rest, 16).ok()
   } else if let Some(rest) = input.strip_prefix("0b") {
-    u64::from_str_radix(rest, 2).map_err(|err| format!("{err:?}"))
+    u64::from_str_radix(rest, 2).ok()
   } else {
-    input.parse::<u64>().map_err(|err| format!("{err:?}"))
+    input.parse::<u64>().ok()
   }
 }
 
 /// Parses the input with the callback, ensuring that the whole input is
 /// consumed.
-fn parse_eof<'i, T>(input: &'i str, parse_fn: impl Fn(&mut HvmcParser<'i>) -> Result<T, String>) -> Result<T, String> {
+fn parse_eof<'i, T>(
+  input: &'i str,
+  max_depth: usize,
+  parse_fn: impl Fn(&mut HvmcParser<'i>) -> Result<T, ParseError>,
+) -> Result<T, ParseError> {
   let mut parser = HvmcParser::new(input);
+  parser.max_depth = max_depth;
   let out = parse_fn(&mut parser)?;
   if parser.index != parser.input.len() {
-    return Err("Unable to parse the whole input. Is this not an hvmc file?".to_owned());
+    return Err(ParseError::new(parser.index, ParseErrorKind::TrailingInput));
   }
   Ok(out)
 }
 
+/// An error encountered while parsing.
+///
+/// This only records what went wrong and where, so it is cheap to construct;
+/// see [`ParseError::highlight`] to show it in the context of the input.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "std", derive(Error))]
+#[cfg_attr(feature = "std", error("{kind} (at byte {offset})"))]
+pub struct ParseError {
+  /// The byte offset in the input at which the error was found.
+  pub offset: usize,
+  pub kind: ParseErrorKind,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+#[cfg_attr(feature = "std", derive(Error))]
+pub enum ParseErrorKind {
+  #[cfg_attr(feature = "std", error("expected {0}"))]
+  Expected(&'static str),
+  #[cfg_attr(feature = "std", error("invalid number"))]
+  InvalidNumber,
+  #[cfg_attr(feature = "std", error("invalid integer type"))]
+  InvalidType,
+  #[cfg_attr(feature = "std", error("integer literal out of range for {0}"))]
+  IntOutOfRange(Ty),
+  #[cfg_attr(feature = "std", error("unknown operator"))]
+  UnknownOperator,
+  #[cfg_attr(feature = "std", error("unknown label name"))]
+  UnknownLabel,
+  #[cfg_attr(feature = "std", error("unknown constant name"))]
+  UnknownConst,
+  #[cfg_attr(feature = "std", error("a constant and a def have the same name"))]
+  ConstNamesDef,
+  #[cfg_attr(feature = "std", error("unterminated name"))]
+  UnterminatedName,
+  #[cfg_attr(feature = "std", error("unterminated import path"))]
+  UnterminatedPath,
+  #[cfg_attr(feature = "std", error("variant count cannot be zero"))]
+  ZeroVariants,
+  #[cfg_attr(feature = "std", error("adt has too many variants"))]
+  TooManyVariants,
+  #[cfg_attr(feature = "std", error("variant index out of range"))]
+  VariantIndexOutOfRange,
+  #[cfg_attr(feature = "std", error("adt has too many fields"))]
+  TooManyFields,
+  #[cfg_attr(feature = "std", error("ctr has too many ports"))]
+  TooManyPorts,
+  #[cfg_attr(feature = "std", error("switch label cannot be zero"))]
+  ZeroSwitchLabel,
+  #[cfg_attr(feature = "std", error("switch has no output"))]
+  EmptySwitch,
+  #[cfg_attr(feature = "std", error("switch has too many arms"))]
+  TooManyArms,
+  #[cfg_attr(feature = "std", error("invalid legacy match"))]
+  InvalidLegacyMatch,
+  #[cfg_attr(feature = "std", error("unable to parse the whole input; is this not an hvmc file?"))]
+  TrailingInput,
+  #[cfg_attr(feature = "std", error("trees are nested too deeply"))]
+  TooDeep,
+}
+
+impl ParseError {
+  fn new(offset: usize, kind: ParseErrorKind) -> Self {
+    ParseError { offset, kind }
+  }
+
+  /// Renders the line(s) of `input` around the error, highlighting where it
+  /// was found. `input` must be the input that failed to parse.
+  #[cfg(feature = "std")]
+  pub fn highlight(&self, input: &str) -> String {
+    highlight_error::highlight_error(self.offset, self.offset + 1, input)
+  }
+}
+
 impl FromStr for Book {
-  type Err = String;
+  type Err = ParseError;
   fn from_str(str: &str) -> Result<Self, Self::Err> {
-    parse_eof(str, HvmcParser::parse_book)
+    parse_eof(str, usize::MAX, HvmcParser::parse_book)
+  }
+}
+
+impl Book {
+  /// Parses a book, like [`str::parse`], but fails with
+  /// [`ParseErrorKind::TooDeep`] if any tree in it is nested more than
+  /// `max_depth` levels deep, counting the leaves (e.g. `(a (b c))` is three
+  /// levels deep).
+  ///
+  /// This bounds the stack usage of parsing untrusted input.
+  pub fn parse_with_max_depth(str: &str, max_depth: usize) -> Result<Book, ParseError> {
+    parse_eof(str, max_depth, HvmcParser::parse_book)
   }
 }
 
 impl FromStr for Net {
-  type Err = String;
+  type Err = ParseError;
   fn from_str(str: &str) -> Result<Self, Self::Err> {
-    parse_eof(str, HvmcParser::parse_net)
+    parse_eof(str, usize::MAX, HvmcParser::parse_net)
   }
 }
 
 impl FromStr for Tree {
-  type Err = String;
+  type Err = ParseError;
   fn from_str(str: &str) -> Result<Self, Self::Err> {
-    parse_eof(str, HvmcParser::parse_tree)
+    let root = parse_eof(str, usize::MAX, HvmcParser::parse_tree)?;
+    let mut net = Net { root, redexes: vec![] };
+    net.rename_sections();
+    Ok(mem::take(&mut net.root))
+  }
+}
+/// The prefix of the names of the variables introduced when parsing operator
+/// sections, and when expanding constants. Since it can't be written in a
+/// name, these variables can't clash with user variables; once the whole net
+/// has been parsed, they are renamed apart from the other variables of the net
+/// (see [`Net::rename_sections`]).
+const SECTION_VAR: &str = "<sec>";
+
+/// Calls `f` on the name of every variable in `tree`.
+fn visit_vars(tree: &mut Tree, f: &mut impl FnMut(&mut String)) {
+  maybe_grow(|| match tree {
+    Tree::Var { nam } => f(nam),
+    _ => tree.children_mut().for_each(|child| visit_vars(child, f)),
+  })
+}
+
+impl Net {
+  /// Renames the variables introduced by operator sections and constants to
+  /// names that are not otherwise used in the net.
+  fn rename_sections(&mut self) {
+    let mut used = Set::new();
+    let mut sections = false;
+    for tree in self.trees_mut() {
+      visit_vars(tree, &mut |nam| {
+        sections |= nam.starts_with(SECTION_VAR);
+        used.insert(nam.clone());
+      });
+    }
+    if !sections {
+      return;
+    }
+    let mut names = Map::new();
+    let mut next = 0;
+    for tree in self.trees_mut() {
+      visit_vars(tree, &mut |nam| {
+        if nam.starts_with(SECTION_VAR) {
+          *nam = names
+            .entry(mem::take(nam))
+            .or_insert_with(|| {
+              loop {
+                let fresh = format!("_sec{next}");
+                next += 1;
+                if !used.contains(&fresh) {
+                  break fresh;
+                }
+              }
+            })
+            .clone();
+        }
+      });
+    }
+  }
+}
+
+impl Net {
+  /// Evaluates the operations applied to two literals, such as `#2 ~ <+ #3 x>`,
+  /// replacing them with their results, as they would be computed at runtime.
+  ///
+  /// If the output of such an operation is a variable, the result is
+  /// substituted for it, which may in turn make other operations foldable.
+  /// This is meant to be used right after parsing, before any nodes are built
+  /// for the net; see `--fold-on-parse`.
+  pub fn fold_literal_ops(&mut self) {
+    let mut i = 0;
+    while i < self.redexes.len() {
+      let Some(val) = literal_op(&self.redexes[i]) else {
+        i += 1;
+        continue;
+      };
+      let out = match &mut self.redexes[i] {
+        (Tree::Op { out, .. }, _) | (_, Tree::Op { out, .. }) => mem::take(&mut **out),
+        _ => unreachable!(),
+      };
+      if let Tree::Var { nam } = &out {
+        self.redexes.remove(i);
+        match self.substitute(nam, val) {
+          // the substituted tree may be the side of an earlier redex
+          Ok(()) => i = 0,
+          Err(val) => self.redexes.insert(i, (val, out)),
+        }
+      } else {
+        self.redexes[i] = (val, out);
+      }
+    }
+  }
+
+  /// Replaces the variable named `nam` with `val`, returning `val` if there is
+  /// no such variable.
+  fn substitute(&mut self, nam: &str, val: Tree) -> Result<(), Tree> {
+    fn find<'t>(tree: &'t mut Tree, nam: &str) -> Option<&'t mut Tree> {
+      maybe_grow(|| match tree {
+        Tree::Var { nam: x } if x == nam => Some(tree),
+        _ => tree.children_mut().find_map(|child| find(child, nam)),
+      })
+    }
+    let Some(tree) = self.trees_mut().find_map(|tree| find(tree, nam)) else { return Err(val) };
+    *tree = val;
+    Ok(())
+  }
+}
+
+/// If `redex` applies an operation to two literals, returns its result, as
+/// computed by [`Net::op_num`](crate::run::Net::op_num). Operations that
+/// would fail, such as `ext` ops (whose functions are only known to a host),
+/// are left as they are.
+fn literal_op(redex: &(Tree, Tree)) -> Option<Tree> {
+  let literal = |tree: &Tree| match tree {
+    Tree::Int { val, .. } => Some(Port::new_int(*val).num()),
+    Tree::F32 { val } => Some(Port::new_float(val.0).num()),
+    _ => None,
+  };
+  let ((Tree::Op { op, rhs, .. }, lhs) | (lhs, Tree::Op { op, rhs, .. })) = redex else { None? };
+  let val = op.op(literal(lhs)?, literal(rhs)?, &ExtOps::new()).ok()?;
+  Some(if op.is_int() {
+    Tree::Int { val: Port::new_num(Tag::Int, val).int(), ty: None }
+  } else {
+    Tree::F32 { val: Port::new_num(Tag::F32, val).float().into() }
+  })
+}
+
+/// Custom brackets with which to print the nodes with particular labels, in
+/// place of the default `(...)`, `[...]`, or `{lab ...}`, or names to print
+/// in place of the labels, as in `{name ...}`; along with the [`NumFormat`] in
+/// which to print ints.
+///
+/// Output that uses brackets generally can't be parsed back; output that uses
+/// names can, given the `label` declarations of the names (see
+/// [`Book::labels`]).
+///
+/// ```
+/// # use hvmc::ast::{LabelAliases, Tree};
+/// let tree: Tree = "({3 a b} {4 a b})".parse().unwrap();
+/// let mut aliases = LabelAliases::default();
+/// aliases.insert(3, "<|", "|>");
+/// assert_eq!(tree.display_with(&aliases).to_string(), "(<|a b|> {4 a b})");
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct LabelAliases {
+  brackets: Map<Lab, (String, String)>,
+  names: Map<Lab, String>,
+  num_format: NumFormat,
+  sections: bool,
+}
+
+/// How to print the value of an int.
+///
+/// Both formats can be parsed back.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "cli", derive(clap::ValueEnum))]
+pub enum NumFormat {
+  /// In decimal, e.g. `#-42` or `#255u8`.
+  #[default]
+  Dec,
+  /// In hexadecimal, e.g. `#-0x2a` or `#0xffu8`.
+  Hex,
+}
+
+impl LabelAliases {
+  /// Prints the nodes with label `lab` between `open` and `close`.
+  pub fn insert(&mut self, lab: Lab, open: &str, close: &str) -> &mut Self {
+    self.brackets.insert(lab, (open.to_owned(), close.to_owned()));
+    self
+  }
+
+  /// Prints `name` in place of the label `lab`, in the nodes with that label
+  /// that aren't printed with custom brackets (including `(...)` and `[...]`
+  /// for labels 0 and 1) and in switches.
+  pub fn name(&mut self, lab: Lab, name: &str) -> &mut Self {
+    self.names.insert(lab, name.to_owned());
+    self
+  }
+
+  /// Prints ints in `format`.
+  pub fn num_format(&mut self, format: NumFormat) -> &mut Self {
+    self.num_format = format;
+    self
+  }
+
+  /// Prints operator sections, i.e. nodes of the form `(<op rhs x> x)`, with
+  /// the `<op rhs>` shorthand.
+  pub fn sections(&mut self, sections: bool) -> &mut Self {
+    self.sections = sections;
+    self
+  }
+
+  fn int(&self, f: &mut fmt::Formatter<'_>, val: i64) -> fmt::Result {
+    match self.num_format {
+      NumFormat::Dec => write!(f, "{val}"),
+      NumFormat::Hex if val < 0 => write!(f, "-{:#x}", val.unsigned_abs()),
+      NumFormat::Hex => write!(f, "{val:#x}"),
+    }
+  }
+
+  fn open(&self, f: &mut fmt::Formatter<'_>, lab: Lab) -> fmt::Result {
+    match (self.brackets.get(&lab), lab) {
+      (Some((open, _)), _) => f.write_str(open),
+      (None, 0 | 1) if !self.names.contains_key(&lab) => f.write_str(if lab == 0 { "(" } else { "[" }),
+      (None, _) => {
+        f.write_str("{")?;
+        self.label(f, lab)
+      }
+    }
+  }
+
+  fn close(&self, f: &mut fmt::Formatter<'_>, lab: Lab) -> fmt::Result {
+    match (self.brackets.get(&lab), lab) {
+      (Some((_, close)), _) => f.write_str(close),
+      (None, 0 | 1) if !self.names.contains_key(&lab) => f.write_str(if lab == 0 { ")" } else { "]" }),
+      (None, _) => f.write_str("}"),
+    }
+  }
+
+  /// Writes the label `lab`, or its name.
+  fn label(&self, f: &mut fmt::Formatter<'_>, lab: Lab) -> fmt::Result {
+    match self.names.get(&lab) {
+      Some(name) => f.write_str(name),
+      None => write!(f, "{lab}"),
+    }
+  }
+
+  /// Whether the first port of a node with label `lab` must be preceded by a
+  /// space, to separate it from the label.
+  fn space_after_open(&self, lab: Lab) -> bool {
+    (lab > 1 || self.names.contains_key(&lab)) && !self.brackets.contains_key(&lab)
+  }
+}
+
+/// See [`Book::display_with`], [`Net::display_with`], and
+/// [`Tree::display_with`].
+struct DisplayWith<'a, T>(&'a T, &'a LabelAliases);
+
+impl Book {
+  /// Displays this book, using `aliases` to print labelled nodes.
+  pub fn display_with<'a>(&'a self, aliases: &'a LabelAliases) -> impl fmt::Display + 'a {
+    DisplayWith(self, aliases)
+  }
+}
+
+impl Net {
+  /// Displays this net, using `aliases` to print labelled nodes.
+  ///
+  /// Like the `Display` impl of `Net`, this prints each redex on its own line,
+  /// unless the alternate flag is given (i.e. `{:#}`), in which case the whole
+  /// net is printed on a single line.
+  pub fn display_with<'a>(&'a self, aliases: &'a LabelAliases) -> impl fmt::Display + 'a {
+    DisplayWith(self, aliases)
+  }
+}
+
+impl Tree {
+  /// Displays this tree, using `aliases` to print labelled nodes.
+  pub fn display_with<'a>(&'a self, aliases: &'a LabelAliases) -> impl fmt::Display + 'a {
+    DisplayWith(self, aliases)
   }
 }
 
 impl fmt::Display for Book {
   fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-    for (i, (name, net)) in self.iter().enumerate() {
+    DisplayWith(self, &LabelAliases::default()).fmt(f)
+  }
+}
+
+impl fmt::Display for Net {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    DisplayWith(self, &LabelAliases::default()).fmt(f)
+  }
+}
+
+impl fmt::Display for Tree {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    DisplayWith(self, &LabelAliases::default()).fmt(f)
+  }
+}
+
+impl fmt::Display for DisplayWith<'_, Book> {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    let DisplayWith(book, aliases) = *self;
+    for path in &book.imports {
+      f.write_str("import ")?;
+      write_quoted(f, path)?;
+      f.write_str("\n")?;
+    }
+    for (name, lab) in &book.labels {
+      writeln!(f, "label {name} = {lab}")?;
+    }
+    let aliases = if book.labels.is_empty() {
+      Cow::Borrowed(aliases)
+    } else {
+      let mut aliases = aliases.clone();
+      for (name, &lab) in &book.labels {
+        aliases.name(lab, name);
+      }
+      Cow::Owned(aliases)
+    };
+    for (i, (name, net)) in book.iter().enumerate() {
       if i != 0 {
         f.write_str("\n\n")?;
+      } else if !book.imports.is_empty() || !book.labels.is_empty() {
+        f.write_str("\n")?;
+      }
+      if let Some(doc) = book.docs.get(name) {
+        for line in doc.split('\n') {
+          if line.is_empty() {
+            writeln!(f, "///")?;
+          } else {
+            writeln!(f, "/// {line}")?;
+          }
+        }
+      }
+      write!(f, "@{} = {}", DefName(name), net.display_with(&aliases))?;
+    }
+    for (i, net) in book.reduce.iter().enumerate() {
+      if i != 0 || !book.is_empty() {
+        f.write_str("\n\n")?;
+      } else if !book.imports.is_empty() || !book.labels.is_empty() {
+        f.write_str("\n")?;
       }
-      write!(f, "@{name} = {net}")?;
+      write!(f, "#reduce {}", net.display_with(&aliases))?;
     }
     Ok(())
   }
 }
 
-impl fmt::Display for Net {
+impl fmt::Display for DisplayWith<'_, Net> {
   fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-    write!(f, "{}", &self.root)?;
-    for (a, b) in &self.redexes {
-      write!(f, "\n  & {a} ~ {b}")?;
+    let DisplayWith(net, aliases) = *self;
+    write!(f, "{}", net.root.display_with(aliases))?;
+    let separator = if f.alternate() { " " } else { "\n  " };
+    for (a, b) in &net.redexes {
+      write!(f, "{separator}& {} ~ {}", a.display_with(aliases), b.display_with(aliases))?;
     }
     Ok(())
   }
 }
 
-impl fmt::Display for Tree {
+impl fmt::Display for DisplayWith<'_, Tree> {
   fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-    maybe_grow(move || match self {
+    let DisplayWith(tree, aliases) = *self;
+    maybe_grow(move || match tree {
       Tree::Era => write!(f, "*"),
+      Tree::Ctr { lab: 0, ports }
+        if aliases.sections && ports.len() == 2 && ports[0].as_section(&ports[1]).is_some() =>
+      {
+        let (op, rhs) = ports[0].as_section(&ports[1]).unwrap();
+        write!(f, "<{op} {}>", rhs.display_with(aliases))
+      }
       Tree::Ctr { lab, ports } => {
-        match lab {
-          0 => write!(f, "("),
-          1 => write!(f, "["),
-          _ => write!(f, "{{{lab}"),
-        }?;
-        let mut space = *lab > 1;
+        aliases.open(f, *lab)?;
+        let mut space = aliases.space_after_open(*lab);
         for port in ports {
           if space {
             write!(f, " ")?;
           }
-          write!(f, "{port}")?;
+          write!(f, "{}", port.display_with(aliases))?;
           space = true;
         }
-        match lab {
-          0 => write!(f, ")"),
-          1 => write!(f, "]"),
-          _ => write!(f, "}}"),
-        }?;
-        Ok(())
+        aliases.close(f, *lab)
       }
       Tree::Adt { lab, variant_index, variant_count, fields } => {
-        match lab {
-          0 => write!(f, "("),
-          1 => write!(f, "["),
-          _ => write!(f, "{{{lab}"),
-        }?;
+        aliases.open(f, *lab)?;
         write!(f, ":{}:{}", variant_index, variant_count)?;
         for field in fields {
-          write!(f, " {field}")?;
+          write!(f, " {}", field.display_with(aliases))?;
         }
-        match lab {
-          0 => write!(f, ")"),
-          1 => write!(f, "]"),
-          _ => write!(f, "}}"),
-        }?;
-        Ok(())
+        aliases.close(f, *lab)
       }
       Tree::Var { nam } => write!(f, "{nam}"),
-      Tree::Ref { nam } => write!(f, "@{nam}"),
-      Tree::Int { val } => write!(f, "#{val}"),
+      Tree::Ref { nam } => write!(f, "@{}", DefName(nam)),
+      Tree::Int { val, ty } => {
+        f.write_str("#")?;
+        aliases.int(f, *val)?;
+        ty.map_or(Ok(()), |ty| write!(f, "{ty}"))
+      }
       Tree::F32 { val } => write!(f, "#{:?}", val.0),
-      Tree::Op { op, rhs, out } => write!(f, "<{op} {rhs} {out}>"),
-      Tree::Mat { zero, succ, out } => write!(f, "?<{zero} {succ} {out}>"),
+      Tree::Op { op, rhs, out } => write!(f, "<{op} {} {}>", rhs.display_with(aliases), out.display_with(aliases)),
+      Tree::Mat { zero, succ, out } => {
+        write!(f, "?<{} {} {}>", zero.display_with(aliases), succ.display_with(aliases), out.display_with(aliases))
+      }
+      Tree::Switch { lab, arms, out } => {
+        f.write_str("?{")?;
+        aliases.label(f, *lab)?;
+        for arm in arms {
+          write!(f, " {}", arm.display_with(aliases))?;
+        }
+        write!(f, " {}}}", out.display_with(aliases))
+      }
     })
   }
 }
 
+/// Displays the name of a def as it is written after `@`.
+///
+/// Names that can't be parsed as they are (e.g. those containing spaces) are
+/// quoted, with any `"` or `\` in them escaped by a `\`.
+pub struct DefName<'a>(pub &'a str);
+
+impl fmt::Display for DefName<'_> {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    let DefName(name) = *self;
+    if !name.is_empty() && name.chars().all(|c| c.is_alphanumeric() || "_.$".contains(c)) {
+      return f.write_str(name);
+    }
+    write_quoted(f, name)
+  }
+}
+
+/// Writes `str` in quotes, escaping any `"` or `\` in it with a `\`.
+fn write_quoted(f: &mut fmt::Formatter<'_>, str: &str) -> fmt::Result {
+  f.write_str("\"")?;
+  for c in str.chars() {
+    if c == '"' || c == '\\' {
+      f.write_str("\\")?;
+    }
+    write!(f, "{c}")?;
+  }
+  f.write_str("\"")
+}
+
 // Manually implemented to avoid stack overflows.
 impl Clone for Tree {
   fn clone(&self) -> Tree {
     maybe_grow(|| match self {
       Tree::Era => Tree::Era,
-      Tree::Int { val } => Tree::Int { val: *val },
+      Tree::Int { val, ty } => Tree::Int { val: *val, ty: *ty },
       Tree::F32 { val } => Tree::F32 { val: *val },
       Tree::Ref { nam } => Tree::Ref { nam: nam.clone() },
       Tree::Ctr { lab, ports } => Tree::Ctr { lab: *lab, ports: ports.clone() },
       Tree::Op { op, rhs, out } => Tree::Op { op: *op, rhs: rhs.clone(), out: out.clone() },
       Tree::Mat { zero, succ, out } => Tree::Mat { zero: zero.clone(), succ: succ.clone(), out: out.clone() },
+      Tree::Switch { lab, arms, out } => Tree::Switch { lab: *lab, arms: arms.clone(), out: out.clone() },
       Tree::Adt { lab, variant_index, variant_count, fields } => {
         Tree::Adt { lab: *lab, variant_index: *variant_index, variant_count: *variant_count, fields: fields.clone() }
       }
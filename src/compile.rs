@@ -5,9 +5,11 @@ use alloc::collections::{BTreeMap, BTreeSet};
 use crate::prelude::*;
 
 use crate::{
+  ast::{Book, Tree},
   host::Host,
   run::{Def, Instruction, InterpretedDef, LabSet, Port, Tag},
   stdlib::HostedDef,
+  util::maybe_grow,
 };
 use core::{fmt::Write, hash::Hasher};
 use std::hash::DefaultHasher;
@@ -20,10 +22,16 @@ struct DefInfo<'a> {
 
 /// Compiles a [`Host`] to Rust, returning a file to replace `gen.rs`.
 pub fn compile_host(host: &Host) -> String {
-  _compile_host(host).unwrap()
+  compile_host_with_args(host, &[])
 }
 
-fn _compile_host(host: &Host) -> Result<String, fmt::Error> {
+/// Like [`compile_host`], but the compiled executable reduces its entry point
+/// applied to `args` when it is run without arguments.
+pub fn compile_host_with_args(host: &Host, args: &[String]) -> String {
+  _compile_host(host, args).unwrap()
+}
+
+fn _compile_host(host: &Host, args: &[String]) -> Result<String, fmt::Error> {
   let mut code = String::default();
 
   let mut def_infos: BTreeMap<&str, DefInfo<'_>> = BTreeMap::new();
@@ -40,19 +48,19 @@ fn _compile_host(host: &Host) -> Result<String, fmt::Error> {
   writeln!(code, "#![allow(warnings)]")?;
   writeln!(
     code,
-    "use crate::{{host::Host, stdlib::{{AsHostedDef, HostedDef}}, run::*, ops::{{TypedOp, Ty::*, Op::*}}}};"
+    "use crate::{{host::Host, stdlib::{{AsHostedDef, HostedDef}}, run::*, ops::{{TypedOp, Ty::*, Op::*, Overflow::*}}}};"
   )?;
   writeln!(code)?;
 
+  writeln!(code, "pub const BAKED_ARGS: &[&str] = &{args:?};")?;
+  writeln!(code)?;
+
   writeln!(code, "pub fn insert_into_host(host: &mut Host) {{")?;
 
   // insert empty defs
   for (hvmc_name, DefInfo { rust_name, def, .. }) in &def_infos {
     let labs = compile_lab_set(&def.labs)?;
-    writeln!(
-      code,
-      r##"  host.insert_def(r#"{hvmc_name}"#, unsafe {{ HostedDef::<Def_{rust_name}>::new({labs}) }});"##,
-    )?;
+    writeln!(code, r##"  host.insert_def({hvmc_name:?}, unsafe {{ HostedDef::<Def_{rust_name}>::new({labs}) }});"##)?;
   }
   writeln!(code)?;
 
@@ -60,7 +68,7 @@ fn _compile_host(host: &Host) -> Result<String, fmt::Error> {
   for hvmc_name in def_infos.values().flat_map(|info| &info.refs).collect::<BTreeSet<_>>() {
     let rust_name = &def_infos[hvmc_name].rust_name;
 
-    writeln!(code, r##"  let def_{rust_name} = Port::new_ref(&host.defs[r#"{hvmc_name}"#]);"##)?;
+    writeln!(code, r##"  let def_{rust_name} = Port::new_ref(&host.defs[{hvmc_name:?}]);"##)?;
   }
   writeln!(code)?;
 
@@ -74,7 +82,7 @@ fn _compile_host(host: &Host) -> Result<String, fmt::Error> {
 
     writeln!(
       code,
-      r##"  host.get_mut::<HostedDef<Def_{rust_name}>>(r#"{hvmc_name}"#).data.0 = Def_{rust_name} {{ {fields} }};"##
+      r##"  host.get_mut::<HostedDef<Def_{rust_name}>>({hvmc_name:?}).data.0 = Def_{rust_name} {{ {fields} }};"##
     )?;
   }
 
@@ -88,6 +96,74 @@ fn _compile_host(host: &Host) -> Result<String, fmt::Error> {
   Ok(code)
 }
 
+/// Static metrics about the code generated by [`compile_host`].
+#[derive(Debug, Default)]
+pub struct CompileReport {
+  /// The metrics of each compiled def, by name.
+  pub defs: BTreeMap<String, DefReport>,
+  /// The total number of lines of the generated file.
+  pub lines: usize,
+  /// The defs that are referred to in the book, but not by any compiled def,
+  /// e.g. because they were inlined into each of their callers.
+  pub inlined: BTreeSet<String>,
+}
+
+/// Static metrics about the code generated for a single def.
+#[derive(Debug, Default)]
+pub struct DefReport {
+  /// The number of lines of its generated struct and `call` function.
+  pub lines: usize,
+  /// The number of instructions that allocate a node when the def is called;
+  /// an estimate of its allocation sites.
+  pub allocs: usize,
+  /// The number of distinct defs it refers to.
+  pub refs: usize,
+}
+
+/// Computes a [`CompileReport`] of the code generated for `host`, which was
+/// created from `book` (before it was transformed).
+pub fn compile_report(host: &Host, book: &Book) -> CompileReport {
+  let mut report = CompileReport { lines: compile_host(host).lines().count(), ..Default::default() };
+
+  let mut compiled_refs = BTreeSet::new();
+  for (hvmc_name, def) in &host.defs {
+    if let Some(def) = def.downcast_ref::<HostedDef<InterpretedDef>>() {
+      let mut code = String::new();
+      compile_struct(&mut code, host, &sanitize_name(hvmc_name), def).unwrap();
+      let instr = &def.data.0.instr;
+      let refs = refs(host, instr);
+      report.defs.insert(hvmc_name.to_owned(), DefReport {
+        lines: code.lines().count(),
+        allocs: instr.iter().filter(|instr| allocates(instr)).count(),
+        refs: refs.len(),
+      });
+      compiled_refs.extend(refs);
+    }
+  }
+
+  let mut book_refs = BTreeSet::new();
+  for net in book.nets.values() {
+    net.trees().for_each(|tree| tree_refs(tree, &mut book_refs));
+  }
+  report.inlined = book_refs.difference(&compiled_refs).map(|&name| name.to_owned()).collect();
+
+  report
+}
+
+/// Whether `instr` allocates a node.
+fn allocates(instr: &Instruction) -> bool {
+  !matches!(instr, Instruction::Const { .. } | Instruction::Link { .. } | Instruction::LinkConst { .. })
+}
+
+fn tree_refs<'a>(tree: &'a Tree, refs: &mut BTreeSet<&'a str>) {
+  maybe_grow(|| {
+    if let Tree::Ref { nam } = tree {
+      refs.insert(nam);
+    }
+    tree.children().for_each(|tree| tree_refs(tree, refs));
+  })
+}
+
 fn refs<'a>(host: &'a Host, instructions: &'a [Instruction]) -> BTreeSet<&'a str> {
   let mut refs = BTreeSet::new();
 
@@ -148,11 +224,18 @@ fn compile_struct(
       Instruction::Mat { trg, lft, rgt } => {
         writeln!(code, "let ({lft}, {rgt}) = net.do_mat({trg});")
       }
+      Instruction::Switch { lab, trg, lft, rgt } => {
+        writeln!(code, "let ({lft}, {rgt}) = net.do_switch({lab}, {trg});")
+      }
       Instruction::Wires { av, aw, bv, bw } => {
         writeln!(code, "let ({av}, {aw}, {bv}, {bw}) = net.do_wires();")
       }
     }?;
   }
+  // the free variables of an open def are erased when it is called
+  for (_, trg) in &def.data.0.free {
+    writeln!(code, "    net.link_trg({trg}, Trg::port(Port::ERA));")?;
+  }
   writeln!(code, "  }}")?;
   writeln!(code, "}}")?;
 
@@ -186,14 +269,14 @@ fn compile_port(host: &Host, port: &Port) -> String {
 
 /// Adapts `name` to be a valid suffix for a rust identifier, if necessary.
 fn sanitize_name(name: &str) -> String {
-  if !name.contains('.') && !name.contains('$') {
+  if name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') {
     name.to_owned()
   } else {
     // Append a hash to the name to avoid clashes between `foo.bar` and `foo_bar`.
     let mut hasher = DefaultHasher::new();
     hasher.write(name.as_bytes());
     let hash = hasher.finish();
-    let mut sanitized = name.replace(|c| c == '.' || c == '$', "_");
+    let mut sanitized = name.replace(|c: char| !c.is_ascii_alphanumeric() && c != '_', "_");
     sanitized.push_str("__");
     write!(sanitized, "__{:016x}", hash).unwrap();
     sanitized
@@ -207,13 +290,18 @@ fn compile_lab_set(labs: &LabSet) -> Result<String, fmt::Error> {
   if labs == &LabSet::NONE {
     return Ok("LabSet::NONE".to_owned());
   }
-  let mut str = "LabSet::from_bits(&[".to_owned();
+  let mut str =
+    if labs.all_from.is_some() { "LabSet::from_bits_and_all_from(&[" } else { "LabSet::from_bits(&[" }.to_owned();
   for (i, word) in labs.bits.iter().enumerate() {
     if i != 0 {
       write!(str, ", ")?;
     }
     write!(str, "0x{:x}", word)?;
   }
-  str.push_str("])");
+  str.push(']');
+  if let Some(lab) = labs.all_from {
+    write!(str, ", {lab}")?;
+  }
+  str.push(')');
   Ok(str)
 }
@@ -0,0 +1,532 @@
+//! A flat, index-based encoding of books, for consumption by external
+//! backends (e.g. GPU runtimes) that cannot load [`run::Def`]s directly.
+//!
+//! Each net of a [`FlatBook`] is stored as a vector of binary nodes, each of
+//! which is a pair of [`FlatPort`]s (its two auxiliary ports), along with its
+//! root port and its active pairs. Nodes are referred to by their index into
+//! this vector, and defs are referred to by their index into
+//! [`FlatBook::names`]. Nodes are laid out in the order they are reached when
+//! traversing the net, so the encoding of a given book is stable.
+//!
+//! [`FlatBook::to_bytes`] serializes a flat book into a little-endian binary
+//! format, documented on that method. [`FlatBook::to_rust`] emits it as Rust
+//! static data instead, so that a book can be embedded in a program and loaded
+//! without being parsed (see [`DefData`]).
+//!
+//! [`run::Def`]: crate::run::Def
+
+use crate::{
+  ast::{Book, Net, Tree, MAX_ARITY},
+  ops::TypedOp,
+  prelude::*,
+  run::{Lab, Port, Tag},
+  util::{create_var, maybe_grow},
+};
+
+/// A port in a [`FlatNet`].
+///
+/// This mirrors the layout of [`Port`]: the bottom three bits are the [`Tag`].
+/// [`Int`] and [`F32`] ports store their value in the top 60 bits, exactly as
+/// in the runtime. All other ports store a 16-bit label in the top 16 bits and
+/// a 45-bit *index* in the bits in between:
+/// - for [`Var`] ports, the index of the wire; each wire appears exactly twice
+///   in a net
+/// - for [`Ref`] ports, one more than the index of the def in
+///   [`FlatBook::names`]; the index `0` is reserved for the eraser, so that, as
+///   in the runtime, [`FlatPort::ERA`] is the null `Ref`
+/// - for [`Ctr`], [`Op`], and [`Mat`] ports, the index of the node in
+///   [`FlatNet::nodes`]
+///
+/// The label of an [`Op`] port is its operation, encoded exactly as in the
+/// runtime (see [`TypedOp`]). The first auxiliary port of a [`Mat`] node
+/// is always a [`Ctr`] port with label `0`, holding the zero and succ branches,
+/// unless the node has a nonzero label, in which case it is a switch node (see
+/// [`Tree::Switch`]) and its first auxiliary port holds its arms.
+///
+/// [`Int`]: Tag::Int
+/// [`F32`]: Tag::F32
+/// [`Var`]: Tag::Var
+/// [`Ref`]: Tag::Ref
+/// [`Ctr`]: Tag::Ctr
+/// [`Op`]: Tag::Op
+/// [`Mat`]: Tag::Mat
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[repr(transparent)]
+pub struct FlatPort(pub u64);
+
+impl FlatPort {
+  /// The principal port of an eraser node.
+  pub const ERA: FlatPort = FlatPort(Tag::Ref as u64);
+
+  const INDEX_MASK: u64 = 0x0000_1FFF_FFFF_FFFF;
+
+  /// Creates a new port with a given tag, label, and index.
+  pub fn new(tag: Tag, lab: Lab, index: u64) -> Self {
+    debug_assert!(index <= Self::INDEX_MASK);
+    FlatPort(((lab as u64) << 48) | (index << 3) | (tag as u64))
+  }
+
+  /// Creates a new [`Tag::Int`] port with a given 60-bit numeric value.
+  pub fn new_int(val: i64) -> Self {
+    FlatPort(Port::new_int(val).0)
+  }
+
+  /// Creates a new [`Tag::F32`] port with a given value.
+  pub fn new_float(val: f32) -> Self {
+    FlatPort(Port::new_float(val).0)
+  }
+
+  pub fn tag(&self) -> Tag {
+    unsafe { Tag::from_unchecked((self.0 & 0x7) as u8) }
+  }
+
+  /// Accesses the label of this port; this is valid for all non-numeric ports.
+  pub fn lab(&self) -> Lab {
+    (self.0 >> 48) as Lab
+  }
+
+  /// Accesses the index of this port; this is valid for all non-numeric ports.
+  pub fn index(&self) -> u64 {
+    (self.0 >> 3) & Self::INDEX_MASK
+  }
+
+  /// Accesses the value of a [`Tag::Int`] port.
+  pub fn int(&self) -> i64 {
+    Port(self.0).int()
+  }
+
+  /// Accesses the value of a [`Tag::F32`] port.
+  pub fn float(&self) -> f32 {
+    Port(self.0).float()
+  }
+}
+
+/// A net with one free port, encoded as a vector of nodes. See the module
+/// documentation for details.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct FlatNet {
+  pub root: FlatPort,
+  /// The auxiliary ports of each binary node, indexed by the ports pointing to
+  /// them.
+  pub nodes: Vec<(FlatPort, FlatPort)>,
+  pub redexes: Vec<(FlatPort, FlatPort)>,
+}
+
+impl Default for FlatPort {
+  fn default() -> Self {
+    FlatPort::ERA
+  }
+}
+
+/// A book in the flat encoding.
+///
+/// `names[i]` is the name of the def with index `i`. The first `nets.len()`
+/// names are the defs of the book, in order; any remaining names are defs that
+/// were referenced but not defined by the book (e.g. ones provided natively by
+/// the host), which the consumer must supply itself.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct FlatBook {
+  pub names: Vec<String>,
+  pub nets: Vec<FlatNet>,
+}
+
+impl Book {
+  /// Converts this book to the flat encoding.
+  ///
+  /// Fails if a net has an unbound variable.
+  pub fn to_flat(&self) -> Result<FlatBook, String> {
+    let mut names: Vec<String> = self.keys().cloned().collect();
+    let mut ids: Map<&str, u64> = names.iter().enumerate().map(|(i, n)| (&**n, i as u64)).collect();
+    let mut external = Vec::new();
+    for net in self.values() {
+      for tree in net.trees() {
+        collect_refs(tree, &mut |nam| {
+          if !ids.contains_key(nam) {
+            ids.insert(nam, (names.len() + external.len()) as u64);
+            external.push(nam.to_owned());
+          }
+        })
+      }
+    }
+    let nets = self
+      .iter()
+      .map(|(name, net)| {
+        Flattener { ids: &ids, net: FlatNet::default(), vars: Map::default(), wires: 0 }
+          .flatten_net(net)
+          .map_err(|e| format!("in {name}: {e}"))
+      })
+      .collect::<Result<_, _>>()?;
+    names.extend(external);
+    Ok(FlatBook { names, nets })
+  }
+}
+
+fn collect_refs<'a>(tree: &'a Tree, f: &mut impl FnMut(&'a str)) {
+  maybe_grow(|| {
+    if let Tree::Ref { nam } = tree {
+      f(nam)
+    }
+    tree.children().for_each(|child| collect_refs(child, f))
+  })
+}
+
+struct Flattener<'a> {
+  ids: &'a Map<&'a str, u64>,
+  net: FlatNet,
+  vars: Map<&'a str, u64>,
+  wires: u64,
+}
+
+impl<'a> Flattener<'a> {
+  fn flatten_net(mut self, net: &'a Net) -> Result<FlatNet, String> {
+    self.net.root = self.flatten_tree(&net.root);
+    for (a, b) in &net.redexes {
+      let redex = (self.flatten_tree(a), self.flatten_tree(b));
+      self.net.redexes.push(redex);
+    }
+    if let Some(nam) = self.vars.keys().min() {
+      return Err(format!("unbound variable `{nam}`"));
+    }
+    Ok(self.net)
+  }
+
+  fn node(&mut self, tag: Tag, lab: Lab, aux: impl FnOnce(&mut Self) -> (FlatPort, FlatPort)) -> FlatPort {
+    let index = self.net.nodes.len();
+    self.net.nodes.push(Default::default());
+    self.net.nodes[index] = aux(self);
+    FlatPort::new(tag, lab, index as u64)
+  }
+
+  fn wire(&mut self) -> FlatPort {
+    let index = self.wires;
+    self.wires += 1;
+    FlatPort::new(Tag::Var, 0, index)
+  }
+
+  fn flatten_tree(&mut self, tree: &'a Tree) -> FlatPort {
+    maybe_grow(move || match tree {
+      Tree::Era => FlatPort::ERA,
+      Tree::Int { val, .. } => FlatPort::new_int(*val),
+      Tree::F32 { val } => FlatPort::new_float(val.0),
+      Tree::Ref { nam } => FlatPort::new(Tag::Ref, 0, self.ids[&**nam] + 1),
+      Tree::Ctr { lab, ports } => self.flatten_ctr(*lab, ports),
+      Tree::Op { op, rhs, out } => self.node(Tag::Op, u16::from(*op), |s| (s.flatten_tree(rhs), s.flatten_tree(out))),
+      Tree::Mat { zero, succ, out } => self.node(Tag::Mat, 0, |s| {
+        let arms = s.node(Tag::Ctr, 0, |s| (s.flatten_tree(zero), s.flatten_tree(succ)));
+        (arms, s.flatten_tree(out))
+      }),
+      Tree::Switch { lab, arms, out } => self.node(Tag::Mat, *lab, |s| {
+        let mut arms: Vec<_> = arms.iter().map(|arm| s.flatten_tree(arm)).collect();
+        arms.push(FlatPort::ERA);
+        (s.flatten_ports(0, arms), s.flatten_tree(out))
+      }),
+      Tree::Adt { lab, variant_index, variant_count, fields } => {
+        let ret = self.wire();
+        let mut ports = vec![FlatPort::ERA; *variant_index];
+        let mut fields: Vec<_> = fields.iter().map(|field| self.flatten_tree(field)).collect();
+        fields.push(ret);
+        ports.push(self.flatten_ports(*lab, fields));
+        ports.extend((*variant_index + 1 .. *variant_count).map(|_| FlatPort::ERA));
+        ports.push(ret);
+        self.flatten_ports(*lab, ports)
+      }
+      Tree::Var { nam } => match self.vars.remove(&**nam) {
+        Some(index) => FlatPort::new(Tag::Var, 0, index),
+        None => {
+          let wire = self.wire();
+          self.vars.insert(nam, wire.index());
+          wire
+        }
+      },
+    })
+  }
+
+  fn flatten_ctr(&mut self, lab: Lab, ports: &'a [Tree]) -> FlatPort {
+    match ports {
+      [] => FlatPort::ERA,
+      [port] => self.flatten_tree(port),
+      [fst, rest @ ..] => self.node(Tag::Ctr, lab, |s| (s.flatten_tree(fst), s.flatten_ctr(lab, rest))),
+    }
+  }
+
+  /// Like `flatten_ctr`, but for ports that have already been flattened.
+  fn flatten_ports(&mut self, lab: Lab, ports: Vec<FlatPort>) -> FlatPort {
+    ports.into_iter().rev().reduce(|rgt, lft| self.node(Tag::Ctr, lab, |_| (lft, rgt))).unwrap_or(FlatPort::ERA)
+  }
+}
+
+impl FlatBook {
+  /// Converts this flat book back to an ast [`Book`].
+  ///
+  /// External defs (those in `names` past `nets.len()`) are left as dangling
+  /// references. Fails if the flat book is malformed -- e.g. if a node is
+  /// referenced more than once, or an index is out of bounds.
+  pub fn to_book(&self) -> Result<Book, String> {
+    if self.nets.len() > self.names.len() {
+      return Err("more nets than names".to_owned());
+    }
+    let mut book = Book::default();
+    for (name, net) in self.names.iter().zip(&self.nets) {
+      let net = Unflattener { book: self, net, used: vec![false; net.nodes.len()] }
+        .unflatten_net()
+        .map_err(|e| format!("in {name}: {e}"))?;
+      book.insert(name.clone(), net);
+    }
+    Ok(book)
+  }
+}
+
+struct Unflattener<'a> {
+  book: &'a FlatBook,
+  net: &'a FlatNet,
+  used: Vec<bool>,
+}
+
+impl<'a> Unflattener<'a> {
+  fn unflatten_net(mut self) -> Result<Net, String> {
+    let root = self.unflatten_tree(self.net.root)?;
+    let redexes = self
+      .net
+      .redexes
+      .iter()
+      .map(|&(a, b)| Ok((self.unflatten_tree(a)?, self.unflatten_tree(b)?)))
+      .collect::<Result<_, String>>()?;
+    Ok(Net { root, redexes })
+  }
+
+  fn node(&mut self, port: FlatPort) -> Result<(FlatPort, FlatPort), String> {
+    let index = port.index() as usize;
+    match self.used.get_mut(index) {
+      None => Err(format!("node index {index} out of bounds")),
+      Some(true) => Err(format!("node {index} referenced more than once")),
+      Some(used) => {
+        *used = true;
+        Ok(self.net.nodes[index])
+      }
+    }
+  }
+
+  fn unflatten_tree(&mut self, port: FlatPort) -> Result<Tree, String> {
+    maybe_grow(move || {
+      Ok(match port.tag() {
+        Tag::Red => Err("unexpected redirect port")?,
+        Tag::Var => Tree::Var { nam: create_var(port.index() as usize) },
+        Tag::Ref if port == FlatPort::ERA => Tree::Era,
+        Tag::Ref => {
+          let index = port.index().checked_sub(1).ok_or("invalid eraser port")? as usize;
+          let nam = self.book.names.get(index).ok_or_else(|| format!("def index {index} out of bounds"))?;
+          Tree::Ref { nam: nam.clone() }
+        }
+        Tag::Int => Tree::Int { val: port.int(), ty: None },
+        Tag::F32 => Tree::F32 { val: port.float().into() },
+        Tag::Op => {
+          let op = TypedOp::try_from(port.lab()).map_err(|_| format!("invalid operation {}", port.lab()))?;
+          let (rhs, out) = self.node(port)?;
+          Tree::Op { op, rhs: Box::new(self.unflatten_tree(rhs)?), out: Box::new(self.unflatten_tree(out)?) }
+        }
+        Tag::Mat if port.lab() != 0 => {
+          let (mut arms, out) = self.node(port)?;
+          let mut list = vec![];
+          while arms != FlatPort::ERA {
+            if arms.tag() != Tag::Ctr || arms.lab() != 0 || list.len() >= MAX_ARITY {
+              Err("the first auxiliary port of a switch node must be a chain of ctrs with label 0")?
+            }
+            let (arm, rest) = self.node(arms)?;
+            list.push(self.unflatten_tree(arm)?);
+            arms = rest;
+          }
+          Tree::Switch { lab: port.lab(), arms: list, out: Box::new(self.unflatten_tree(out)?) }
+        }
+        Tag::Mat => {
+          let (arms, out) = self.node(port)?;
+          if arms.tag() != Tag::Ctr || arms.lab() != 0 {
+            Err("the first auxiliary port of a mat node must be a ctr with label 0")?
+          }
+          let (zero, succ) = self.node(arms)?;
+          Tree::Mat {
+            zero: Box::new(self.unflatten_tree(zero)?),
+            succ: Box::new(self.unflatten_tree(succ)?),
+            out: Box::new(self.unflatten_tree(out)?),
+          }
+        }
+        Tag::Ctr => {
+          let (lft, rgt) = self.node(port)?;
+          Tree::Ctr { lab: port.lab(), ports: vec![self.unflatten_tree(lft)?, self.unflatten_tree(rgt)?] }
+        }
+      })
+    })
+  }
+}
+
+impl FlatBook {
+  /// The magic bytes at the start of the binary encoding.
+  pub const MAGIC: [u8; 8] = *b"HVMCFLAT";
+
+  /// Serializes this book to a binary format. All integers are little-endian
+  /// `u64`s, and all offsets are byte offsets from the start of the buffer.
+  ///
+  /// ```text
+  /// magic        [u8; 8]  = FlatBook::MAGIC
+  /// name_count   u64
+  /// net_count    u64
+  /// nets         [NetHeader; net_count]
+  /// names        [NameHeader; name_count]
+  /// ...          the nodes and redexes of every net, as pairs of ports
+  /// ...          the names of every def, as UTF-8
+  ///
+  /// NetHeader  = { root: u64, nodes_offset: u64, node_count: u64, redexes_offset: u64, redex_count: u64 }
+  /// NameHeader = { offset: u64, len: u64 }
+  /// ```
+  pub fn to_bytes(&self) -> Vec<u8> {
+    let pairs = |net: &FlatNet| (net.nodes.len() + net.redexes.len()) as u64;
+    let header_len = 24 + 40 * self.nets.len() as u64 + 16 * self.names.len() as u64;
+    let ports_len = 16 * self.nets.iter().map(pairs).sum::<u64>();
+
+    let mut bytes = Vec::new();
+    let word = |bytes: &mut Vec<u8>, x: u64| bytes.extend_from_slice(&x.to_le_bytes());
+    bytes.extend_from_slice(&Self::MAGIC);
+    word(&mut bytes, self.names.len() as u64);
+    word(&mut bytes, self.nets.len() as u64);
+    let mut offset = header_len;
+    for net in &self.nets {
+      word(&mut bytes, net.root.0);
+      word(&mut bytes, offset);
+      word(&mut bytes, net.nodes.len() as u64);
+      word(&mut bytes, offset + 16 * net.nodes.len() as u64);
+      word(&mut bytes, net.redexes.len() as u64);
+      offset += 16 * pairs(net);
+    }
+    let mut offset = header_len + ports_len;
+    for name in &self.names {
+      word(&mut bytes, offset);
+      word(&mut bytes, name.len() as u64);
+      offset += name.len() as u64;
+    }
+    for net in &self.nets {
+      for (a, b) in net.nodes.iter().chain(&net.redexes) {
+        word(&mut bytes, a.0);
+        word(&mut bytes, b.0);
+      }
+    }
+    for name in &self.names {
+      bytes.extend_from_slice(name.as_bytes());
+    }
+    bytes
+  }
+
+  /// Deserializes a book from the format written by [`FlatBook::to_bytes`].
+  pub fn from_bytes(bytes: &[u8]) -> Result<FlatBook, String> {
+    // offsets are untrusted, so all arithmetic on them is checked
+    let at = |base: u64, index: u64, size: u64| {
+      index.checked_mul(size).and_then(|x| x.checked_add(base)).ok_or_else(|| "offset out of range".to_owned())
+    };
+    let slice = |offset: u64, len: u64| {
+      let end = at(offset, 1, len)?;
+      usize::try_from(offset)
+        .ok()
+        .zip(usize::try_from(end).ok())
+        .and_then(|(start, end)| bytes.get(start .. end))
+        .ok_or_else(|| format!("unexpected end of input at byte {offset}"))
+    };
+    // the `index`th word after `offset`
+    let word = |offset: u64, index: u64| {
+      Ok::<_, String>(u64::from_le_bytes(slice(at(offset, index, 8)?, 8)?.try_into().unwrap()))
+    };
+    let pairs = |offset: u64, count: u64| -> Result<Vec<_>, String> {
+      (0 .. count).map(|i| Ok((FlatPort(word(offset, 2 * i)?), FlatPort(word(offset, 2 * i + 1)?)))).collect()
+    };
+
+    if slice(0, 8)? != Self::MAGIC {
+      return Err("invalid magic bytes".to_owned());
+    }
+    let name_count = word(8, 0)?;
+    let net_count = word(16, 0)?;
+    let mut nets = Vec::new();
+    for i in 0 .. net_count {
+      let header = at(24, i, 40)?;
+      nets.push(FlatNet {
+        root: FlatPort(word(header, 0)?),
+        nodes: pairs(word(header, 1)?, word(header, 2)?)?,
+        redexes: pairs(word(header, 3)?, word(header, 4)?)?,
+      });
+    }
+    let mut names = Vec::new();
+    for i in 0 .. name_count {
+      let header = at(at(24, net_count, 40)?, i, 16)?;
+      let name = slice(word(header, 0)?, word(header, 1)?)?;
+      names.push(String::from_utf8(name.to_vec()).map_err(|_| format!("invalid utf-8 in name {i}"))?);
+    }
+    Ok(FlatBook { names, nets })
+  }
+}
+
+/// A def of a book embedded in a program as static data, as emitted by
+/// [`FlatBook::to_rust`] (and `hvmc compile --emit const`).
+///
+/// ```ignore
+/// include!("book_data.rs");
+///
+/// let book = FlatBook::from_defs(BOOK).unwrap().to_book().unwrap();
+/// let host = hvmc::stdlib::create_host(&book);
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DefData {
+  pub name: &'static str,
+  /// The net of the def, or `None` for a def that is referenced but not
+  /// defined by the book; these come after all the others.
+  pub net: Option<NetData>,
+}
+
+/// A [`FlatNet`] as static data, with each port as its `u64` encoding.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct NetData {
+  pub root: u64,
+  pub nodes: &'static [(u64, u64)],
+  pub redexes: &'static [(u64, u64)],
+}
+
+impl FlatBook {
+  /// Emits this book as Rust source, declaring `pub static BOOK: &[DefData]`,
+  /// with a [`DefData`] for each name of the book, in order.
+  pub fn to_rust(&self) -> String {
+    let pairs = |pairs: &[(FlatPort, FlatPort)]| {
+      let pairs: Vec<_> = pairs.iter().map(|(a, b)| format!("({:#x}, {:#x})", a.0, b.0)).collect();
+      format!("&[{}]", pairs.join(", "))
+    };
+    let mut out = String::new();
+    out += "// Generated by `hvmc compile --emit const`.\n\n";
+    out += "pub static BOOK: &[hvmc::flat::DefData] = &[\n";
+    for (i, name) in self.names.iter().enumerate() {
+      let net = match self.nets.get(i) {
+        Some(net) => format!(
+          "Some(hvmc::flat::NetData {{ root: {:#x}, nodes: {}, redexes: {} }})",
+          net.root.0,
+          pairs(&net.nodes),
+          pairs(&net.redexes)
+        ),
+        None => "None".to_owned(),
+      };
+      out += &format!("  hvmc::flat::DefData {{ name: {name:?}, net: {net} }},\n");
+    }
+    out += "];\n";
+    out
+  }
+
+  /// Builds a flat book from the static data emitted by [`FlatBook::to_rust`].
+  ///
+  /// Fails if a def with a net comes after one without.
+  pub fn from_defs(defs: &[DefData]) -> Result<FlatBook, String> {
+    let mut book = FlatBook::default();
+    for def in defs {
+      book.names.push(def.name.to_owned());
+      if let Some(net) = &def.net {
+        if book.nets.len() + 1 != book.names.len() {
+          return Err(format!("the net of {} comes after an undefined def", def.name));
+        }
+        let pairs = |pairs: &[(u64, u64)]| pairs.iter().map(|&(a, b)| (FlatPort(a), FlatPort(b))).collect();
+        book.nets.push(FlatNet { root: FlatPort(net.root), nodes: pairs(net.nodes), redexes: pairs(net.redexes) });
+      }
+    }
+    Ok(book)
+  }
+}
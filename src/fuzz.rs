@@ -77,6 +77,7 @@ use core::{
   any::Any,
   cell::{OnceCell, RefCell},
   fmt::Debug,
+  hint,
   marker::PhantomData,
   ops::Add,
   sync::atomic,
@@ -199,6 +200,19 @@ impl<T: HasAtomic> Atomic<T> {
       (true, old)
     })
   }
+  pub fn fetch_max(&self, val: T, _: Ordering) -> T
+  where
+    T: Ord,
+  {
+    self.with(|_, history, index| {
+      *index = history.len();
+      let old = *history.last().unwrap();
+      let new = old.max(val);
+      history.push(new);
+      T::store(&self.value, new);
+      (true, old)
+    })
+  }
 }
 
 pub fn spin_loop() {
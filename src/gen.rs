@@ -2,4 +2,8 @@
 
 use crate::host::Host;
 
+/// The arguments passed to the entry point when none are given; see
+/// `compile --bake-args`.
+pub const BAKED_ARGS: &[&str] = &[];
+
 pub fn insert_into_host(_: &mut Host) {}
@@ -1,21 +1,31 @@
 //! The runtime's host, which acts as a translation layer between the AST and
 //! the runtime.
+//!
+//! Nets are encoded into the [`Port`]-based representation of the runtime,
+//! which is the only one it uses, and read back from it.
 
 use crate::prelude::*;
 
 use crate::{
   ast::{Book, Net, Tree},
+  ops::{ExtOpFn, ExtOps},
   run::{self, Addr, Def, Instruction, InterpretedDef, LabSet, Mode, Port, Tag, TrgId, Wire},
   stdlib::HostedDef,
   util::create_var,
 };
+use alloc::sync::Arc;
 use core::ops::{Deref, DerefMut, RangeFrom};
 
 mod calc_labels;
 mod encode;
+mod owned;
 mod readback;
 
+pub use calc_labels::calc_labels;
 use calc_labels::calculate_label_sets;
+#[cfg(feature = "async")]
+pub use owned::NormalStream;
+pub use owned::OwnedNet;
 
 /// Stores a bidirectional mapping between names and runtime defs.
 #[derive(Default)]
@@ -24,6 +34,33 @@ pub struct Host {
   pub defs: Map<String, DefRef>,
   /// the backward mapping, from the address of a runtime def to the name
   pub back: Map<Addr, String>,
+  /// the id of each def, assigned in order of insertion (see [`Host::def_id`])
+  ids: Map<String, u32>,
+  /// the name of the def with each id, or `None` if it was removed
+  names: Vec<Option<String>>,
+  /// the functions implementing the `ext` operations (see
+  /// [`Host::register_op`])
+  ext_ops: Arc<ExtOps>,
+}
+
+/// An error returned by [`Host::replace_def`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+#[cfg_attr(feature = "std", derive(Error))]
+pub enum ReplaceDefError {
+  #[cfg_attr(feature = "std", error("the new definition of `@{0}` uses labels that the old one does not"))]
+  NewLabels(String),
+}
+
+/// An error returned by [`Host::instantiate`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+#[cfg_attr(feature = "std", derive(Error))]
+pub enum InstantiateError {
+  #[cfg_attr(feature = "std", error("no binding was given for the free variable `{0}`"))]
+  MissingBinding(String),
+  #[cfg_attr(feature = "std", error("`{0}` is not a free variable of the def"))]
+  UnknownBinding(String),
 }
 
 /// A potentially-owned reference to a [`Def`]. Vitally, the address of the
@@ -61,6 +98,10 @@ impl Host {
   /// Like `insert_book`, but allows specifying a function (`default_def`) that
   /// will be run when the name of a definition is not found in the book.
   /// The return value of the function will be inserted into the host.
+  ///
+  /// The order in which defs are inserted only depends on the book: first,
+  /// those returned by `default_def`, in the order they are first reached by a
+  /// traversal of the book (by name); then, the defs of the book, by name.
   pub fn insert_book_with_default(&mut self, book: &Book, default_def: &mut dyn FnMut(&str) -> DefRef) {
     #[cfg(feature = "std")]
     {
@@ -99,6 +140,163 @@ impl Host {
   pub fn insert_def(&mut self, name: &str, def: DefRef) {
     self.back.insert(Port::new_ref(&def).addr(), name.to_owned());
     self.defs.insert(name.to_owned(), def);
+    if !self.ids.contains_key(name) {
+      self.ids.insert(name.to_owned(), self.names.len() as u32);
+      self.names.push(Some(name.to_owned()));
+    }
+  }
+
+  /// Returns the id of the def named `name`.
+  ///
+  /// Ids are assigned consecutively from `0` as defs are first inserted, and
+  /// a def keeps its id when it is replaced. As the order in which
+  /// [`Host::insert_book`] inserts defs only depends on the book, hosts built
+  /// the same way from the same book assign the same ids, even across
+  /// processes; unlike the addresses in [`Host::back`], they can be used to
+  /// refer to defs in serialized data. The ids of removed defs are not reused,
+  /// except after [`Host::clear`].
+  pub fn def_id(&self, name: &str) -> Option<u32> {
+    self.ids.get(name).copied()
+  }
+
+  /// Returns the name of the def with the given id (see [`Host::def_id`]).
+  pub fn def_name(&self, id: u32) -> Option<&str> {
+    self.names.get(id as usize)?.as_deref()
+  }
+
+  /// Removes the def named `name` from the mapping, returning it.
+  ///
+  /// Ports referencing the def point directly at it, so it must outlive any net
+  /// that may still contain such a port; drop the returned [`DefRef`] only
+  /// once nothing can reach it anymore.
+  pub fn remove_def(&mut self, name: &str) -> Option<DefRef> {
+    let def = self.defs.remove(name)?;
+    self.back.remove(&Port::new_ref(&def).addr());
+    if let Some(id) = self.ids.remove(name) {
+      self.names[id as usize] = None;
+    }
+    Some(def)
+  }
+
+  /// Removes all defs from the mapping, retaining the allocated capacity so the
+  /// host can be reused for another book.
+  ///
+  /// As with [`Host::remove_def`], no net may still contain ports referencing
+  /// any of the removed defs.
+  pub fn clear(&mut self) {
+    self.defs.clear();
+    self.back.clear();
+    self.ids.clear();
+    self.names.clear();
+  }
+
+  /// Replaces the instructions of the def named `name` with those of `net`.
+  ///
+  /// The def is modified in place, so its address doesn't change, and existing
+  /// ports referencing it will use the new instructions the next time they are
+  /// expanded. Any refs in `net` must already be in the host.
+  ///
+  /// The labels used by `net` (including those of the defs it references)
+  /// must be a subset of the def's label set, as the label sets of the defs
+  /// referencing it, and the labels of existing ports referencing it (see
+  /// [`Tag::Ref`]), can't be updated; otherwise, the def is left unchanged and
+  /// an error is returned. The def's label set is not narrowed.
+  ///
+  /// # Panics
+  ///
+  /// Panics if there is no def named `name`, or if it was not created from a
+  /// book (i.e. it is not a `HostedDef<InterpretedDef>`).
+  ///
+  /// # Safety
+  ///
+  /// No net may be expanding the def while it is being replaced; in
+  /// particular, this must not be called during a reduction that may reach
+  /// the def.
+  pub unsafe fn replace_def(&mut self, name: &str, net: &Net) -> Result<(), ReplaceDefError> {
+    let book = Book { nets: [(name.to_owned(), net.clone())].into(), ..Default::default() };
+    let (_, labs) = calculate_label_sets(&book, |nam| self.defs[nam].labs.clone()).into_iter().next().unwrap();
+    if !labs.is_subset(&self.defs[name].labs) {
+      return Err(ReplaceDefError::NewLabels(name.to_owned()));
+    }
+    let data = self.encode_def(net);
+    self.get_mut::<HostedDef<InterpretedDef>>(name).data.0 = data;
+    Ok(())
+  }
+
+  /// Inserts `net` as an open def named `name`, whose variables that only
+  /// occur once are its free variables, rather than an error as in the defs
+  /// inserted by [`Host::insert_book`]. Referencing the def erases them, while
+  /// [`Host::instantiate`] binds them. Any refs in `net` other than to the def
+  /// itself must already be in the host.
+  pub fn insert_open_def(&mut self, name: &str, net: &Net) {
+    let book = Book { nets: [(name.to_owned(), net.clone())].into(), ..Default::default() };
+    let (_, labs) = calculate_label_sets(&book, |nam| self.defs[nam].labs.clone()).into_iter().next().unwrap();
+    // the def is inserted before it is encoded, as it may reference itself
+    self.insert_def(name, unsafe { HostedDef::new_hosted(labs, InterpretedDef::default()) });
+    let data = self.encode_open_def(net);
+    self.get_mut::<HostedDef<InterpretedDef>>(name).data.0 = data;
+  }
+
+  /// Expands the open def named `name` into the root of `net`, substituting
+  /// each of its free variables (see [`InterpretedDef::free`]) with the net
+  /// bound to it in `bindings`, whose root takes the place of the variable.
+  ///
+  /// Every free variable must be bound, and every binding must be to a free
+  /// variable; otherwise, `net` is left unchanged and an error is returned.
+  ///
+  /// # Panics
+  ///
+  /// Panics if there is no def named `name`, or if it was not created from a
+  /// book or by [`Host::insert_open_def`] (i.e. it is not a
+  /// `HostedDef<InterpretedDef>`).
+  pub fn instantiate<M: Mode>(
+    &self,
+    net: &mut run::Net<M>,
+    name: &str,
+    bindings: &Map<String, Net>,
+  ) -> Result<(), InstantiateError> {
+    let def = &self.defs[name].downcast_ref::<HostedDef<InterpretedDef>>().unwrap().data.0;
+    if let Some((nam, _)) = def.free.iter().find(|(nam, _)| !bindings.contains_key(nam)) {
+      return Err(InstantiateError::MissingBinding(nam.clone()));
+    }
+    if let Some(nam) = bindings.keys().find(|nam| !def.free.iter().any(|(free, _)| free == *nam)) {
+      return Err(InstantiateError::UnknownBinding(nam.clone()));
+    }
+    let root = Port::new_var(net.root.addr());
+    def.expand(net, root, |net, nam, trg| self.encode_net(net, trg, &bindings[nam]));
+    Ok(())
+  }
+
+  /// Registers `f` as the implementation of the custom operation `ext:label`
+  /// (see [`Op::Ext`]) in this host, replacing any previous one.
+  ///
+  /// `f` is called with the values of both operands, as ints of the type of
+  /// the operation (which can't be `f32`), and its result is truncated to that
+  /// type; for untyped operations, these are signed 60-bit ints, and the low
+  /// 60 bits of the result are the result of the operation. It is only called
+  /// by the nets given this host's [`Host::ext_ops`]; an `ext` op with no
+  /// registered function stops reduction with an [`OpError`]. It may be called
+  /// at any point of a reduction, from any of the threads reducing the net, so
+  /// it must be deterministic for the result of the reduction to be.
+  ///
+  /// # Panics
+  ///
+  /// Panics if `label` is not below [`EXT_OPS`].
+  ///
+  /// [`Op::Ext`]: crate::ops::Op::Ext
+  /// [`OpError`]: crate::ops::OpError
+  /// [`EXT_OPS`]: crate::ops::EXT_OPS
+  pub fn register_op(&mut self, label: u8, f: Box<ExtOpFn>) {
+    Arc::make_mut(&mut self.ext_ops).register(label, f.into())
+  }
+
+  /// The functions registered with [`Host::register_op`], to be stored in
+  /// [`Linker::ext_ops`] of the nets that run this host's defs; registering
+  /// an op doesn't affect the nets that were given the table before.
+  ///
+  /// [`Linker::ext_ops`]: run::Linker::ext_ops
+  pub fn ext_ops(&self) -> &Arc<ExtOps> {
+    &self.ext_ops
   }
 
   /// Returns a mutable [`Def`] named `name`.
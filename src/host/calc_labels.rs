@@ -2,6 +2,21 @@ use crate::prelude::*;
 
 use super::*;
 use crate::util::maybe_grow;
+use alloc::collections::BTreeMap;
+
+/// Calculates the set of labels used by each definition in `book`, including
+/// the labels of all of the definitions it (transitively) references.
+///
+/// `resolver` is called with the name of any referenced definition that is not
+/// in `book`, and must return the label set of that definition; this allows
+/// books that reference externally-defined nets (e.g. builtins such as
+/// `HVM.log`) to be analyzed.
+///
+/// This is the same computation used by [`Host::insert_book`]; see
+/// [`LabSet`] for how the resulting sets are used by the runtime.
+pub fn calc_labels(book: &Book, resolver: impl FnMut(&str) -> LabSet) -> BTreeMap<String, LabSet> {
+  calculate_label_sets(book, resolver).into_iter().map(|(nam, labs)| (nam.to_owned(), labs)).collect()
+}
 
 /// Calculates the labels used in each definition of a book.
 ///
@@ -96,8 +111,12 @@ pub(crate) fn calculate_label_sets<'b, 'l>(book: &'b Book, lookup: impl FnMut(&'
 pub(crate) struct LabelSets<'b>(Map<&'b str, LabelState>);
 
 impl<'b> LabelSets<'b> {
+  /// Yields the label set of each def, in order of name, so that the defs of a
+  /// book are always inserted into the host in the same order.
   pub(crate) fn into_iter(self) -> impl Iterator<Item = (&'b str, LabSet)> {
-    self.0.into_iter().map(|(nam, lab)| match lab {
+    let mut labels: Vec<_> = self.0.into_iter().collect();
+    labels.sort_unstable_by_key(|&(nam, _)| nam);
+    labels.into_iter().map(|(nam, lab)| match lab {
       LabelState::Done(lab) => (nam, lab),
       _ => unreachable!(),
     })
@@ -181,6 +200,12 @@ impl<'b, F: FnMut(&'b str) -> LabSet> State<'b, F> {
           out.add(lab);
         }
       }
+      if let Tree::Switch { lab, .. } = tree {
+        // a switch matches on every label from its own upwards
+        if let Some(out) = out.as_deref_mut() {
+          out.add_all_from(*lab);
+        }
+      }
       if let Tree::Ref { nam } = tree {
         if self.book.contains_key(nam) {
           return self.visit_def(nam, depth.map(|x| x + 1), out);
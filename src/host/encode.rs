@@ -6,15 +6,30 @@ use crate::{ops::TypedOp as Op, run::Lab, util::maybe_grow};
 impl Host {
   /// Converts an ast net to a list of instructions to create the net.
   ///
-  /// `get_def` must return the `Port` corresponding to a given `Ref` name.
+  /// # Panics
+  ///
+  /// Panics if the net has unbound variables.
   pub(crate) fn encode_def(&self, net: &Net) -> InterpretedDef {
-    let mut def = InterpretedDef { instr: Vec::new(), trgs: 1 };
+    let mut def = InterpretedDef { instr: Vec::new(), trgs: 1, free: Vec::new() };
     let mut state = State { host: self, encoder: &mut def, scope: Default::default() };
     state.visit_net(net, TrgId::new(0));
     state.finish();
     def
   }
 
+  /// Like [`Host::encode_def`], but variables that only occur once in the net
+  /// are recorded as the free variables of the def (see
+  /// [`InterpretedDef::free`]) instead.
+  pub(crate) fn encode_open_def(&self, net: &Net) -> InterpretedDef {
+    let mut def = InterpretedDef { instr: Vec::new(), trgs: 1, free: Vec::new() };
+    let mut state = State { host: self, encoder: &mut def, scope: Default::default() };
+    state.visit_net(net, TrgId::new(0));
+    let mut free = state.scope.into_iter().map(|(nam, trg)| (nam.to_owned(), trg)).collect::<Vec<_>>();
+    free.sort();
+    def.free = free;
+    def
+  }
+
   /// Encode `tree` directly into `trg`, skipping the intermediate `Def`
   /// representation.
   pub fn encode_tree<M: Mode>(&self, net: &mut run::Net<M>, trg: run::Trg, tree: &Tree) {
@@ -50,7 +65,7 @@ impl<'a, E: Encoder> State<'a, E> {
     let (port, tree) = match (a, b) {
       (Tree::Era, t) | (t, Tree::Era) => (Port::ERA, t),
       (Tree::Ref { nam }, t) | (t, Tree::Ref { nam }) => (Port::new_ref(&self.host.defs[nam]), t),
-      (Tree::Int { val }, t) | (t, Tree::Int { val }) => (Port::new_int(*val), t),
+      (Tree::Int { val, .. }, t) | (t, Tree::Int { val, .. }) => (Port::new_int(*val), t),
       (t, u) => {
         let (av, aw, bv, bw) = self.encoder.wires();
         self.visit_tree(t, av);
@@ -66,7 +81,7 @@ impl<'a, E: Encoder> State<'a, E> {
     static ERA: Tree = Tree::Era;
     maybe_grow(move || match tree {
       Tree::Era => self.encoder.link_const(trg, Port::ERA),
-      Tree::Int { val } => self.encoder.link_const(trg, Port::new_int(*val)),
+      Tree::Int { val, .. } => self.encoder.link_const(trg, Port::new_int(*val)),
       Tree::F32 { val } => self.encoder.link_const(trg, Port::new_float(val.0)),
       Tree::Ref { nam } => self.encoder.link_const(trg, Port::new_ref(&self.host.defs[nam])),
       Tree::Ctr { lab, ports } => {
@@ -102,7 +117,7 @@ impl<'a, E: Encoder> State<'a, E> {
         self.encoder.link(l, r);
       }
       Tree::Op { op, rhs: lft, out: rgt } => match &**lft {
-        Tree::Int { val } => {
+        Tree::Int { val, .. } => {
           let o = self.encoder.op_num(*op, trg, Port::new_int(*val));
           self.visit_tree(rgt, o);
         }
@@ -123,6 +138,16 @@ impl<'a, E: Encoder> State<'a, E> {
         self.visit_tree(succ, s);
         self.visit_tree(out, o);
       }
+      Tree::Switch { lab, arms, out } => {
+        let (mut a, o) = self.encoder.switch(*lab, trg);
+        for arm in arms {
+          let (l, r) = self.encoder.ctr(0, a);
+          self.visit_tree(arm, l);
+          a = r;
+        }
+        self.visit_tree(&ERA, a);
+        self.visit_tree(out, o);
+      }
       Tree::Var { nam } => match self.scope.entry(nam) {
         Entry::Occupied(e) => self.encoder.link(e.remove(), trg),
         Entry::Vacant(e) => {
@@ -142,6 +167,7 @@ trait Encoder {
   fn op(&mut self, op: Op, trg: Self::Trg) -> (Self::Trg, Self::Trg);
   fn op_num(&mut self, op: Op, trg: Self::Trg, rhs: Port) -> Self::Trg;
   fn mat(&mut self, trg: Self::Trg) -> (Self::Trg, Self::Trg);
+  fn switch(&mut self, lab: Lab, trg: Self::Trg) -> (Self::Trg, Self::Trg);
   fn wires(&mut self) -> (Self::Trg, Self::Trg, Self::Trg, Self::Trg);
 }
 
@@ -189,6 +215,12 @@ impl Encoder for InterpretedDef {
     self.instr.push(Instruction::Mat { trg, lft, rgt });
     (lft, rgt)
   }
+  fn switch(&mut self, lab: Lab, trg: Self::Trg) -> (Self::Trg, Self::Trg) {
+    let lft = self.new_trg_id();
+    let rgt = self.new_trg_id();
+    self.instr.push(Instruction::Switch { lab, trg, lft, rgt });
+    (lft, rgt)
+  }
   fn wires(&mut self) -> (Self::Trg, Self::Trg, Self::Trg, Self::Trg) {
     let av = self.new_trg_id();
     let aw = self.new_trg_id();
@@ -222,6 +254,9 @@ impl<'a, M: Mode> Encoder for run::Net<'a, M> {
   fn mat(&mut self, trg: Self::Trg) -> (Self::Trg, Self::Trg) {
     self.do_mat(trg)
   }
+  fn switch(&mut self, lab: Lab, trg: Self::Trg) -> (Self::Trg, Self::Trg) {
+    self.do_switch(lab, trg)
+  }
   fn wires(&mut self) -> (Self::Trg, Self::Trg, Self::Trg, Self::Trg) {
     self.do_wires()
   }
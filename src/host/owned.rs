@@ -0,0 +1,183 @@
+use crate::prelude::*;
+
+use super::*;
+use crate::{
+  dispatch_dyn_net,
+  run::{DynNet, Heap, Rewrites, Trg},
+};
+use alloc::sync::Arc;
+use core::{mem::ManuallyDrop, ptr::NonNull};
+#[cfg(feature = "async")]
+use core::{
+  pin::Pin,
+  task::{Context, Poll},
+};
+
+/// A runtime net bundled with the heap it is allocated in and the host its
+/// refs point into.
+///
+/// A [`run::Net`] borrows its [`Heap`], which makes it awkward to store; an
+/// `OwnedNet` owns both, and so has no lifetime parameter.
+///
+/// An `OwnedNet` is `Send`, so it can be moved to another thread, but not
+/// `Sync`, like the [`run::Net`] it wraps.
+///
+/// ```
+/// # use hvmc::{host::OwnedNet, stdlib::create_host};
+/// let mut net = OwnedNet::new(create_host(&"@two = #2".parse().unwrap()));
+/// net.load(&"a & @two ~ <* #3 a>".parse().unwrap());
+/// let net = std::thread::spawn(move || {
+///   assert_eq!(net.normal().to_string(), "#6");
+///   net
+/// });
+/// assert_eq!(net.join().unwrap().rewrites().total(), 3);
+/// ```
+pub struct OwnedNet {
+  /// Borrows from `heap`; the `'static` lifetime stands for the lifetime of
+  /// the `OwnedNet`, and must not escape it.
+  net: ManuallyDrop<DynNet<'static>>,
+  /// Allocated with `Box::into_raw`, and freed after `net` is dropped.
+  ///
+  /// This is a pointer rather than a `Box`, as moving a `Box` would invalidate
+  /// the references derived from it, such as those held by `net`.
+  heap: NonNull<Heap>,
+  host: Arc<Mutex<Host>>,
+  lazy: bool,
+}
+
+impl OwnedNet {
+  /// Creates an empty strict-mode net, with a heap of the default size.
+  ///
+  /// # Panics
+  ///
+  /// Panics if the heap cannot be allocated.
+  pub fn new(host: Arc<Mutex<Host>>) -> Self {
+    Self::with_options(host, None, false).expect("memory allocation failed")
+  }
+
+  /// Creates an empty net, with a heap of `memory` bytes (or the default size),
+  /// in lazy mode if `lazy` is set. Returns `None` if the heap cannot be
+  /// allocated.
+  pub fn with_options(host: Arc<Mutex<Host>>, memory: Option<usize>, lazy: bool) -> Option<Self> {
+    let heap = NonNull::from(Box::leak(Heap::new(memory)?));
+    // SAFETY: the heap is only freed once the net is dropped (see `drop`)
+    let net = ManuallyDrop::new(DynNet::new(unsafe { heap.as_ref() }, lazy));
+    Some(OwnedNet { net, heap, host, lazy })
+  }
+
+  /// Replaces the contents of the net with `expr`.
+  pub fn load(&mut self, expr: &Net) {
+    self.reset();
+    let host = self.host.lock();
+    dispatch_dyn_net!(net = &mut *self.net => {
+      net.ext_ops = host.ext_ops().clone();
+      host.encode_net(net, Trg::port(Port::new_var(net.root.addr())), expr);
+    });
+  }
+
+  /// Replaces the contents of the net with the def named `name`.
+  ///
+  /// # Panics
+  ///
+  /// Panics if there is no def named `name` in the host.
+  pub fn boot(&mut self, name: &str) {
+    self.reset();
+    let host = self.host.lock();
+    dispatch_dyn_net!(net = &mut *self.net => {
+      net.ext_ops = host.ext_ops().clone();
+      net.boot(&host.defs[name]);
+    });
+  }
+
+  /// Reduces the net to normal form, and reads it back.
+  pub fn normal(&mut self) -> Net {
+    dispatch_dyn_net!(net = &mut *self.net => net.normal());
+    self.readback()
+  }
+
+  /// Reads back the current contents of the net.
+  pub fn readback(&self) -> Net {
+    let host = self.host.lock();
+    dispatch_dyn_net!(net = &*self.net => host.readback(net))
+  }
+
+  /// The rewrites performed since the net was last loaded.
+  pub fn rewrites(&self) -> Rewrites {
+    dispatch_dyn_net!(net = &*self.net => net.rwts)
+  }
+
+  /// Reduces the net to normal form as a [`Stream`], which yields a readback
+  /// of the net after every `interval` interactions, and finally its normal
+  /// form, e.g. to show the progress of a reduction as it happens.
+  ///
+  /// Each poll of the stream performs up to `interval` interactions on the
+  /// polling thread, and never returns [`Poll::Pending`]. Reading back the net
+  /// can be much slower than reducing it, so `interval` should be large enough
+  /// that each snapshot is amortized over many interactions.
+  ///
+  /// # Panics
+  ///
+  /// Panics if the net is in lazy mode, or if `interval` is `0`.
+  ///
+  /// [`Stream`]: futures_core::Stream
+  #[cfg(feature = "async")]
+  pub fn stream(&mut self, interval: usize) -> NormalStream<'_> {
+    assert!(!self.lazy && interval != 0);
+    NormalStream { net: self, interval, started: false, finished: false }
+  }
+
+  /// The host that the net's refs point into.
+  pub fn host(&self) -> &Arc<Mutex<Host>> {
+    &self.host
+  }
+
+  fn reset(&mut self) {
+    // SAFETY: see `with_options`
+    *self.net = DynNet::new(unsafe { self.heap.as_ref() }, self.lazy);
+  }
+}
+
+/// The stream returned by [`OwnedNet::stream`].
+#[cfg(feature = "async")]
+pub struct NormalStream<'a> {
+  net: &'a mut OwnedNet,
+  interval: usize,
+  started: bool,
+  finished: bool,
+}
+
+#[cfg(feature = "async")]
+impl futures_core::Stream for NormalStream<'_> {
+  type Item = Net;
+
+  fn poll_next(self: Pin<&mut Self>, _: &mut Context<'_>) -> Poll<Option<Net>> {
+    let this = self.get_mut();
+    if this.finished {
+      return Poll::Ready(None);
+    }
+    let (started, interval) = (mem::replace(&mut this.started, true), this.interval);
+    this.finished = dispatch_dyn_net!(net = &mut *this.net.net => {
+      if !started {
+        net.expand();
+      }
+      net.reduce(interval).is_some()
+    });
+    Poll::Ready(Some(this.net.readback()))
+  }
+}
+
+impl Drop for OwnedNet {
+  fn drop(&mut self) {
+    // SAFETY: `net` is dropped before the heap it borrows from is freed, and
+    // neither is used afterwards
+    unsafe {
+      ManuallyDrop::drop(&mut self.net);
+      drop(Box::from_raw(self.heap.as_ptr()));
+    }
+  }
+}
+
+// SAFETY: the heap is owned by the `OwnedNet`, and is only accessed through
+// `net`, which is `Send`; the `OwnedNet` is not `Sync`, as the heap pointer
+// isn't, so no two threads can access them at once.
+unsafe impl Send for OwnedNet {}
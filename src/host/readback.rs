@@ -11,6 +11,11 @@ impl Host {
 
   /// Creates an ast net from a runtime net.
   ///
+  /// The net need not be in normal form: if its reduction was stopped early,
+  /// the redexes still in its queue are read back as the redexes of the ast
+  /// net, so that printing it gives a net that can be parsed and reduced to
+  /// the same result.
+  ///
   /// Note that vicious circles and disconnected subnets will not be in the
   /// resulting ast net, as it is impossible to read these back from the runtime
   /// net representation. In the case of vicious circles, this may result in
@@ -26,7 +31,47 @@ impl Host {
 
     net
   }
+
+  /// Reads back the agent whose principal port is `port`, without the rest of
+  /// its tree: each auxiliary port is shown if it is connected to a nilary
+  /// agent, and as `_` otherwise.
+  ///
+  /// Unlike the other readback methods, this does not require refs to be in
+  /// the host; refs that aren't (such as those used internally by the
+  /// runtime) are also shown as `_`.
+  pub fn readback_agent(&self, port: &Port) -> Tree {
+    let leaf = |port: Port| match (port.tag(), self.back.get(&port.addr())) {
+      (Tag::Ref, _) if port == Port::ERA => Tree::Era,
+      (Tag::Ref, Some(nam)) => Tree::Ref { nam: nam.clone() },
+      (Tag::Int, _) => Tree::Int { val: port.int(), ty: None },
+      (Tag::F32, _) => Tree::F32 { val: port.float().into() },
+      _ => Tree::Var { nam: "_".to_owned() },
+    };
+    let hole = || Box::new(Tree::Var { nam: "_".to_owned() });
+    match port.tag() {
+      Tag::Op => {
+        let node = port.clone().traverse_node();
+        let (rhs, out) = (node.p1.load_target(), node.p2.load_target());
+        Tree::Op { op: port.op(), rhs: Box::new(leaf(rhs)), out: Box::new(leaf(out)) }
+      }
+      Tag::Ctr => {
+        let node = port.clone().traverse_node();
+        Tree::Ctr { lab: node.lab, ports: vec![leaf(node.p1.load_target()), leaf(node.p2.load_target())] }
+      }
+      Tag::Mat => {
+        let node = port.clone().traverse_node();
+        let out = Box::new(leaf(node.p2.load_target()));
+        if node.lab == 0 {
+          Tree::Mat { zero: hole(), succ: hole(), out }
+        } else {
+          Tree::Switch { lab: node.lab, arms: vec![], out }
+        }
+      }
+      _ => leaf(port.clone()),
+    }
+  }
 }
+
 /// See [`Host::readback`].
 struct ReadbackState<'a> {
   host: &'a Host,
@@ -40,24 +85,36 @@ impl<'a> ReadbackState<'a> {
     let port = wire.load_target();
     self.read_port(port, Some(wire))
   }
+  /// Reads back one occurrence of the variable keyed by `key`.
+  fn read_var(&mut self, key: Addr) -> Tree {
+    Tree::Var {
+      nam: create_var(match self.vars.entry(key) {
+        Entry::Occupied(e) => e.remove(),
+        Entry::Vacant(e) => *e.insert(self.var_id.next().unwrap()),
+      }),
+    }
+  }
+
   /// Reads a tree out from a given `port`. If this is a var port, the
   /// `wire` this port was reached from must be supplied to key into the
   /// `vars` map.
   fn read_port(&mut self, port: Port, wire: Option<Wire>) -> Tree {
     maybe_grow(move || match port.tag() {
+      // a wire still waiting for a pending expansion; see below
+      _ if port == Port::LOCK => self.read_var(wire.unwrap().addr()),
       Tag::Var | Tag::Red => {
         // todo: resolve redirects
-        let key = wire.unwrap().addr().min(port.addr());
-        Tree::Var {
-          nam: create_var(match self.vars.entry(key) {
-            Entry::Occupied(e) => e.remove(),
-            Entry::Vacant(e) => *e.insert(self.var_id.next().unwrap()),
-          }),
-        }
+        self.read_var(wire.unwrap().addr().min(port.addr()))
       }
       Tag::Ref if port == Port::ERA => Tree::Era,
-      Tag::Ref => Tree::Ref { nam: self.host.back[&port.addr()].clone() },
-      Tag::Int => Tree::Int { val: port.int() },
+      Tag::Ref => match run::expansion_target(&port) {
+        // a pending expansion (left in a net whose reduction was stopped part
+        // way) will link the tree it's connected to to its target, so it reads
+        // back as a variable shared with the (locked) target wire
+        Some(target) => self.read_var(target.addr()),
+        None => Tree::Ref { nam: self.host.back[&port.addr()].clone() },
+      },
+      Tag::Int => Tree::Int { val: port.int(), ty: None },
       Tag::F32 => Tree::F32 { val: port.float().into() },
       Tag::Op => {
         let op = port.op();
@@ -72,8 +129,112 @@ impl<'a> ReadbackState<'a> {
         let node = port.traverse_node();
         let arms = self.read_wire(node.p1);
         let out = self.read_wire(node.p2);
-        Tree::legacy_mat(arms, out).expect("invalid mat node")
+        if node.lab == 0 {
+          Tree::legacy_mat(arms, out).expect("invalid mat node")
+        } else {
+          Tree::switch(node.lab, arms, out).expect("invalid switch node")
+        }
       }
     })
   }
 }
+
+impl Net {
+  /// Replaces every church- or scott-encoded numeral in this net with the
+  /// corresponding `#n` literal; see [`Tree::fold_numerals`].
+  pub fn fold_numerals(&mut self) {
+    self.trees_mut().for_each(Tree::fold_numerals);
+  }
+}
+
+impl Tree {
+  /// Replaces every church- or scott-encoded numeral in this tree with the
+  /// corresponding `#n` literal.
+  ///
+  /// A subtree is only folded if it exactly matches one of these encodings
+  /// (with its variables bound within the subtree):
+  /// - the church numeral `n` is `(F (x r))`, where `F` is either `*` (for
+  ///   `0`), or a tree of duplicators whose leaves are `n` applications `(a b)`
+  ///   chained from `x` to `r` (along with, optionally, erasers)
+  /// - the scott numeral `0` is `(z (* z))`, and the scott numeral `n + 1` is
+  ///   `(* ((p r) r))`, where `p` is the scott numeral `n`
+  ///
+  /// Note that other data may share these encodings; e.g. the church numeral
+  /// `0` is also the church-encoded `false`.
+  pub fn fold_numerals(&mut self) {
+    maybe_grow(|| {
+      if let Some(val) = church_numeral(self).or_else(|| scott_numeral(self)) {
+        *self = Tree::Int { val, ty: None };
+      } else {
+        self.children_mut().for_each(Tree::fold_numerals);
+      }
+    })
+  }
+}
+
+/// Matches `(a b)`, returning `[a, b]`.
+fn lam(tree: &Tree) -> Option<[&Tree; 2]> {
+  match tree {
+    Tree::Ctr { lab: 0, ports } if ports.len() == 2 => Some([&ports[0], &ports[1]]),
+    _ => None,
+  }
+}
+
+fn var(tree: &Tree) -> Option<&str> {
+  match tree {
+    Tree::Var { nam } => Some(nam),
+    _ => None,
+  }
+}
+
+fn church_numeral(tree: &Tree) -> Option<i64> {
+  let [f, body] = lam(tree)?;
+  let [x, r] = lam(body)?;
+  let (x, r) = (var(x)?, var(r)?);
+
+  // Maps the argument of each application of `f` to its result.
+  let mut apps = Map::<&str, &str>::default();
+  fn collect_apps<'a>(tree: &'a Tree, apps: &mut Map<&'a str, &'a str>) -> Option<()> {
+    match tree {
+      Tree::Era => Some(()),
+      Tree::Ctr { lab: 0, .. } => {
+        let [a, b] = lam(tree)?;
+        apps.insert(var(a)?, var(b)?).is_none().then_some(())
+      }
+      Tree::Ctr { ports, .. } if ports.len() == 2 => ports.iter().try_for_each(|port| collect_apps(port, apps)),
+      _ => None,
+    }
+  }
+  maybe_grow(|| collect_apps(f, &mut apps))?;
+  if *f == Tree::Era {
+    return (x == r).then_some(0);
+  }
+
+  let mut n = 0;
+  let mut cur = x;
+  while let Some(next) = apps.remove(cur) {
+    cur = next;
+    n += 1;
+  }
+  (n > 0 && cur == r && apps.is_empty()).then_some(n)
+}
+
+fn scott_numeral(mut tree: &Tree) -> Option<i64> {
+  let mut n = 0;
+  loop {
+    let [z, rest] = lam(tree)?;
+    let [s, ret] = lam(rest)?;
+    match z {
+      Tree::Var { nam } if *s == Tree::Era && var(ret)? == nam => return Some(n),
+      Tree::Era => {
+        let [pred, r] = lam(s)?;
+        if var(r)? != var(ret)? {
+          return None;
+        }
+        tree = pred;
+        n += 1;
+      }
+      _ => return None,
+    }
+  }
+}
@@ -1,4 +1,4 @@
-#![feature(const_type_id, extern_types, inline_const, generic_const_exprs, new_uninit)]
+#![feature(const_type_id, extern_types, inline_const, generic_const_exprs, new_uninit, allocator_api)]
 #![cfg_attr(feature = "trace", feature(const_type_name))]
 #![cfg_attr(not(feature = "std"), no_std)]
 #![allow(
@@ -20,18 +20,29 @@ extern crate alloc;
 mod prelude;
 
 pub mod ast;
+#[cfg(feature = "std")]
 pub mod compile;
+#[cfg(feature = "std")]
+pub mod flat;
 pub mod host;
 pub mod ops;
 pub mod run;
 pub mod stdlib;
+#[cfg(feature = "std")]
 pub mod transform;
 pub mod util;
 
+#[cfg(feature = "std")]
 #[doc(hidden)] // not public api
 pub mod fuzz;
+#[cfg(feature = "std")]
 #[doc(hidden)] // not public api
 pub mod trace;
+#[cfg(not(feature = "std"))]
+#[doc(hidden)] // not public api
+#[path = "trace/minimal.rs"]
+pub mod trace;
 
+#[cfg(feature = "std")]
 #[doc(hidden)] // shim for compilation
 pub mod gen;
@@ -2,21 +2,30 @@
 
 use clap::{Args, Parser, Subcommand};
 use hvmc::{
-  ast::{Book, Net, Tree},
+  ast::{Book, DefName, LabelAliases, Net, NumFormat, Tree},
+  flat::FlatBook,
   host::Host,
-  run::{DynNet, Mode, Trg},
-  stdlib::create_host,
+  run::{DynNet, Trg},
+  stdlib::{create_host, Effect},
   transform::{TransformOpts, TransformPass, TransformPasses},
+  util::ApplySide,
   *,
 };
 
 use parking_lot::Mutex;
 use std::{
-  fs, io,
-  path::Path,
+  borrow::Cow,
+  cell::OnceCell,
+  collections::{BTreeSet, HashMap, HashSet},
+  fmt, fs, io, mem,
+  path::{Path, PathBuf},
   process::{self, Stdio},
   str::FromStr,
-  sync::Arc,
+  sync::{
+    atomic::{AtomicBool, AtomicUsize, Ordering},
+    Arc,
+  },
+  thread,
   time::{Duration, Instant},
 };
 
@@ -26,37 +35,124 @@ fn main() {
   }
   if cfg!(feature = "_full_cli") {
     let cli = FullCli::parse();
+    QUIET.store(cli.quiet, Ordering::Relaxed);
     match cli.mode {
-      CliMode::Compile { file, transform_args, output } => {
+      CliMode::Compile {
+        file,
+        transform_args,
+        output,
+        build_dir,
+        profile,
+        dry_run,
+        report,
+        bake_args,
+        emit: Emit::Const,
+      } => {
+        // the other options only apply to executables
+        if build_dir != DEFAULT_BUILD_DIR || profile != Profile::Release || dry_run || report || !bake_args.is_empty() {
+          fail(
+            Failure::Other,
+            "`--build-dir`, `--profile`, `--dry-run`, `--report`, and `--bake-args` can't be used with `--emit const`",
+          );
+        }
+        let mut book = read_book(&[file], &transform_args);
+        transform_book(&mut book, &transform_args);
+        let flat = book.to_flat().unwrap_or_else(|e| fail(Failure::Other, e));
+        match output {
+          Some(output) => fs::write(&output, flat.to_rust())
+            .unwrap_or_else(|e| fail(Failure::Io, format_args!("Cannot write {output}: {e}"))),
+          None => print!("{}", flat.to_rust()),
+        }
+      }
+      CliMode::Compile {
+        file,
+        transform_args,
+        output,
+        build_dir,
+        profile,
+        dry_run,
+        report,
+        bake_args,
+        emit: Emit::Exe,
+      } => {
         let output = output.as_deref().or_else(|| file.strip_suffix(".hvmc")).unwrap_or_else(|| {
-          eprintln!("file missing `.hvmc` extension; explicitly specify an output path with `--output`.");
-          process::exit(1);
+          fail(Failure::Other, "file missing `.hvmc` extension; explicitly specify an output path with `--output`.")
         });
-        let host = create_host(&load_book(&[file.clone()], &transform_args));
-        compile_executable(output, host).unwrap();
+        // fail now, rather than when the compiled executable is run
+        for arg in &bake_args {
+          parse_arg(arg);
+        }
+        let book = read_book(&[file.clone()], &transform_args);
+        let mut transformed = book.clone();
+        transform_book(&mut transformed, &transform_args);
+        let host = create_host(&transformed);
+        if report {
+          print_compile_report(&compile::compile_report(&host.lock(), &book));
+        }
+        compile_executable(output, host, &bake_args, Path::new(&build_dir), profile, dry_run)
+          .unwrap_or_else(|e| fail(Failure::Io, e));
       }
       CliMode::Run { run_opts, mut transform_args, file, args } => {
-        // Don't pre-reduce or prune the entry point
-        transform_args.transform_opts.pre_reduce_skip.push(args.entry_point.clone());
-        transform_args.transform_opts.prune_entrypoints.push(args.entry_point.clone());
-        let host = create_host(&load_book(&[file], &transform_args));
-        run(host, run_opts, args);
+        let mut book = read_book(&[file], &transform_args);
+        let entry_points = args.entry_points(book.nets.keys());
+        // Don't pre-reduce or prune the entry points
+        for entry_point in &entry_points {
+          transform_args.transform_opts.add_entrypoint(entry_point);
+        }
+        transform_book(&mut book, &transform_args);
+        let host = create_host(&book);
+        run(host, run_opts, args, &entry_points);
       }
-      CliMode::Reduce { run_opts, transform_args, files, exprs } => {
-        let host = create_host(&load_book(&files, &transform_args));
-        let exprs: Vec<_> = exprs.iter().map(|x| Net::from_str(x).unwrap()).collect();
-        reduce_exprs(host, &exprs, &run_opts);
+      CliMode::Bench { run_opts, mut transform_args, file, args, runs, warmup } => {
+        let mut book = read_book(&[file], &transform_args);
+        let [entry_point] = &args.entry_points(book.nets.keys())[..] else {
+          fail(Failure::Other, "`bench` requires a single entry point");
+        };
+        transform_args.transform_opts.add_entrypoint(entry_point);
+        transform_book(&mut book, &transform_args);
+        let host = create_host(&book);
+        let net = entry_net(entry_point, &args);
+        bench(host, run_opts, net, runs, warmup);
+      }
+      CliMode::Reduce { run_opts, transform_args, files, exprs, jobs } => {
+        let mut book = load_book(&files, &transform_args);
+        let exprs: Vec<_> =
+          if exprs.is_empty() { mem::take(&mut book.reduce) } else { exprs.iter().map(|x| parse_arg(x)).collect() };
+        let host = create_host(&book);
+        match jobs {
+          Some(jobs) if jobs > 1 => reduce_exprs_concurrently(host, &exprs, &run_opts, jobs),
+          _ => reduce_exprs(host, &exprs, None, &run_opts),
+        }
       }
       CliMode::Transform { transform_args, files } => {
         let book = load_book(&files, &transform_args);
         println!("{}", book);
       }
+      CliMode::Diff { transform_args, old, new } => {
+        let diff = load_book(&[old], &transform_args).diff(&load_book(&[new], &transform_args));
+        print!("{diff}");
+        if !diff.is_empty() {
+          process::exit(Failure::Differ as i32);
+        }
+      }
+      CliMode::Validate { files, strict, transform_args } => {
+        let mut book = read_book(&files, &transform_args);
+        if strict {
+          check_linearity(&book);
+        }
+        transform_book(&mut book, &transform_args);
+      }
     }
   } else {
-    let cli = BareCli::parse();
+    let mut cli = BareCli::parse();
+    QUIET.store(cli.quiet, Ordering::Relaxed);
+    if cli.args.args.is_empty() {
+      cli.args.args = gen::BAKED_ARGS.iter().map(|arg| arg.to_string()).collect();
+    }
     let host = create_host(&Book::default());
     gen::insert_into_host(&mut host.lock());
-    run(host, cli.opts, cli.args);
+    let entry_points = cli.args.entry_points(host.lock().defs.keys().collect::<BTreeSet<_>>());
+    run(host, cli.opts, cli.args, &entry_points);
   }
   if cfg!(feature = "trace") {
     hvmc::trace::_read_traces(usize::MAX);
@@ -75,10 +171,22 @@ Examples:
 $ hvmc run examples/church_encoding/church.hvm
 $ hvmc run examples/addition.hvmc "#16" "#3"
 $ hvmc compile examples/addition.hvmc
+$ hvmc bench examples/addition.hvmc --runs 20 --warmup 3
 $ hvmc reduce examples/addition.hvmc -- "a & @mul ~ (#3 (#4 a))"
-$ hvmc reduce -- "a & #3 ~ <* #4 a>""##
+$ hvmc reduce -- "a & #3 ~ <* #4 a>"
+
+Exit codes:
+  1  an invalid combination of arguments, or any other failure
+  2  a program, expression, or input could not be parsed
+  3  an input file could not be read
+  4  reduction failed, or produced an unexpected normal form
+  5  the programs compared by `diff` differ
+  6  the executable generated by `compile` could not be built"##
 )]
 struct FullCli {
+  #[arg(short = 'q', long = "quiet", global = true)]
+  /// Don't print diagnostics; failures are only reported by the exit code.
+  pub quiet: bool,
   #[command(subcommand)]
   pub mode: CliMode,
 }
@@ -86,6 +194,9 @@ struct FullCli {
 #[derive(Parser, Debug)]
 #[command(author, version)]
 struct BareCli {
+  #[arg(short = 'q', long = "quiet")]
+  /// Don't print diagnostics; failures are only reported by the exit code.
+  pub quiet: bool,
   #[command(flatten)]
   pub opts: RuntimeOpts,
   #[command(flatten)]
@@ -100,8 +211,50 @@ enum CliMode {
     /// hvm-core file to compile.
     file: String,
     #[arg(short = 'o', long = "output")]
-    /// Output path; defaults to the input file with `.hvmc` stripped.
+    /// Output path; defaults to the input file with `.hvmc` stripped, or to
+    /// standard output with `--emit const`.
     output: Option<String>,
+    #[arg(long = "build-dir", default_value = DEFAULT_BUILD_DIR)]
+    /// Scratch directory in which the generated crate is built.
+    ///
+    /// Its contents are replaced on every compilation, so concurrent
+    /// compilations must use different directories. To avoid deleting user
+    /// data, this must be empty or a directory previously created by `compile`.
+    build_dir: String,
+    #[arg(long = "profile", value_enum, default_value_t = Profile::Release)]
+    /// Cargo profile with which to build the executable.
+    profile: Profile,
+    #[arg(long = "dry-run")]
+    /// Only write the generated crate to the build directory, and print the
+    /// path of its generated source, without building it.
+    dry_run: bool,
+    #[arg(long = "report")]
+    /// Print static metrics about the generated code to standard error.
+    ///
+    /// For each compiled definition, this shows the number of lines generated
+    /// for it, the number of nodes it allocates when called (an estimate of
+    /// its cost), and the number of definitions it refers to; it also lists
+    /// the definitions that are no longer referred to after the
+    /// transformations, e.g. because they were inlined.
+    report: bool,
+    #[arg(long = "bake-args", num_args = 1 ..)]
+    /// Arguments to compile into the executable.
+    ///
+    /// When the executable is run without arguments, the entry point is
+    /// applied to these, as if they had been passed on the command line, so
+    /// that it reduces a fixed expression. Arguments passed when running it
+    /// replace these.
+    bake_args: Vec<String>,
+    #[arg(long = "emit", value_enum, default_value_t = Emit::Exe)]
+    /// What to compile the program to.
+    ///
+    /// With `const`, the transformed book is written (to `--output`, or to
+    /// standard output) as Rust source declaring `pub static BOOK:
+    /// &[hvmc::flat::DefData]`, which can be embedded in a program with
+    /// `include!` and loaded into a host without parsing; see
+    /// `hvmc::flat::FlatBook::from_defs`. The options for building executables
+    /// can't be used with it.
+    emit: Emit,
     #[command(flatten)]
     transform_args: TransformArgs,
   },
@@ -116,6 +269,26 @@ enum CliMode {
     #[command(flatten)]
     transform_args: TransformArgs,
   },
+  /// Repeatedly run a program, reporting timing statistics.
+  ///
+  /// Each run reduces the program on a fresh heap. Warmup runs are performed
+  /// first and excluded from the statistics.
+  Bench {
+    /// Name of the file to load.
+    file: String,
+    #[command(flatten)]
+    args: RunArgs,
+    #[arg(long = "runs", default_value_t = 10)]
+    /// How many runs to measure.
+    runs: usize,
+    #[arg(long = "warmup", default_value_t = 0)]
+    /// How many runs to perform before measuring.
+    warmup: usize,
+    #[command(flatten)]
+    run_opts: RuntimeOpts,
+    #[command(flatten)]
+    transform_args: TransformArgs,
+  },
   /// Reduce hvm-core expressions to their normal form.
   ///
   /// The expressions are passed as command-line arguments.
@@ -134,7 +307,21 @@ enum CliMode {
     /// The normal form of each expression will be
     /// printed on a new line. This list must be separated from the file list
     /// with a double dash ('--').
+    ///
+    /// If no expressions are given, those of the `#reduce <expr>` directives
+    /// in the files (but not in the files they import) are reduced instead,
+    /// so that a file can hold both a program and the expressions to reduce
+    /// with it.
     exprs: Vec<String>,
+    #[arg(short = 'j', long = "jobs", conflicts_with_all = ["io", "record_log", "replay_log"])]
+    /// How many expressions to reduce concurrently.
+    ///
+    /// Each job reduces its expressions on a single thread, so that the jobs
+    /// don't contend for the cores, and on its own heap (of the size given by
+    /// `--memory`), so memory usage grows with the number of jobs. Results are
+    /// still printed in the order the expressions were given. Can't be used
+    /// with `--io`, `--record-log`, or `--replay-log`.
+    jobs: Option<usize>,
     #[command(flatten)]
     run_opts: RuntimeOpts,
     #[command(flatten)]
@@ -150,14 +337,78 @@ enum CliMode {
     #[command(flatten)]
     transform_args: TransformArgs,
   },
+  /// Compare the definitions of two hvm-core programs.
+  ///
+  /// Prints the definitions only in the new program (`+`), only in the old one
+  /// (`-`), and in both but with different nets (`~`). Nets that only differ
+  /// in the names of their variables are considered equal. For each changed
+  /// definition, the outermost subtrees that differ are printed, along with
+  /// their location in the net, as a path of port indices, e.g. `root.1.0`.
+  ///
+  /// Exits with code 5 if the programs differ.
+  Diff {
+    /// The old program.
+    old: String,
+    /// The new program.
+    new: String,
+    #[command(flatten)]
+    transform_args: TransformArgs,
+  },
+  /// Check that hvm-core programs are well-formed.
+  ///
+  /// Parses the files, along with the files they import, and applies the
+  /// enabled transformation passes to them, reporting the first error found.
+  /// Prints nothing if there are none.
+  Validate {
+    /// Files to check.
+    ///
+    /// Multiple files will act as if they're concatenated together.
+    #[arg(required = true)]
+    files: Vec<String>,
+    #[arg(long = "strict")]
+    /// Also check that every variable appears exactly twice in its net.
+    ///
+    /// Each variable of a net stands for a wire between two ports, so one
+    /// that appears once or more than twice makes the net malformed, which
+    /// leads to wrong results or crashes when it is reduced. The variables of
+    /// every definition and `#reduce` expression are checked before any
+    /// transformation, and each one that doesn't appear twice is reported.
+    strict: bool,
+    #[command(flatten)]
+    transform_args: TransformArgs,
+  },
 }
 
 #[derive(Args, Clone, Debug)]
 struct TransformArgs {
   /// Enables or disables transformation passes.
+  ///
+  /// No pass is enabled by default. The flags are applied in order: `all`
+  /// enables every pass, and prefixing a pass with `no-` disables it, so that
+  /// e.g. `-Oall -Ono-eta-reduce` runs every pass but `eta-reduce`.
   #[arg(short = 'O', value_delimiter = ' ', action = clap::ArgAction::Append)]
   transform_passes: Vec<TransformPass>,
 
+  /// Prints the names of the enabled transformation passes.
+  #[arg(long = "print-passes")]
+  print_passes: bool,
+
+  /// Evaluates operations applied to two literals, such as `#2 ~ <+ #3 x>`,
+  /// as soon as the program is parsed, before any transformation passes.
+  ///
+  /// The results are the same as those computed at runtime; operations that
+  /// would stop reduction, such as checked operations that overflow, are left
+  /// to fail at runtime.
+  #[arg(long = "fold-on-parse")]
+  fold_on_parse: bool,
+
+  /// Rejects programs with trees nested more than this many levels deep.
+  ///
+  /// Without a limit, deeply nested trees can exhaust the memory available to
+  /// the parser, so this should be set when loading untrusted programs.
+  #[arg(long = "max-parse-depth")]
+  max_parse_depth: Option<usize>,
+
   #[command(flatten)]
   transform_opts: TransformOpts,
 }
@@ -167,9 +418,71 @@ struct RuntimeOpts {
   #[arg(short = 's', long = "stats")]
   /// Show performance statistics.
   show_stats: bool,
+  #[arg(short = 'v', long = "verbose", requires = "show_stats")]
+  /// Show additional statistics with `--stats`.
+  ///
+  /// This includes the total number of nodes allocated during reduction
+  /// (`ALLOCS`), including those that were later freed, which indicates
+  /// the pressure on the allocator independently of peak memory usage.
+  ///
+  /// The allocations are split into those that reused a freed node from the
+  /// free list (`- FREE`) and those that grew the used part of the heap
+  /// (`- BUMP`), along with the fraction of them that hit the free list
+  /// (`HITRATE`) and the greatest length it reached (`MAXFREE`). A low hit
+  /// rate means the program mostly grows its net, and needs a heap as large
+  /// as the sum of its allocations.
+  ///
+  /// It also includes the causal depth of the reduction (`DEPTH`): the length
+  /// of the longest chain of interactions that each depend on the previous
+  /// one. This approximates the number of steps needed to reduce the program
+  /// with unlimited parallelism, so comparing it to the total number of
+  /// rewrites indicates how parallelizable the program is. Tracking it slows
+  /// down reduction.
+  ///
+  /// When reducing in parallel, it also includes how the redexes were
+  /// distributed among the threads: the number of exchanges of redexes
+  /// between threads (`XCHG`), of which some filled the share buffer
+  /// (`FULL`) or moved nothing (`EMPTY`), and the smallest, mean, and largest
+  /// number of redexes held by a thread in each synchronization round, on
+  /// average (`BAGS`). `IMBAL` summarizes these, from `0` when every thread
+  /// holds as many redexes, to nearly `1` when a single thread holds all of
+  /// them, which indicates poor scaling.
+  verbose: bool,
+  #[arg(long = "stats-per-expr")]
+  /// Print the number of rewrites performed alongside each normal form.
+  ///
+  /// Each normal form is followed by a tab and the total number of rewrites
+  /// performed to reduce it.
+  stats_per_expr: bool,
+  #[arg(long = "count-initial-redexes", conflicts_with = "lazy_mode")]
+  /// Print the number of redexes in each expression before it is reduced.
+  ///
+  /// If the root of the expression is a reference (as with `run` when no
+  /// arguments are passed), it is expanded first, so that the redexes of the
+  /// definition are counted. This indicates how much parallelism is
+  /// initially available. Not available in lazy mode.
+  count_initial_redexes: bool,
+  #[arg(long = "explain", requires = "single_core", conflicts_with = "lazy_mode")]
+  /// Print each interaction performed, along with the rule used.
+  ///
+  /// Each active pair is printed to standard error before it is reduced, as
+  /// the name of the rule that reduces it followed by the two agents
+  /// involved; ports that aren't connected to a nilary agent are shown as
+  /// `_`. Requires `--single`.
+  explain: bool,
   #[arg(short = '1', long = "single")]
   /// Single-core mode (no parallelism).
   single_core: bool,
+  #[arg(long = "pin-threads", conflicts_with_all = ["single_core", "lazy_mode"])]
+  /// Pin each reduction thread to a distinct CPU core.
+  ///
+  /// This keeps the OS scheduler from migrating threads between cores, which
+  /// makes timings (such as the RPS reported by `--stats`) more stable across
+  /// runs. There is one thread per core the process may run on, rounded down
+  /// to a power of two, so no two threads share a core. Has no effect with
+  /// `--jobs`, whose reductions are each single-threaded, or where thread
+  /// affinity isn't supported (it is only set on Linux).
+  pin_threads: bool,
   #[arg(short = 'l', long = "lazy")]
   /// Lazy mode.
   ///
@@ -177,11 +490,231 @@ struct RuntimeOpts {
   /// by a walk from the root of the net. This leads to a dramatic slowdown,
   /// but allows running programs that would expand indefinitely otherwise.
   lazy_mode: bool,
+  #[arg(
+    long = "lazy-fallback",
+    conflicts_with_all = ["lazy_mode", "explain"],
+    value_parser = clap::value_parser!(u8).range(1 ..= 100)
+  )]
+  /// Switch to lazy mode once more than this percentage of memory is in use.
+  ///
+  /// Reduction starts in strict mode, as usual; if it uses more than this
+  /// percentage of the memory (of its share of it, for each thread, when
+  /// reducing in parallel), it is stopped, and the partially reduced net is
+  /// read back and reduced the rest of the way in lazy mode (see `--lazy`).
+  /// `--stats` then reports the switch.
+  lazy_fallback: Option<u8>,
+  #[arg(
+    long = "checkpoint-every",
+    requires_all = ["checkpoint_path", "single_core"],
+    conflicts_with_all = ["lazy_mode", "explain"],
+    value_parser = util::parse_abbrev_number::<usize>
+  )]
+  /// Save the state of the reduction every this many interactions.
+  ///
+  /// The partially reduced net is read back and written to
+  /// `checkpoint.hvmc` in the directory given by `--checkpoint-path`, as an
+  /// expression, so that an interrupted reduction can be resumed by reducing
+  /// it with the same program, e.g. `hvmc reduce program.hvmc -- "$(cat
+  /// checkpoint.hvmc)"`. Each checkpoint replaces the last one. Requires
+  /// `--single`.
+  checkpoint_every: Option<usize>,
+  #[arg(long = "checkpoint-path", requires = "checkpoint_every")]
+  /// The directory to write checkpoints to; see `--checkpoint-every`.
+  checkpoint_path: Option<PathBuf>,
+  #[arg(long = "checkpoint-keep", requires = "checkpoint_every")]
+  /// Keep every checkpoint, rather than replacing the last one.
+  ///
+  /// The checkpoints of each expression are numbered in order, as
+  /// `checkpoint-1.hvmc`, `checkpoint-2.hvmc`, and so on.
+  checkpoint_keep: bool,
+  #[arg(
+    long = "record-log",
+    requires = "single_core",
+    conflicts_with_all = ["lazy_mode", "lazy_fallback", "explain", "checkpoint_every", "replay_log"]
+  )]
+  /// Write every interaction performed to this file, so that the reduction can
+  /// be replayed with `--replay-log`.
+  ///
+  /// Each interaction is written on its own line, as the name of the rule that
+  /// reduces it followed by its two agents: nodes are identified by their
+  /// index in the heap, and nilary agents are written as trees. The
+  /// interactions of each expression are followed by an empty line. Requires
+  /// `--single`.
+  record_log: Option<PathBuf>,
+  #[arg(
+    long = "replay-log",
+    requires = "single_core",
+    conflicts_with_all = ["lazy_mode", "lazy_fallback", "explain", "checkpoint_every"]
+  )]
+  /// Replay a reduction recorded with `--record-log`, checking that it performs
+  /// the same interactions.
+  ///
+  /// Each expression is reduced as usual, while checking each interaction
+  /// against the log; if the reduction diverges from it, or performs fewer or
+  /// more interactions, it is stopped with an error describing the first
+  /// difference. This checks that a reduction is deterministic, and that a
+  /// recorded bug is reproduced. Requires `--single`.
+  replay_log: Option<PathBuf>,
+  #[arg(
+    long = "steps",
+    requires = "single_core",
+    conflicts_with_all = ["lazy_mode", "lazy_fallback", "explain", "checkpoint_every", "record_log", "replay_log"],
+    value_parser = util::parse_abbrev_number::<usize>
+  )]
+  /// Stop reduction after this many interactions.
+  ///
+  /// The partially reduced net is printed instead of the normal form, with the
+  /// active pairs left to reduce as redexes, so that reduction can be resumed
+  /// by reducing it with the same program. The number of interactions actually
+  /// performed, which is lower if the expression reached its normal form
+  /// first, is printed to standard error (`STEPS`). Requires `--single`.
+  ///
+  /// Each interaction reduces one active pair, which may count as several
+  /// rewrites in `--stats`, e.g. when it expands a reference.
+  steps: Option<usize>,
   #[arg(short = 'm', long = "memory", value_parser = util::parse_abbrev_number::<usize>)]
   /// How much memory to allocate on startup.
   ///
   /// Supports abbreviations such as '4G' or '400M'.
   memory: Option<usize>,
+  #[arg(long = "no-skip")]
+  /// Disable skipping of trivial active pairs.
+  ///
+  /// By default, active pairs between two nilary agents (such as `* ~ *`) are
+  /// discarded without being reduced. With this flag, they are reduced like
+  /// any other active pair, expanding the references involved. This is
+  /// slower, but can be useful for studying raw interaction counts.
+  no_skip: bool,
+  #[arg(long = "linear")]
+  /// Reject interactions that erase or duplicate agents.
+  ///
+  /// If the program erases a node or duplicates a node or a number, reduction
+  /// stops with an error describing the offending interaction.
+  linear: bool,
+  #[arg(long = "readback", value_delimiter = ',')]
+  /// Post-processing to apply when reading back the normal form.
+  ///
+  /// `numerals` folds church- and scott-encoded numerals into `#n` literals.
+  ///
+  /// `refs` leaves the references to definitions in the normal form
+  /// unexpanded, so that a result that selects a definition is shown as e.g.
+  /// `@true`, rather than as its net. This is only supported in strict mode.
+  readback: Vec<ReadbackOpt>,
+  #[arg(long = "normalize-output")]
+  /// Rename the variables of each result in order of first occurrence.
+  ///
+  /// This is applied after `--readback`, so that results which only differ in
+  /// the names of their variables are printed identically, e.g. for comparing
+  /// them against expected outputs.
+  normalize_output: bool,
+  #[arg(long = "readback-compact")]
+  /// Print each result on a single line.
+  ///
+  /// By default, any redexes left in a result are printed on separate lines;
+  /// with this, they are joined to the root with `&` on the same line, which
+  /// is easier to grep for in logs or to embed in other formats.
+  readback_compact: bool,
+  #[arg(long = "num-format", value_enum, default_value_t = NumFormat::Dec)]
+  /// How to print the ints in each result.
+  ///
+  /// With `hex`, ints are printed in hexadecimal, e.g. `#0xff` or `#-0x2a`,
+  /// which is easier to read for large values and bit patterns. Either format
+  /// can be parsed back.
+  num_format: NumFormat,
+  #[arg(long = "sections")]
+  /// Print operator sections with the `<op rhs>` shorthand.
+  ///
+  /// By default, a function like `<+ #1>` is printed as the net it stands
+  /// for, `(<+ #1 a> a)`.
+  sections: bool,
+  #[arg(long = "verify", requires = "single_core")]
+  /// Check for leaked nodes after reduction.
+  ///
+  /// Reports the number of nodes that were allocated but are no longer
+  /// reachable, which indicates a bug in the runtime. Requires `--single`.
+  verify: bool,
+  #[arg(long = "coverage")]
+  /// Report which interaction rules were exercised.
+  ///
+  /// After each expression is reduced, the number of times each interaction
+  /// rule was performed is printed to standard error, marking the rules that
+  /// were never performed. This is useful for checking that a set of test
+  /// programs covers every rule of the runtime.
+  coverage: bool,
+  #[arg(long = "dump-heap", requires = "single_core")]
+  /// Print the raw contents of the heap after reduction.
+  ///
+  /// Every allocated node is printed along with the root and any remaining
+  /// redexes, to standard error. Requires `--single`.
+  dump_heap: bool,
+  #[arg(long = "trace-defs", value_delimiter = ',')]
+  /// Only trace the calls to these definitions.
+  ///
+  /// This traces the events emitted while creating the nodes of the
+  /// definitions, and the later interactions of those nodes and of the nodes
+  /// they create. Only has an effect when built with the `trace` feature.
+  /// Defaults to the comma-separated list in the `HVMC_TRACE_DEFS` environment
+  /// variable.
+  trace_defs: Vec<String>,
+  #[arg(long = "io")]
+  /// Interpret the normal form as an IO action.
+  ///
+  /// The normal form must be an effect: `(:0:3 #code)` exits with `code`,
+  /// `(:1:3 value next)` prints `value` and continues with `next`, and
+  /// `(:2:3 cont)` reads a tree from a line of standard input and continues
+  /// with `(cont input)`. Exiting with `#0` moves on to the next expression;
+  /// codes that are not between 0 and 255 are a reduction failure.
+  io: bool,
+  #[arg(long = "result-separator", value_parser = parse_escapes)]
+  /// String to print between the normal forms of multiple expressions.
+  ///
+  /// Supports the escapes `\0`, `\n`, `\t`, and `\\`. When not given, each
+  /// normal form is printed on its own line.
+  result_separator: Option<String>,
+  #[arg(long = "trailing-separator")]
+  /// Also print the result separator after the last normal form.
+  trailing_separator: bool,
+}
+
+/// Replaces the escape sequences supported by `--result-separator`.
+fn parse_escapes(arg: &str) -> Result<String, String> {
+  let mut out = String::new();
+  let mut chars = arg.chars();
+  while let Some(c) = chars.next() {
+    if c != '\\' {
+      out.push(c);
+      continue;
+    }
+    out.push(match chars.next() {
+      Some('0') => '\0',
+      Some('n') => '\n',
+      Some('t') => '\t',
+      Some('\\') => '\\',
+      Some(c) => return Err(format!("unknown escape sequence `\\{c}`")),
+      None => return Err("trailing backslash".to_string()),
+    });
+  }
+  Ok(out)
+}
+
+#[derive(clap::ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+enum Profile {
+  Debug,
+  Release,
+}
+
+#[derive(clap::ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+enum Emit {
+  /// An executable, built from the generated Rust code.
+  Exe,
+  /// The book as Rust static data.
+  Const,
+}
+
+#[derive(clap::ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+enum ReadbackOpt {
+  Numerals,
+  Refs,
 }
 
 #[derive(Args, Clone, Debug)]
@@ -189,6 +722,19 @@ struct RunArgs {
   #[arg(short = 'e', default_value = "main")]
   /// Name of the definition that will get reduced.
   entry_point: String,
+  #[arg(long = "entry-glob", conflicts_with_all = ["entry_point", "entry_index", "io"])]
+  /// Reduce every definition whose name matches this pattern, instead of a
+  /// single entry point.
+  ///
+  /// In the pattern, `*` matches any sequence of characters, and `?` matches
+  /// any single character. The matching definitions are reduced in order of
+  /// their names, and each result is prefixed with the name of its
+  /// definition. Can't be used with `--io`.
+  entry_glob: Option<String>,
+  #[arg(long = "entry-index", conflicts_with = "entry_point")]
+  /// Reduce the definition at this (zero-based) position, with definitions
+  /// ordered by name, instead of the one named by `-e`.
+  entry_index: Option<usize>,
   /// List of arguments to pass to the program.
   ///
   /// Arguments are passed using the lambda-calculus interpretation
@@ -196,73 +742,793 @@ struct RunArgs {
   /// "#1" "#2" "#3", then the expression that will get reduced is
   /// `r & @main ~ (#1 (#2 (#3 r)))`.
   args: Vec<String>,
+  #[arg(long = "apply-side", value_enum, default_value_t = ApplySide::Left)]
+  /// The port of each application node that the arguments are passed on.
+  ///
+  /// With `right`, the arguments "#1" "#2" give `r & @main ~ ((r #2) #1)`.
+  apply_side: ApplySide,
 }
 
-fn run(host: Arc<Mutex<Host>>, opts: RuntimeOpts, args: RunArgs) {
-  let mut net = Net { root: Tree::Ref { nam: args.entry_point }, redexes: vec![] };
-  for arg in args.args {
-    let arg: Net = Net::from_str(&arg).unwrap();
+impl RunArgs {
+  /// Resolves the entry points selected by these arguments among the
+  /// definitions named by `names`, which must be sorted.
+  fn entry_points<'a>(&self, names: impl IntoIterator<Item = &'a String>) -> Vec<String> {
+    if let Some(pattern) = &self.entry_glob {
+      let entry_points: Vec<_> = names.into_iter().filter(|name| glob_matches(pattern, name)).cloned().collect();
+      if entry_points.is_empty() {
+        fail(Failure::Other, format_args!("no definition matches `{pattern}`"));
+      }
+      entry_points
+    } else if let Some(index) = self.entry_index {
+      let Some(name) = names.into_iter().nth(index) else {
+        fail(Failure::Other, format_args!("entry index {index} is out of range"));
+      };
+      vec![name.clone()]
+    } else {
+      vec![self.entry_point.clone()]
+    }
+  }
+}
+
+/// Whether `name` matches the glob `pattern`, in which `*` matches any
+/// sequence of characters and `?` matches any single character.
+fn glob_matches(pattern: &str, name: &str) -> bool {
+  let (pattern, name) = (pattern.as_bytes(), name.as_bytes());
+  // the position after the last `*`, and the position in `name` it matched up to
+  let mut backtrack = None;
+  let (mut p, mut n) = (0, 0);
+  while n < name.len() {
+    match pattern.get(p) {
+      Some(b'*') => {
+        p += 1;
+        backtrack = Some((p, n));
+      }
+      Some(&c) if c == b'?' || c == name[n] => {
+        p += 1;
+        n += 1;
+      }
+      _ => match backtrack {
+        Some((bp, bn)) => {
+          p = bp;
+          n = bn + 1;
+          backtrack = Some((bp, bn + 1));
+        }
+        None => return false,
+      },
+    }
+  }
+  pattern[p ..].iter().all(|&c| c == b'*')
+}
+
+/// Reduces the given entry points, applied to the arguments in `args`. When
+/// running multiple entry points (with `--entry-glob`), each result is
+/// prefixed with its name.
+fn run(host: Arc<Mutex<Host>>, opts: RuntimeOpts, args: RunArgs, entry_points: &[String]) {
+  let exprs: Vec<_> = entry_points.iter().map(|entry_point| entry_net(entry_point, &args)).collect();
+  let names = args.entry_glob.is_some().then_some(entry_points);
+  reduce_exprs(host, &exprs, names, &opts);
+}
+
+/// Builds the net that applies the entry point to the given arguments.
+fn entry_net(entry_point: &str, args: &RunArgs) -> Net {
+  let mut net = Net { root: Tree::Ref { nam: entry_point.to_owned() }, redexes: vec![] };
+  for arg in &args.args {
+    let arg = parse_arg(arg);
     net.redexes.extend(arg.redexes);
-    net.apply_tree(arg.root);
+    net.apply_tree_at(arg.root, args.apply_side);
   }
+  net
+}
 
-  reduce_exprs(host, &[net], &opts);
+fn bench(host: Arc<Mutex<Host>>, opts: RuntimeOpts, net: Net, runs: usize, warmup: usize) {
+  if runs == 0 {
+    fail(Failure::Other, "`--runs` must be at least 1");
+  }
+  let pool = OnceCell::new();
+  let mut samples = vec![];
+  for i in 0 .. warmup + runs {
+    let heap = run::Heap::new(opts.memory).expect("memory allocation failed");
+    let (_, stats) = reduce_expr(&host, &heap, Some(&pool), &net, &opts);
+    if i >= warmup {
+      let elapsed = stats.elapsed.as_secs_f64();
+      samples.push((elapsed, stats.rwts.total() as f64 / elapsed / 1_000_000.0));
+    }
+  }
+  let (time, rps): (Vec<_>, Vec<_>) = samples.into_iter().unzip();
+  println!("RUNS   : {runs} ({warmup} warmup)");
+  println!("         {:>12} {:>12} {:>12} {:>12}", "min", "median", "mean", "stddev");
+  let [min, median, mean, stddev] = summarize(time).map(Duration::from_secs_f64);
+  println!(
+    "TIME   : {:>12} {:>12} {:>12} {:>12}",
+    format!("{min:.3?}"),
+    format!("{median:.3?}"),
+    format!("{mean:.3?}"),
+    format!("{stddev:.3?}")
+  );
+  let [min, median, mean, stddev] = summarize(rps);
+  println!("RPS (M): {min:>12.3} {median:>12.3} {mean:>12.3} {stddev:>12.3}");
+}
+
+/// Returns the minimum, median, mean, and standard deviation of `samples`.
+fn summarize(mut samples: Vec<f64>) -> [f64; 4] {
+  samples.sort_by(f64::total_cmp);
+  let n = samples.len();
+  let median = if n % 2 == 0 { (samples[n / 2 - 1] + samples[n / 2]) / 2.0 } else { samples[n / 2] };
+  let mean = samples.iter().sum::<f64>() / n as f64;
+  let variance = samples.iter().map(|x| (x - mean) * (x - mean)).sum::<f64>() / n as f64;
+  [samples[0], median, mean, variance.sqrt()]
 }
 
 fn load_book(files: &[String], transform_args: &TransformArgs) -> Book {
-  let mut book = files
-    .iter()
-    .map(|name| {
-      let contents = fs::read_to_string(name).unwrap_or_else(|_| {
-        eprintln!("Input file {:?} not found", name);
-        process::exit(1);
-      });
-      contents.parse::<Book>().unwrap_or_else(|e| {
-        eprintln!("Parsing error {e}");
-        process::exit(1);
+  let mut book = read_book(files, transform_args);
+  transform_book(&mut book, transform_args);
+  book
+}
+
+/// Parses and concatenates the books in `files`, along with the books they
+/// import.
+///
+/// Imports are resolved relative to the importing file, and each file is read
+/// once, however many times it is imported. A def may be redefined by a later
+/// file in `files`, but it is an error for an imported book to define a def
+/// that is defined elsewhere.
+///
+/// Files may also be binary books (see `hvmc::flat::FlatBook::to_bytes`), which
+/// have no imports. Files compressed with gzip (as detected by their magic
+/// number, whatever their extension) are decompressed, if hvmc is built with
+/// the `gzip` feature.
+fn read_book(files: &[String], transform_args: &TransformArgs) -> Book {
+  let mut loader = BookLoader { max_depth: transform_args.max_parse_depth.unwrap_or(usize::MAX), ..Default::default() };
+  for file in files {
+    loader.load(Path::new(file), false);
+  }
+  loader.book
+}
+
+#[derive(Default)]
+struct BookLoader {
+  book: Book,
+  /// The file each def was read from, and whether that file was imported.
+  origins: HashMap<String, (PathBuf, bool)>,
+  /// The canonical paths of the files that have been read.
+  loaded: HashSet<PathBuf>,
+  /// The files whose imports are being loaded, along with their canonical
+  /// paths, to detect import cycles.
+  stack: Vec<(PathBuf, PathBuf)>,
+  /// See `--max-parse-depth`.
+  max_depth: usize,
+}
+
+impl BookLoader {
+  fn load(&mut self, path: &Path, imported: bool) {
+    let not_found = || fail(Failure::Io, format_args!("Input file {:?} not found", path));
+    let Ok(canonical) = fs::canonicalize(path) else { not_found() };
+    if let Some(i) = self.stack.iter().position(|(_, c)| *c == canonical) {
+      let cycle = self.stack[i ..].iter().map(|(p, _)| p.as_path()).chain([path]);
+      fail(
+        Failure::Parse,
+        format_args!("Import cycle: {}", cycle.map(|p| p.display().to_string()).collect::<Vec<_>>().join(" -> ")),
+      );
+    }
+    if !self.loaded.insert(canonical.clone()) {
+      return;
+    }
+    let Ok(contents) = fs::read(path) else { not_found() };
+    let contents = decompress_input(path, contents);
+    let mut book = if contents.starts_with(&FlatBook::MAGIC) {
+      FlatBook::from_bytes(&contents)
+        .and_then(|flat| flat.to_book())
+        .unwrap_or_else(|e| fail(Failure::Parse, format_args!("Invalid binary book {}: {e}", path.display())))
+    } else {
+      let contents = String::from_utf8(contents)
+        .unwrap_or_else(|_| fail(Failure::Io, format_args!("Input file {:?} is not valid UTF-8", path)));
+      Book::parse_with_max_depth(&contents, self.max_depth).unwrap_or_else(|e| {
+        fail(Failure::Parse, format_args!("Parsing error in {}: {e}\n{}", path.display(), e.highlight(&contents)))
       })
-    })
-    .fold(Book::default(), |mut acc, i| {
-      acc.nets.extend(i.nets);
-      acc
-    });
+    };
+    self.stack.push((path.to_owned(), canonical));
+    let dir = path.parent().unwrap_or(Path::new(""));
+    for import in &book.imports {
+      self.load(&dir.join(import), true);
+    }
+    self.stack.pop();
+    self.book.labels.extend(book.labels);
+    // the expressions of imported books are only meant for reducing those
+    if !imported {
+      self.book.reduce.extend(book.reduce);
+    }
+    for (name, net) in book.nets {
+      if let Some((origin, origin_imported)) = self.origins.get(&name) {
+        if imported || *origin_imported {
+          fail(
+            Failure::Parse,
+            format_args!("@{} is defined in both {} and {}", DefName(&name), origin.display(), path.display()),
+          );
+        }
+      }
+      // a def redefined by a later file takes its doc comment from there too
+      self.book.docs.remove(&name);
+      if let Some(doc) = book.docs.remove(&name) {
+        self.book.docs.insert(name.clone(), doc);
+      }
+      self.origins.insert(name.clone(), (path.to_owned(), imported));
+      self.book.nets.insert(name, net);
+    }
+  }
+}
 
-  let transform_passes = TransformPasses::from(&transform_args.transform_passes[..]);
-  book.transform(transform_passes, &transform_args.transform_opts).unwrap();
+/// The magic number that starts every gzip stream.
+const GZIP_MAGIC: [u8; 2] = [0x1f, 0x8b];
 
-  book
+/// Decompresses the contents of an input file, if they are gzip-compressed.
+fn decompress_input(path: &Path, contents: Vec<u8>) -> Vec<u8> {
+  if contents.starts_with(&GZIP_MAGIC) { gunzip(path, &contents) } else { contents }
+}
+
+#[cfg(feature = "gzip")]
+fn gunzip(path: &Path, contents: &[u8]) -> Vec<u8> {
+  let mut decompressed = Vec::new();
+  io::Read::read_to_end(&mut flate2::read::MultiGzDecoder::new(contents), &mut decompressed)
+    .unwrap_or_else(|e| fail(Failure::Io, format_args!("Cannot decompress input file {:?}: {e}", path)));
+  decompressed
+}
+
+#[cfg(not(feature = "gzip"))]
+fn gunzip(path: &Path, _: &[u8]) -> Vec<u8> {
+  fail(Failure::Io, format_args!("Input file {:?} is gzip-compressed, which requires the `gzip` feature", path))
 }
 
-fn reduce_exprs(host: Arc<Mutex<Host>>, exprs: &[Net], opts: &RuntimeOpts) {
+/// Fails with every variable of `book` that doesn't appear exactly twice in its
+/// net; see `validate --strict`.
+fn check_linearity(book: &Book) {
+  let nets = book.iter().map(|(name, net)| (format!("@{}", DefName(name)), net));
+  let exprs = book.reduce.iter().enumerate().map(|(i, net)| (format!("#reduce {}", i + 1), net));
+  let errors: Vec<_> = nets
+    .chain(exprs)
+    .filter_map(|(name, net)| net.check_linearity().err().map(|errors| (name, errors)))
+    .flat_map(|(name, errors)| errors.into_iter().map(move |error| format!("{name}: {error}")))
+    .collect();
+  if !errors.is_empty() {
+    fail(Failure::Parse, errors.join("\n"));
+  }
+}
+
+fn transform_book(book: &mut Book, transform_args: &TransformArgs) {
+  if transform_args.fold_on_parse {
+    book.nets.values_mut().for_each(Net::fold_literal_ops);
+  }
+  let transform_passes = TransformPasses::from(&transform_args.transform_passes[..]);
+  if transform_args.print_passes {
+    for pass in transform_passes.enabled() {
+      eprintln!("{pass}");
+    }
+  }
+  book.transform(transform_passes, &transform_args.transform_opts).unwrap_or_else(|e| fail(Failure::Other, e));
+}
+
+/// Reduces each expression in turn, printing its normal form, prefixed with
+/// the corresponding name in `names` if given.
+fn reduce_exprs(host: Arc<Mutex<Host>>, exprs: &[Net], names: Option<&[String]>, opts: &RuntimeOpts) {
   let heap = run::Heap::new(opts.memory).expect("memory allocation failed");
-  for expr in exprs {
-    let mut net = DynNet::new(&heap, opts.lazy_mode);
-    dispatch_dyn_net!(&mut net => {
-      host.lock().encode_net(net, Trg::port(run::Port::new_var(net.root.addr())), expr);
+  let pool = OnceCell::new();
+  if opts.io {
+    for expr in exprs {
+      perform_effects(&host, &heap, Some(&pool), expr.clone(), opts);
+    }
+    return;
+  }
+  for (i, expr) in exprs.iter().enumerate() {
+    let (res, stats) = reduce_expr(&host, &heap, Some(&pool), expr, opts);
+    if let Some(names) = names {
+      print!("@{}: ", DefName(&names[i]));
+    }
+    print_result(&res, &stats.rwts, i + 1 == exprs.len(), opts);
+    if opts.show_stats {
+      print_stats(&stats, opts);
+    }
+    if opts.coverage {
+      print_coverage(&stats.coverage);
+    }
+  }
+}
+
+/// Like `reduce_exprs`, but distributes the expressions among `jobs` threads,
+/// each with its own heap.
+fn reduce_exprs_concurrently(host: Arc<Mutex<Host>>, exprs: &[Net], opts: &RuntimeOpts, jobs: usize) {
+  // the jobs already share the cores, so each reduces sequentially, rather
+  // than spawning a thread per core of its own
+  let opts = &RuntimeOpts { single_core: true, ..opts.clone() };
+  let next = AtomicUsize::new(0);
+  let results: Vec<_> = exprs.iter().map(|_| Mutex::new(None)).collect();
+  thread::scope(|s| {
+    for _ in 0 .. jobs.min(exprs.len()) {
+      s.spawn(|| {
+        let heap = run::Heap::new(opts.memory).expect("memory allocation failed");
+        loop {
+          let i = next.fetch_add(1, Ordering::Relaxed);
+          let Some(expr) = exprs.get(i) else { break };
+          *results[i].lock() = Some(reduce_expr(&host, &heap, None, expr, opts));
+        }
+      });
+    }
+  });
+  for (i, result) in results.into_iter().enumerate() {
+    let (res, stats) = result.into_inner().unwrap();
+    print_result(&res, &stats.rwts, i + 1 == exprs.len(), opts);
+    if opts.show_stats {
+      print_stats(&stats, opts);
+    }
+    if opts.coverage {
+      print_coverage(&stats.coverage);
+    }
+  }
+}
+
+/// Statistics about the reduction of a single expression.
+struct Stats {
+  rwts: run::Rewrites,
+  coverage: run::Coverage,
+  /// The total number of nodes allocated during reduction.
+  allocs: u64,
+  /// The causal depth of the reduction, if it was tracked; see `--verbose`.
+  depth: Option<u64>,
+  /// How the redexes were distributed among threads, if they were tracked.
+  exchange: Option<run::ExchangeStats>,
+  /// How the nodes were allocated, if it was tracked.
+  alloc_stats: Option<run::AllocStats>,
+  elapsed: Duration,
+  /// Whether the reduction switched to lazy mode; see `--lazy-fallback`.
+  degraded: bool,
+}
+
+/// Reduces a single expression on `heap`, returning its normal form along with
+/// statistics about its reduction.
+///
+/// If `pool` is given, parallel reduction runs on its threads, which are
+/// spawned by the first reduction that needs them; this lets the reductions of
+/// a command share threads, rather than spawning new ones for each expression.
+fn reduce_expr(
+  host: &Mutex<Host>,
+  heap: &run::Heap,
+  pool: Option<&OnceCell<run::ThreadPool>>,
+  expr: &Net,
+  opts: &RuntimeOpts,
+) -> (Net, Stats) {
+  if cfg!(feature = "trace") {
+    set_trace_filter(&host.lock(), opts);
+  }
+  let mut expr = Cow::Borrowed(expr);
+  // the stats of the strict reduction, once it has switched to lazy mode
+  let mut degraded: Option<Stats> = None;
+  loop {
+    let mut net = DynNet::new(heap, opts.lazy_mode || degraded.is_some());
+    return dispatch_dyn_net!(&mut net => {
+      net.skip_pairs = !opts.no_skip;
+      net.linear = opts.linear;
+      net.memory_limit = opts.lazy_fallback.map(|percent| percent as f64 / 100.0);
+      net.depth = opts.verbose.then(Default::default);
+      net.exchange = opts.verbose.then(Default::default);
+      net.pin_threads = opts.pin_threads;
+      net.ext_ops = host.lock().ext_ops().clone();
+      let refs = opts.readback.contains(&ReadbackOpt::Refs);
+      if refs {
+        net.opaque_refs = Some(Arc::new(host.lock().back.keys().copied().collect()));
+      }
+      host.lock().encode_net(net, Trg::port(run::Port::new_var(net.root.addr())), &expr);
+      // with `--readback refs`, an expression that is just a ref (such as
+      // `@main`) would otherwise be left as-is
+      if (opts.count_initial_redexes || refs) && degraded.is_none() {
+        let root = net.root.load_target();
+        if root.tag() == run::Tag::Ref && root != run::Port::ERA {
+          net.call(root, run::Port::new_var(net.root.addr()));
+        }
+      }
+      if opts.count_initial_redexes && degraded.is_none() {
+        eprintln!("REDEXES: {:>15}", net.redexes.len());
+      }
       let start_time = Instant::now();
-      if opts.single_core {
+      let start_allocs = net.allocs;
+      net.alloc_stats = opts.verbose.then(Default::default);
+      // an expression with no redexes or refs to expand is already in normal
+      // form, as is common when just parsing and printing values
+      let normal = degraded.is_none()
+        && !opts.lazy_mode
+        && net.redexes.is_empty()
+        && net.root.load_target().is_principal()
+        && !expr.trees().any(has_refs);
+      let mut steps = 0;
+      if normal {
+        // there is nothing to reduce
+      } else if let Some(limit) = opts.steps {
+        net.expand();
+        let (Ok(count) | Err(count)) = net.reduce_counted(limit);
+        steps = count;
+      } else if opts.explain {
+        // the host is only locked for each event, as the stdlib defs called
+        // during the reduction lock it too
+        net.normal_explained(|rule, a, b| {
+          let host = host.lock();
+          eprintln!("{rule:<10} {} ~ {}", host.readback_agent(a), host.readback_agent(b))
+        });
+      } else if opts.record_log.is_some() || opts.replay_log.is_some() {
+        let mut log = EVENT_LOG.lock();
+        let log = log.get_or_insert_with(|| EventLog::open(opts));
+        let mut events = log.start();
+        net.normal_explained(|rule, a, b| {
+          let host = host.lock();
+          let agent = |port: &run::Port| match heap.node_index(port.addr()) {
+            Some(index) if port.is_full_node() => index.to_string(),
+            _ => host.readback_agent(port).to_string(),
+          };
+          events.push(format!("{rule} {} {}", agent(a), agent(b)));
+        });
+        log.finish(events);
+      } else if let (Some(interval), None) = (opts.checkpoint_every, &degraded) {
+        let mut seq = 0;
+        net.normal_with_checkpoints(interval, |net| {
+          seq += 1;
+          write_checkpoint(&host.lock().readback(net), seq, opts);
+        });
+      } else if opts.single_core || degraded.is_some() {
         net.normal();
+      } else if let Some(pool) = pool {
+        net.parallel_normal_on(pool.get_or_init(run::ThreadPool::with_available_parallelism));
       } else {
         net.parallel_normal();
       }
       let elapsed = start_time.elapsed();
-      println!("{}", host.lock().readback(net));
-      if opts.show_stats {
-        print_stats(net, elapsed);
+      if opts.steps.is_some() {
+        eprintln!("STEPS  : {:>15}", pretty_num(steps as u64));
+      }
+      let mut stats = Stats {
+        rwts: net.rwts,
+        coverage: net.coverage,
+        allocs: net.allocs - start_allocs,
+        depth: net.depth.as_ref().map(|depth| depth.max),
+        exchange: net.exchange.take(),
+        alloc_stats: net.alloc_stats.take(),
+        elapsed,
+        degraded: false,
+      };
+      if net.memory_exceeded {
+        expr = Cow::Owned(host.lock().readback(net));
+        degraded = Some(stats);
+        continue;
+      }
+      if let Some(strict) = degraded {
+        stats.rwts += strict.rwts;
+        stats.coverage += &strict.coverage;
+        stats.allocs += strict.allocs;
+        // the lazy reduction continues from where the strict one stopped
+        stats.depth = stats.depth.zip(strict.depth).map(|(lazy, strict)| lazy + strict);
+        // only the strict reduction is parallel
+        stats.exchange = strict.exchange;
+        if let (Some(stats), Some(strict)) = (&mut stats.alloc_stats, &strict.alloc_stats) {
+          *stats += strict;
+        }
+        stats.elapsed += strict.elapsed;
+        stats.degraded = true;
+      }
+      if opts.verify {
+        eprintln!("LEAKS  : {:>15}", net.count_leaks());
+      }
+      if opts.dump_heap {
+        let mut dump = String::new();
+        net.dump_heap(&mut dump).unwrap();
+        eprint!("{dump}");
       }
+      if let Some(violation) = net.violation {
+        fail(Failure::Reduce, format_args!("linearity violation: {violation}"));
+      }
+      if let Some(error) = net.op_error {
+        fail(Failure::Reduce, format_args!("failed operation: {error}"));
+      }
+      let mut res = host.lock().readback(net);
+      if opts.readback.contains(&ReadbackOpt::Numerals) {
+        res.fold_numerals();
+      }
+      if opts.normalize_output {
+        res.canonicalize();
+      }
+      (res, stats)
     });
   }
 }
 
-fn print_stats<M: Mode>(net: &run::Net<M>, elapsed: Duration) {
-  eprintln!("RWTS   : {:>15}", pretty_num(net.rwts.total()));
-  eprintln!("- ANNI : {:>15}", pretty_num(net.rwts.anni));
-  eprintln!("- COMM : {:>15}", pretty_num(net.rwts.comm));
-  eprintln!("- ERAS : {:>15}", pretty_num(net.rwts.eras));
-  eprintln!("- DREF : {:>15}", pretty_num(net.rwts.dref));
-  eprintln!("- OPER : {:>15}", pretty_num(net.rwts.oper));
+/// Whether `tree` contains a ref, which reduction would expand.
+fn has_refs(tree: &Tree) -> bool {
+  let mut visit = vec![tree];
+  while let Some(tree) = visit.pop() {
+    if let Tree::Ref { .. } = tree {
+      return true;
+    }
+    visit.extend(tree.children());
+  }
+  false
+}
+
+/// Reduces `expr`, interpreting its normal form as an [`Effect`] and performing
+/// it, until it exits. Exits the process if the exit code is not zero.
+fn perform_effects(
+  host: &Mutex<Host>,
+  heap: &run::Heap,
+  pool: Option<&OnceCell<run::ThreadPool>>,
+  mut expr: Net,
+  opts: &RuntimeOpts,
+) {
+  loop {
+    let (res, stats) = reduce_expr(host, heap, pool, &expr, opts);
+    if opts.show_stats {
+      print_stats(&stats, opts);
+    }
+    let Some(effect) = Effect::from_tree(res.root.clone()) else {
+      fail(Failure::Reduce, format_args!("Normal form is not an effect: {res}"));
+    };
+    expr = match effect {
+      Effect::Exit(0) => return,
+      // exit statuses are truncated to a byte on unix, which could turn a
+      // failure into a success
+      Effect::Exit(code @ 1 ..= 255) => process::exit(code as i32),
+      Effect::Exit(code) => fail(Failure::Reduce, format_args!("Exit code {code} is not between 0 and 255")),
+      Effect::Print(value, next) => {
+        println!("{value}");
+        Net { root: next, redexes: vec![] }
+      }
+      Effect::Read(cont) => {
+        let mut line = String::new();
+        io::stdin().read_line(&mut line).unwrap_or_else(|e| fail(Failure::Io, format_args!("Cannot read input: {e}")));
+        let input = Tree::from_str(line.trim())
+          .unwrap_or_else(|e| fail(Failure::Parse, format_args!("Invalid input {:?}: {e}", line.trim())));
+        let mut net = Net { root: cont, redexes: vec![] };
+        net.apply_tree(input);
+        net
+      }
+    };
+  }
+}
+
+/// Writes the `seq`th checkpoint of a reduction; see `--checkpoint-every`.
+///
+/// The checkpoint is written to a temporary file first, and then renamed, so
+/// that a crash while writing it never leaves a truncated checkpoint behind.
+fn write_checkpoint(net: &Net, seq: usize, opts: &RuntimeOpts) {
+  let dir = opts.checkpoint_path.as_deref().unwrap();
+  let name = if opts.checkpoint_keep { format!("checkpoint-{seq}.hvmc") } else { "checkpoint.hvmc".to_owned() };
+  let path = dir.join(name);
+  let tmp = dir.join("checkpoint.hvmc.tmp");
+  fs::create_dir_all(dir)
+    .and_then(|_| fs::write(&tmp, format!("{net}\n")))
+    .and_then(|_| fs::rename(&tmp, &path))
+    .unwrap_or_else(|e| fail(Failure::Io, format_args!("Cannot write checkpoint {}: {e}", path.display())));
+}
+
+/// The log of `--record-log` or `--replay-log`, opened by the first reduction
+/// and shared by the following ones, each of which records or replays the next
+/// expression in the log.
+static EVENT_LOG: Mutex<Option<EventLog>> = Mutex::new(None);
+
+enum EventLog {
+  Record(PathBuf, fs::File),
+  /// The interactions of each expression, and the index of the next one.
+  Replay(Vec<Vec<String>>, usize),
+}
+
+impl EventLog {
+  fn open(opts: &RuntimeOpts) -> Self {
+    if let Some(path) = &opts.record_log {
+      let file = fs::File::create(path)
+        .unwrap_or_else(|e| fail(Failure::Io, format_args!("Cannot write log {}: {e}", path.display())));
+      EventLog::Record(path.clone(), file)
+    } else {
+      let path = opts.replay_log.as_ref().unwrap();
+      let log = fs::read_to_string(path)
+        .unwrap_or_else(|e| fail(Failure::Io, format_args!("Cannot read log {}: {e}", path.display())));
+      let mut exprs = vec![vec![]];
+      for line in log.lines() {
+        if line.is_empty() {
+          exprs.push(vec![]);
+        } else {
+          exprs.last_mut().unwrap().push(line.to_owned());
+        }
+      }
+      EventLog::Replay(exprs, 0)
+    }
+  }
+
+  /// Starts recording the interactions of the next expression; they are
+  /// pushed to the returned buffer, which, when replaying, checks each of them
+  /// against the log as it is pushed.
+  fn start(&mut self) -> EventBuffer {
+    match self {
+      EventLog::Record(..) => EventBuffer { events: vec![], expected: None },
+      EventLog::Replay(exprs, next) => {
+        *next += 1;
+        EventBuffer { events: vec![], expected: Some(exprs.get_mut(*next - 1).map(mem::take).unwrap_or_default()) }
+      }
+    }
+  }
+
+  /// Writes the interactions of an expression to the log, or checks that none
+  /// of the ones in the log are missing.
+  fn finish(&mut self, buffer: EventBuffer) {
+    let events = &buffer.events;
+    match self {
+      EventLog::Record(path, file) => {
+        let mut text = events.join("\n");
+        text.push_str(if events.is_empty() { "\n" } else { "\n\n" });
+        io::Write::write_all(file, text.as_bytes())
+          .unwrap_or_else(|e| fail(Failure::Io, format_args!("Cannot write log {}: {e}", path.display())));
+      }
+      EventLog::Replay(..) => {
+        let expected = buffer.expected.map_or(0, |expected| expected.len());
+        if events.len() < expected {
+          fail(
+            Failure::Reduce,
+            format_args!(
+              "replay diverged: the reduction finished after {} interactions, but the log has {expected}",
+              events.len()
+            ),
+          );
+        }
+      }
+    }
+  }
+}
+
+/// The interactions of an expression being recorded or replayed; see
+/// [`EventLog::start`].
+struct EventBuffer {
+  events: Vec<String>,
+  expected: Option<Vec<String>>,
+}
+
+impl EventBuffer {
+  fn push(&mut self, event: String) {
+    if let Some(expected) = &self.expected {
+      let index = self.events.len();
+      match expected.get(index) {
+        Some(expected) if *expected == event => {}
+        Some(expected) => fail(
+          Failure::Reduce,
+          format_args!("replay diverged at interaction {}: expected `{expected}`, got `{event}`", index + 1),
+        ),
+        None => fail(
+          Failure::Reduce,
+          format_args!("replay diverged at interaction {}: the log has no more interactions, got `{event}`", index + 1),
+        ),
+      }
+    }
+    self.events.push(event);
+  }
+}
+
+/// Whether diagnostics are suppressed; see `--quiet`.
+static QUIET: AtomicBool = AtomicBool::new(false);
+
+/// The class of a fatal error, which determines the exit code of the process.
+#[derive(Clone, Copy, Debug)]
+enum Failure {
+  /// Any other failure, such as an invalid combination of arguments.
+  Other = 1,
+  /// A program, expression, or input could not be parsed.
+  Parse = 2,
+  /// An input file could not be read.
+  Io = 3,
+  /// Reduction failed, or produced an unexpected normal form.
+  Reduce = 4,
+  /// The programs compared by `diff` differ. This is not reported as an error,
+  /// but it is distinguished from the other classes so that `diff` can be used
+  /// in scripts.
+  Differ = 5,
+  /// The executable generated by `compile` could not be built.
+  Build = 6,
+}
+
+/// Reports a fatal error (unless `--quiet` was passed), and exits with the
+/// exit code of its class.
+fn fail(class: Failure, msg: impl fmt::Display) -> ! {
+  if !QUIET.load(Ordering::Relaxed) {
+    eprintln!("{msg}");
+  }
+  process::exit(class as i32)
+}
+
+/// Parses a net passed on the command line.
+fn parse_arg(arg: &str) -> Net {
+  Net::from_str(arg).unwrap_or_else(|e| fail(Failure::Parse, format_args!("Invalid argument {arg:?}: {e}")))
+}
+
+/// Restricts tracing to the defs given by `--trace-defs` or `HVMC_TRACE_DEFS`.
+fn set_trace_filter(host: &Host, opts: &RuntimeOpts) {
+  let names = match std::env::var("HVMC_TRACE_DEFS") {
+    _ if !opts.trace_defs.is_empty() => opts.trace_defs.clone(),
+    Ok(var) if !var.is_empty() => var.split(',').map(|x| x.trim().to_owned()).collect(),
+    _ => return trace::set_def_filter(None),
+  };
+  let defs = names.iter().map(|name| match host.defs.get(name) {
+    Some(def) => run::Port::new_ref(def).addr(),
+    None => fail(Failure::Other, format_args!("Cannot trace unknown definition {name:?}")),
+  });
+  trace::set_def_filter(Some(defs.collect()));
+}
+
+/// Prints a normal form, followed by the result separator unless it is the
+/// last one and no trailing separator was requested.
+fn print_result(res: &Net, rwts: &run::Rewrites, last: bool, opts: &RuntimeOpts) {
+  let separator = opts.result_separator.as_deref().unwrap_or("\n");
+  let mut aliases = LabelAliases::default();
+  aliases.num_format(opts.num_format).sections(opts.sections);
+  if opts.readback_compact {
+    print!("{:#}", res.display_with(&aliases));
+  } else {
+    print!("{}", res.display_with(&aliases));
+  }
+  if opts.stats_per_expr {
+    print!("\t{}", rwts.total());
+  }
+  if !last || opts.trailing_separator || opts.result_separator.is_none() {
+    print!("{separator}");
+  }
+  io::Write::flush(&mut io::stdout()).unwrap();
+}
+
+fn print_stats(stats: &Stats, opts: &RuntimeOpts) {
+  let Stats { rwts, allocs, depth, exchange, alloc_stats, elapsed, degraded, .. } = stats;
+  eprintln!("RWTS   : {:>15}", pretty_num(rwts.total()));
+  eprintln!("- ANNI : {:>15}", pretty_num(rwts.anni));
+  eprintln!("- COMM : {:>15}", pretty_num(rwts.comm));
+  eprintln!("- ERAS : {:>15}", pretty_num(rwts.eras));
+  eprintln!("- DREF : {:>15}", pretty_num(rwts.dref));
+  eprintln!("- OPER : {:>15}", pretty_num(rwts.oper));
+  if opts.verbose {
+    eprintln!("ALLOCS : {:>15}", pretty_num(*allocs));
+    if let Some(alloc_stats) = alloc_stats {
+      eprintln!("- FREE : {:>15}", pretty_num(alloc_stats.hits));
+      eprintln!("- BUMP : {:>15}", pretty_num(alloc_stats.fallbacks));
+      eprintln!("HITRATE: {:>15.3}", alloc_stats.hit_rate());
+      eprintln!("MAXFREE: {:>15}", pretty_num(alloc_stats.max_free as u64));
+    }
+    if let Some(depth) = depth {
+      eprintln!("DEPTH  : {:>15}", pretty_num(*depth));
+    }
+    if let Some(exchange) = exchange.as_ref().filter(|exchange| exchange.rounds != 0) {
+      let [min, mean, max] = exchange.bags();
+      eprintln!("XCHG   : {:>15}", pretty_num(exchange.exchanges));
+      eprintln!("- FULL : {:>15}", pretty_num(exchange.full));
+      eprintln!("- EMPTY: {:>15}", pretty_num(exchange.empty));
+      eprintln!("BAGS   : {:>15}", format!("{min:.1}/{mean:.1}/{max:.1}"));
+      eprintln!("IMBAL  : {:>15.3}", exchange.imbalance());
+    }
+  }
+  if *degraded {
+    eprintln!("MODE   : {:>15}", "strict -> lazy");
+  }
   eprintln!("TIME   : {:.3?}", elapsed);
-  eprintln!("RPS    : {:.3} M", (net.rwts.total() as f64) / (elapsed.as_millis() as f64) / 1000.0);
+  eprintln!("RPS    : {:.3} M", (rwts.total() as f64) / (elapsed.as_millis() as f64) / 1000.0);
+}
+
+fn print_coverage(coverage: &run::Coverage) {
+  let covered = run::Interaction::ALL.len() - coverage.missing().count();
+  eprintln!("COVER  : {:>15}", format!("{covered}/{}", run::Interaction::ALL.len()));
+  for interaction in run::Interaction::ALL {
+    let count = coverage.count(interaction);
+    eprintln!("- {interaction} : {:>15}{}", pretty_num(count), if count == 0 { " (missing)" } else { "" });
+  }
+}
+
+fn print_compile_report(report: &compile::CompileReport) {
+  let width = report.defs.keys().map(|name| DefName(name).to_string().len() + 1).max().unwrap_or(0).max(3);
+  eprintln!("{:<width$}  {:>7}  {:>7}  {:>7}", "DEF", "LINES", "ALLOCS", "REFS");
+  for (name, def) in &report.defs {
+    let name = format!("@{}", DefName(name));
+    eprintln!("{name:<width$}  {:>7}  {:>7}  {:>7}", def.lines, def.allocs, def.refs);
+  }
+  eprintln!("DEFS   : {:>15}", report.defs.len());
+  eprintln!("LINES  : {:>15}", report.lines);
+  if !report.inlined.is_empty() {
+    let inlined: Vec<_> = report.inlined.iter().map(|name| format!("@{}", DefName(name))).collect();
+    eprintln!("INLINED: {}", inlined.join(", "));
+  }
 }
 
 fn pretty_num(n: u64) -> String {
@@ -276,25 +1542,43 @@ fn pretty_num(n: u64) -> String {
     .collect()
 }
 
-fn compile_executable(target: &str, host: Arc<Mutex<host::Host>>) -> Result<(), io::Error> {
-  let gen = compile::compile_host(&host.lock());
-  let outdir = ".hvm";
-  if Path::new(&outdir).exists() {
+/// The default build directory of `compile`.
+const DEFAULT_BUILD_DIR: &str = ".hvm";
+
+/// A file marking a directory as a build directory created by
+/// [`compile_executable`], which may thus be deleted by it.
+const BUILD_DIR_MARKER: &str = ".hvmc-build";
+
+fn compile_executable(
+  target: &str,
+  host: Arc<Mutex<host::Host>>,
+  bake_args: &[String],
+  outdir: &Path,
+  profile: Profile,
+  dry_run: bool,
+) -> Result<(), io::Error> {
+  let gen = compile::compile_host_with_args(&host.lock(), bake_args);
+  if outdir.join(BUILD_DIR_MARKER).exists() {
     fs::remove_dir_all(outdir)?;
+  } else if outdir.exists() && outdir.read_dir()?.next().is_some() {
+    return Err(io::Error::new(
+      io::ErrorKind::AlreadyExists,
+      format!("Build directory {outdir:?} is not empty, and was not created by `hvmc compile`"),
+    ));
   }
   let cargo_toml = include_str!("../Cargo.toml");
   let mut cargo_toml = cargo_toml.split_once("##--COMPILER-CUTOFF--##").unwrap().0.to_owned();
-  cargo_toml.push_str("[features]\ndefault = ['cli']\ncli = ['std', 'dep:clap']\nstd = []");
+  cargo_toml.push_str("default = ['cli']\ncli = ['std', 'dep:clap']\n");
 
   macro_rules! include_files {
     ($([$($prefix:ident)*])? $mod:ident {$($sub:tt)*} $($rest:tt)*) => {
-      fs::create_dir_all(concat!(".hvm/src/", $($(stringify!($prefix), "/",)*)? stringify!($mod)))?;
+      fs::create_dir_all(outdir.join(concat!("src/", $($(stringify!($prefix), "/",)*)? stringify!($mod))))?;
       include_files!([$($($prefix)* $mod)?] $($sub)*);
       include_files!([$($($prefix)*)?] $mod $($rest)*);
     };
     ($([$($prefix:ident)*])? $file:ident $($rest:tt)*) => {
       fs::write(
-        concat!(".hvm/src/", $($(stringify!($prefix), "/",)*)* stringify!($file), ".rs"),
+        outdir.join(concat!("src/", $($(stringify!($prefix), "/",)*)* stringify!($file), ".rs")),
         include_str!(concat!($($(stringify!($prefix), "/",)*)* stringify!($file), ".rs")),
       )?;
       include_files!([$($($prefix)*)?] $($rest)*);
@@ -302,17 +1586,20 @@ fn compile_executable(target: &str, host: Arc<Mutex<host::Host>>) -> Result<(),
     ($([$($prefix:ident)*])?) => {};
   }
 
-  fs::create_dir_all(".hvm/src")?;
-  fs::write(".hvm/Cargo.toml", cargo_toml)?;
-  fs::write(".hvm/src/gen.rs", gen)?;
+  fs::create_dir_all(outdir.join("src"))?;
+  fs::write(outdir.join(BUILD_DIR_MARKER), "")?;
+  fs::write(outdir.join("Cargo.toml"), cargo_toml)?;
+  fs::write(outdir.join("src/gen.rs"), gen)?;
 
   include_files! {
     ast
     compile
+    flat
     fuzz
     host {
       calc_labels
       encode
+      owned
       readback
     }
     lib
@@ -333,18 +1620,22 @@ fn compile_executable(target: &str, host: Arc<Mutex<host::Host>>) -> Result<(),
       net
       node
       parallel
+      pool
       port
       wire
     }
     stdlib
     trace
     transform {
+      cache
       coalesce_ctrs
+      elide_apps
       encode_adts
       eta_reduce
       inline
       pre_reduce
       prune
+      rewrite
     }
     util {
       apply_tree
@@ -352,23 +1643,33 @@ fn compile_executable(target: &str, host: Arc<Mutex<host::Host>>) -> Result<(),
       bi_enum
       create_var
       deref
+      diff
       maybe_grow
       parse_abbrev_number
       stats
     }
   }
 
-  let output = process::Command::new("cargo")
-    .current_dir(".hvm")
-    .arg("build")
-    .arg("--release")
-    .stderr(Stdio::inherit())
-    .output()?;
+  if dry_run {
+    println!("{}", outdir.join("src/gen.rs").display());
+    return Ok(());
+  }
+
+  let mut cargo = process::Command::new("cargo");
+  cargo.current_dir(outdir).arg("build");
+  if profile == Profile::Release {
+    cargo.arg("--release");
+  }
+  let output = cargo.stderr(Stdio::inherit()).output()?;
   if !output.status.success() {
-    process::exit(1);
+    fail(Failure::Build, format_args!("Failed to build the executable in {outdir:?}"));
   }
 
-  fs::copy(".hvm/target/release/hvmc", target)?;
+  let profile_dir = match profile {
+    Profile::Debug => "debug",
+    Profile::Release => "release",
+  };
+  fs::copy(outdir.join("target").join(profile_dir).join("hvmc"), target)?;
 
   Ok(())
 }
@@ -7,6 +7,7 @@ use self::{
   num::Numeric,
   word::{FromWord, ToWord},
 };
+use alloc::sync::Arc;
 use core::{
   cmp::{Eq, Ord},
   str::FromStr,
@@ -27,57 +28,293 @@ bi_enum! {
     "i8":  I8  = 4,
     "i16": I16 = 5,
     "i32": I32 = 6,
+    /// IEEE 754 single-precision floats.
+    ///
+    /// Operations round to nearest, ties to even, and subnormal results are
+    /// kept rather than flushed to zero, as Rust guarantees on every platform.
+    /// The sign and payload of a NaN, however, depend on the platform (`0.0 /
+    /// 0.0` is negative on x86, but not on ARM), so every NaN result is
+    /// canonicalized to [`f32::NAN`] (with bits `0x7fc00000`). Thus, the
+    /// results of float operations are the same on every platform.
     "f32": F32 = 7,
   }
 }
 
 impl Ty {
   #[inline(always)]
-  fn is_int(&self) -> bool {
+  pub fn is_int(&self) -> bool {
     *self < Self::F32
   }
+
+  /// Whether `val` is representable in this type; always true for `f32`.
+  pub fn contains(self, val: i64) -> bool {
+    match self {
+      Ty::U8 => u8::try_from(val).is_ok(),
+      Ty::U16 => u16::try_from(val).is_ok(),
+      Ty::U32 => u32::try_from(val).is_ok(),
+      Ty::U60 => (0 .. 1 << 60).contains(&val),
+      Ty::I8 => i8::try_from(val).is_ok(),
+      Ty::I16 => i16::try_from(val).is_ok(),
+      Ty::I32 => i32::try_from(val).is_ok(),
+      Ty::F32 => true,
+    }
+  }
 }
 
 bi_enum! {
   #[repr(u8)]
-  /// Native operations on numerics (u8, u16, u32, u60, i8, i16, i32, f32).
+  /// What an arithmetic operation does when its result doesn't fit in its
+  /// [`Ty`]; written as a suffix of the operator, e.g. `<+s a b>` or
+  /// `<i32.*c a b>`.
   ///
-  /// Each operation has a swapped counterpart (accessible with `.swap()`),
-  /// where the order of the operands is swapped.
+  /// This only affects `+`, `-`, `*`, and `pow` (along with their swapped
+  /// counterparts), except that checked division and remainder (including
+  /// `/hi` and `%hi`) also trap when dividing by zero, checked `/hi` traps
+  /// when the quotient doesn't fit, checked shifts trap when shifting by at
+  /// least the width of the type (60 bits for `u60`), and checked `pow` and
+  /// `sqrt` trap on negative operands. It has no effect on floats or
+  /// [`Op::Ext`] operations.
+  #[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+  pub enum Overflow {
+    /// The result wraps around, keeping its low bits; this is the default, and
+    /// may be written explicitly with a `.` suffix, e.g. `<+. a b>`.
+    #[default]
+    "": Wrapping = 0,
+    /// The result is clamped to the range of the type.
+    "s": Saturating = 1,
+    /// Reduction traps, stopping with an [`OpError::Overflow`].
+    "c": Checked = 2,
+  }
+}
+
+/// Native operations on numerics (u8, u16, u32, u60, i8, i16, i32, f32).
+///
+/// Each operation has a swapped counterpart (accessible with `.swap()`),
+/// where the order of the operands is swapped.
+///
+/// Operations without an already-named counterpart (e.g. `Add <-> Add` and
+/// `Lt <-> Gt`) are suffixed with `$`/`S`: `(-$ 1 2) = (- 2 1) = 1`.
+///
+/// `MulHi` returns the high half of the full product of its operands, i.e.
+/// the bits that `Mul` discards: `u8.*hi #200 #3` is `2`, as `200 * 3` is
+/// `0x258`. Along with `Mul`, it gives the full-width product of two ints.
+///
+/// `DivHi` and `RemHi` divide the full-width int whose high half is their first
+/// operand, and whose low half is `0`, by their second operand, returning the
+/// quotient and the remainder: `u8./hi #2 #3` is `170` and `u8.%hi #2 #3` is
+/// `2`, as `0x200` is `170 * 3 + 2`. The quotient only fits in the type when
+/// the first operand is smaller than the second, as with the remainders of a
+/// long division. Like `Div` and `Rem`, they return `0` when dividing by zero.
+///
+/// Comparison operations (`Eq` to `Ge`) truncate both operands to `Ty`
+/// before comparing them, like every other operation; e.g. `u32.==` treats
+/// `0x1_0000_0000` as `0`. Their result is always the integer `0` or `1`,
+/// whatever the `Ty`.
+///
+/// `Pow` raises its first operand to the power of its second, and `Sqrt`
+/// returns the integer square root of its first operand (rounded down): `<sqrt
+/// * x>` applied to `#144` is `#12`. `Sqrt` is unary (see [`Op::is_unary`]):
+/// its second operand is ignored, and erased without waiting for it, so it is
+/// its own swapped counterpart. A negative exponent, or the square root of a
+/// negative number, is `0`. Like the bitwise operations, they are only
+/// defined on ints, and return `0` on floats.
+///
+/// `Ext` operations, written `ext:n`, call the function registered for the
+/// label `n` in the net's [`ExtOps`] (see [`Host::register_op`]). They are only
+/// defined on ints; typed ones can't be `f32`.
+///
+/// [`Host::register_op`]: crate::host::Host::register_op
+#[repr(u8)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Op {
+  Add = 0,
+  Sub = 1,
+  SubS = 2,
+  Mul = 3,
+  Div = 4,
+  DivS = 5,
+  Rem = 6,
+  RemS = 7,
+  And = 8,
+  Or = 9,
+  Xor = 10,
+  Shl = 11,
+  ShlS = 12,
+  Shr = 13,
+  ShrS = 14,
+  // operators returning ints should go between `Eq` and `Ge`
+  Eq = 15,
+  Ne = 16,
+  Lt = 17,
+  Gt = 18,
+  Le = 19,
+  Ge = 20,
+  MulHi = 21,
+  Pow = 22,
+  PowS = 23,
+  Sqrt = 24,
+  DivHi = 25,
+  DivHiS = 26,
+  RemHi = 27,
+  RemHiS = 28,
+  // the label of an `Ext` is stored in the low bits of its encoding
+  Ext(u8) = EXT,
+  ExtS(u8) = EXT | EXT_SWAPPED,
+}
+
+/// The native operations, by encoding, along with their symbols.
+const NATIVE_OPS: [(Op, &str); 29] = [
+  (Op::Add, "+"),
+  (Op::Sub, "-"),
+  (Op::SubS, "-$"),
+  (Op::Mul, "*"),
+  (Op::Div, "/"),
+  (Op::DivS, "/$"),
+  (Op::Rem, "%"),
+  (Op::RemS, "%$"),
+  (Op::And, "&"),
+  (Op::Or, "|"),
+  (Op::Xor, "^"),
+  (Op::Shl, "<<"),
+  (Op::ShlS, "<<$"),
+  (Op::Shr, ">>"),
+  (Op::ShrS, ">>$"),
+  (Op::Eq, "=="),
+  (Op::Ne, "!="),
+  (Op::Lt, "<"),
+  (Op::Gt, ">"),
+  (Op::Le, "<="),
+  (Op::Ge, ">="),
+  (Op::MulHi, "*hi"),
+  (Op::Pow, "pow"),
+  (Op::PowS, "pow$"),
+  (Op::Sqrt, "sqrt"),
+  (Op::DivHi, "/hi"),
+  (Op::DivHiS, "/hi$"),
+  (Op::RemHi, "%hi"),
+  (Op::RemHiS, "%hi$"),
+];
+
+/// The number of labels available to [`Op::Ext`] operations.
+pub const EXT_OPS: u8 = 64;
+const EXT: u8 = 0x80;
+const EXT_SWAPPED: u8 = 0x40;
+
+/// A function implementing an [`Op::Ext`] operation on ints.
+pub type ExtOpFn = dyn Fn(i64, i64) -> i64 + Send + Sync;
+
+/// The functions implementing the [`Op::Ext`] operations of a net, indexed by
+/// label (see [`Host::register_op`]).
+///
+/// [`Host::register_op`]: crate::host::Host::register_op
+#[derive(Clone)]
+pub struct ExtOps([Option<Arc<ExtOpFn>>; EXT_OPS as usize]);
+
+impl ExtOps {
+  /// Creates a table with no registered functions.
+  pub const fn new() -> Self {
+    ExtOps([const { None }; EXT_OPS as usize])
+  }
+
+  /// Sets the function implementing `ext:label`, replacing any previous one.
   ///
-  /// Operations without an already-named counterpart (e.g. `Add <-> Add` and
-  /// `Lt <-> Gt`) are suffixed with `$`/`S`: `(-$ 1 2) = (- 2 1) = 1`.
-  #[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
-  pub enum Op {
-    "+":   Add  = 0,
-    "-":   Sub  = 1,
-    "-$":  SubS = 2,
-    "*":   Mul  = 3,
-    "/":   Div  = 4,
-    "/$":  DivS = 5,
-    "%":   Rem  = 6,
-    "%$":  RemS = 7,
-    "&":   And  = 8,
-    "|":   Or   = 9,
-    "^":   Xor  = 10,
-    "<<":  Shl  = 11,
-    "<<$": ShlS = 12,
-    ">>":  Shr  = 13,
-    ">>$": ShrS = 14,
-    // operators returning ints should go after `Eq`
-    "==":  Eq   = 15,
-    "!=":  Ne   = 16,
-    "<":   Lt   = 17,
-    ">":   Gt   = 18,
-    "<=":  Le   = 19,
-    ">=":  Ge   = 20,
+  /// # Panics
+  ///
+  /// Panics if `label` is not below [`EXT_OPS`].
+  pub fn register(&mut self, label: u8, f: Arc<ExtOpFn>) {
+    assert!(label < EXT_OPS, "ext op labels must be below {EXT_OPS}");
+    self.0[label as usize] = Some(f);
+  }
+
+  /// Calls the function implementing `ext:label` on ints of type `T`, passing
+  /// it their values, and truncating its result to a `T`. Untyped ints (which
+  /// are handled as `u64`s) are sign-extended from 60 bits, like int literals.
+  fn call<T: FromWord + ToWord>(&self, label: u8, a: u64, b: u64) -> Result<u64, OpError> {
+    let int = |x: u64| ((T::from_word(x).to_word() << 4) as i64) >> 4;
+    let f = self.0[label as usize].as_ref().ok_or(OpError::Unregistered(label))?;
+    Ok(T::from_word(f(int(a), int(b)) as u64).to_word())
+  }
+}
+
+impl Default for ExtOps {
+  fn default() -> Self {
+    Self::new()
+  }
+}
+
+/// An operation that can't be performed, which stops reduction (see
+/// [`Linker::op_error`]).
+///
+/// [`Linker::op_error`]: crate::run::Linker::op_error
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+#[cfg_attr(feature = "std", derive(Error))]
+pub enum OpError {
+  /// An `ext` operation with no function in the net's [`ExtOps`].
+  #[cfg_attr(feature = "std", error("no function is registered for `ext:{0}`"))]
+  Unregistered(u8),
+  /// A checked operation (see [`Overflow::Checked`]) trapped.
+  #[cfg_attr(feature = "std", error("arithmetic overflow in checked operation `{0}c`"))]
+  Overflow(Op),
+}
+
+impl TryFrom<u8> for Op {
+  type Error = ();
+
+  fn try_from(value: u8) -> Result<Self, Self::Error> {
+    let label = value & (EXT_OPS - 1);
+    match value & (EXT | EXT_SWAPPED) {
+      EXT => Ok(Op::Ext(label)),
+      x if x == EXT | EXT_SWAPPED => Ok(Op::ExtS(label)),
+      _ => NATIVE_OPS.get(value as usize).map(|&(op, _)| op).ok_or(()),
+    }
+  }
+}
+
+impl From<Op> for u8 {
+  fn from(op: Op) -> Self {
+    // SAFETY: `Op` is `repr(u8)`, so its discriminant is its first byte
+    let discriminant = unsafe { *(&op as *const Op as *const u8) };
+    match op {
+      Op::Ext(label) | Op::ExtS(label) => discriminant | label,
+      _ => discriminant,
+    }
+  }
+}
+
+impl fmt::Display for Op {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self {
+      Op::Ext(label) => write!(f, "ext:{label}"),
+      Op::ExtS(label) => write!(f, "ext:{label}$"),
+      op => f.write_str(NATIVE_OPS.iter().find(|(x, _)| x == op).unwrap().1),
+    }
+  }
+}
+
+impl FromStr for Op {
+  type Err = ();
+
+  fn from_str(s: &str) -> Result<Self, Self::Err> {
+    if let Some(label) = s.strip_prefix("ext:") {
+      let (label, swapped) = label.strip_suffix('$').map_or((label, false), |label| (label, true));
+      let label = label.parse::<u8>().ok().filter(|&label| label < EXT_OPS).ok_or(())?;
+      return Ok(if swapped { Op::ExtS(label) } else { Op::Ext(label) });
+    }
+    NATIVE_OPS.iter().find(|(_, sym)| *sym == s).map(|&(op, _)| op).ok_or(())
   }
 }
 
 impl Op {
+  /// Decodes an operation from its `u8` encoding, which must be valid.
+  pub unsafe fn from_unchecked(value: u8) -> Self {
+    Self::try_from(value).unwrap_unchecked()
+  }
+
   /// Returns this operation's swapped counterpart.
   ///
-  /// For all `op, a, b`, `op.swap().op(a, b) == op.op(b, a)`.
+  /// For all `op, a, b`, `op.swap().op(a, b) == op.op(b, a)`, unless `op` is
+  /// unary (see [`Op::is_unary`]), in which case it is its own counterpart.
   #[inline]
   pub fn swap(self) -> Self {
     match self {
@@ -102,14 +339,66 @@ impl Op {
       Self::Gt => Self::Lt,
       Self::Le => Self::Ge,
       Self::Ge => Self::Le,
+      Self::MulHi => Self::MulHi,
+      Self::Pow => Self::PowS,
+      Self::PowS => Self::Pow,
+      Self::Sqrt => Self::Sqrt,
+      Self::DivHi => Self::DivHiS,
+      Self::DivHiS => Self::DivHi,
+      Self::RemHi => Self::RemHiS,
+      Self::RemHiS => Self::RemHi,
+      Self::Ext(label) => Self::ExtS(label),
+      Self::ExtS(label) => Self::Ext(label),
     }
   }
 
-  fn op<T: Numeric + FromWord + ToWord>(self, a: u64, b: u64) -> u64 {
+  fn op<T: Numeric + FromWord + ToWord>(
+    self,
+    a: u64,
+    b: u64,
+    overflow: Overflow,
+    ext: &ExtOps,
+  ) -> Result<u64, OpError> {
+    match self {
+      Self::Ext(label) => return ext.call::<T>(label, a, b),
+      Self::ExtS(label) => return ext.call::<T>(label, b, a),
+      _ => {}
+    }
+
     let a = T::from_word(a);
     let b = T::from_word(b);
 
-    match self {
+    let checked = |res: Option<T>| res.map(T::to_word).ok_or(OpError::Overflow(self));
+    match (overflow, self) {
+      (Overflow::Saturating, Self::Add) => return Ok(T::saturating_add(a, b).to_word()),
+      (Overflow::Saturating, Self::Sub) => return Ok(T::saturating_sub(a, b).to_word()),
+      (Overflow::Saturating, Self::SubS) => return Ok(T::saturating_sub(b, a).to_word()),
+      (Overflow::Saturating, Self::Mul) => return Ok(T::saturating_mul(a, b).to_word()),
+      (Overflow::Saturating, Self::Pow) => return Ok(T::saturating_pow(a, b).to_word()),
+      (Overflow::Saturating, Self::PowS) => return Ok(T::saturating_pow(b, a).to_word()),
+      (Overflow::Checked, Self::Add) => return checked(T::checked_add(a, b)),
+      (Overflow::Checked, Self::Sub) => return checked(T::checked_sub(a, b)),
+      (Overflow::Checked, Self::SubS) => return checked(T::checked_sub(b, a)),
+      (Overflow::Checked, Self::Mul) => return checked(T::checked_mul(a, b)),
+      (Overflow::Checked, Self::Div) => return checked(T::checked_div(a, b)),
+      (Overflow::Checked, Self::DivS) => return checked(T::checked_div(b, a)),
+      (Overflow::Checked, Self::Rem) => return checked(T::checked_rem(a, b)),
+      (Overflow::Checked, Self::RemS) => return checked(T::checked_rem(b, a)),
+      (Overflow::Checked, Self::Shl) => return checked(T::checked_shl(a, b)),
+      (Overflow::Checked, Self::ShlS) => return checked(T::checked_shl(b, a)),
+      (Overflow::Checked, Self::Shr) => return checked(T::checked_shr(a, b)),
+      (Overflow::Checked, Self::ShrS) => return checked(T::checked_shr(b, a)),
+      (Overflow::Checked, Self::Pow) => return checked(T::checked_pow(a, b)),
+      (Overflow::Checked, Self::PowS) => return checked(T::checked_pow(b, a)),
+      (Overflow::Checked, Self::Sqrt) => return checked(T::checked_sqrt(a)),
+      (Overflow::Checked, Self::DivHi) => return checked(T::checked_divhi(a, b)),
+      (Overflow::Checked, Self::DivHiS) => return checked(T::checked_divhi(b, a)),
+      (Overflow::Checked, Self::RemHi) => return checked(T::checked_remhi(a, b)),
+      (Overflow::Checked, Self::RemHiS) => return checked(T::checked_remhi(b, a)),
+      _ => {}
+    }
+
+    Ok(match self {
       Self::Add => T::add(a, b).to_word(),
       Self::Sub => T::sub(a, b).to_word(),
       Self::SubS => T::sub(b, a).to_word(),
@@ -125,63 +414,150 @@ impl Op {
       Self::ShlS => T::shl(b, a).to_word(),
       Self::Shr => T::shr(a, b).to_word(),
       Self::ShrS => T::shr(b, a).to_word(),
+      Self::MulHi => T::mulhi(a, b).to_word(),
+      Self::Pow => T::pow(a, b).to_word(),
+      Self::PowS => T::pow(b, a).to_word(),
+      Self::Sqrt => T::sqrt(a).to_word(),
+      Self::DivHi => T::divhi(a, b).to_word(),
+      Self::DivHiS => T::divhi(b, a).to_word(),
+      Self::RemHi => T::remhi(a, b).to_word(),
+      Self::RemHiS => T::remhi(b, a).to_word(),
 
       // comparison operators return an integer, which is not necessarily a `T`.
+      // the result is always exactly 0 or 1, so it survives any masking below.
       Self::Eq => (a == b).into(),
       Self::Ne => (a != b).into(),
       Self::Lt => (a < b).into(),
       Self::Le => (a <= b).into(),
       Self::Gt => (a > b).into(),
       Self::Ge => (a >= b).into(),
-    }
+
+      Self::Ext(_) | Self::ExtS(_) => unreachable!(),
+    })
   }
 
+  /// Whether this operation only depends on its first operand. Unary operations
+  /// are applied as soon as their first operand is a number, erasing the
+  /// second, so they are never swapped.
   #[inline(always)]
-  fn is_comparison(&self) -> bool {
-    *self >= Self::Eq
+  pub fn is_unary(&self) -> bool {
+    matches!(self, Self::Sqrt)
+  }
+
+  /// Whether this operation works on the full-width ints of twice the size of
+  /// the `Ty`.
+  #[inline(always)]
+  fn is_wide(&self) -> bool {
+    matches!(self, Self::MulHi | Self::DivHi | Self::DivHiS | Self::RemHi | Self::RemHiS)
+  }
+
+  /// Whether this operation returns an int, whatever the `Ty`.
+  #[inline(always)]
+  fn is_int(&self) -> bool {
+    (Self::Eq ..= Self::Ge).contains(self) || matches!(self, Self::Ext(_) | Self::ExtS(_))
   }
 }
 
 /// A numeric operator.
+///
+/// In the runtime, the operator of an op node is stored in the label of its
+/// principal port; it is encoded as a `u16` with the [`Ty`] in the low byte and
+/// the [`Op`] in the high byte, i.e. `u16::from_le_bytes([ty, op])`, on every
+/// platform. The [`Overflow`] is stored in the top two bits of the low byte,
+/// above the `Ty`. Use `u16::from` and `TypedOp::try_from` to convert between
+/// the two.
 #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
-#[repr(C, align(2))]
 pub struct TypedOp {
   /// The type of the operands.
   pub ty: Ty,
   /// The operation. An opaque type whose interpretation depends on `ty`.
   pub op: Op,
+  /// What the operation does when its result doesn't fit in `ty`.
+  pub overflow: Overflow,
 }
 
+/// The position of the [`Overflow`] in the low byte of an encoded [`TypedOp`].
+const OVERFLOW_SHIFT: u8 = 6;
+
 impl TypedOp {
+  /// Decodes an operator from its `u16` encoding, which must be valid.
   pub unsafe fn from_unchecked(val: u16) -> Self {
-    mem::transmute(val)
+    let [ty, op] = val.to_le_bytes();
+    Self {
+      ty: Ty::from_unchecked(ty & ((1 << OVERFLOW_SHIFT) - 1)),
+      op: Op::from_unchecked(op),
+      overflow: Overflow::from_unchecked(ty >> OVERFLOW_SHIFT),
+    }
   }
 
   /// Whether this operation returns an int.
   #[inline(always)]
   pub fn is_int(&self) -> bool {
-    self.ty.is_int() || self.op.is_comparison()
+    self.ty.is_int() || self.op.is_int()
+  }
+
+  /// Whether this operation only depends on its first operand (see
+  /// [`Op::is_unary`]).
+  #[inline(always)]
+  pub fn is_unary(&self) -> bool {
+    self.op.is_unary()
   }
 
   pub fn swap(self) -> Self {
-    Self { op: self.op.swap(), ty: self.ty }
+    Self { op: self.op.swap(), ..self }
   }
 
+  /// Applies the operation to `a` and `b`, calling the functions in `ext` for
+  /// [`Op::Ext`] operations.
   #[inline]
-  pub fn op(self, a: u64, b: u64) -> u64 {
+  pub fn op(self, a: u64, b: u64, ext: &ExtOps) -> Result<u64, OpError> {
     const U60: u64 = 0xFFF_FFFF_FFFF_FFFF;
 
+    let overflow = self.overflow;
     match self.ty {
-      Ty::I8 => self.op.op::<i8>(a, b),
-      Ty::I16 => self.op.op::<i16>(a, b),
-      Ty::I32 => self.op.op::<i32>(a, b),
+      Ty::I8 => self.op.op::<i8>(a, b, overflow, ext),
+      Ty::I16 => self.op.op::<i16>(a, b, overflow, ext),
+      Ty::I32 => self.op.op::<i32>(a, b, overflow, ext),
 
-      Ty::U8 => self.op.op::<u8>(a, b),
-      Ty::U16 => self.op.op::<u16>(a, b),
-      Ty::U32 => self.op.op::<u32>(a, b),
-      Ty::U60 => self.op.op::<u64>(a, b) & U60,
+      Ty::U8 => self.op.op::<u8>(a, b, overflow, ext),
+      Ty::U16 => self.op.op::<u16>(a, b, overflow, ext),
+      Ty::U32 => self.op.op::<u32>(a, b, overflow, ext),
+      // the high half of a full-width u60 is above bit 60, not bit 64
+      Ty::U60 if self.op.is_wide() => {
+        let (a, b) = (a & U60, b & U60);
+        let (a, b) = if matches!(self.op, Op::DivHiS | Op::RemHiS) { (b, a) } else { (a, b) };
+        let res = match self.op {
+          Op::MulHi => Some((a as u128 * b as u128) >> 60),
+          Op::DivHi | Op::DivHiS => ((a as u128) << 60).checked_div(b as u128),
+          _ => ((a as u128) << 60).checked_rem(b as u128),
+        };
+        match (overflow, res) {
+          (Overflow::Checked, Some(res)) if res <= U60 as u128 => Ok(res as u64),
+          (Overflow::Checked, _) => Err(OpError::Overflow(self.op)),
+          (_, res) => Ok(res.unwrap_or(0) as u64 & U60),
+        }
+      }
+      Ty::U60 => {
+        // shifts by 60 to 63 bits are in range for a u64, but not for a u60
+        let shift = match self.op {
+          Op::Shl | Op::Shr => Some(b),
+          Op::ShlS | Op::ShrS => Some(a),
+          _ => None,
+        };
+        if overflow == Overflow::Checked && shift.is_some_and(|shift| shift & U60 >= 60) {
+          return Err(OpError::Overflow(self.op));
+        }
+        let res = self.op.op::<u64>(a, b, overflow, ext)?;
+        // results that fit in 64 bits, but not in 60, overflow too
+        let overflowed = res > U60 && matches!(self.op, Op::Add | Op::Sub | Op::SubS | Op::Mul | Op::Pow | Op::PowS);
+        match overflow {
+          Overflow::Saturating if overflowed => Ok(U60),
+          Overflow::Checked if overflowed => Err(OpError::Overflow(self.op)),
+          _ => Ok(res & U60),
+        }
+      }
 
-      Ty::F32 => self.op.op::<f32>(a, b),
+      Ty::F32 => self.op.op::<f32>(a, b, overflow, ext),
     }
   }
 }
@@ -189,25 +565,32 @@ impl TypedOp {
 impl fmt::Display for TypedOp {
   fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
     match self.ty {
-      Ty::U60 => write!(f, "{}", self.op),
-      _ => write!(f, "{}.{}", self.ty, self.op),
+      Ty::U60 => write!(f, "{}{}", self.op, self.overflow),
+      _ => write!(f, "{}.{}{}", self.ty, self.op, self.overflow),
     }
   }
 }
 
+/// Decodes an operator from its `u16` encoding (see [`TypedOp`]), failing if
+/// either byte is out of range.
 impl TryFrom<u16> for TypedOp {
   type Error = ();
 
   fn try_from(value: u16) -> Result<Self, Self::Error> {
-    let [ty, op] = value.to_ne_bytes();
+    let [ty, op] = value.to_le_bytes();
 
-    Ok(Self { ty: Ty::try_from(ty)?, op: Op::try_from(op)? })
+    Ok(Self {
+      ty: Ty::try_from(ty & ((1 << OVERFLOW_SHIFT) - 1))?,
+      op: Op::try_from(op)?,
+      overflow: Overflow::try_from(ty >> OVERFLOW_SHIFT)?,
+    })
   }
 }
 
+/// Encodes an operator as a `u16` (see [`TypedOp`]).
 impl From<TypedOp> for u16 {
-  fn from(TypedOp { ty, op }: TypedOp) -> Self {
-    u16::from_ne_bytes([ty as u8, op as u8])
+  fn from(TypedOp { ty, op, overflow }: TypedOp) -> Self {
+    u16::from_le_bytes([ty as u8 | (overflow as u8) << OVERFLOW_SHIFT, op.into()])
   }
 }
 
@@ -224,12 +607,26 @@ impl FromStr for TypedOp {
   type Err = OpParseError;
 
   fn from_str(s: &str) -> Result<Self, Self::Err> {
+    // no operator ends in one of these, so they are always a suffix
+    let (s, overflow) = match s.as_bytes().last() {
+      Some(b'.') => (&s[.. s.len() - 1], Overflow::Wrapping),
+      Some(b's') => (&s[.. s.len() - 1], Overflow::Saturating),
+      Some(b'c') => (&s[.. s.len() - 1], Overflow::Checked),
+      _ => (s, Overflow::Wrapping),
+    };
+    // ext ops can't overflow, and only operate on ints
+    let parse_op = |ty: Ty, op: &str| match Op::from_str(op) {
+      Ok(Op::Ext(_) | Op::ExtS(_)) if overflow != Overflow::Wrapping || ty == Ty::F32 => {
+        Err(OpParseError::Op(op.to_string()))
+      }
+      res => res.map_err(|_| OpParseError::Op(op.to_string())),
+    };
     match s.split('.').collect::<Vec<_>>().as_slice() {
-      [ty, op] => Ok(Self {
-        ty: Ty::from_str(ty).map_err(|_| OpParseError::Type(ty.to_string()))?,
-        op: Op::from_str(op).map_err(|_| OpParseError::Op(op.to_string()))?,
-      }),
-      [op] => Ok(Self { ty: Ty::U60, op: Op::from_str(op).map_err(|_| OpParseError::Op(op.to_string()))? }),
+      [ty, op] => {
+        let ty = Ty::from_str(ty).map_err(|_| OpParseError::Type(ty.to_string()))?;
+        Ok(Self { ty, op: parse_op(ty, op)?, overflow })
+      }
+      [op] => Ok(Self { ty: Ty::U60, op: parse_op(Ty::U60, op)?, overflow }),
 
       _ => Err(OpParseError::Op(s.to_string())),
     }
@@ -12,10 +12,71 @@ pub trait Numeric: PartialEq + PartialOrd + Sized {
   fn xor(_: Self, _: Self) -> Self { Self::ZERO }
   fn shl(_: Self, _: Self) -> Self { Self::ZERO }
   fn shr(_: Self, _: Self) -> Self { Self::ZERO }
+  fn mulhi(_: Self, _: Self) -> Self { Self::ZERO }
+  fn divhi(_: Self, _: Self) -> Self { Self::ZERO }
+  fn remhi(_: Self, _: Self) -> Self { Self::ZERO }
+  fn pow(_: Self, _: Self) -> Self { Self::ZERO }
+  fn sqrt(_: Self) -> Self { Self::ZERO }
+
+  // see `Overflow`; the defaults are for types that can't overflow
+  fn saturating_add(a: Self, b: Self) -> Self { Self::add(a, b) }
+  fn saturating_sub(a: Self, b: Self) -> Self { Self::sub(a, b) }
+  fn saturating_mul(a: Self, b: Self) -> Self { Self::mul(a, b) }
+  fn saturating_pow(a: Self, b: Self) -> Self { Self::pow(a, b) }
+  fn checked_add(a: Self, b: Self) -> Option<Self> { Some(Self::add(a, b)) }
+  fn checked_sub(a: Self, b: Self) -> Option<Self> { Some(Self::sub(a, b)) }
+  fn checked_mul(a: Self, b: Self) -> Option<Self> { Some(Self::mul(a, b)) }
+  fn checked_div(a: Self, b: Self) -> Option<Self> { Some(Self::div(a, b)) }
+  fn checked_rem(a: Self, b: Self) -> Option<Self> { Some(Self::rem(a, b)) }
+  fn checked_shl(a: Self, b: Self) -> Option<Self> { Some(Self::shl(a, b)) }
+  fn checked_shr(a: Self, b: Self) -> Option<Self> { Some(Self::shr(a, b)) }
+  fn checked_pow(a: Self, b: Self) -> Option<Self> { Some(Self::pow(a, b)) }
+  fn checked_sqrt(a: Self) -> Option<Self> { Some(Self::sqrt(a)) }
+  fn checked_divhi(a: Self, b: Self) -> Option<Self> { Some(Self::divhi(a, b)) }
+  fn checked_remhi(a: Self, b: Self) -> Option<Self> { Some(Self::remhi(a, b)) }
+}
+
+/// Raises `base` to the power of `exp` by repeated squaring, multiplying with
+/// `mul`, which returns `None` on overflow.
+///
+/// The base is only squared when the result depends on the square, so this
+/// fails only if the result itself overflows.
+fn pow_by<T: Copy>(one: T, mut base: T, mut exp: u64, mul: impl Fn(T, T) -> Option<T>) -> Option<T> {
+  let mut acc = one;
+  loop {
+    if exp & 1 == 1 {
+      acc = mul(acc, base)?;
+    }
+    exp >>= 1;
+    if exp == 0 {
+      return Some(acc);
+    }
+    base = mul(base, base)?;
+  }
+}
+
+/// The square root of `n`, rounded down, computed digit by digit.
+fn isqrt(mut n: u64) -> u64 {
+  if n == 0 {
+    return 0;
+  }
+  // the highest power of four not above `n`
+  let mut bit = 1 << ((63 - n.leading_zeros()) & !1);
+  let mut res = 0;
+  while bit != 0 {
+    if n >= res + bit {
+      n -= res + bit;
+      res = (res >> 1) + bit;
+    } else {
+      res >>= 1;
+    }
+    bit >>= 2;
+  }
+  res
 }
 
 macro_rules! impl_numeric {
-  ( $($ty:ty),+ ) => {
+  ( $($ty:ty => $wide:ty),+ ) => {
     $(
       impl Numeric for $ty {
         const ZERO: Self = 0;
@@ -30,12 +91,46 @@ macro_rules! impl_numeric {
         fn xor(a: Self, b: Self) -> Self { a ^ b }
         fn shl(a: Self, b: Self) -> Self { a.wrapping_shl(b as u32) }
         fn shr(a: Self, b: Self) -> Self { a.wrapping_shr(b as u32) }
+        fn mulhi(a: Self, b: Self) -> Self { ((a as $wide * b as $wide) >> Self::BITS) as Self }
+        fn divhi(a: Self, b: Self) -> Self { ((a as $wide) << Self::BITS).checked_div(b as $wide).map_or(0, |q| q as Self) }
+        fn remhi(a: Self, b: Self) -> Self { ((a as $wide) << Self::BITS).checked_rem(b as $wide).map_or(0, |r| r as Self) }
+        fn pow(a: Self, b: Self) -> Self {
+          u64::try_from(b).map_or(0, |b| pow_by(1, a, b, |x, y| Some(x.wrapping_mul(y))).unwrap())
+        }
+        fn sqrt(a: Self) -> Self { u64::try_from(a).map_or(0, |a| isqrt(a) as Self) }
+
+        fn saturating_add(a: Self, b: Self) -> Self { a.saturating_add(b) }
+        fn saturating_sub(a: Self, b: Self) -> Self { a.saturating_sub(b) }
+        fn saturating_mul(a: Self, b: Self) -> Self { a.saturating_mul(b) }
+        fn saturating_pow(a: Self, b: Self) -> Self {
+          match u64::try_from(b) {
+            // the result is only negative for odd powers of negative numbers
+            Ok(exp) => <Self as Numeric>::checked_pow(a, b)
+              .unwrap_or(if a < Self::ZERO && exp & 1 == 1 { Self::MIN } else { Self::MAX }),
+            Err(_) => 0,
+          }
+        }
+        fn checked_add(a: Self, b: Self) -> Option<Self> { a.checked_add(b) }
+        fn checked_sub(a: Self, b: Self) -> Option<Self> { a.checked_sub(b) }
+        fn checked_mul(a: Self, b: Self) -> Option<Self> { a.checked_mul(b) }
+        fn checked_div(a: Self, b: Self) -> Option<Self> { a.checked_div(b) }
+        fn checked_rem(a: Self, b: Self) -> Option<Self> { a.checked_rem(b) }
+        fn checked_shl(a: Self, b: Self) -> Option<Self> { u32::try_from(b).ok().and_then(|b| a.checked_shl(b)) }
+        fn checked_shr(a: Self, b: Self) -> Option<Self> { u32::try_from(b).ok().and_then(|b| a.checked_shr(b)) }
+        fn checked_pow(a: Self, b: Self) -> Option<Self> { pow_by(1, a, u64::try_from(b).ok()?, Self::checked_mul) }
+        fn checked_sqrt(a: Self) -> Option<Self> { Some(isqrt(u64::try_from(a).ok()?) as Self) }
+        fn checked_divhi(a: Self, b: Self) -> Option<Self> {
+          ((a as $wide) << Self::BITS).checked_div(b as $wide).and_then(|q| Self::try_from(q).ok())
+        }
+        fn checked_remhi(a: Self, b: Self) -> Option<Self> {
+          ((a as $wide) << Self::BITS).checked_rem(b as $wide).map(|r| r as Self)
+        }
       }
     )*
   }
 }
 
-impl_numeric! { u8, u16, u32, u64, i8, i16, i32 }
+impl_numeric! { u8 => i128, u16 => i128, u32 => i128, u64 => u128, i8 => i128, i16 => i128, i32 => i128 }
 
 impl Numeric for f32 {
   const ZERO: Self = 0.0;
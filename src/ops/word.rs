@@ -35,9 +35,10 @@ impl FromWord for f32 {
   }
 }
 
+// NaNs are canonicalized; see `Ty::F32`
 impl ToWord for f32 {
   #[inline(always)]
   fn to_word(self) -> u64 {
-    self.to_bits() as u64
+    if self.is_nan() { f32::NAN } else { self }.to_bits() as u64
   }
 }
@@ -6,7 +6,7 @@ pub use alloc::{
   vec,
   vec::Vec,
 };
-pub use core::{fmt, hint, iter, mem, ptr};
+pub use core::{fmt, iter, mem, ptr};
 
 #[cfg(feature = "std")]
 pub use std::collections::{hash_map::Entry, HashMap as Map, HashSet as Set};
@@ -14,6 +14,12 @@ pub use std::collections::{hash_map::Entry, HashMap as Map, HashSet as Set};
 #[cfg(not(feature = "std"))]
 pub use alloc::collections::{btree_map::Entry, BTreeMap as Map, BTreeSet as Set};
 
+#[cfg(feature = "std")]
+pub use parking_lot::Mutex;
+
+#[cfg(not(feature = "std"))]
+pub use spin::Mutex;
+
 #[cfg(feature = "std")]
 pub use std::error::Error;
 #[cfg(feature = "std")]
@@ -21,26 +21,34 @@
 use crate::prelude::*;
 
 use crate::{
-  ops::TypedOp as Op,
+  ops::{ExtOps, OpError, TypedOp as Op},
   trace,
   trace::Tracer,
   util::{bi_enum, deref},
 };
-use alloc::borrow::Cow;
+use alloc::{borrow::Cow, sync::Arc};
 use core::{
   alloc::Layout,
   any::{Any, TypeId},
   hint::unreachable_unchecked,
+  iter::Sum,
   marker::PhantomData,
   mem::size_of,
   ops::{Add, AddAssign, Deref, DerefMut},
 };
-use nohash_hasher::{IntMap, IsEnabled};
+#[cfg(feature = "std")]
+use nohash_hasher::IntMap;
+use nohash_hasher::IsEnabled;
+
+#[cfg(not(feature = "std"))]
+type IntMap<K, V> = Map<K, V>;
 
 #[cfg(feature = "_fuzz")]
 use crate::fuzz as atomic;
-#[cfg(not(feature = "_fuzz"))]
+#[cfg(all(not(feature = "_fuzz"), target_has_atomic = "64"))]
 use core::sync::atomic;
+#[cfg(all(not(feature = "_fuzz"), not(target_has_atomic = "64")))]
+use portable_atomic as atomic;
 
 #[cfg(feature = "_fuzz")]
 use crate::fuzz::spin_loop;
@@ -61,6 +69,7 @@ mod linker;
 mod net;
 mod node;
 mod parallel;
+mod pool;
 mod port;
 mod wire;
 
@@ -69,9 +78,14 @@ pub use allocator::*;
 pub use def::*;
 pub use dyn_net::*;
 pub use instruction::*;
+pub use interact::*;
 pub use linker::*;
 pub use net::*;
 pub use node::*;
+#[cfg(feature = "std")]
+pub use parallel::*;
+#[cfg(feature = "std")]
+pub use pool::*;
 pub use port::*;
 pub use wire::*;
 
@@ -159,3 +173,28 @@ impl<T: AddAssign> AddAssign for Rewrites<T> {
     self.oper += rhs.oper;
   }
 }
+impl<'a, T: AddAssign<&'a T>> AddAssign<&'a Rewrites<T>> for Rewrites<T> {
+  fn add_assign(&mut self, rhs: &'a Rewrites<T>) {
+    self.anni += &rhs.anni;
+    self.comm += &rhs.comm;
+    self.eras += &rhs.eras;
+    self.dref += &rhs.dref;
+    self.oper += &rhs.oper;
+  }
+}
+impl<T: AddAssign + Default> Sum for Rewrites<T> {
+  fn sum<I: Iterator<Item = Self>>(iter: I) -> Self {
+    iter.fold(Rewrites::default(), |mut acc, rwts| {
+      acc += rwts;
+      acc
+    })
+  }
+}
+impl<'a, T: AddAssign<&'a T> + Default + 'a> Sum<&'a Rewrites<T>> for Rewrites<T> {
+  fn sum<I: Iterator<Item = &'a Self>>(iter: I) -> Self {
+    iter.fold(Rewrites::default(), |mut acc, rwts| {
+      acc += rwts;
+      acc
+    })
+  }
+}
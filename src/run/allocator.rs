@@ -1,7 +1,6 @@
 use super::*;
 
-// Really, rust?
-use alloc::alloc::alloc;
+use alloc::alloc::{Allocator as Alloc, Global};
 
 /// The memory behind a two-word allocation.
 ///
@@ -20,52 +19,96 @@ impl Heap {
   /// Allocates a new heap with the given size in bytes, defaulting to the
   /// largest power-of-two allocation the system will allow.
   pub fn new(bytes: Option<usize>) -> Option<Box<Self>> {
+    Self::new_with(bytes, Global)
+  }
+  /// Allocates a new heap with exactly the given size in words.
+  #[inline]
+  pub fn new_exact(words: usize) -> Option<Box<Self>> {
+    Self::new_exact_with(words, Global)
+  }
+  /// Like [`Heap::new`], but allocates the heap with `alloc`, rather than the
+  /// global allocator.
+  pub fn new_in<A: Alloc>(bytes: Option<usize>, alloc: &A) -> Option<Box<Self, &A>> {
+    Self::new_with(bytes, alloc)
+  }
+  /// Like [`Heap::new_exact`], but allocates the heap with `alloc`, rather than
+  /// the global allocator.
+  pub fn new_exact_in<A: Alloc>(words: usize, alloc: &A) -> Option<Box<Self, &A>> {
+    Self::new_exact_with(words, alloc)
+  }
+  /// The index in the heap of the node containing `addr`, or `None` if `addr`
+  /// is not in the heap (e.g. if it points to a def).
+  ///
+  /// A net allocates its nodes in the same order every time it is reduced, so
+  /// unlike their addresses, their indices can be compared across processes.
+  pub fn node_index(&self, addr: Addr) -> Option<usize> {
+    let offset = addr.0.wrapping_sub(self.0.as_ptr() as usize);
+    (offset < mem::size_of_val(&self.0)).then(|| offset / mem::size_of::<Node>())
+  }
+  fn new_with<A: Alloc + Copy>(bytes: Option<usize>, alloc: A) -> Option<Box<Self, A>> {
     if let Some(bytes) = bytes {
-      return Self::new_exact(bytes / 8);
+      return Self::new_exact_with(bytes / 8, alloc);
     }
-    let mut size = if cfg!(target_pointer_width = "64") {
-      1 << 40 // 1 TiB
-    } else {
-      1 << 30 // 1 GiB
-    } / 8;
+    #[cfg(target_pointer_width = "64")]
+    let mut size = (1 << 40) / 8; // 1 TiB
+    #[cfg(not(target_pointer_width = "64"))]
+    let mut size = (1 << 30) / 8; // 1 GiB
     while size != 0 {
-      if let Some(heap) = Self::new_exact(size) {
+      if let Some(heap) = Self::new_exact_with(size, alloc) {
         return Some(heap);
       }
       size /= 2;
     }
     None
   }
-  /// Allocates a new heap with exactly the given size in words.
-  #[inline]
-  pub fn new_exact(words: usize) -> Option<Box<Self>> {
+  fn new_exact_with<A: Alloc>(words: usize, alloc: A) -> Option<Box<Self, A>> {
     let nodes = words / 2;
     if nodes == 0 {
       return None;
     }
-    unsafe {
-      let ptr = alloc(Layout::array::<Node>(nodes).unwrap()) as *mut Node;
-      if ptr.is_null() {
-        return None;
-      }
-      Some(Box::from_raw(ptr::slice_from_raw_parts_mut(ptr, nodes) as *mut _))
-    }
+    let ptr = alloc.allocate(Layout::array::<Node>(nodes).ok()?).ok()?.cast::<Node>().as_ptr();
+    unsafe { Some(Box::from_raw_in(ptr::slice_from_raw_parts_mut(ptr, nodes) as *mut _, alloc)) }
   }
 }
 
+/// The error returned by [`Net::reduce_bounded`] when the heap may not have
+/// room for the nodes created by the next interaction.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "std", derive(Error))]
+#[cfg_attr(feature = "std", error("out of memory"))]
+pub struct OutOfMemory;
+
 /// Manages allocating and freeing nodes within the net.
 pub struct Allocator<'h> {
   pub(super) tracer: Tracer,
   pub(super) heap: &'h Heap,
   pub(super) next: usize,
   pub(super) head: Addr,
+  /// The number of nodes in the free list starting at `head`.
+  pub(super) free: usize,
+  /// The number of nodes allocated so far, including those that have since
+  /// been freed.
+  pub allocs: u64,
+  /// The causal depth of the net, if it is being tracked; see [`CausalDepth`].
+  pub depth: Option<CausalDepth>,
+  /// How nodes were allocated, if it is being tracked; see [`AllocStats`].
+  pub alloc_stats: Option<AllocStats>,
 }
 
 deref!({<'h>} Allocator<'h> => self.tracer: Tracer);
 
 impl<'h> Allocator<'h> {
   pub fn new(heap: &'h Heap) -> Self {
-    Allocator { tracer: Tracer::default(), heap, next: 0, head: Addr::NULL }
+    Allocator {
+      tracer: Tracer::default(),
+      heap,
+      next: 0,
+      head: Addr::NULL,
+      free: 0,
+      allocs: 0,
+      depth: None,
+      alloc_stats: None,
+    }
   }
 
   /// Frees one word of a two-word allocation.
@@ -87,6 +130,10 @@ impl<'h> Allocator<'h> {
           let new_head = addr;
           trace!(self.tracer, "appended", old_head, new_head);
           self.head = new_head;
+          self.free += 1;
+          if let Some(stats) = &mut self.alloc_stats {
+            stats.max_free = stats.max_free.max(self.free);
+          }
         } else {
           trace!(self.tracer, "too slow");
         };
@@ -97,22 +144,53 @@ impl<'h> Allocator<'h> {
   /// Allocates a two-word node.
   #[inline(never)]
   pub fn alloc(&mut self) -> Addr {
+    self.try_alloc().expect("OOM")
+  }
+
+  /// Allocates a two-word node, returning `None` if the heap is full.
+  #[inline(always)]
+  pub fn try_alloc(&mut self) -> Option<Addr> {
     trace!(self.tracer, self.head);
     let addr = if self.head != Addr::NULL {
       let addr = self.head;
       let next = Addr(self.head.val().load(Relaxed) as usize);
       trace!(self.tracer, next);
       self.head = next;
+      self.free -= 1;
+      if let Some(stats) = &mut self.alloc_stats {
+        stats.hits += 1;
+      }
       addr
     } else {
-      let index = self.next;
+      let node = self.heap.0.get(self.next)?;
       self.next += 1;
-      Addr(&self.heap.0.get(index).expect("OOM").0 as *const _ as _)
+      if let Some(stats) = &mut self.alloc_stats {
+        stats.fallbacks += 1;
+      }
+      Addr(&node.0 as *const _ as _)
     };
     trace!(self.tracer, addr, self.head);
+    self.tracer.alloc_node(addr);
+    self.allocs += 1;
+    if let Some(depth) = &mut self.depth {
+      depth.nodes.insert(addr, depth.current);
+    }
     addr.val().store(Port::LOCK.0, Relaxed);
     addr.other_half().val().store(Port::LOCK.0, Relaxed);
-    addr
+    Some(addr)
+  }
+
+  /// The number of nodes that can still be allocated.
+  pub fn available(&self) -> usize {
+    self.free + self.heap.0.len().saturating_sub(self.next)
+  }
+
+  /// The number of nodes that are in use.
+  ///
+  /// In parallel reduction, a thread can free nodes allocated by another
+  /// thread, so this is only an estimate of the thread's share of the heap.
+  pub fn used(&self) -> usize {
+    self.heap.0.len().saturating_sub(self.available())
   }
 
   #[inline(always)]
@@ -128,3 +206,71 @@ impl<'h> Allocator<'h> {
     }
   }
 }
+
+/// Statistics about how an [`Allocator`] allocated nodes, which help tune the
+/// size of the heap.
+///
+/// Freed nodes are kept in a free list, from which nodes are allocated while
+/// it isn't empty; otherwise, nodes are allocated from the part of the heap
+/// that has never been used, growing it. A reduction that mostly allocates from
+/// the free list reuses its memory well, and needs little more heap than its
+/// largest net.
+#[derive(Debug, Default, Clone)]
+pub struct AllocStats {
+  /// The number of nodes allocated from the free list.
+  pub hits: u64,
+  /// The number of nodes allocated from the unused part of the heap, because
+  /// the free list was empty.
+  pub fallbacks: u64,
+  /// The greatest length reached by the free list (of any thread, when
+  /// reducing in parallel).
+  pub max_free: usize,
+}
+
+impl AllocStats {
+  /// The fraction of the allocations that came from the free list, between `0`
+  /// and `1`.
+  pub fn hit_rate(&self) -> f64 {
+    self.hits as f64 / (self.hits + self.fallbacks).max(1) as f64
+  }
+}
+
+impl<'a> AddAssign<&'a AllocStats> for AllocStats {
+  fn add_assign(&mut self, rhs: &'a AllocStats) {
+    self.hits += rhs.hits;
+    self.fallbacks += rhs.fallbacks;
+    self.max_free = self.max_free.max(rhs.max_free);
+  }
+}
+
+/// Tracks the causal depth of a net's reduction: the length of the longest
+/// chain of interactions that each depend on the previous one.
+///
+/// Each node is labelled with the depth of the interaction that allocated it
+/// (nodes allocated before reduction, and nilary agents, have depth `0`), and
+/// each interaction has a depth one greater than the deepest of its two agents.
+/// The greatest depth reached, `max`, approximates the number of steps needed
+/// to reduce the net with unlimited parallelism; compared with the total number
+/// of rewrites, it indicates how parallelizable the reduction is.
+///
+/// This is only an approximation: dependencies through the wires between
+/// agents are not accounted for, and when reducing in parallel, the depths of
+/// nodes allocated by other threads are not known, and are taken to be `0`.
+#[derive(Debug, Default, Clone)]
+pub struct CausalDepth {
+  pub(super) nodes: IntMap<Addr, u64>,
+  /// The depth of the interaction being performed.
+  pub(super) current: u64,
+  /// The greatest depth of any interaction performed so far.
+  pub max: u64,
+}
+
+impl CausalDepth {
+  /// Records an interaction between `a` and `b`.
+  #[inline(always)]
+  pub(super) fn interact(&mut self, a: &Port, b: &Port) {
+    let depth = |port: &Port| if port.is_full_node() { self.nodes.get(&port.addr()).copied().unwrap_or(0) } else { 0 };
+    self.current = depth(a).max(depth(b)) + 1;
+    self.max = self.max.max(self.current);
+  }
+}
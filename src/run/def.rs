@@ -3,18 +3,33 @@ use crate::stdlib::AsHostedDef;
 use super::*;
 
 /// A bitset representing the set of labels used in a def.
+///
+/// When a [`Ref`] node interacts with a combinator whose label is not in the
+/// def's label set, the def cannot possibly interact with that combinator after
+/// being expanded -- none of the nodes it would expand to could annihilate with
+/// it -- so the runtime commutes the combinator through the reference instead
+/// of expanding it. Conversely, combinators whose label is in the set may
+/// annihilate with a node of the def, so the def must be expanded.
+///
+/// Label sets can be computed for a whole book with
+/// [`crate::host::calc_labels`].
 #[derive(Default, Debug, Clone, PartialEq, Eq)]
 pub struct LabSet {
   /// The least label greater than every label in the set.
   ///
-  /// `lab >= set.min_safe` implies `!set.has(lab)`.
+  /// `lab >= set.min_safe` implies `!set.has(lab)`. This is stored in the label
+  /// bits of [`Ref`] ports, so that a combinator with a label at least
+  /// `min_safe` can be commuted with the reference without loading the def.
   pub(crate) min_safe: Lab,
+  /// If set, every label at least this is in the set (up to `min_safe`, which
+  /// is then [`Lab::MAX`]).
+  pub(crate) all_from: Option<Lab>,
   pub(crate) bits: Cow<'static, [u64]>,
 }
 
 impl LabSet {
-  pub const NONE: LabSet = LabSet { min_safe: 0, bits: Cow::Borrowed(&[]) };
-  pub const ALL: LabSet = LabSet { min_safe: Lab::MAX, bits: Cow::Borrowed(&[u64::MAX; 1024]) };
+  pub const NONE: LabSet = LabSet { min_safe: 0, all_from: None, bits: Cow::Borrowed(&[]) };
+  pub const ALL: LabSet = LabSet { min_safe: Lab::MAX, all_from: Some(0), bits: Cow::Borrowed(&[]) };
 
   pub fn add(&mut self, lab: Lab) {
     self.min_safe = self.min_safe.max(lab + 1);
@@ -27,18 +42,30 @@ impl LabSet {
     bits[index] |= 1 << bit;
   }
 
+  /// Adds every label at least `lab` to this set.
+  pub fn add_all_from(&mut self, lab: Lab) {
+    self.min_safe = Lab::MAX;
+    self.all_from = Some(self.all_from.map_or(lab, |x| x.min(lab)));
+  }
+
   pub fn has(&self, lab: Lab) -> bool {
     if lab >= self.min_safe {
       return false;
     }
+    if self.all_from.is_some_and(|x| lab >= x) {
+      return true;
+    }
     let index = (lab >> 6) as usize;
     let bit = lab & 63;
-    unsafe { self.bits.get_unchecked(index) & 1 << bit != 0 }
+    self.bits.get(index).is_some_and(|word| word & 1 << bit != 0)
   }
 
   /// Adds all of the labels in `other` to this set.
   pub fn union(&mut self, other: &LabSet) {
     self.min_safe = self.min_safe.max(other.min_safe);
+    if let Some(lab) = other.all_from {
+      self.add_all_from(lab);
+    }
     let bits = self.bits.to_mut();
     for (a, b) in bits.iter_mut().zip(other.bits.iter()) {
       *a |= b;
@@ -48,12 +75,40 @@ impl LabSet {
     }
   }
 
+  /// Returns whether every label in this set is also in `other`.
+  pub fn is_subset(&self, other: &LabSet) -> bool {
+    if let Some(lab) = self.all_from {
+      if other.all_from.map_or(true, |x| x > lab) {
+        return false;
+      }
+    }
+    self.bits.iter().enumerate().all(|(i, bits)| bits & !other.word(i) == 0)
+  }
+
+  /// Returns the `index`th word of the bitset, including the labels in the
+  /// `all_from` range.
+  fn word(&self, index: usize) -> u64 {
+    let bits = self.bits.get(index).copied().unwrap_or(0);
+    let range = match self.all_from.map(|lab| lab as usize) {
+      Some(lab) if lab <= index << 6 => u64::MAX,
+      Some(lab) if lab < (index + 1) << 6 => u64::MAX << (lab & 63),
+      _ => 0,
+    };
+    bits | range
+  }
+
   pub const fn from_bits(bits: &'static [u64]) -> Self {
     if bits.is_empty() {
       return LabSet::NONE;
     }
     let min_safe = (bits.len() << 6) as u16 - bits[bits.len() - 1].leading_zeros() as u16;
-    LabSet { min_safe, bits: Cow::Borrowed(bits) }
+    LabSet { min_safe, all_from: None, bits: Cow::Borrowed(bits) }
+  }
+
+  /// Like [`LabSet::from_bits`], but also containing every label at least
+  /// `all_from`.
+  pub const fn from_bits_and_all_from(bits: &'static [u64], all_from: Lab) -> Self {
+    LabSet { min_safe: Lab::MAX, all_from: Some(all_from), bits: Cow::Borrowed(bits) }
   }
 }
 
@@ -182,17 +237,20 @@ impl<'a, M: Mode> Net<'a, M> {
   /// Expands a [`Ref`] node connected to `trg`.
   #[inline(never)]
   pub fn call(&mut self, port: Port, trg: Port) {
+    let prev = self.tracer.enter_def(port.addr());
     trace!(self, port, trg);
 
     let def = port.addr().def();
 
     if trg.tag() == Ctr && !def.labs.has(trg.lab()) {
-      return self.comm02(port, trg);
+      self.comm02(port, trg);
+    } else {
+      self.rwts.dref += 1;
+      self.coverage.record(Interaction::Call);
+      unsafe { Def::call(port.addr().0 as *const _, self, trg) }
     }
 
-    self.rwts.dref += 1;
-
-    unsafe { Def::call(port.addr().0 as *const _, self, trg) }
+    self.tracer.exit_def(prev);
   }
 }
 
@@ -203,35 +261,75 @@ pub struct InterpretedDef {
   /// The number of targets used in the def; must be greater than all of the
   /// `TrgId` indices in `instr`.
   pub(crate) trgs: usize,
+  /// The free variables of an open def, sorted by name, along with the targets
+  /// they are left at once `instr` has run. When the def is called through a
+  /// ref, these are erased; see [`Host::instantiate`] to bind them instead.
+  ///
+  /// [`Host::instantiate`]: crate::host::Host::instantiate
+  pub(crate) free: Vec<(String, TrgId)>,
 }
 
 impl AsHostedDef for InterpretedDef {
   fn call<M: Mode>(def: &Def<InterpretedDef>, net: &mut Net<M>, trg: Port) {
-    let def = &def.data;
+    def.data.expand(net, trg, |net, _, trg| net.link_trg_port(trg, Port::ERA))
+  }
+}
+
+impl InterpretedDef {
+  /// The most nodes that expanding the def can allocate.
+  pub(crate) fn max_nodes(&self) -> usize {
+    self
+      .instr
+      .iter()
+      .map(|i| match i {
+        Instruction::Const { .. } | Instruction::Link { .. } | Instruction::LinkConst { .. } => 0,
+        Instruction::Ctr { .. }
+        | Instruction::Op { .. }
+        | Instruction::OpNum { .. }
+        | Instruction::Switch { .. }
+        | Instruction::Wires { .. } => 1,
+        // see `Net::do_mat`
+        Instruction::Mat { .. } => 3,
+      })
+      .sum()
+  }
+
+  /// Runs the instructions of the def, connecting its root to `trg`, then
+  /// calls `free` with the name and target of each of its free variables.
+  #[inline(always)]
+  pub(crate) fn expand<M: Mode>(&self, net: &mut Net<M>, trg: Port, mut free: impl FnMut(&mut Net<M>, &str, Trg)) {
+    let def = self;
     let instructions = &def.instr;
 
-    if def.trgs >= net.trgs.len() {
+    if def.trgs > net.trgs.len() {
       net.trgs = Box::new_uninit_slice(def.trgs);
     }
 
-    let mut trgs = Trgs(&mut net.trgs[..] as *mut _ as *mut _);
+    let mut trgs = Trgs(&mut net.trgs[..] as *mut _ as *mut _, def.trgs);
 
-    /// Points to an array of `Trg`s of length at least `def.trgs`. The `Trg`s
-    /// may not all be initialized.
+    /// Points to an array of `Trg`s of length at least `def.trgs` (the second
+    /// field). The `Trg`s may not all be initialized.
     ///
     /// Only `TrgId`s with index less than `def.trgs` may be passed to `get_trg`
     /// and `set_trg`, and `get_trg` can only be called after `set_trg` was
     /// called with the same `TrgId`.
-    struct Trgs(*mut Trg);
+    struct Trgs(*mut Trg, usize);
 
     impl Trgs {
+      #[inline(always)]
+      fn check(&self, i: TrgId) {
+        debug_assert!(i.index() < self.1, "target {} out of bounds for a def with {} targets", i.index(), self.1,);
+      }
+
       #[inline(always)]
       fn get_trg(&self, i: TrgId) -> Trg {
+        self.check(i);
         unsafe { (*self.0.byte_offset(i.byte_offset as _)).clone() }
       }
 
       #[inline(always)]
       fn set_trg(&mut self, i: TrgId, trg: Trg) {
+        self.check(i);
         unsafe { *self.0.byte_offset(i.byte_offset as _) = trg }
       }
     }
@@ -267,6 +365,11 @@ impl AsHostedDef for InterpretedDef {
             trgs.set_trg(lft, l);
             trgs.set_trg(rgt, r);
           }
+          Instruction::Switch { lab, trg, lft, rgt } => {
+            let (l, r) = net.do_switch(lab, trgs.get_trg(trg));
+            trgs.set_trg(lft, l);
+            trgs.set_trg(rgt, r);
+          }
           Instruction::Wires { av, aw, bv, bw } => {
             let (avt, awt, bvt, bwt) = net.do_wires();
             trgs.set_trg(av, avt);
@@ -277,5 +380,8 @@ impl AsHostedDef for InterpretedDef {
         }
       }
     }
+    for (nam, trg) in &def.free {
+      free(net, nam, trgs.get_trg(*trg));
+    }
   }
 }
@@ -64,6 +64,11 @@ pub enum Instruction {
   /// let (lft, rgt) = net.do_mat(trg);
   /// ```
   Mat { trg: TrgId, lft: TrgId, rgt: TrgId },
+  /// See [`Net::do_switch`].
+  /// ```rust,ignore
+  /// let (lft, rgt) = net.do_switch(lab, trg);
+  /// ```
+  Switch { lab: Lab, trg: TrgId, lft: TrgId, rgt: TrgId },
   /// See [`Net::do_wires`].
   /// ```rust,ignore
   /// let (av, aw, bv, bw) = net.do_wires();
@@ -136,7 +141,11 @@ impl<'a, M: Mode> Net<'a, M> {
   pub(crate) fn do_op(&mut self, op: Op, trg: Trg) -> (Trg, Trg) {
     trace!(self.tracer, op, trg);
     let port = trg.target();
-    if !M::LAZY && port.is_num() {
+    if !M::LAZY && port.is_num() && op.is_unary() {
+      self.rwts.oper += 1;
+      self.free_trg(trg);
+      (Trg::port(Port::ERA), Trg::port(self.apply_op(op, port.num(), 0)))
+    } else if !M::LAZY && port.is_num() {
       self.free_trg(trg);
       let n = self.create_node(Op, op.swap().into());
       n.p1.wire().set_target(port);
@@ -159,9 +168,7 @@ impl<'a, M: Mode> Net<'a, M> {
       self.rwts.oper += 1;
       self.free_trg(trg);
 
-      let res = op.op(port.num(), rhs.num());
-
-      Trg::port(Port::new_num(if op.is_int() { Tag::Int } else { Tag::F32 }, res))
+      Trg::port(self.apply_op(op, port.num(), rhs.num()))
     } else if !M::LAZY && port == Port::ERA {
       self.free_trg(trg);
       Trg::port(Port::ERA)
@@ -203,6 +210,14 @@ impl<'a, M: Mode> Net<'a, M> {
     }
   }
 
+  /// `trg ~ ?{lab x y}`
+  #[inline(always)]
+  pub(crate) fn do_switch(&mut self, lab: Lab, trg: Trg) -> (Trg, Trg) {
+    let m = self.create_node(Mat, lab);
+    self.link_trg_port(trg, m.p0);
+    (Trg::port(m.p1), Trg::port(m.p2))
+  }
+
   #[inline(always)]
   pub(crate) fn do_wires(&mut self) -> (Trg, Trg, Trg, Trg) {
     let a = self.alloc();
@@ -1,48 +1,288 @@
 use super::*;
 
+use crate::{ast::MAX_ARITY, stdlib::HostedDef};
+
+/// An interaction rejected by linear mode (see [`Linker::linear`]).
+///
+/// In linear mode, every agent must be used exactly once, so the interactions
+/// that would erase or duplicate agents are rejected:
+/// - an eraser meeting a binary agent ([`Net::comm02`] with [`Port::ERA`]),
+///   which erases the agent along with everything connected to it
+/// - two binary agents commuting ([`Net::comm22`]), which duplicates each of
+///   them
+/// - a combinator meeting a number or a reference that it commutes with
+///   ([`Net::comm02`]), which duplicates the nilary agent
+///
+/// Erasing nilary agents (e.g. `* ~ #1`) is not considered a violation, as
+/// these interactions are usually skipped entirely (see
+/// [`Linker::skip_pairs`]).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "std", derive(Error))]
+pub enum LinearityViolation {
+  #[cfg_attr(feature = "std", error("an eraser erased {} node", Article(*.0)))]
+  Erase(Tag),
+  #[cfg_attr(feature = "std", error("{} node and {} node duplicated each other", Article(*.0), Article(*.1)))]
+  Commute(Tag, Tag),
+  #[cfg_attr(feature = "std", error("{} node duplicated {} node", Article(*.0), Article(*.1)))]
+  Copy(Tag, Tag),
+}
+
+/// Displays a tag preceded by its indefinite article, e.g. `an Int`.
+#[cfg(feature = "std")]
+struct Article(Tag);
+
+#[cfg(feature = "std")]
+impl fmt::Display for Article {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    let article = if matches!(self.0, Tag::Int | Tag::F32 | Tag::Op) { "an" } else { "a" };
+    write!(f, "{article} {:?}", self.0)
+  }
+}
+
+/// The rule with which an active pair is reduced; these correspond to the
+/// methods of [`Net`] that [`Net::interact`] dispatches to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Rule {
+  /// Two nilary agents that are discarded without being expanded.
+  Void,
+  /// [`Net::call`]
+  Call,
+  /// [`Net::anni2`]
+  Anni2,
+  /// [`Net::comm22`]
+  Comm22,
+  /// [`Net::comm02`]
+  Comm02,
+  /// [`Net::op_num`]
+  OpNum,
+  /// [`Net::mat_int`]
+  MatInt,
+  /// [`Net::switch_ctr`]
+  SwitchCtr,
+}
+
+impl fmt::Display for Rule {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    f.pad(match self {
+      Rule::Void => "void",
+      Rule::Call => "call",
+      Rule::Anni2 => "anni2",
+      Rule::Comm22 => "comm22",
+      Rule::Comm02 => "comm02",
+      Rule::OpNum => "op_num",
+      Rule::MatInt => "mat_int",
+      Rule::SwitchCtr => "switch_ctr",
+    })
+  }
+}
+
+/// An individual interaction rule, as counted by [`Coverage`].
+///
+/// These refine the [`Rule`]s that [`Net::interact`] dispatches on, telling
+/// apart the distinct rewrites that some of their methods perform.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Interaction {
+  /// Two nilary agents that are discarded without being expanded.
+  Void,
+  /// A reference expanded by [`Net::call`].
+  Call,
+  /// [`Net::anni2`]
+  Anni,
+  /// [`Net::comm22`]
+  Comm,
+  /// An eraser erasing a binary agent, in [`Net::comm02`].
+  Erase,
+  /// A number or reference copied by a binary agent, in [`Net::comm02`].
+  Copy,
+  /// A number meeting an operation whose other operand is not yet known, in
+  /// [`Net::op_num`]; the operation is flipped to wait for the other operand.
+  OpSwap,
+  /// A number meeting an operation whose other operand is known, in
+  /// [`Net::op_num`]; the operation is computed.
+  OpNum,
+  /// [`Net::mat_int`]
+  MatInt,
+  /// [`Net::switch_ctr`]
+  Switch,
+}
+
+impl Interaction {
+  pub const ALL: [Interaction; 10] = [
+    Interaction::Void,
+    Interaction::Call,
+    Interaction::Anni,
+    Interaction::Comm,
+    Interaction::Erase,
+    Interaction::Copy,
+    Interaction::OpSwap,
+    Interaction::OpNum,
+    Interaction::MatInt,
+    Interaction::Switch,
+  ];
+}
+
+impl fmt::Display for Interaction {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    f.pad(match self {
+      Interaction::Void => "VOID",
+      Interaction::Call => "DREF",
+      Interaction::Anni => "ANNI",
+      Interaction::Comm => "COMM",
+      Interaction::Erase => "ERA2",
+      Interaction::Copy => "COPY",
+      Interaction::OpSwap => "OP2N",
+      Interaction::OpNum => "OP1N",
+      Interaction::MatInt => "MTCH",
+      Interaction::Switch => "SWIT",
+    })
+  }
+}
+
+/// The number of times each [`Interaction`] has been performed; see
+/// [`Linker::coverage`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Coverage([u64; Interaction::ALL.len()]);
+
+impl Coverage {
+  /// Returns the number of times `interaction` has been performed.
+  pub fn count(&self, interaction: Interaction) -> u64 {
+    self.0[interaction as usize]
+  }
+
+  #[inline(always)]
+  pub(crate) fn record(&mut self, interaction: Interaction) {
+    self.0[interaction as usize] += 1;
+  }
+
+  /// Returns the interactions that have never been performed.
+  pub fn missing(&self) -> impl Iterator<Item = Interaction> + '_ {
+    Interaction::ALL.into_iter().filter(|&interaction| self.count(interaction) == 0)
+  }
+
+  /// Whether every interaction has been performed at least once.
+  pub fn is_full(&self) -> bool {
+    self.missing().next().is_none()
+  }
+}
+
+impl<'a> AddAssign<&'a Coverage> for Coverage {
+  fn add_assign(&mut self, rhs: &'a Coverage) {
+    for (a, b) in self.0.iter_mut().zip(rhs.0) {
+      *a += b;
+    }
+  }
+}
+
 impl<'a, M: Mode> Net<'a, M> {
-  /// Performs an interaction between two connected principal ports.
+  /// Returns the rule with which [`Net::interact`] would reduce the active pair
+  /// of `a` and `b`.
+  pub fn rule(&self, a: &Port, b: &Port) -> Rule {
+    self.select(a.clone(), b.clone()).0
+  }
+
+  /// Selects the rule with which the active pair of `a` and `b` is reduced,
+  /// returning the ports in the order in which the rule's method takes them.
   #[inline(always)]
-  pub fn interact(&mut self, a: Port, b: Port) {
-    self.tracer.sync();
-    trace!(self.tracer, a, b);
-    match (a.tag(), b.tag()) {
+  pub(crate) fn select(&self, a: Port, b: Port) -> (Rule, Port, Port) {
+    let rule = match (a.tag(), b.tag()) {
       // not actually an active pair
       (Var | Red, _) | (_, Var | Red) => unreachable!(),
       // nil-nil
-      (Ref, Ref | Int | F32) if !a.is_skippable() => self.call(a, b),
-      (Ref | Int | F32, Ref) if !b.is_skippable() => self.call(b, a),
-      (Int | F32 | Ref, Int | F32 | Ref) => self.rwts.eras += 1,
-      // comm 2/2
-      (Ctr, Mat) if a.lab() != 0 => self.comm22(a, b),
-      (Mat, Ctr) if b.lab() != 0 => self.comm22(a, b),
-      (Ctr, Op) | (Op, Ctr) => self.comm22(a, b),
-      (Ctr, Ctr) if a.lab() != b.lab() => self.comm22(a, b),
+      (Ref, Ref | Int | F32) if !a.is_skippable() || !self.skips(&a, &b) && a != Port::ERA => Rule::Call,
+      (Ref | Int | F32, Ref) if !b.is_skippable() || !self.skips(&a, &b) && b != Port::ERA => {
+        return (Rule::Call, b, a);
+      }
+      (Int | F32 | Ref, Int | F32 | Ref) => Rule::Void,
+      // switch
+      (Ctr, Mat) if b.lab() != 0 && a.lab() >= b.lab() => return (Rule::SwitchCtr, b, a),
+      (Mat, Ctr) if a.lab() != 0 && b.lab() >= a.lab() => Rule::SwitchCtr,
+      // comm 2/2; switches commute with everything they don't match on
+      (Ctr, Mat) | (Mat, Ctr) if a.lab() != 0 || b.lab() != 0 => Rule::Comm22,
+      (Mat, Op) if a.lab() != 0 => Rule::Comm22,
+      (Op, Mat) if b.lab() != 0 => Rule::Comm22,
+      (Ctr, Op) | (Op, Ctr) => Rule::Comm22,
+      (Ctr, Ctr) | (Mat, Mat) if a.lab() != b.lab() => Rule::Comm22,
       // anni
-      (Mat, Mat) | (Op, Op) | (Ctr, Ctr) => self.anni2(a, b),
+      (Mat, Mat) | (Op, Op) | (Ctr, Ctr) => Rule::Anni2,
       // comm 2/0
-      (Ref, Ctr) if b.lab() >= a.lab() => self.comm02(a, b),
-      (Ctr, Ref) if a.lab() >= b.lab() => self.comm02(b, a),
-      (Int | F32, Ctr) => self.comm02(a, b),
-      (Ctr, Int | F32) => self.comm02(b, a),
-      (Ref, _) if a == Port::ERA => self.comm02(a, b),
-      (_, Ref) if b == Port::ERA => self.comm02(b, a),
+      (Ref, Ctr) if b.lab() >= a.lab() => Rule::Comm02,
+      (Ctr, Ref) if a.lab() >= b.lab() => return (Rule::Comm02, b, a),
+      (Int | F32, Ctr) => Rule::Comm02,
+      (Ctr, Int | F32) => return (Rule::Comm02, b, a),
+      (Int | F32, Mat) if b.lab() != 0 => Rule::Comm02,
+      (Mat, Int | F32) if a.lab() != 0 => return (Rule::Comm02, b, a),
+      (Ref, _) if a == Port::ERA => Rule::Comm02,
+      (_, Ref) if b == Port::ERA => return (Rule::Comm02, b, a),
       // deref
-      (Ref, _) => self.call(a, b),
-      (_, Ref) => self.call(b, a),
+      (Ref, _) => Rule::Call,
+      (_, Ref) => return (Rule::Call, b, a),
       // native ops
-      (Op, Int | F32) => self.op_num(a, b),
-      (Int | F32, Op) => self.op_num(b, a),
-      (Mat, Int) => self.mat_int(a, b),
-      (Int, Mat) => self.mat_int(b, a),
+      (Op, Int | F32) => Rule::OpNum,
+      (Int | F32, Op) => return (Rule::OpNum, b, a),
+      (Mat, Int) => Rule::MatInt,
+      (Int, Mat) => return (Rule::MatInt, b, a),
       // todo: what should the semantics of these be?
       (Mat, F32)
       | (F32, Mat)
-      | (Mat, Ctr) // b.lab() == 0
-      | (Ctr, Mat) // a.lab() == 0
+      | (Mat, Ctr) // a.lab() == b.lab() == 0
+      | (Ctr, Mat) // a.lab() == b.lab() == 0
       | (Op, Mat)
       | (Mat, Op) => unimplemented!("{:?}-{:?}", a.tag(), b.tag()),
+    };
+    (rule, a, b)
+  }
+
+  /// Returns the most nodes that reducing the active pair of `a` and `b` with
+  /// `rule` can allocate, or `None` if it calls a def whose allocations are not
+  /// known ahead of time (i.e. one that is not interpreted).
+  pub(crate) fn max_nodes(&self, rule: Rule, a: &Port, b: &Port) -> Option<usize> {
+    Some(match rule {
+      Rule::Void | Rule::Anni2 | Rule::Comm02 => 0,
+      Rule::Call => {
+        let def = a.addr().def();
+        if b.tag() == Ctr && !def.labs.has(b.lab()) {
+          0
+        } else {
+          def.downcast_ref::<HostedDef<InterpretedDef>>()?.data.0.max_nodes()
+        }
+      }
+      Rule::Comm22 => 4,
+      Rule::OpNum => 1,
+      Rule::MatInt => 2,
+      Rule::SwitchCtr => 3 + switch_skips(a, b),
+    })
+  }
+
+  /// Performs an interaction between two connected principal ports.
+  #[inline(always)]
+  pub fn interact(&mut self, a: Port, b: Port) {
+    let (rule, a, b) = self.select(a, b);
+    self.apply(rule, a, b);
+  }
+
+  /// Reduces an active pair with the rule [`Net::select`]ed for it.
+  #[inline(always)]
+  pub(crate) fn apply(&mut self, rule: Rule, a: Port, b: Port) {
+    self.tracer.sync();
+    let prev = self.tracer.enter_pair(&a, &b);
+    trace!(self.tracer, a, b);
+    if let Some(depth) = &mut self.depth {
+      depth.interact(&a, &b);
+    }
+    match rule {
+      Rule::Void => {
+        self.rwts.eras += 1;
+        self.coverage.record(Interaction::Void);
+      }
+      Rule::Call => self.call(a, b),
+      Rule::Anni2 => self.anni2(a, b),
+      Rule::Comm22 => self.comm22(a, b),
+      Rule::Comm02 => self.comm02(a, b),
+      Rule::OpNum => self.op_num(a, b),
+      Rule::MatInt => self.mat_int(a, b),
+      Rule::SwitchCtr => self.switch_ctr(a, b),
     }
+    self.tracer.exit_pair(prev);
   }
 
   /// Annihilates two binary agents.
@@ -74,6 +314,7 @@ impl<'a, M: Mode> Net<'a, M> {
   pub fn anni2(&mut self, a: Port, b: Port) {
     trace!(self.tracer, a, b);
     self.rwts.anni += 1;
+    self.coverage.record(Interaction::Anni);
     let a = a.consume_node();
     let b = b.consume_node();
     self.link_wire_wire(a.p1, b.p1);
@@ -118,7 +359,11 @@ impl<'a, M: Mode> Net<'a, M> {
   #[inline(never)]
   pub fn comm22(&mut self, a: Port, b: Port) {
     trace!(self.tracer, a, b);
+    if self.linear {
+      return self.violate(LinearityViolation::Commute(a.tag(), b.tag()));
+    }
     self.rwts.comm += 1;
+    self.coverage.record(Interaction::Comm);
 
     let a = a.consume_node();
     let b = b.consume_node();
@@ -162,7 +407,15 @@ impl<'a, M: Mode> Net<'a, M> {
   #[inline(never)]
   pub fn comm02(&mut self, a: Port, b: Port) {
     trace!(self.tracer, a, b);
+    if self.linear {
+      return self.violate(if a == Port::ERA {
+        LinearityViolation::Erase(b.tag())
+      } else {
+        LinearityViolation::Copy(b.tag(), a.tag())
+      });
+    }
     self.rwts.comm += 1;
+    self.coverage.record(if a == Port::ERA { Interaction::Erase } else { Interaction::Copy });
     let b = b.consume_node();
     self.link_wire_port(b.p1, a.clone());
     self.link_wire_port(b.p2, a);
@@ -202,6 +455,7 @@ impl<'a, M: Mode> Net<'a, M> {
   pub fn mat_int(&mut self, a: Port, b: Port) {
     trace!(self.tracer, a, b);
     self.rwts.oper += 1;
+    self.coverage.record(Interaction::MatInt);
     let a = a.consume_node();
     let b = b.int();
     if b == 0 {
@@ -222,6 +476,59 @@ impl<'a, M: Mode> Net<'a, M> {
     }
   }
 
+  /// Interacts a combinator with label `lab + i` and a switch node with label
+  /// `lab`, selecting the `i`th arm of the switch.
+  ///
+  /// ```text
+  ///         b1 |   | b2
+  ///           _|___|_
+  ///           \     /
+  ///         b  \   /
+  ///             \ /
+  ///              |
+  ///             / \
+  ///         a  /sw \
+  ///           /_____\
+  ///            |   |
+  ///         a1 |   | a2
+  ///
+  /// --------------------------- switch_ctr
+  ///
+  ///   a1 ~ (* (* ... ((b1 (b2 a2)) *)))
+  ///         ^^^^^^^^ `i` skipped arms
+  /// ```
+  ///
+  /// Switches have fewer than [`MAX_ARITY`] arms, so no more than `MAX_ARITY -
+  /// 1` arms are skipped: with a larger `i`, this is already past the last arm,
+  /// and the combinator is erased.
+  #[inline(never)]
+  pub fn switch_ctr(&mut self, a: Port, b: Port) {
+    trace!(self.tracer, a, b);
+    self.rwts.oper += 1;
+    self.coverage.record(Interaction::Switch);
+    let index = switch_skips(&a, &b);
+    let a = a.consume_node();
+    let b = b.consume_node();
+    let x = self.create_node(Ctr, 0);
+    let y = self.create_node(Ctr, 0);
+    let s = self.create_node(Ctr, 0);
+    trace!(self.tracer, x.p0, y.p0, s.p0);
+    self.link_port_port(x.p2, y.p0);
+    self.link_port_port(s.p1, x.p0);
+    self.link_port_port(s.p2, Port::ERA);
+    let mut arms = s.p0;
+    for _ in 0 .. index {
+      let skip = self.create_node(Ctr, 0);
+      self.link_port_port(skip.p1, Port::ERA);
+      self.link_port_port(skip.p2, arms);
+      arms = skip.p0;
+    }
+    self.link_wire_port(b.p1, x.p1);
+    self.link_wire_port(b.p2, y.p1);
+    self.link_wire_port(a.p2, y.p2);
+    self.link_wire_port(a.p1, arms);
+  }
+
   /// Interacts a number and a binary numeric operation node.
   ///
   /// ```text
@@ -253,18 +560,22 @@ impl<'a, M: Mode> Net<'a, M> {
     let a = a.consume_node();
     let op = unsafe { Op::try_from(a.lab).unwrap_unchecked() };
     let a1 = a.p1.load_target();
-    if a1.is_num() {
+    if a1.is_num() || op.is_unary() {
       self.rwts.oper += 1;
-      self.half_free(a.p1.addr());
-
-      let out = if op.is_int() {
-        Port::new_num(Tag::Int, op.op(b.num(), a1.num()))
+      self.coverage.record(Interaction::OpNum);
+      let rhs = if a1.is_num() {
+        self.half_free(a.p1.addr());
+        a1.num()
       } else {
-        Port::new_num(Tag::F32, op.op(b.num(), a1.num()))
+        // unary operations ignore their second operand, so it isn't awaited
+        self.link_wire_port(a.p1, Port::ERA);
+        0
       };
 
+      let out = self.apply_op(op, b.num(), rhs);
       self.link_wire_port(a.p2, out);
     } else {
+      self.coverage.record(Interaction::OpSwap);
       let op = op.swap();
       let x = self.create_node(Op, op.into());
       trace!(self.tracer, x.p0);
@@ -273,4 +584,39 @@ impl<'a, M: Mode> Net<'a, M> {
       self.link_wire_port(a.p1, x.p0);
     }
   }
+
+  /// Applies `op` to two numbers, returning the resulting number, or an eraser
+  /// if the operation fails (see [`Net::op_failed`]).
+  #[inline(always)]
+  pub(super) fn apply_op(&mut self, op: Op, a: u64, b: u64) -> Port {
+    match op.op(a, b, &self.ext_ops) {
+      Ok(res) if op.is_int() => Port::new_num(Tag::Int, res),
+      Ok(res) => Port::new_num(Tag::F32, res),
+      Err(error) => self.op_failed(error),
+    }
+  }
+
+  /// Records a linearity violation, and stops reduction by discarding all
+  /// pending redexes.
+  #[cold]
+  fn violate(&mut self, violation: LinearityViolation) {
+    self.violation.get_or_insert(violation);
+    self.redexes.clear();
+  }
+
+  /// Records an operation that could not be performed, and stops reduction by
+  /// discarding all pending redexes; returns the eraser that takes the place
+  /// of the operation's output.
+  #[cold]
+  fn op_failed(&mut self, error: OpError) -> Port {
+    self.op_error.get_or_insert(error);
+    self.redexes.clear();
+    Port::ERA
+  }
+}
+
+/// The number of arms skipped by [`Net::switch_ctr`] when the switch `a`
+/// interacts with the combinator `b`.
+fn switch_skips(a: &Port, b: &Port) -> usize {
+  ((b.lab() - a.lab()) as usize).min(MAX_ARITY - 1)
 }
@@ -19,19 +19,182 @@ pub(super) struct Header {
 pub struct Linker<'h, M: Mode> {
   pub(super) allocator: Allocator<'h>,
   pub rwts: Rewrites,
+  /// The number of times each individual interaction rule has been
+  /// performed; a finer-grained version of `rwts`.
+  pub coverage: Coverage,
+  /// The active pairs waiting to be reduced (in strict mode; in lazy mode,
+  /// active pairs are instead found by walking from the root).
+  ///
+  /// Before reduction, this holds the top-level redexes of the net that was
+  /// loaded, which indicates how much parallelism is initially available.
   pub redexes: RedexQueue,
+  /// Whether skippable active pairs (see [`Port::is_skippable`]) are discarded
+  /// immediately, rather than being added to the redex queue and reduced.
+  ///
+  /// This is `true` by default; disabling it materializes every interaction
+  /// between nilary agents (expanding the references involved), which is
+  /// significantly slower, but can be useful for studying raw interaction
+  /// counts or for debugging.
+  pub skip_pairs: bool,
+  /// Which of the skippable active pairs are skipped, when `skip_pairs` is
+  /// enabled; `None` (the default) skips all of them.
+  ///
+  /// The policy is only consulted for active pairs between two nilary agents
+  /// (references and numbers); the pairs it rejects are reduced as if
+  /// `skip_pairs` were disabled. This allows
+  /// studying the effect of skipping particular interactions, e.g. between
+  /// two references, on rewrite counts.
+  pub skip_policy: Option<SkipPolicy>,
+  /// Whether interactions that erase or duplicate agents are rejected; see
+  /// [`LinearityViolation`] for the interactions this covers.
+  ///
+  /// When such an interaction is encountered, it is not performed; instead, it
+  /// is recorded in `violation`, and reduction stops (on every thread, when
+  /// reducing in parallel).
+  pub linear: bool,
+  /// The first linearity violation encountered, if `linear` is enabled.
+  pub violation: Option<LinearityViolation>,
+  /// The functions implementing the `ext` operations of the net; usually
+  /// those of the host the net was loaded from (see [`Host::ext_ops`]).
+  ///
+  /// [`Host::ext_ops`]: crate::host::Host::ext_ops
+  pub ext_ops: Arc<ExtOps>,
+  /// The first operation that could not be performed, e.g. an `ext` operation
+  /// that is missing from `ext_ops`. Its output is erased, and reduction stops
+  /// (on every thread, when reducing in parallel).
+  pub op_error: Option<OpError>,
+  /// The addresses of the defs whose refs [`Net::expand`] leaves unexpanded
+  /// (in strict mode), so that they read back as refs to the defs, rather than
+  /// as their nets. `None` (the default) expands every ref; see also
+  /// [`Net::expand_where`].
+  pub opaque_refs: Option<Arc<Set<Addr>>>,
+  /// If set, strict reduction stops once more than this fraction of the heap
+  /// is in use, leaving the remaining active pairs in the redex queue and
+  /// setting `memory_exceeded`. When reducing in parallel, every thread stops
+  /// once one of them exceeds this fraction of its share of the heap; the net
+  /// can then be read back, but not reduced further.
+  pub memory_limit: Option<f64>,
+  /// Whether reduction was stopped because of `memory_limit`.
+  pub memory_exceeded: bool,
+  /// Whether each thread of a parallel reduction is pinned to a distinct CPU
+  /// core, so that the OS scheduler doesn't migrate it between cores.
+  ///
+  /// Pinning reduces the variance of timings across runs; it is only
+  /// supported on Linux, and is silently skipped elsewhere (or when the
+  /// affinity can't be set). Thread `i` is pinned to the `i`th core the process
+  /// may run on, wrapping around if there are more threads than cores. The
+  /// workers of a `ThreadPool` remain pinned after the reduction.
+  pub pin_threads: bool,
+  /// Statistics about the distribution of redexes among the threads of a
+  /// parallel reduction, if they are being tracked; see [`ExchangeStats`].
+  pub exchange: Option<ExchangeStats>,
   headers: IntMap<Addr, Header>,
   _mode: PhantomData<M>,
 }
 
 deref!({<'h, M: Mode>} Linker<'h, M> => self.allocator: Allocator<'h>);
 
+/// Decides whether the active pair of two skippable ports (see
+/// [`Port::is_skippable`]) is skipped; see [`Linker::skip_policy`].
+pub type SkipPolicy = fn(&Port, &Port) -> bool;
+
+/// Statistics about the distribution of redexes among the threads of a
+/// parallel reduction, which help diagnose poor scaling.
+///
+/// Between local reductions, the threads of a parallel reduction synchronize
+/// in rounds, in which each of them counts its pending redexes (its *bag*),
+/// and then exchanges redexes with another thread, so as to even out their
+/// bags. The exchanges go through a share buffer of limited size; those that
+/// fill it are limited by it, and those that move no redexes are wasted
+/// synchronization.
+#[derive(Debug, Default, Clone)]
+pub struct ExchangeStats {
+  /// The number of times a thread exchanged redexes with another.
+  pub exchanges: u64,
+  /// The number of exchanges that filled the share buffer.
+  pub full: u64,
+  /// The number of exchanges that moved no redexes.
+  pub empty: u64,
+  /// The number of rounds in which some redexes were pending.
+  pub rounds: u64,
+  /// The sums, over every round, of the smallest, mean, and largest bag.
+  bags: [f64; 3],
+  /// The sum, over every round, of the imbalance of the bags.
+  imbalance: f64,
+}
+
+impl ExchangeStats {
+  /// Records the exchange of `sent` and `received` redexes, out of at most
+  /// `limit` each.
+  #[cfg_attr(not(feature = "std"), allow(unused))] // only used in parallel
+  pub(super) fn exchange(&mut self, sent: usize, received: usize, limit: usize) {
+    self.exchanges += 1;
+    self.full += (sent == limit || received == limit) as u64;
+    self.empty += (sent == 0 && received == 0) as u64;
+  }
+
+  /// Records a round in which the threads held bags of the given sizes, not all
+  /// empty.
+  #[cfg_attr(not(feature = "std"), allow(unused))] // only used in parallel
+  pub(super) fn round(&mut self, bags: impl Iterator<Item = usize>) {
+    let (mut min, mut max, mut sum, mut threads) = (usize::MAX, 0, 0, 0);
+    for bag in bags {
+      (min, max, sum, threads) = (min.min(bag), max.max(bag), sum + bag, threads + 1);
+    }
+    let mean = sum as f64 / threads as f64;
+    self.rounds += 1;
+    self.bags[0] += min as f64;
+    self.bags[1] += mean;
+    self.bags[2] += max as f64;
+    self.imbalance += 1.0 - mean / max as f64;
+  }
+
+  /// The smallest, mean, and largest bag of a round, averaged over every round.
+  pub fn bags(&self) -> [f64; 3] {
+    self.bags.map(|bag| bag / self.rounds.max(1) as f64)
+  }
+
+  /// How unevenly the redexes were distributed, averaged over every round.
+  ///
+  /// The imbalance of a round is `1 - mean / max` of its bags: `0` when every
+  /// thread holds as many redexes, and approaching `1` when a single thread
+  /// holds all of them, while the others idle.
+  pub fn imbalance(&self) -> f64 {
+    self.imbalance / self.rounds.max(1) as f64
+  }
+}
+
+impl<'a> AddAssign<&'a ExchangeStats> for ExchangeStats {
+  fn add_assign(&mut self, rhs: &'a ExchangeStats) {
+    self.exchanges += rhs.exchanges;
+    self.full += rhs.full;
+    self.empty += rhs.empty;
+    self.rounds += rhs.rounds;
+    for (a, b) in self.bags.iter_mut().zip(rhs.bags) {
+      *a += b;
+    }
+    self.imbalance += rhs.imbalance;
+  }
+}
+
 impl<'h, M: Mode> Linker<'h, M> {
   pub fn new(heap: &'h Heap) -> Self {
     Linker {
       allocator: Allocator::new(heap),
       redexes: RedexQueue::default(),
       rwts: Default::default(),
+      coverage: Default::default(),
+      skip_pairs: true,
+      skip_policy: None,
+      linear: false,
+      violation: None,
+      ext_ops: Default::default(),
+      op_error: None,
+      opaque_refs: None,
+      memory_limit: None,
+      memory_exceeded: false,
+      pin_threads: false,
+      exchange: None,
       headers: Default::default(),
       _mode: PhantomData,
     }
@@ -87,7 +250,7 @@ impl<'h, M: Mode> Linker<'h, M> {
   pub fn redux(&mut self, a: Port, b: Port) {
     trace!(self, a, b);
     debug_assert!(!(a.is(Tag::Var) || a.is(Tag::Red) || b.is(Tag::Var) || b.is(Tag::Red)));
-    if a.is_skippable() && b.is_skippable() {
+    if a.is_skippable() && b.is_skippable() && self.skips(&a, &b) {
       self.rwts.eras += 1;
     } else if !M::LAZY {
       // Prioritize redexes that do not allocate memory,
@@ -104,6 +267,14 @@ impl<'h, M: Mode> Linker<'h, M> {
     }
   }
 
+  /// Whether the active pair of `a` and `b`, two nilary agents, is skipped if
+  /// they are skippable; see [`Linker::skip_pairs`] and
+  /// [`Linker::skip_policy`].
+  #[inline(always)]
+  pub(super) fn skips(&self, a: &Port, b: &Port) -> bool {
+    self.skip_pairs && self.skip_policy.map_or(true, |policy| policy(a, b))
+  }
+
   /// Half-links `a_port` to `b_port`, without linking `b_port` back to
   /// `a_port`.
   #[inline(always)]
@@ -140,7 +311,9 @@ impl<'h, M: Mode> Linker<'h, M> {
           a_wire.set_target(b_port.clone());
           self.resolve_redirect_pri(a_port, a_wire, b_port);
         } else {
-          unreachable!();
+          // every port that is not a var is principal, and redirections are
+          // never linked
+          unreachable!("linking to a redirection: a_port = {a_port:?}, a_wire = {a_wire:?}, b_port = {b_port:?}");
         }
       }
     } else {
@@ -221,9 +394,12 @@ impl<'h, M: Mode> Linker<'h, M> {
           return;
         }
       }
-      // Shouldn't be reached.
+      // The target of a wire is always locked, a redirection, a var, a
+      // principal port, or gone, so this shouldn't be reached.
       trace!(self, t_port, a_wire, a_port, b_port);
-      unreachable!()
+      unreachable!(
+        "unexpected target while resolving redirections: t_port = {t_port:?}, a_wire = {a_wire:?}, a_port = {a_port:?}, b_port = {b_port:?}"
+      )
     }
   }
 
@@ -350,6 +526,10 @@ impl<'h, M: Mode> Linker<'h, M> {
   }
 }
 
+/// The active pairs of a net that are waiting to be reduced.
+///
+/// Redexes that will not allocate memory when reduced are kept apart from
+/// the rest, and are reduced first.
 #[derive(Debug, Default)]
 pub struct RedexQueue {
   pub(super) fast: Vec<(Port, Port)>,
@@ -357,11 +537,17 @@ pub struct RedexQueue {
 }
 
 impl RedexQueue {
+  /// Creates an empty queue with room for `capacity` redexes of each priority.
+  pub fn with_capacity(capacity: usize) -> Self {
+    RedexQueue { fast: Vec::with_capacity(capacity), slow: Vec::with_capacity(capacity) }
+  }
+
   /// Returns the highest-priority redex in the queue, if any
   #[inline(always)]
   pub fn pop(&mut self) -> Option<(Port, Port)> {
     self.fast.pop().or_else(|| self.slow.pop())
   }
+  /// The number of redexes in the queue.
   #[inline(always)]
   pub fn len(&self) -> usize {
     self.fast.len() + self.slow.len()
@@ -374,6 +560,7 @@ impl RedexQueue {
   pub fn drain(&mut self) -> impl Iterator<Item = (Port, Port)> + '_ {
     self.fast.drain(..).chain(self.slow.drain(..))
   }
+  /// Iterates over the redexes in the queue, in no particular order.
   #[inline(always)]
   pub fn iter(&self) -> impl Iterator<Item = &(Port, Port)> {
     self.fast.iter().chain(self.slow.iter())
@@ -1,5 +1,7 @@
 use super::*;
 
+use crate::host::Host;
+use alloc::sync::Arc;
 use mem::MaybeUninit;
 
 /// An interaction combinator net.
@@ -13,16 +15,35 @@ pub struct Net<'a, M: Mode> {
 
 deref!({<'a, M: Mode>} Net<'a, M> => self.linker: Linker<'a, M>);
 
+/// The default length of [`Net::trgs`].
+///
+/// Interpreted defs store one target per wire while they are being expanded;
+/// calling a def that needs more than this many grows the buffer to fit.
+pub const DEFAULT_TRGS: usize = 1 << 16;
+
 impl<'h, M: Mode> Net<'h, M> {
   /// Creates an empty net with a given heap.
   pub fn new(heap: &'h Heap) -> Self {
-    let mut net = Net::new_with_root(heap, Wire(ptr::null()));
-    net.root = Wire::new(net.alloc());
+    Net::with_capacity(heap, 0, DEFAULT_TRGS)
+  }
+
+  /// Creates an empty net with a given heap, with room for `redexes` redexes
+  /// before the redex queue must reallocate, and `trgs` targets (see
+  /// [`DEFAULT_TRGS`]).
+  pub fn with_capacity(heap: &'h Heap, redexes: usize, trgs: usize) -> Self {
+    let mut linker = Linker::new(heap);
+    linker.redexes = RedexQueue::with_capacity(redexes);
+    let mut net = Net { linker, tid: 0, tids: 1, trgs: Box::new_uninit_slice(trgs), root: Wire(ptr::null()) };
+    let addr = net.alloc();
+    net.root = Wire::new(addr);
+    // the other half of the root's node is never used
+    net.half_free(addr.other_half());
     net
   }
 
+  #[cfg(feature = "std")]
   pub(super) fn new_with_root(heap: &'h Heap, root: Wire) -> Self {
-    Net { linker: Linker::new(heap), tid: 0, tids: 1, trgs: Box::new_uninit_slice(1 << 16), root }
+    Net { linker: Linker::new(heap), tid: 0, tids: 1, trgs: Box::new_uninit_slice(DEFAULT_TRGS), root }
   }
 
   /// Boots a net from a Def.
@@ -35,20 +56,91 @@ impl<'a, M: Mode> Net<'a, M> {
   /// Reduces at most `limit` redexes.
   ///
   /// If normalized, returns `Some(num_redexes)`.
-  /// If stopped because the limit was reached, returns `None`.
+  /// If stopped because the limit was reached, or because
+  /// [`Linker::memory_limit`] was exceeded, returns `None`.
   #[inline(always)]
   pub fn reduce(&mut self, limit: usize) -> Option<usize> {
+    self.reduce_counted(limit).ok()
+  }
+
+  /// Like [`Net::reduce`], but also returns the number of redexes reduced when
+  /// stopped early, as `Err(num_redexes)`.
+  #[inline(always)]
+  pub fn reduce_counted(&mut self, limit: usize) -> Result<usize, usize> {
     assert!(!M::LAZY);
     let mut count = 0;
+    let max_used = self.memory_limit.map_or(usize::MAX, |limit| (self.heap.0.len() as f64 * limit) as usize);
 
-    while let Some((a, b)) = self.redexes.pop() {
+    while count < limit {
+      let Some((a, b)) = self.redexes.pop() else { return Ok(count) };
       self.interact(a, b);
       count += 1;
+      if self.op_error.is_some() {
+        // redexes linked after the failed operation was recorded
+        self.redexes.clear();
+        return Ok(count);
+      }
+      if self.memory_limit.is_some() && self.used() > max_used && !self.redexes.is_empty() {
+        self.memory_exceeded = true;
+        return Err(count);
+      }
+    }
+    Err(count)
+  }
+
+  /// Performs a single interaction, popping an active pair from the redex
+  /// queue and reducing it. Returns `false`, without doing anything, if there
+  /// are no active pairs left. Only supported in strict mode.
+  ///
+  /// Stepping until this returns `false` reduces the net just like
+  /// [`Net::reduce`]; the refs in the normal form are not expanded unless
+  /// [`Net::expand`] is called first.
+  ///
+  /// An interaction isn't always a single rewrite in [`Linker::rwts`]: pairs
+  /// of nilary agents that are skipped (see [`Linker::skip_pairs`]) are
+  /// counted without being stepped through, and expanding a ref can perform
+  /// some rewrites between its nodes on the spot.
+  pub fn step(&mut self) -> bool {
+    assert!(!M::LAZY);
+    let Some((a, b)) = self.redexes.pop() else { return false };
+    self.interact(a, b);
+    true
+  }
+
+  /// Like [`Net::reduce`], but never allocates beyond the end of the heap:
+  /// before each interaction, checks that the heap has room for every node it
+  /// could create, and if not, leaves the active pair in the redex queue and
+  /// returns [`OutOfMemory`], without blocking or panicking. The net can then
+  /// be read back as is, or discarded.
+  ///
+  /// The nodes created by defs that are not interpreted (e.g. compiled or
+  /// built-in ones) can't be counted ahead of time, so they are still called
+  /// unchecked, and may run out of memory like in [`Net::reduce`].
+  ///
+  /// This doesn't allocate from the heap of the process either, as long as the
+  /// redex queue and the target buffer have enough capacity (see
+  /// [`Net::with_capacity`]).
+  pub fn reduce_bounded(&mut self, limit: usize) -> Result<Option<usize>, OutOfMemory> {
+    assert!(!M::LAZY);
+    let mut count = 0;
+
+    while let Some((a, b)) = self.redexes.pop() {
+      let (rule, a, b) = self.select(a, b);
+      if self.max_nodes(rule, &a, &b).is_some_and(|nodes| nodes > self.available()) {
+        self.redux(a, b);
+        return Err(OutOfMemory);
+      }
+      self.apply(rule, a, b);
+      count += 1;
+      if self.op_error.is_some() {
+        self.redexes.clear();
+        break;
+      }
       if count >= limit {
-        return None;
+        return Ok(None);
       }
     }
-    Some(count)
+    Ok(Some(count))
   }
 
   // Lazy mode weak head normalizer
@@ -74,6 +166,9 @@ impl<'a, M: Mode> Net<'a, M> {
         // If prev is a main port, reduce the active pair.
         if prev.is_principal() {
           self.interact(next, prev.clone());
+          if self.violation.is_some() || self.op_error.is_some() {
+            return Port::ERA;
+          }
           prev = path.pop().unwrap();
           continue;
         // Otherwise, if it is a ref, expand it.
@@ -110,13 +205,51 @@ impl<'a, M: Mode> Net<'a, M> {
     }
   }
 
+  /// Like [`Net::normal`], but calls `f` with each active pair before it is
+  /// reduced, along with the rule that reduces it. Only supported in strict
+  /// mode.
+  ///
+  /// The interactions used internally to expand the refs in the normal form
+  /// are not reported (though the expansions themselves are).
+  pub fn normal_explained(&mut self, mut f: impl FnMut(Rule, &Port, &Port)) {
+    assert!(!M::LAZY);
+    self.expand();
+    while let Some((a, b)) = self.redexes.pop() {
+      let (rule, x, y) = self.select(a.clone(), b.clone());
+      if !is_expand_def(&a) && !is_expand_def(&b) {
+        f(rule, &a, &b);
+      }
+      self.apply(rule, x, y);
+      if self.op_error.is_some() {
+        self.redexes.clear();
+      }
+    }
+  }
+
+  /// Like [`Net::normal`], but calls `f` every `interval` interactions, so that
+  /// the state of a long reduction can be saved along the way (e.g. by reading
+  /// it back). Only supported in strict mode.
+  ///
+  /// The refs in the normal form are expanded once the net has no redexes
+  /// left, without further calls to `f`.
+  pub fn normal_with_checkpoints(&mut self, interval: usize, mut f: impl FnMut(&mut Self)) {
+    assert!(!M::LAZY);
+    self.expand();
+    while self.reduce(interval).is_none() && !self.memory_exceeded {
+      f(self);
+    }
+    if !self.memory_exceeded {
+      self.normal();
+    }
+  }
+
   /// Reduces a net to normal form.
   pub fn normal(&mut self) {
     if M::LAZY {
       self.normal_from(self.root.clone());
     } else {
       self.expand();
-      while !self.redexes.is_empty() {
+      while !self.redexes.is_empty() && !self.memory_exceeded {
         self.reduce(usize::MAX);
       }
     }
@@ -124,22 +257,308 @@ impl<'a, M: Mode> Net<'a, M> {
 }
 
 impl<'h, M: Mode> Net<'h, M> {
+  /// Counts the nodes that have been allocated and not freed, but that are not
+  /// reachable from the root or the redexes.
+  ///
+  /// Any such node has been leaked, which indicates a bug in the runtime. This
+  /// only considers the nodes allocated by this net itself, so it is not
+  /// meaningful after a parallel reduction.
+  pub fn count_leaks(&self) -> usize {
+    let free = self.free_list();
+    let mut reachable = Set::new();
+    let mut visit = vec![Port::new_var(self.root.addr())];
+    visit.extend(self.redexes.iter().flat_map(|(a, b)| [a.clone(), b.clone()]));
+    while let Some(port) = visit.pop() {
+      if port == Port::LOCK {
+        continue;
+      }
+      let words = match port.tag() {
+        Tag::Red | Tag::Var => [port.addr(), Addr::NULL],
+        Tag::Ref | Tag::Int | Tag::F32 => continue,
+        Tag::Op | Tag::Mat | Tag::Ctr => [port.addr(), port.addr().other_half()],
+      };
+      for word in words {
+        if word != Addr::NULL && reachable.insert(word) {
+          visit.push(Port(word.val().load(Relaxed)));
+        }
+      }
+    }
+
+    self
+      .allocated_nodes()
+      .filter(|&node| !free.contains(&node))
+      .filter(|&node| {
+        [node, node.other_half()]
+          .into_iter()
+          .any(|word| word.val().load(Relaxed) != Port::FREE.0 && !reachable.contains(&word))
+      })
+      .count()
+  }
+
+  /// Writes every allocated node that is not in the free list as
+  /// `addr: (left, right)`, followed by the root and the pending redexes.
+  ///
+  /// Like [`Net::count_leaks`], this only considers the nodes allocated by this
+  /// net itself.
+  pub fn dump_heap(&self, f: &mut impl fmt::Write) -> fmt::Result {
+    let free = self.free_list();
+    for node in self.allocated_nodes().filter(|node| !free.contains(node)) {
+      let (left, right) = (node.val().load(Relaxed), node.other_half().val().load(Relaxed));
+      if left != Port::FREE.0 || right != Port::FREE.0 {
+        writeln!(f, "{node:?}: ({:?}, {:?})", Port(left), Port(right))?;
+      }
+    }
+    writeln!(f, "root: {:?}", Port(self.root.addr().val().load(Relaxed)))?;
+    for (a, b) in self.redexes.iter() {
+      writeln!(f, "redex: {a:?} ~ {b:?}")?;
+    }
+    Ok(())
+  }
+
+  /// Copies this net into `heap`, returning an independent net that can be
+  /// reduced separately, e.g. to continue a computation along different paths
+  /// from a shared prefix.
+  ///
+  /// Every node reachable from the root or the redexes is copied, and the
+  /// ports pointing to them are relocated into the new heap; references to
+  /// defs are shared. The rewrite counts and settings of this net are carried
+  /// over, and the redexes keep their priorities. Defs that hold ports into
+  /// the net themselves (such as those of `HVM.log`) are not copied, so calls
+  /// to them that are pending would affect the original net.
+  ///
+  /// This must not be called on a net being reduced in parallel.
+  ///
+  /// ```
+  /// # use hvmc::{ast::Book, run, stdlib::create_host};
+  /// let book: Book = "@main = a & #3 ~ <* #4 a>".parse().unwrap();
+  /// let host = create_host(&book);
+  /// let host = host.lock();
+  /// let heap = run::Heap::new(Some(1 << 16)).unwrap();
+  /// let mut net = run::Net::<run::Strict>::new(&heap);
+  /// net.boot(&host.defs["main"]);
+  /// let copy_heap = run::Heap::new(Some(1 << 16)).unwrap();
+  /// let mut copy = net.snapshot_into(&copy_heap);
+  /// net.normal();
+  /// copy.normal();
+  /// assert_eq!(host.readback(&net), host.readback(&copy));
+  /// ```
+  pub fn snapshot_into<'b>(&self, heap: &'b Heap) -> Net<'b, M> {
+    assert!(!M::LAZY);
+    let mut net = Net {
+      linker: Linker::new(heap),
+      tid: 0,
+      tids: 1,
+      trgs: Box::new_uninit_slice(self.trgs.len()),
+      root: Wire(ptr::null()),
+    };
+    net.rwts = self.rwts;
+    net.coverage = self.coverage;
+    net.skip_pairs = self.skip_pairs;
+    net.skip_policy = self.skip_policy;
+    net.linear = self.linear;
+    net.violation = self.violation;
+    net.ext_ops = self.ext_ops.clone();
+    net.op_error = self.op_error;
+    net.opaque_refs = self.opaque_refs.clone();
+
+    let mut state = Snapshot { net, nodes: Map::new(), pending: vec![] };
+    let root = state.relocate(Port::new_var(self.root.addr()));
+    state.net.root = root.wire();
+    let mut relocate_all = |redexes: &[(Port, Port)]| {
+      redexes.iter().map(|(a, b)| (state.relocate(a.clone()), state.relocate(b.clone()))).collect()
+    };
+    let redexes = RedexQueue { fast: relocate_all(&self.redexes.fast), slow: relocate_all(&self.redexes.slow) };
+    state.net.redexes = redexes;
+    while let Some(node) = state.pending.pop() {
+      let copy = state.nodes[&node];
+      for (word, copy) in [(node, copy), (node.other_half(), copy.other_half())] {
+        let port = state.relocate(Port(word.val().load(Relaxed)));
+        copy.val().store(port.0, Relaxed);
+      }
+    }
+    state.net.allocs = self.allocs;
+    state.net
+  }
+
+  /// Serializes this net into a canonical image, which only depends on the
+  /// structure of the net, and not on where its nodes are in the heap.
+  ///
+  /// The layout of the heap after a parallel reduction depends on how the
+  /// interactions were distributed among the threads. This compacts the nodes
+  /// reachable from the root and the redexes, relocating them in the order
+  /// they are first reached by a traversal like that of
+  /// [`Net::snapshot_into`], so reducing the same net always results in the
+  /// same image, whether in parallel or not.
+  ///
+  /// The image is a sequence of little-endian words: the root, the number of
+  /// redexes, the two ports of each redex, and then the two words of each
+  /// node. Ports are encoded as in the runtime, except that the address of a
+  /// port into a node is replaced by the offset in bytes of its word from the
+  /// first node, and that of a ref by one more than the id of its def in
+  /// `host` (see [`Host::def_id`]), times eight; the eraser remains `0`.
+  ///
+  /// The net must not have any pending expansions (see [`Net::expand`]), and
+  /// must not be being reduced in parallel.
+  pub fn canonical_image(&self, host: &Host) -> Vec<u8> {
+    let mut image = CanonicalImage { host, nodes: Map::new(), words: vec![], pending: vec![] };
+    let root = image.relocate(Port::new_var(self.root.addr()));
+    let mut header = vec![root.0, self.redexes.len() as u64];
+    for (a, b) in self.redexes.iter() {
+      header.extend([image.relocate(a.clone()).0, image.relocate(b.clone()).0]);
+    }
+    while let Some(node) = image.pending.pop() {
+      let index = image.nodes[&node];
+      for (i, word) in [node, node.other_half()].into_iter().enumerate() {
+        image.words[2 * index + i] = image.relocate(Port(word.val().load(Relaxed))).0;
+      }
+    }
+    header.into_iter().chain(image.words).flat_map(u64::to_le_bytes).collect()
+  }
+
+  /// Returns the addresses of the nodes this net has allocated from the heap.
+  fn allocated_nodes(&self) -> impl Iterator<Item = Addr> + '_ {
+    (0 .. self.next).map(|i| Addr(&self.heap.0[i].0 as *const _ as usize))
+  }
+
+  /// Returns the addresses of the nodes in this net's free list.
+  fn free_list(&self) -> Set<Addr> {
+    let mut free = Set::new();
+    let mut head = self.head;
+    while head != Addr::NULL {
+      free.insert(head);
+      head = Addr(head.val().load(Relaxed) as usize);
+    }
+    free
+  }
+
   /// Expands [`Tag::Ref`] nodes in the tree connected to `root`.
+  ///
+  /// Refs to the defs in [`Linker::opaque_refs`] are left in the tree as-is.
   pub fn expand(&mut self) {
+    let opaque = self.opaque_refs.clone();
+    self.expand_root(opaque);
+  }
+
+  /// Like [`Net::expand`], but only expands the refs to the defs of `host`
+  /// whose names satisfy `pred`; other refs are left in the tree as-is.
+  ///
+  /// Refs to defs that are not in `host` are always expanded.
+  pub fn expand_where(&mut self, host: &Host, pred: impl Fn(&str) -> bool) {
+    let opaque = host.back.iter().filter(|(_, name)| !pred(name)).map(|(&addr, _)| addr).collect();
+    self.expand_root(Some(Arc::new(opaque)));
+  }
+
+  fn expand_root(&mut self, opaque: Option<Arc<Set<Addr>>>) {
     assert!(!M::LAZY);
     let (new_root, out_port) = self.create_wire();
     let old_root = mem::replace(&mut self.root, new_root);
-    self.link_wire_port(old_root, ExpandDef::new(out_port));
+    self.link_wire_port(old_root, ExpandDef::new(out_port, opaque));
+  }
+}
+
+/// The state of [`Net::snapshot_into`].
+struct Snapshot<'b, M: Mode> {
+  net: Net<'b, M>,
+  /// Maps the nodes of the original net to their copies.
+  nodes: Map<Addr, Addr>,
+  /// Nodes whose contents have not yet been copied.
+  pending: Vec<Addr>,
+}
+
+impl<'b, M: Mode> Snapshot<'b, M> {
+  /// Returns the copy of the word at `addr`, allocating a copy of its node if
+  /// necessary.
+  fn relocate_addr(&mut self, addr: Addr) -> Addr {
+    let node = addr.left_half();
+    let copy = *self.nodes.entry(node).or_insert_with(|| {
+      self.pending.push(node);
+      self.net.alloc()
+    });
+    if addr == node { copy } else { copy.other_half() }
+  }
+
+  /// Returns the equivalent of `port` in the copied net.
+  fn relocate(&mut self, port: Port) -> Port {
+    match port.tag() {
+      _ if port == Port::FREE || port == Port::LOCK => port,
+      Tag::Red => unreachable!("cannot snapshot a net during parallel reduction"),
+      Tag::Var => Port::new_var(self.relocate_addr(port.addr())),
+      // pending expansions hold a port into the net, so they must be copied
+      Tag::Ref if is_expand_def(&port) => {
+        let def = unsafe { &*Def::downcast_ptr::<ExpandDef>(port.addr().def()).unwrap() };
+        ExpandDef::new(self.relocate(def.data.out.clone()), def.data.opaque.clone())
+      }
+      Tag::Ref | Tag::Int | Tag::F32 => port,
+      Tag::Op | Tag::Mat | Tag::Ctr => Port::new(port.tag(), port.lab(), self.relocate_addr(port.addr())),
+    }
+  }
+}
+
+/// The state of [`Net::canonical_image`].
+struct CanonicalImage<'a> {
+  host: &'a Host,
+  /// Maps the nodes of the net to their indices in the image.
+  nodes: Map<Addr, usize>,
+  /// The words of the nodes in the image.
+  words: Vec<u64>,
+  /// Nodes whose contents have not yet been written.
+  pending: Vec<Addr>,
+}
+
+impl CanonicalImage<'_> {
+  /// Returns the offset in the image of the word at `addr`, assigning an index
+  /// to its node if necessary.
+  fn relocate_addr(&mut self, addr: Addr) -> Addr {
+    let node = addr.left_half();
+    let index = *self.nodes.entry(node).or_insert_with(|| {
+      self.pending.push(node);
+      self.words.extend([0, 0]);
+      self.words.len() / 2 - 1
+    });
+    Addr(index * mem::size_of::<Node>() + (addr.0 - node.0))
+  }
+
+  /// Returns the encoding of `port` in the image.
+  fn relocate(&mut self, port: Port) -> Port {
+    match port.tag() {
+      _ if port == Port::FREE || port == Port::LOCK || port == Port::ERA => port,
+      Tag::Red => unreachable!("cannot serialize a net during parallel reduction"),
+      Tag::Var => Port::new_var(self.relocate_addr(port.addr())),
+      Tag::Ref => {
+        assert!(!is_expand_def(&port), "cannot serialize a net with pending expansions");
+        let id = self.host.def_id(&self.host.back[&port.addr()]).unwrap();
+        Port::new(Tag::Ref, port.lab(), Addr((id as usize + 1) * 8))
+      }
+      Tag::Int | Tag::F32 => port,
+      Tag::Op | Tag::Mat | Tag::Ctr => Port::new(port.tag(), port.lab(), self.relocate_addr(port.addr())),
+    }
   }
 }
 
 struct ExpandDef {
   out: Port,
+  /// The addresses of the defs that should not be expanded; see
+  /// [`Net::expand_where`].
+  opaque: Option<Arc<Set<Addr>>>,
+}
+
+fn is_expand_def(port: &Port) -> bool {
+  port.tag() == Tag::Ref && *port != Port::ERA && unsafe { Def::downcast_ptr::<ExpandDef>(port.addr().def()) }.is_some()
+}
+
+/// If `port` is a pending expansion (see [`Net::expand`]), returns the port the
+/// expanded tree will be linked to.
+pub(crate) fn expansion_target(port: &Port) -> Option<&Port> {
+  if port.tag() != Tag::Ref || *port == Port::ERA {
+    return None;
+  }
+  let def = unsafe { Def::downcast_ptr::<ExpandDef>(port.addr().def())? };
+  Some(unsafe { &(*def).data.out })
 }
 
 impl ExpandDef {
-  fn new(out: Port) -> Port {
-    Port::new_ref(Box::leak(Box::new(Def::new(LabSet::ALL, ExpandDef { out }))))
+  fn new(out: Port, opaque: Option<Arc<Set<Addr>>>) -> Port {
+    Port::new_ref(Box::leak(Box::new(Def::new(LabSet::ALL, ExpandDef { out, opaque }))))
   }
 }
 
@@ -151,7 +570,7 @@ impl AsDef for ExpandDef {
         let def = *Box::from_raw(def as *mut Def<Self>);
         let other = *Box::from_raw(other as *mut Def<Self>);
         return net.link_port_port(def.data.out, other.data.out);
-      } else {
+      } else if !(*def).data.opaque.as_ref().is_some_and(|opaque| opaque.contains(&port.addr())) {
         return net.call(port, Port::new_ref(Def::upcast(unsafe { &*def })));
       }
     }
@@ -165,8 +584,8 @@ impl AsDef for ExpandDef {
         let old = port.consume_node();
         let new = net.create_node(tag, old.lab);
         net.link_port_port(def.data.out, new.p0);
-        net.link_wire_port(old.p1, ExpandDef::new(new.p1));
-        net.link_wire_port(old.p2, ExpandDef::new(new.p2));
+        net.link_wire_port(old.p1, ExpandDef::new(new.p1, def.data.opaque.clone()));
+        net.link_wire_port(old.p2, ExpandDef::new(new.p2, def.data.opaque));
       }
     }
   }
@@ -1,19 +1,162 @@
 #![cfg(feature = "std")]
 
-use std::{sync::Barrier, thread};
+// the stop flags are only exchanged across barriers, so they are not fuzzed
+use core::sync::atomic::{self as core_atomic, AtomicBool};
+use std::{
+  panic,
+  sync::{Condvar, Mutex},
+  thread::{self, Scope, ScopedJoinHandle},
+};
 
 use ::alloc::sync::Arc;
 use atomic::AtomicUsize;
 
 use super::*;
 
+/// See [`Net::parallel_normal_with_progress`].
+type ProgressFn<'a> = dyn Fn(usize, &Rewrites) + Sync + 'a;
+
+/// A reduction started by [`Net::spawn_parallel_normal`], whose progress can be
+/// polled from other threads while it runs.
+pub struct ReductionHandle<'s> {
+  progress: Arc<ReductionProgress>,
+  thread: ScopedJoinHandle<'s, ()>,
+}
+
+#[derive(Default)]
+struct ReductionProgress {
+  redexes: AtomicUsize,
+  rwts: AtomicRewrites,
+}
+
+impl ReductionHandle<'_> {
+  /// The number of pending redexes, as of the last synchronization round.
+  pub fn redexes(&self) -> usize {
+    self.progress.redexes.load(Relaxed)
+  }
+
+  /// The rewrites performed so far, as of the last synchronization round.
+  pub fn rewrites(&self) -> Rewrites {
+    let mut rwts = Rewrites::default();
+    self.progress.rwts.add_to(&mut rwts);
+    rwts
+  }
+
+  /// Whether the reduction has finished.
+  pub fn is_finished(&self) -> bool {
+    self.thread.is_finished()
+  }
+
+  /// Waits for the reduction to finish, propagating any panic.
+  pub fn join(self) {
+    if let Err(err) = self.thread.join() {
+      panic::resume_unwind(err)
+    }
+  }
+}
+
+impl ReductionProgress {
+  fn update(&self, redexes: usize, rwts: &Rewrites) {
+    self.redexes.store(redexes, Relaxed);
+    self.rwts.anni.store(rwts.anni, Relaxed);
+    self.rwts.comm.store(rwts.comm, Relaxed);
+    self.rwts.eras.store(rwts.eras, Relaxed);
+    self.rwts.dref.store(rwts.dref, Relaxed);
+    self.rwts.oper.store(rwts.oper, Relaxed);
+  }
+}
+
+/// Like [`std::sync::Barrier`], but poisoned when one of the threads using it
+/// panics; as the other threads would otherwise wait for it forever, they then
+/// panic too, on their next (or current) wait.
+struct Barrier {
+  threads: usize,
+  /// the number of threads waiting, the generation, and whether it's poisoned
+  state: Mutex<(usize, usize, bool)>,
+  cvar: Condvar,
+}
+
+impl Barrier {
+  fn new(threads: usize) -> Self {
+    Barrier { threads, state: Mutex::new((0, 0, false)), cvar: Condvar::new() }
+  }
+
+  fn wait(&self) {
+    let mut state = self.state.lock().unwrap();
+    let generation = state.1;
+    state.0 += 1;
+    if state.0 == self.threads {
+      *state = (0, generation + 1, state.2);
+      self.cvar.notify_all();
+    } else {
+      while state.1 == generation && !state.2 {
+        state = self.cvar.wait(state).unwrap();
+      }
+    }
+    let poisoned = state.2;
+    drop(state);
+    if poisoned {
+      panic!("another thread panicked during parallel reduction");
+    }
+  }
+
+  fn poison(&self) {
+    self.state.lock().unwrap().2 = true;
+    self.cvar.notify_all();
+  }
+}
+
+/// Poisons the barrier if the thread panics.
+struct PoisonOnPanic<'a>(&'a Barrier);
+
+impl Drop for PoisonOnPanic<'_> {
+  fn drop(&mut self) {
+    if thread::panicking() {
+      self.0.poison();
+    }
+  }
+}
+
+/// Pins the current thread to the `i`th core it may run on (wrapping around);
+/// see [`Linker::pin_threads`]. Does nothing if the affinity can't be set.
+#[cfg(target_os = "linux")]
+fn pin_to_core(i: usize) {
+  // SAFETY: `cpu_set_t` is plain data, and the calls only access the sets
+  unsafe {
+    let mut set: libc::cpu_set_t = mem::zeroed();
+    if libc::sched_getaffinity(0, mem::size_of_val(&set), &mut set) != 0 {
+      return;
+    }
+    let cores = (0 .. libc::CPU_SETSIZE as usize).filter(|&core| libc::CPU_ISSET(core, &set)).collect::<Vec<_>>();
+    if cores.is_empty() {
+      return;
+    }
+    libc::CPU_ZERO(&mut set);
+    libc::CPU_SET(cores[i % cores.len()], &mut set);
+    libc::sched_setaffinity(0, mem::size_of_val(&set), &set);
+  }
+}
+
+#[cfg(not(target_os = "linux"))]
+fn pin_to_core(_: usize) {}
+
 impl<'h, M: Mode> Net<'h, M> {
   /// Forks the net into `tids` child nets, for parallel operation.
   pub fn fork(&mut self, tids: usize) -> impl Iterator<Item = Self> + '_ {
     let redexes_len = self.linker.redexes.len();
+    // the depths of the existing nodes are not shared with the threads
+    let depth = self.linker.depth.as_ref().map(|depth| CausalDepth { max: depth.max, ..Default::default() });
+    let alloc_stats = self.linker.alloc_stats.is_some();
     let mut redexes = self.linker.redexes.drain();
     let heap = &self.linker.allocator.heap;
     let next = &self.linker.allocator.next;
+    let skip_pairs = self.linker.skip_pairs;
+    let skip_policy = self.linker.skip_policy;
+    let linear = self.linker.linear;
+    let ext_ops = &self.linker.ext_ops;
+    let memory_limit = self.linker.memory_limit;
+    let pin_threads = self.linker.pin_threads;
+    let exchange = self.linker.exchange.is_some();
     let root = &self.root;
     (0 .. tids).map(move |tid| {
       let heap_size = (heap.0.len() / tids) & !63; // round down to needed alignment
@@ -24,6 +167,15 @@ impl<'h, M: Mode> Net<'h, M> {
       net.head = if tid == 0 { net.head } else { Addr::NULL };
       net.tid = tid;
       net.tids = tids;
+      net.skip_pairs = skip_pairs;
+      net.skip_policy = skip_policy;
+      net.linear = linear;
+      net.ext_ops = ext_ops.clone();
+      net.memory_limit = memory_limit;
+      net.pin_threads = pin_threads;
+      net.exchange = exchange.then(Default::default);
+      net.alloc_stats = alloc_stats.then(Default::default);
+      net.depth = depth.clone();
       net.tracer.set_tid(tid);
       let count = redexes_len / (tids - tid);
       (&mut redexes).take(count).for_each(|i| net.redux(i.0, i.1));
@@ -33,59 +185,311 @@ impl<'h, M: Mode> Net<'h, M> {
 
   // Evaluates a term to normal form in parallel
   pub fn parallel_normal(&mut self) {
+    self._parallel_normal(thread::available_parallelism().unwrap().get(), None, None)
+  }
+
+  /// Like [`Net::parallel_normal`], but uses `threads` threads instead of one
+  /// per core. `threads` is rounded down to a power of two.
+  pub fn parallel_normal_with_threads(&mut self, threads: usize) {
+    self._parallel_normal(threads, None, None)
+  }
+
+  /// Like [`Net::parallel_normal`], but runs on the worker threads of `pool`,
+  /// rather than spawning new threads. Uses every thread of the pool, rounded
+  /// down to a power of two.
+  ///
+  /// ```
+  /// # use hvmc::{ast::Book, run, stdlib::create_host};
+  /// let book: Book = "@main = a & #3 ~ <* #4 a>".parse().unwrap();
+  /// let host = create_host(&book);
+  /// let pool = run::ThreadPool::new(4);
+  /// let heap = run::Heap::new(Some(1 << 16)).unwrap();
+  /// for _ in 0 .. 10 {
+  ///   let mut net = run::Net::<run::Strict>::new(&heap);
+  ///   net.boot(&host.lock().defs["main"]);
+  ///   net.parallel_normal_on(&pool);
+  /// }
+  /// ```
+  pub fn parallel_normal_on(&mut self, pool: &ThreadPool) {
+    self._parallel_normal(pool.threads(), None, Some(pool))
+  }
+
+  /// Like [`Net::parallel_normal`], but periodically calls `progress` with the
+  /// total number of pending redexes and the rewrites performed so far.
+  ///
+  /// `progress` is called by a single thread, once per synchronization round.
+  ///
+  /// ```
+  /// # use hvmc::{ast::Book, run, stdlib::create_host};
+  /// let book: Book = "@main = a & #3 ~ <* #4 a>".parse().unwrap();
+  /// let host = create_host(&book);
+  /// let heap = run::Heap::new(None).unwrap();
+  /// let mut net = run::Net::<run::Strict>::new(&heap);
+  /// net.boot(&host.lock().defs["main"]);
+  /// net.parallel_normal_with_progress(&|redexes, rwts| {
+  ///   eprintln!("{redexes} redexes left after {} rewrites", rwts.total());
+  /// });
+  /// ```
+  pub fn parallel_normal_with_progress(&mut self, progress: &(dyn Fn(usize, &Rewrites) + Sync)) {
+    self._parallel_normal(thread::available_parallelism().unwrap().get(), Some(progress), None)
+  }
+
+  /// Like [`Net::parallel_normal`], but runs on a new thread of `scope` and
+  /// returns immediately, with a handle through which the progress of the
+  /// reduction can be polled.
+  ///
+  /// ```
+  /// # use hvmc::{ast::Book, run, stdlib::create_host};
+  /// let book: Book = "@main = a & #3 ~ <* #4 a>".parse().unwrap();
+  /// let host = create_host(&book);
+  /// let heap = run::Heap::new(None).unwrap();
+  /// let mut net = run::Net::<run::Strict>::new(&heap);
+  /// net.boot(&host.lock().defs["main"]);
+  /// std::thread::scope(|s| {
+  ///   let handle = net.spawn_parallel_normal(s);
+  ///   while !handle.is_finished() {
+  ///     eprintln!("{} redexes left after {} rewrites", handle.redexes(), handle.rewrites().total());
+  ///   }
+  ///   handle.join();
+  /// });
+  /// ```
+  pub fn spawn_parallel_normal<'s>(&'s mut self, scope: &'s Scope<'s, '_>) -> ReductionHandle<'s> {
+    let progress = Arc::new(ReductionProgress::default());
+    let thread = {
+      let progress = Arc::clone(&progress);
+      scope.spawn(move || {
+        let threads = thread::available_parallelism().unwrap().get();
+        self._parallel_normal(threads, Some(&|redexes, rwts| progress.update(redexes, rwts)), None);
+        progress.update(0, &self.rwts);
+      })
+    };
+    ReductionHandle { progress, thread }
+  }
+
+  /// Like [`Net::parallel_normal_with_threads`], but only reduces the redexes
+  /// in the region of the net below the subtree at `path`, leaving the others
+  /// pending; this is useful when only that part of the net is expensive to
+  /// reduce, e.g. after reducing the rest of it on a single thread.
+  ///
+  /// `path` is a sequence of `0`s and `1`s, selecting the first or second
+  /// auxiliary port of each binary node from the root; e.g. in `(a {2 b c})`,
+  /// `[1, 0]` is `b`, and `[]` is the whole tree. The region is made up of the
+  /// subtree at `path`, and the trees of the redexes connected to it by wires,
+  /// directly or through other redexes, but not through the rest of the root
+  /// tree. The redexes in the region can't interact with the others, so
+  /// reducing them leaves the rest of the net as it is.
+  ///
+  /// Unlike [`Net::parallel_normal`], the refs in the normal form of the region
+  /// are not expanded (see [`Net::expand`]).
+  ///
+  /// Returns the number of redexes in the region, which were reduced in
+  /// parallel.
+  ///
+  /// # Panics
+  ///
+  /// Panics if `path` goes through a port that is not that of a node.
+  ///
+  /// ```
+  /// # use hvmc::{ast::Book, run, stdlib::create_host};
+  /// let book: Book = "@main = (a b) & (c c) ~ (#1 a) & (d d) ~ (#2 b)".parse().unwrap();
+  /// let host = create_host(&book);
+  /// let host = host.lock();
+  /// let heap = run::Heap::new(None).unwrap();
+  /// let mut net = run::Net::<run::Strict>::new(&heap);
+  /// net.boot(&host.defs["main"]);
+  /// assert_eq!(net.parallel_normal_region(2, &[1]), 1);
+  /// assert_eq!(host.readback(&net).to_string(), "(a #2)\n  & (b b) ~ (#1 a)");
+  /// ```
+  pub fn parallel_normal_region(&mut self, threads: usize, path: &[usize]) -> usize {
     assert!(!M::LAZY);
+    let region = self.region_redexes(path);
+    let mut others = mem::take(&mut self.linker.redexes);
+    let redexes = &mut self.linker.redexes;
+    let mut index = 0;
+    for (queue, selected) in [(&mut others.fast, &mut redexes.fast), (&mut others.slow, &mut redexes.slow)] {
+      queue.retain(|redex| {
+        index += 1;
+        if region.contains(&(index - 1)) {
+          selected.push(redex.clone());
+          false
+        } else {
+          true
+        }
+      });
+    }
+    self._parallel_reduce(threads, None, None);
+    others.drain().for_each(|(a, b)| self.redux(a, b));
+    region.len()
+  }
+
+  /// Returns the indices, in the order of [`RedexQueue::iter`], of the redexes
+  /// in the region below `path`; see [`Net::parallel_normal_region`].
+  fn region_redexes(&self, path: &[usize]) -> Set<usize> {
+    let mut subtree = self.root.addr();
+    for &step in path {
+      let port = Port(subtree.val().load(Relaxed));
+      assert!(port.is_full_node(), "no node at {path:?}: {port:?}");
+      subtree = if step == 0 { port.addr() } else { port.addr().other_half() };
+    }
+
+    // the tree each wire end belongs to: `0` for the subtree, `1` for the rest
+    // of the root tree, and `i + 2` for the redex `i`
+    let mut owners = Map::new();
+    let mut visit = vec![(1, self.root.addr())];
+    for (i, (a, b)) in self.redexes.iter().enumerate() {
+      for port in [a, b].into_iter().filter(|port| port.is_full_node()) {
+        visit.extend([(i + 2, port.addr()), (i + 2, port.addr().other_half())]);
+      }
+    }
+    while let Some((tree, word)) = visit.pop() {
+      let tree = if word == subtree { 0 } else { tree };
+      let port = Port(word.val().load(Relaxed));
+      if port.tag() == Tag::Var {
+        owners.insert(word, tree);
+      } else if port.is_full_node() {
+        visit.extend([(tree, port.addr()), (tree, port.addr().other_half())]);
+      }
+    }
+
+    let mut wires = Map::<usize, Vec<usize>>::new();
+    for (word, &tree) in &owners {
+      let other = owners[&Port(word.val().load(Relaxed)).addr()];
+      wires.entry(tree).or_default().push(other);
+    }
+    let mut region = Set::from([0]);
+    let mut visit = vec![0];
+    while let Some(tree) = visit.pop() {
+      for &other in wires.get(&tree).into_iter().flatten() {
+        if other != 1 && region.insert(other) {
+          visit.push(other);
+        }
+      }
+    }
+    region.into_iter().filter(|&tree| tree >= 2).map(|tree| tree - 2).collect()
+  }
 
+  fn _parallel_normal(&mut self, threads: usize, progress: Option<&ProgressFn>, pool: Option<&ThreadPool>) {
+    assert!(!M::LAZY);
     self.expand();
+    self._parallel_reduce(threads, progress, pool)
+  }
+
+  fn _parallel_reduce(&mut self, threads: usize, progress: Option<&ProgressFn>, pool: Option<&ThreadPool>) {
+    assert!(threads > 0);
 
     const SHARE_LIMIT: usize = 1 << 12; // max share redexes per split
     const LOCAL_LIMIT: usize = 1 << 18; // max local rewrites per epoch
 
     // Local thread context
     struct ThreadContext<'a, M: Mode> {
-      tid: usize,                             // thread id
-      tlog2: usize,                           // log2 of thread count
-      tick: usize,                            // current tick
-      net: Net<'a, M>,                        // thread's own net object
-      delta: &'a AtomicRewrites,              // global delta rewrites
-      share: &'a Vec<(AtomicU64, AtomicU64)>, // global share buffer
-      rlens: &'a Vec<AtomicUsize>,            // global redex lengths (only counting shareable ones)
-      total: &'a AtomicUsize,                 // total redex length
-      barry: Arc<Barrier>,                    // synchronization barrier
+      tid: usize,                                       // thread id
+      tlog2: usize,                                     // log2 of thread count
+      tick: usize,                                      // current tick
+      round: usize,                                     // current counting round
+      net: Net<'a, M>,                                  // thread's own net object
+      delta: &'a AtomicRewrites,                        // global delta rewrites
+      allocs: &'a AtomicU64,                            // global allocation count
+      depth: &'a AtomicU64,                             // greatest causal depth
+      share: &'a Vec<(AtomicU64, AtomicU64)>,           // global share buffer
+      rlens: &'a Vec<[AtomicUsize; 2]>,                 // global redex lengths (shareable, total), per round parity
+      barry: Arc<Barrier>,                              // synchronization barrier
+      coverage: &'a Mutex<Coverage>,                    // global interaction counts
+      exchange: &'a Mutex<ExchangeStats>,               // global exchange statistics
+      alloc_stats: &'a Mutex<AllocStats>,               // global allocation statistics
+      violation: &'a Mutex<Option<LinearityViolation>>, // first linearity violation
+      op_error: &'a Mutex<Option<OpError>>,             // first failed operation
+      leftover: &'a Mutex<Vec<(Port, Port)>>,           // redexes left when stopped early
+      stop: &'a [AtomicBool; 2],                        // whether to stop reducing, per round parity
+      progress: Option<&'a ProgressFn<'a>>,             // progress callback
+      base: Rewrites,                                   // rewrites performed before forking
     }
 
     // Initialize global objects
-    let cores = thread::available_parallelism().unwrap().get();
-    let tlog2 = cores.ilog2() as usize;
+    let tlog2 = threads.ilog2() as usize;
     let tids = 1 << tlog2;
     let delta = AtomicRewrites::default(); // delta rewrite counter
-    let rlens = (0 .. tids).map(|_| AtomicUsize::new(0)).collect::<Vec<_>>();
+    let allocs = AtomicU64::new(0); // allocation counter
+    let depth = AtomicU64::new(0); // greatest causal depth
+    let rlens = (0 .. 2 * tids).map(|_| Default::default()).collect::<Vec<_>>();
     let share = (0 .. SHARE_LIMIT * tids).map(|_| Default::default()).collect::<Vec<_>>();
-    let total = AtomicUsize::new(0); // sum of redex bag length
     let barry = Arc::new(Barrier::new(tids)); // global barrier
+    let coverage = Mutex::new(Coverage::default()); // interaction counts
+    let exchange = Mutex::new(ExchangeStats::default()); // exchange statistics
+    let alloc_stats = Mutex::new(AllocStats::default()); // allocation statistics
+    let violation = Mutex::new(None); // first linearity violation
+    let op_error = Mutex::new(None); // first failed operation
+    let leftover = Mutex::new(Vec::new()); // redexes left when stopped early
+    let stop = [AtomicBool::new(false), AtomicBool::new(false)]; // stop flags
+    let base = self.rwts; // rewrites performed before forking
 
     // Perform parallel reductions
-    thread::scope(|s| {
-      for net in self.fork(tids) {
-        let mut ctx = ThreadContext {
-          tid: net.tid,
-          tick: 0,
-          net,
-          tlog2,
-          delta: &delta,
-          share: &share,
-          rlens: &rlens,
-          total: &total,
-          barry: Arc::clone(&barry),
-        };
-        thread::Builder::new().name(format!("t{:02x?}", ctx.net.tid)).spawn_scoped(s, move || main(&mut ctx)).unwrap();
-      }
-    });
+    let contexts = self
+      .fork(tids)
+      .map(|net| ThreadContext {
+        tid: net.tid,
+        tick: 0,
+        round: 0,
+        net,
+        tlog2,
+        delta: &delta,
+        allocs: &allocs,
+        depth: &depth,
+        share: &share,
+        rlens: &rlens,
+        barry: Arc::clone(&barry),
+        coverage: &coverage,
+        exchange: &exchange,
+        alloc_stats: &alloc_stats,
+        violation: &violation,
+        op_error: &op_error,
+        leftover: &leftover,
+        stop: &stop,
+        progress,
+        base,
+      })
+      .collect::<Vec<_>>();
+    if let Some(pool) = pool {
+      let contexts = contexts.into_iter().map(|ctx| Mutex::new(Some(ctx))).collect::<Vec<_>>();
+      pool.run(tids, &|tid| main(&mut contexts[tid].lock().unwrap().take().unwrap()));
+    } else {
+      thread::scope(|s| {
+        for mut ctx in contexts {
+          thread::Builder::new()
+            .name(format!("t{:02x?}", ctx.net.tid))
+            .spawn_scoped(s, move || main(&mut ctx))
+            .unwrap();
+        }
+      });
+    }
 
     delta.add_to(&mut self.rwts);
+    self.allocs += allocs.load(Relaxed);
+    if let Some(max) = self.depth.as_mut().map(|depth| &mut depth.max) {
+      *max = (*max).max(depth.load(Relaxed));
+    }
+    self.coverage += &coverage.into_inner().unwrap();
+    if let Some(stats) = &mut self.exchange {
+      *stats += &exchange.into_inner().unwrap();
+    }
+    if let Some(stats) = &mut self.alloc_stats {
+      *stats += &alloc_stats.into_inner().unwrap();
+    }
+    self.violation = self.violation.take().or(violation.into_inner().unwrap());
+    self.op_error = self.op_error.take().or(op_error.into_inner().unwrap());
+    let leftover = leftover.into_inner().unwrap();
+    if !leftover.is_empty() && self.violation.is_none() && self.op_error.is_none() {
+      self.memory_exceeded = true;
+      leftover.into_iter().for_each(|(a, b)| self.redux(a, b));
+    }
 
     // Main reduction loop
     #[inline(always)]
     fn main<M: Mode>(ctx: &mut ThreadContext<M>) {
+      if ctx.net.pin_threads {
+        pin_to_core(ctx.tid);
+      }
+      let barry = Arc::clone(&ctx.barry);
+      let _poison = PoisonOnPanic(&barry);
       loop {
         reduce(ctx);
         if count(ctx) == 0 {
@@ -93,6 +497,24 @@ impl<'h, M: Mode> Net<'h, M> {
         }
       }
       ctx.net.rwts.add_to(ctx.delta);
+      ctx.allocs.fetch_add(ctx.net.allocs, Relaxed);
+      if let Some(depth) = &ctx.net.depth {
+        ctx.depth.fetch_max(depth.max, Relaxed);
+      }
+      *ctx.coverage.lock().unwrap() += &ctx.net.coverage;
+      if let Some(exchange) = &ctx.net.exchange {
+        *ctx.exchange.lock().unwrap() += exchange;
+      }
+      if let Some(stats) = &ctx.net.alloc_stats {
+        *ctx.alloc_stats.lock().unwrap() += stats;
+      }
+      if let Some(violation) = ctx.net.violation {
+        ctx.violation.lock().unwrap().get_or_insert(violation);
+      }
+      if let Some(error) = ctx.net.op_error {
+        ctx.op_error.lock().unwrap().get_or_insert(error);
+      }
+      ctx.leftover.lock().unwrap().extend(ctx.net.redexes.drain());
     }
 
     // Reduce redexes locally, then share with target
@@ -103,22 +525,74 @@ impl<'h, M: Mode> Net<'h, M> {
         if count(ctx) == 0 {
           break;
         }
-        let tlog2 = ctx.tlog2;
-        split(ctx, tlog2);
-        ctx.tick += 1;
+        if ctx.tlog2 > 0 {
+          let tlog2 = ctx.tlog2;
+          split(ctx, tlog2);
+          ctx.tick += 1;
+        }
       }
     }
 
     // Count total redexes (and populate 'rlens')
+    //
+    // Every thread publishes its redex counts and then, after a barrier, sums
+    // the counts of all threads. Since no thread is reducing while others are
+    // past the barrier, and redexes are only exchanged in `split` (which
+    // completes before the next count), all threads compute the same total,
+    // and a total of zero means the net is in normal form. The counts of
+    // consecutive rounds are stored in separate slots, so that a thread that
+    // moves on to the next round early can't overwrite the counts that slower
+    // threads are still summing.
+    //
+    // A thread that has hit a linearity violation or a failed operation, or
+    // exceeded the memory limit, raises the stop flag of the round, in which case
+    // every thread reports a total of zero, so that they all stop reducing
+    // together.
     #[inline(always)]
     fn count<M: Mode>(ctx: &mut ThreadContext<M>) -> usize {
+      let rlens = lens(ctx, ctx.round);
+      let stop = &ctx.stop[ctx.round % 2];
+      ctx.round += 1;
+      rlens[ctx.tid][0].store(ctx.net.redexes.slow.len(), Relaxed);
+      rlens[ctx.tid][1].store(ctx.net.redexes.len(), Relaxed);
+      if ctx.net.violation.is_some() || ctx.net.op_error.is_some() || ctx.net.memory_exceeded {
+        stop.store(true, core_atomic::Ordering::Relaxed);
+      }
       ctx.barry.wait();
-      ctx.total.store(0, Relaxed);
-      ctx.barry.wait();
-      ctx.rlens[ctx.tid].store(ctx.net.redexes.slow.len(), Relaxed);
-      ctx.total.fetch_add(ctx.net.redexes.len(), Relaxed);
+      let total = if stop.load(core_atomic::Ordering::Relaxed) {
+        0
+      } else {
+        rlens.iter().map(|lens| lens[1].load(Relaxed)).sum()
+      };
+      if let (0, Some(exchange)) = (ctx.tid, &mut ctx.net.exchange) {
+        if total != 0 {
+          exchange.round(rlens.iter().map(|lens| lens[1].load(Relaxed)));
+        }
+      }
+      if let Some(progress) = ctx.progress {
+        report(ctx, total, progress);
+      }
+      total
+    }
+
+    // Flush the rewrite counts of every thread, and report progress from thread 0
+    #[cold]
+    fn report<M: Mode>(ctx: &mut ThreadContext<M>, total: usize, progress: &ProgressFn) {
+      ctx.net.rwts.add_to(ctx.delta);
+      ctx.net.rwts = Rewrites::default();
       ctx.barry.wait();
-      ctx.total.load(Relaxed)
+      if ctx.tid == 0 {
+        let mut rwts = ctx.base;
+        ctx.delta.add_to(&mut rwts);
+        progress(total, &rwts);
+      }
+    }
+
+    // The redex lengths published in the given counting round
+    #[inline(always)]
+    fn lens<'a, M: Mode>(ctx: &ThreadContext<'a, M>, round: usize) -> &'a [[AtomicUsize; 2]] {
+      let tids = 1 << ctx.tlog2;
+      &ctx.rlens[(round % 2) * tids .. (round % 2 + 1) * tids]
     }
 
     // Share redexes with target thread
@@ -130,11 +604,14 @@ impl<'h, M: Mode> Net<'h, M> {
         let a_tid = ctx.tid;
         let b_tid = if side == 1 { a_tid - shift } else { a_tid + shift };
         let a_len = ctx.net.redexes.slow.len();
-        let b_len = ctx.rlens[b_tid].load(Relaxed);
+        let b_len = lens(ctx, ctx.round - 1)[b_tid][0].load(Relaxed);
         let send = if a_len > b_len { (a_len - b_len) / 2 } else { 0 };
         let recv = if b_len > a_len { (b_len - a_len) / 2 } else { 0 };
         let send = usize::min(send, SHARE_LIMIT);
         let recv = usize::min(recv, SHARE_LIMIT);
+        if let Some(exchange) = &mut ctx.net.exchange {
+          exchange.exchange(send, recv, SHARE_LIMIT);
+        }
         for i in 0 .. send {
           let init = a_len - send * 2;
           let rdx0 = ctx.net.redexes.slow[init + i * 2].clone();
@@ -0,0 +1,96 @@
+#![cfg(feature = "std")]
+
+use core::panic::AssertUnwindSafe;
+use std::{
+  panic,
+  sync::{mpsc, Mutex},
+  thread,
+};
+
+use super::*;
+
+type Job = Box<dyn FnOnce() + Send>;
+
+/// A set of persistent worker threads, on which nets can be reduced in parallel
+/// (see [`Net::parallel_normal_on`]).
+///
+/// Reusing a pool across many reductions avoids spawning new threads for each
+/// one, which can dominate the running time when reducing many small nets.
+pub struct ThreadPool {
+  workers: Vec<mpsc::Sender<Job>>,
+  handles: Vec<thread::JoinHandle<()>>,
+  done: Mutex<mpsc::Receiver<thread::Result<()>>>,
+}
+
+impl ThreadPool {
+  /// Spawns a pool with `threads` worker threads.
+  pub fn new(threads: usize) -> Self {
+    assert!(threads > 0);
+    let (done_tx, done) = mpsc::channel();
+    let (workers, handles) = (0 .. threads)
+      .map(|i| {
+        let (tx, rx) = mpsc::channel::<Job>();
+        let done_tx = done_tx.clone();
+        let handle = thread::Builder::new()
+          .name(format!("p{i:02x?}"))
+          .spawn(move || {
+            for job in rx {
+              if done_tx.send(panic::catch_unwind(AssertUnwindSafe(job))).is_err() {
+                break;
+              }
+            }
+          })
+          .unwrap();
+        (tx, handle)
+      })
+      .unzip();
+    ThreadPool { workers, handles, done: Mutex::new(done) }
+  }
+
+  /// Spawns a pool with one worker thread per core.
+  pub fn with_available_parallelism() -> Self {
+    Self::new(thread::available_parallelism().unwrap().get())
+  }
+
+  /// The number of worker threads in the pool.
+  pub fn threads(&self) -> usize {
+    self.workers.len()
+  }
+
+  /// Calls `f(i)` on the `i`th worker thread, for every `i` in `0 .. threads`,
+  /// and waits for all calls to return. The calls run concurrently, so they
+  /// may synchronize with each other.
+  ///
+  /// Calls from different threads are serialized. If any of the calls panics,
+  /// the panic is propagated once all of them have returned; calls that wait
+  /// for each other must therefore stop waiting when one of them panics, or
+  /// this never returns (parallel reduction does so by poisoning its barrier).
+  pub fn run(&self, threads: usize, f: &(dyn Fn(usize) + Sync)) {
+    assert!(threads <= self.threads());
+    let done = self.done.lock().unwrap();
+    // SAFETY: we wait for every job to finish before returning, so `f`
+    // outlives all of its uses
+    let f: &'static (dyn Fn(usize) + Sync) = unsafe { mem::transmute(f) };
+    for (i, worker) in self.workers[.. threads].iter().enumerate() {
+      worker.send(Box::new(move || f(i))).unwrap();
+    }
+    let mut result = Ok(());
+    for _ in 0 .. threads {
+      result = result.and(done.recv().unwrap());
+    }
+    drop(done);
+    if let Err(payload) = result {
+      panic::resume_unwind(payload);
+    }
+  }
+}
+
+impl Drop for ThreadPool {
+  fn drop(&mut self) {
+    // closing the job channels stops the workers
+    self.workers.clear();
+    for handle in self.handles.drain(..) {
+      handle.join().unwrap();
+    }
+  }
+}
@@ -146,10 +146,14 @@ impl Port {
     Port::new_num(Int, val as u64)
   }
 
-  /// Creates a new [`F32`] port with a given 60-bit numeric value.
+  /// Creates a new [`F32`] port with a given float value.
+  ///
+  /// NaNs are canonicalized to [`f32::NAN`]; see [`Ty::F32`].
+  ///
+  /// [`Ty::F32`]: crate::ops::Ty::F32
   #[inline(always)]
   pub fn new_float(val: f32) -> Self {
-    Port::new_num(F32, val.to_bits() as u64)
+    Port::new_num(F32, if val.is_nan() { f32::NAN } else { val }.to_bits() as u64)
   }
 
   /// Creates a new [`Int`] or [`F32`] port with a given 60-bit numeric value.
@@ -213,9 +217,13 @@ impl Port {
   }
 
   /// Accesses the float value of this port; this is valid for [`F32`] ports.
+  ///
+  /// Like [`Port::new_float`], this canonicalizes NaNs, in case the port was
+  /// created from raw bits with [`Port::new_num`].
   #[inline(always)]
   pub fn float(&self) -> f32 {
-    f32::from_bits(self.num() as u32)
+    let val = f32::from_bits(self.num() as u32);
+    if val.is_nan() { f32::NAN } else { val }
   }
 
   /// Accesses the numeric value of this port; this is valid for [`Int`] or
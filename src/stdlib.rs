@@ -5,12 +5,11 @@ use core::{
   marker::PhantomData,
   sync::atomic::{AtomicUsize, Ordering},
 };
-use parking_lot::Mutex;
 
 use crate::{
-  dispatch_dyn_net,
+  ast, dispatch_dyn_net,
   host::{DefRef, Host},
-  run::{AsDef, Def, DynNetMut, LabSet, Mode, Net, Port, Tag, Trg},
+  run::{AsDef, Def, DynNetMut, LabSet, Mode, Net, Port, Tag, Trg, Wire},
   util::create_var,
 };
 
@@ -58,6 +57,152 @@ impl<F: Fn(Tree) + Clone + Send + Sync + 'static> AsHostedDef for LogDef<F> {
   }
 }
 
+/// A native definition taking a fixed number of arguments, implemented by a
+/// Rust function.
+///
+/// `@def ~ (a0 (a1 ... (aN out)))` waits for each argument to be reduced to a
+/// principal port (a number, a constructor, a ref, etc.), then calls the
+/// function with the arguments, in order, and links `out` to the port it
+/// returns.
+///
+/// The function takes ownership of the argument ports; arguments that are not
+/// used in the result must be erased (by linking them to [`Port::ERA`]), or
+/// the nodes they point to will be leaked.
+///
+/// # Evaluation order
+///
+/// The arguments are reduced independently of each other, in no particular
+/// order, and possibly in parallel; the function is called once all of them
+/// have been reduced, by whichever thread reduced the last one. In lazy mode,
+/// each argument is instead fully normalized, from first to last, before the
+/// function is called.
+pub struct NativeDef<F> {
+  arity: usize,
+  f: F,
+}
+
+impl<F: Fn(DynNetMut, Vec<Port>) -> Port + Send + Sync + 'static> NativeDef<F> {
+  /// # SAFETY
+  /// The caller must ensure that the returned value lives at least as long as
+  /// the port where it is used.
+  pub unsafe fn new(arity: usize, f: F) -> DefRef {
+    HostedDef::new_hosted(LabSet::ALL, NativeDef { arity, f })
+  }
+}
+
+impl<F: Fn(DynNetMut, Vec<Port>) -> Port + Send + Sync + 'static> AsHostedDef for NativeDef<F> {
+  fn call<M: Mode>(def: &Def<Self>, net: &mut Net<M>, port: Port) {
+    // SAFETY: see `LogDef::call`
+    let def: &'static Def<Self> = unsafe { mem::transmute(def) };
+    let mut out = Trg::port(port);
+    let mut args = Vec::with_capacity(def.data.arity);
+    for _ in 0 .. def.data.arity {
+      let (arg, rest) = net.do_ctr(0, out);
+      args.push(arg);
+      out = rest;
+    }
+    let out = net.wire_to_trg(out);
+    if M::LAZY {
+      let args = args
+        .into_iter()
+        .map(|arg| {
+          let arg = net.wire_to_trg(arg);
+          net.normal_from(arg.clone());
+          let port = arg.load_target();
+          net.free_wire(arg);
+          port
+        })
+        .collect();
+      let res = (def.data.f)(DynNetMut::from(&mut *net), args);
+      net.link_wire_port(out, res);
+    } else if args.is_empty() {
+      let res = (def.data.f)(DynNetMut::from(&mut *net), vec![]);
+      net.link_wire_port(out, res);
+    } else {
+      let state = Arc::new(NativeArgs { def, out, args: Mutex::new(vec![Port::ERA; args.len()]) });
+      for (index, arg) in args.into_iter().enumerate() {
+        let collector = BoxDef::new_boxed(LabSet::ALL, NativeArg { state: state.clone(), index });
+        net.link_trg_port(arg, Port::new_ref(Box::leak(collector)));
+      }
+    }
+  }
+}
+
+/// The arguments of a call to a [`NativeDef`] that are still being reduced.
+struct NativeArgs<F: Fn(DynNetMut, Vec<Port>) -> Port + Send + Sync + 'static> {
+  def: &'static Def<NativeDef<F>>,
+  out: Wire,
+  args: Mutex<Vec<Port>>,
+}
+
+/// Waits for an argument of a [`NativeDef`] to be reduced, and calls the def
+/// once it has received the last one.
+struct NativeArg<F: Fn(DynNetMut, Vec<Port>) -> Port + Send + Sync + 'static> {
+  state: Arc<NativeArgs<F>>,
+  index: usize,
+}
+
+impl<F: Fn(DynNetMut, Vec<Port>) -> Port + Send + Sync + 'static> AsBoxDef for NativeArg<F> {
+  fn call<M: Mode>(def: Box<Def<Self>>, net: &mut Net<M>, port: Port) {
+    let NativeArg { state, index } = def.data;
+    state.args.lock()[index] = port;
+    let Some(NativeArgs { def, out, args }) = Arc::into_inner(state) else { return };
+    let res = (def.data.f)(DynNetMut::from(&mut *net), args.into_inner());
+    net.link_wire_port(out, res);
+  }
+}
+
+/// An oracle: a definition whose result is computed by a Rust callback from
+/// its argument; see [`Host::set_oracle`].
+///
+/// `@oracle ~ (arg out)` waits for `arg` to be normalized, reads it back, and
+/// passes it to the callback; the net it returns is then substituted for
+/// `out`.
+pub struct OracleDef {
+  /// The host the nets exchanged with the callback are read back from and
+  /// encoded through. This is owned by the oracle, and has no defs.
+  host: Arc<Mutex<Host>>,
+  #[allow(clippy::type_complexity)]
+  f: Mutex<Box<dyn FnMut(&ast::Net) -> ast::Net + Send>>,
+}
+
+impl Host {
+  /// Defines `name` as an oracle, whose result is computed by calling `f`
+  /// with its argument (see [`OracleDef`]). Unlike the functions of native
+  /// defs, `f` can return arbitrary nets.
+  ///
+  /// This must be called before inserting any net that references `name`.
+  ///
+  /// The nets are exchanged through a host owned by the oracle, rather than
+  /// through this one, so the oracle doesn't keep this host alive, and can be
+  /// called while it is locked (e.g. when reducing a net booted from one of
+  /// its defs). That host has no defs, so any refs in the argument are read
+  /// back as erasers, and the returned net must not contain refs.
+  ///
+  /// `f` is called whenever an oracle call is reduced; in parallel mode, the
+  /// order of these calls is not deterministic, so oracles whose results
+  /// depend on previous calls should only be used in single-core mode.
+  pub fn set_oracle(&mut self, name: &str, f: Box<dyn FnMut(&ast::Net) -> ast::Net + Send>) {
+    let oracle = OracleDef { host: Default::default(), f: Mutex::new(f) };
+    // SAFETY: the def is owned by the host, so it lives as long as the nets
+    // that reference it
+    self.insert_def(name, unsafe { HostedDef::new_hosted(LabSet::ALL, oracle) });
+  }
+}
+
+impl AsHostedDef for OracleDef {
+  fn call<M: Mode>(def: &Def<Self>, net: &mut Net<M>, port: Port) {
+    let (arg, out) = net.do_ctr(0, Trg::port(port));
+    let out = net.wire_to_trg(out);
+    // SAFETY: see `LogDef::call`
+    let def: &'static Def<Self> = unsafe { mem::transmute(def) };
+    readback(net, def.data.host.clone(), arg, move |net, tree| {
+      let res = (def.data.f.lock())(&ast::Net { root: tree, redexes: vec![] });
+      dispatch_dyn_net!(net => def.data.host.lock().encode_net(net, Trg::wire(out), &res));
+    });
+  }
+}
+
 /// Create a `Host` from a `Book`, including `hvm-core`'s built-in definitions
 #[cfg(feature = "std")]
 #[allow(clippy::absolute_paths)]
@@ -223,11 +368,31 @@ impl<F: FnOnce(DynNetMut) + Send + Sync + 'static> AsBoxDef for ReadbackDef<F> {
         unsafe { *(def.data.tree.0) = Tree::Era };
       }
       Tag::Int => {
-        unsafe { *(def.data.tree.0) = Tree::Int { val: port.int() } };
+        unsafe { *(def.data.tree.0) = Tree::Int { val: port.int(), ty: None } };
       }
       Tag::F32 => {
         unsafe { *(def.data.tree.0) = Tree::F32 { val: port.float().into() } };
       }
+      Tag::Mat if port.lab() != 0 => {
+        let old = port.clone().consume_node();
+        let mut wires = vec![];
+        let mut rest = old.p1.load_target();
+        while rest.tag() == Tag::Ctr && rest.lab() == 0 {
+          let node = rest.consume_node();
+          rest = node.p2.load_target();
+          wires.push(node.p1);
+        }
+        net.link_port_port(rest, Port::ERA);
+        unsafe {
+          *(def.data.tree.0) =
+            Tree::Switch { lab: port.lab(), arms: vec![Tree::Era; wires.len()], out: Box::new(Tree::Era) }
+        };
+        let Tree::Switch { arms, out, .. } = (unsafe { &mut *(def.data.tree.0) }) else { unreachable!() };
+        net.link_wire_port(old.p2, def.data.with(out.as_mut()));
+        for (wire, arm) in wires.into_iter().zip(arms) {
+          net.link_wire_port(wire, def.data.with(arm));
+        }
+      }
       Tag::Mat => {
         unsafe {
           *(def.data.tree.0) =
@@ -296,3 +461,36 @@ pub fn readback<M: Mode>(
     );
   }
 }
+
+/// An IO action described by the normal form of a program, to be performed by
+/// the runtime (see `hvmc run --io`).
+///
+/// Effects are scott-encoded ADTs with three variants:
+/// - `(:0:3 #code)`: exit with the status code `code`
+/// - `(:1:3 value next)`: print `value`, then perform `next`
+/// - `(:2:3 cont)`: read a tree from a line of input, then perform `(cont
+///   input)`
+#[cfg(feature = "std")]
+#[derive(Debug)]
+pub enum Effect {
+  Exit(i64),
+  Print(Tree, Tree),
+  Read(Tree),
+}
+
+#[cfg(feature = "std")]
+impl Effect {
+  /// Interprets `tree` as an effect, returning `None` if it does not describe
+  /// one.
+  pub fn from_tree(mut tree: Tree) -> Option<Effect> {
+    tree.coalesce_constructors();
+    tree.encode_scott_adts();
+    let Tree::Adt { lab: 0, variant_index, variant_count: 3, fields } = &mut tree else { None? };
+    match (*variant_index, &mut fields[..]) {
+      (0, [Tree::Int { val, .. }]) => Some(Effect::Exit(*val)),
+      (1, [value, next]) => Some(Effect::Print(mem::take(value), mem::take(next))),
+      (2, [cont]) => Some(Effect::Read(mem::take(cont))),
+      _ => None,
+    }
+  }
+}
@@ -60,6 +60,16 @@
 //! program (until an error is encountered). In this case, one can run
 //! [`_reset_traces()`] before each iteration, to discard the traces of the
 //! previous iteration.
+//!
+//! To only trace the expansion of certain definitions, call
+//! [`set_def_filter()`] before creating the net (the CLI does this with the
+//! `--trace-defs` flag or the `HVMC_TRACE_DEFS` environment variable). Only the
+//! events emitted while a listed def is being called -- i.e. while its nodes
+//! are being created and linked into the net -- and the interactions these
+//! nodes (and the nodes created by those interactions) later take part in will
+//! then be recorded. Each net keeps track of the nodes it has allocated for the
+//! listed defs, so in parallel reductions, an interaction is only recorded if
+//! it happens in the thread that allocated one of its nodes.
 
 #![cfg_attr(not(feature = "trace"), allow(unused))]
 
@@ -68,6 +78,7 @@ use crate::prelude::*;
 use core::{
   cell::UnsafeCell,
   fmt::{self, Debug, Formatter, Write},
+  hint,
   sync::atomic::{AtomicBool, AtomicU64, Ordering},
 };
 use parking_lot::{Mutex, Once};
@@ -90,6 +101,20 @@ impl Tracer {
   pub fn trace<S: TraceSourceBearer, A: TraceArgs>(&mut self, _: A) {}
   #[inline(always)]
   pub fn set_tid(&self, _: usize) {}
+  #[inline(always)]
+  pub fn enter_def(&mut self, _: Addr) -> bool {
+    false
+  }
+  #[inline(always)]
+  pub fn exit_def(&mut self, _: bool) {}
+  #[inline(always)]
+  pub fn enter_pair(&mut self, _: &Port, _: &Port) -> bool {
+    false
+  }
+  #[inline(always)]
+  pub fn exit_pair(&mut self, _: bool) {}
+  #[inline(always)]
+  pub fn alloc_node(&mut self, _: Addr) {}
 }
 
 #[macro_export]
@@ -136,6 +161,41 @@ impl Tracer {
   pub fn set_tid(&self, tid: usize) {
     self.0.set_tid(tid)
   }
+  /// Marks the start of a call to the def at `def`, returning the previous
+  /// state to be passed to [`Tracer::exit_def`].
+  #[inline(always)]
+  pub fn enter_def(&mut self, def: Addr) -> bool {
+    let enabled = self.0.filter.as_ref().map_or(true, |filter| filter.contains(&def));
+    mem::replace(&mut self.0.enabled, enabled)
+  }
+  #[inline(always)]
+  pub fn exit_def(&mut self, prev: bool) {
+    self.0.enabled = prev;
+  }
+  /// Marks the start of an interaction between `a` and `b`, which is traced if
+  /// either of them is a node of one of the filtered defs. Returns the previous
+  /// state to be passed to [`Tracer::exit_pair`].
+  #[inline(always)]
+  pub fn enter_pair(&mut self, a: &Port, b: &Port) -> bool {
+    let enabled = self.0.filter.is_none() || self.0.is_traced(a) || self.0.is_traced(b);
+    mem::replace(&mut self.0.enabled, enabled)
+  }
+  #[inline(always)]
+  pub fn exit_pair(&mut self, prev: bool) {
+    self.0.enabled = prev;
+  }
+  /// Records that the node at `addr` was allocated, so that its interactions
+  /// are traced if it was allocated while tracing.
+  #[inline(always)]
+  pub fn alloc_node(&mut self, addr: Addr) {
+    if self.0.filter.is_some() {
+      if self.0.enabled {
+        self.0.nodes.insert(addr);
+      } else {
+        self.0.nodes.remove(&addr);
+      }
+    }
+  }
 }
 
 pub trait TraceSourceBearer {
@@ -182,9 +242,23 @@ impl TraceData {
 #[allow(clippy::vec_box)] // the address of `TraceLock` needs to remain stable
 static ACTIVE_TRACERS: Mutex<Vec<Box<TraceLock>>> = Mutex::new(Vec::new());
 
+static DEF_FILTER: Mutex<Option<Vec<Addr>>> = Mutex::new(None);
+
+/// Restricts tracing to the events emitted while calling one of `defs` (given
+/// by their addresses), or removes the restriction if `defs` is `None`.
+///
+/// This only affects nets created after it is called.
+pub fn set_def_filter(defs: Option<Vec<Addr>>) {
+  *DEF_FILTER.lock() = defs;
+}
+
 struct TraceWriter {
   lock: &'static TraceLock,
   nonce: u64,
+  filter: Option<Vec<Addr>>,
+  enabled: bool,
+  /// The nodes allocated while tracing, if there is a filter.
+  nodes: Set<Addr>,
 }
 
 unsafe impl Send for TraceWriter {}
@@ -198,7 +272,9 @@ impl Default for TraceWriter {
     let lock = unsafe { &*(&*boxed as *const _) };
     let mut active_tracers = ACTIVE_TRACERS.lock();
     active_tracers.push(boxed);
-    TraceWriter { lock, nonce: TRACE_NONCE.fetch_add(1, Ordering::Relaxed) }
+    let filter = DEF_FILTER.lock().clone();
+    let enabled = filter.is_none();
+    TraceWriter { lock, nonce: TRACE_NONCE.fetch_add(1, Ordering::Relaxed), filter, enabled, nodes: Set::default() }
   }
 }
 
@@ -214,6 +290,9 @@ impl TraceWriter {
     self.lock.locked.store(false, Ordering::Release);
   }
   fn trace<S: TraceSourceBearer, A: TraceArgs>(&mut self, args: A) {
+    if !self.enabled {
+      return;
+    }
     if cfg!(feature = "_fuzz") {
       self.sync();
     }
@@ -230,6 +309,9 @@ impl TraceWriter {
   fn set_tid(&self, tid: usize) {
     self.acquire(|data| data.tid = tid);
   }
+  fn is_traced(&self, port: &Port) -> bool {
+    port.is_full_node() && self.nodes.contains(&port.addr())
+  }
 }
 
 struct TraceReader<'a> {
@@ -294,6 +376,11 @@ impl<'a> TraceReader<'a> {
 #[cfg_attr(feature = "trace", no_mangle)]
 #[cfg(feature = "std")]
 pub fn _read_traces(limit: usize) {
+  eprintln!("{}", read_traces(limit));
+}
+
+/// Reads out the most recent `limit` trace entries, in a human-readable format.
+pub fn read_traces(limit: usize) -> String {
   let active_tracers = &*ACTIVE_TRACERS.lock();
   let mut readers = active_tracers
     .iter()
@@ -312,7 +399,10 @@ pub fn _read_traces(limit: usize) {
     };
     r.read_entry(&mut out);
   }
-  eprintln!("{}", out);
+  for t in active_tracers {
+    t.locked.store(false, Ordering::Release);
+  }
+  out
 }
 
 pub unsafe fn _reset_traces() {
@@ -0,0 +1,37 @@
+//! The stand-in for the tracing module (see `trace.rs`) in builds without
+//! `std`, where `trace!` compiles to nothing and [`Tracer`] holds no state.
+
+use crate::run::{Addr, Port};
+
+#[derive(Default)]
+pub struct Tracer(());
+
+impl Tracer {
+  #[inline(always)]
+  pub fn sync(&mut self) {}
+  #[inline(always)]
+  pub fn set_tid(&self, _: usize) {}
+  #[inline(always)]
+  pub fn enter_def(&mut self, _: Addr) -> bool {
+    false
+  }
+  #[inline(always)]
+  pub fn exit_def(&mut self, _: bool) {}
+  #[inline(always)]
+  pub fn enter_pair(&mut self, _: &Port, _: &Port) -> bool {
+    false
+  }
+  #[inline(always)]
+  pub fn exit_pair(&mut self, _: bool) {}
+  #[inline(always)]
+  pub fn alloc_node(&mut self, _: Addr) {}
+}
+
+#[macro_export]
+macro_rules! trace {
+  ($tracer:expr $(, $x:expr)* $(,)?) => {{
+    if false {
+      let _ = (&$tracer, $(&$x,)*);
+    }
+  }};
+}
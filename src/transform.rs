@@ -2,12 +2,15 @@ use crate::prelude::*;
 
 use crate::ast::Book;
 
+pub mod cache;
 pub mod coalesce_ctrs;
+pub mod elide_apps;
 pub mod encode_adts;
 pub mod eta_reduce;
 pub mod inline;
 pub mod pre_reduce;
 pub mod prune;
+pub mod rewrite;
 
 #[derive(Debug, Clone, PartialEq, Eq)]
 #[non_exhaustive]
@@ -15,6 +18,10 @@ pub mod prune;
 pub enum TransformError {
   #[cfg_attr(feature = "std", error("infinite reference cycle in `@{0}`"))]
   InfiniteRefCycle(String),
+  #[cfg_attr(feature = "std", error("cannot inline undefined definition `@{0}`"))]
+  UndefinedInlinee(String),
+  #[cfg_attr(feature = "std", error("recursive def cannot be pre-reduced: `@{0}`"))]
+  RecursivePreReduce(String),
 }
 
 impl Book {
@@ -28,11 +35,15 @@ impl Book {
           def.eta_reduce();
         }
       }
-      self.pre_reduce(
+      let stats = self.pre_reduce(
         &|x| opts.pre_reduce_skip.iter().any(|y| x == y),
         opts.pre_reduce_memory,
         opts.pre_reduce_rewrites,
+        opts.pre_reduce_on_cycle,
       );
+      if let Some(name) = stats.errors.into_iter().next() {
+        return Err(TransformError::RecursivePreReduce(name));
+      }
     }
     for def in &mut self.nets.values_mut() {
       if passes.eta_reduce {
@@ -47,7 +58,23 @@ impl Book {
         }
       }
     }
-    if passes.inline {
+    let post_inline = |book: &mut Book, inline_changed: Set<String>| {
+      for name in inline_changed {
+        let def = book.get_mut(&name).unwrap();
+        if passes.eta_reduce {
+          def.eta_reduce();
+        }
+        if passes.encode_adts {
+          for tree in def.trees_mut() {
+            tree.encode_scott_adts();
+          }
+        }
+      }
+    };
+    if !opts.inline_only.is_empty() {
+      let inline_changed = self.inline_only(&opts.inline_only)?;
+      post_inline(self, inline_changed);
+    } else if passes.inline {
       loop {
         let inline_changed = self.inline()?;
         if inline_changed.is_empty() {
@@ -56,27 +83,23 @@ impl Book {
         if !(passes.eta_reduce || passes.encode_adts) {
           break;
         }
-        for name in inline_changed {
-          let def = self.get_mut(&name).unwrap();
-          if passes.eta_reduce {
-            def.eta_reduce();
-          }
-          if passes.encode_adts {
-            for tree in def.trees_mut() {
-              tree.encode_scott_adts();
-            }
-          }
-        }
+        post_inline(self, inline_changed);
       }
     }
+    if passes.elide_apps {
+      self.elide_apps();
+    }
+    self.rewrite(&opts.rewrite, opts.rewrite_limit);
     if passes.prune {
       self.prune(&opts.prune_entrypoints);
     }
+    // `passes.identity` leaves the book unchanged; it is a placeholder for
+    // testing pass pipelines.
     Ok(())
   }
 }
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
 #[cfg_attr(feature = "cli", derive(clap::Args))]
 #[non_exhaustive]
 pub struct TransformOpts {
@@ -100,9 +123,59 @@ pub struct TransformOpts {
   #[cfg_attr(feature = "cli", arg(long = "pre-reduce-rewrites", default_value = "100M", value_parser = crate::util::parse_abbrev_number::<u64>))]
   pub pre_reduce_rewrites: u64,
 
+  /// What to do with recursive definitions when pre-reducing.
+  ///
+  /// A definition is considered recursive if it is expanded again during its
+  /// own reduction, directly or through other definitions, in which case its
+  /// reduction may never terminate. With `skip`, such definitions are left
+  /// as they are; with `error`, they are also reported as an error. By
+  /// default, they are reduced like any other definition.
+  #[cfg_attr(feature = "cli", arg(long = "pre-reduce-on-cycle", value_enum))]
+  pub pre_reduce_on_cycle: Option<pre_reduce::OnCycle>,
+
   /// Names of the definitions that should not get pruned.
   #[cfg_attr(feature = "cli", arg(long = "prune-entrypoints", default_value = "main"))]
   pub prune_entrypoints: Vec<String>,
+
+  /// Names of the definitions to inline into their call sites, instead of
+  /// running the `inline` pass.
+  ///
+  /// Unlike the `inline` pass, which only inlines definitions that are a
+  /// single leaf, these are inlined whatever their contents; see
+  /// [`Book::inline_only`].
+  #[cfg_attr(feature = "cli", arg(long = "inline-only", value_delimiter = ','))]
+  pub inline_only: Vec<String>,
+
+  /// Rewrite rules to apply to every definition, as `pattern => replacement`.
+  ///
+  /// The variables of the pattern match any subtree, or, if they appear twice,
+  /// a wire; see [`rewrite`]. The rules are applied after the other passes,
+  /// until none of them matches.
+  #[cfg_attr(feature = "cli", arg(long = "rewrite"))]
+  pub rewrite: Vec<rewrite::RewriteRule>,
+
+  /// Maximum amount of rewrites to do in each definition with the rules of
+  /// `--rewrite`.
+  ///
+  /// Supports abbreviations such as '4G' or '400M'.
+  #[cfg_attr(feature = "cli", arg(long = "rewrite-limit", default_value = "10K", value_parser = crate::util::parse_abbrev_number::<usize>))]
+  pub rewrite_limit: usize,
+}
+
+/// The same defaults as the command line.
+impl Default for TransformOpts {
+  fn default() -> Self {
+    TransformOpts {
+      pre_reduce_skip: vec![],
+      pre_reduce_memory: None,
+      pre_reduce_rewrites: 100_000_000,
+      pre_reduce_on_cycle: None,
+      prune_entrypoints: vec!["main".to_owned()],
+      inline_only: vec![],
+      rewrite: vec![],
+      rewrite_limit: 10_000,
+    }
+  }
 }
 
 impl TransformOpts {
@@ -114,7 +187,7 @@ impl TransformOpts {
 
 macro_rules! transform_passes {
   ($($pass:ident: $name:literal $(| $alias:literal)*),* $(,)?) => {
-    #[derive(Debug, Default, Clone, Copy)]
+    #[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
     #[non_exhaustive]
     pub struct TransformPasses {
       $(pub $pass: bool),*
@@ -123,6 +196,11 @@ macro_rules! transform_passes {
     impl TransformPasses {
       pub const NONE: Self = Self { $($pass: false),* };
       pub const ALL: Self = Self { $($pass: true),* };
+
+      /// The names of the enabled passes.
+      pub fn enabled(&self) -> impl Iterator<Item = &'static str> {
+        [$(($name, self.$pass)),*].into_iter().filter_map(|(name, enabled)| enabled.then_some(name))
+      }
     }
 
     #[derive(Debug, Clone, Copy)]
@@ -174,8 +252,10 @@ macro_rules! transform_passes {
 transform_passes! {
   pre_reduce: "pre-reduce" | "pre",
   coalesce_ctrs: "coalesce-ctrs" | "coalesce",
+  elide_apps: "elide-apps" | "elide",
   encode_adts: "encode-adts" | "adts",
   eta_reduce: "eta-reduce" | "eta",
+  identity: "identity" | "nop",
   inline: "inline",
   prune: "prune",
 }
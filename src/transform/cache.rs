@@ -0,0 +1,109 @@
+//! An in-memory cache of transformed books, for tools that load the same
+//! source many times, such as test runners.
+//!
+//! This is unrelated to the build directories of `hvmc compile`; nothing is
+//! stored outside of the [`BookCache`] itself.
+
+use crate::prelude::*;
+
+use alloc::sync::Arc;
+use core::{
+  hash::{Hash, Hasher},
+  sync::atomic::{AtomicUsize, Ordering},
+};
+use parking_lot::Mutex;
+use std::collections::hash_map::DefaultHasher;
+
+use super::{TransformError, TransformOpts, TransformPasses};
+use crate::ast::{Book, ParseError};
+
+/// Maps the source of a book, along with the passes and options it is
+/// transformed with, to the transformed book.
+///
+/// Entries are found by a 64-bit hash of all three, and store them to be
+/// compared on each hit, so a source is only parsed and transformed the first
+/// time it is loaded with a given configuration. Books are shared through an
+/// [`Arc`]; clone one to modify it.
+#[derive(Debug, Default)]
+pub struct BookCache {
+  books: Mutex<Map<u64, Vec<CachedBook>>>,
+  parses: AtomicUsize,
+}
+
+/// A book in a [`BookCache`], along with what it was loaded from.
+#[derive(Debug)]
+struct CachedBook {
+  src: String,
+  passes: TransformPasses,
+  opts: TransformOpts,
+  book: Arc<Book>,
+}
+
+impl CachedBook {
+  fn is(&self, src: &str, passes: TransformPasses, opts: &TransformOpts) -> bool {
+    self.src == src && self.passes == passes && self.opts == *opts
+  }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum LoadError {
+  #[error("{0}")]
+  Parse(ParseError),
+  #[error("{0}")]
+  Transform(TransformError),
+}
+
+impl BookCache {
+  pub fn new() -> Self {
+    Self::default()
+  }
+
+  /// Parses `src` and transforms it with `passes` and `opts`, unless the
+  /// resulting book is already in the cache.
+  ///
+  /// Failures are not cached.
+  pub fn load(&self, src: &str, passes: TransformPasses, opts: &TransformOpts) -> Result<Arc<Book>, LoadError> {
+    let mut hasher = DefaultHasher::new();
+    (src, passes, opts).hash(&mut hasher);
+    let key = hasher.finish();
+    let find = |books: &Map<u64, Vec<CachedBook>>| {
+      books.get(&key)?.iter().find(|entry| entry.is(src, passes, opts)).map(|entry| entry.book.clone())
+    };
+    if let Some(book) = find(&self.books.lock()) {
+      return Ok(book);
+    }
+    // the lock isn't held while loading, so that different sources can be
+    // loaded concurrently; if the same source is, the first result is kept
+    self.parses.fetch_add(1, Ordering::Relaxed);
+    let mut book: Book = src.parse().map_err(LoadError::Parse)?;
+    book.transform(passes, opts).map_err(LoadError::Transform)?;
+    let mut books = self.books.lock();
+    if let Some(book) = find(&books) {
+      return Ok(book);
+    }
+    let book = Arc::new(book);
+    let entry = CachedBook { src: src.to_owned(), passes, opts: opts.clone(), book: book.clone() };
+    books.entry(key).or_default().push(entry);
+    Ok(book)
+  }
+
+  /// The number of times a source has been parsed, i.e. the number of calls
+  /// to [`BookCache::load`] that missed the cache.
+  pub fn parses(&self) -> usize {
+    self.parses.load(Ordering::Relaxed)
+  }
+
+  /// The number of books in the cache.
+  pub fn len(&self) -> usize {
+    self.books.lock().values().map(Vec::len).sum()
+  }
+
+  pub fn is_empty(&self) -> bool {
+    self.len() == 0
+  }
+
+  /// Removes every book from the cache.
+  pub fn clear(&self) {
+    self.books.lock().clear();
+  }
+}
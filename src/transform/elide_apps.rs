@@ -0,0 +1,162 @@
+//! Elides applications of the identity and constant functions, to reduce the
+//! amount of rewrites at runtime.
+//!
+//! An application of the identity function, `(x x) ~ (arg ret)`, annihilates
+//! into `arg ~ ret`; an application of a constant function, `(* val) ~ (arg
+//! ret)`, annihilates into `arg ~ *` and `ret ~ val`. This pass performs these
+//! annihilations ahead of time, wiring the ports directly.
+//!
+//! References to definitions that are exactly `(x x)` or `(* val)` (where
+//! `val` has no variables) are also recognized, which additionally saves the
+//! expansion of the reference.
+//!
+//! When one side of a resulting pair is a variable, it is substituted into the
+//! net; otherwise, the pair is kept as a redex. This may create new
+//! applications, which are elided in turn.
+
+use crate::prelude::*;
+
+use crate::{
+  ast::{Book, Net, Tree},
+  run::Lab,
+  util::maybe_grow,
+};
+
+/// A function recognized by this pass.
+#[derive(Debug, Clone)]
+enum Idiom {
+  /// `(x x)`
+  Identity,
+  /// `(* val)`
+  Constant(Tree),
+}
+
+impl Book {
+  /// Elides applications of the identity and constant functions in every
+  /// definition; see the [module-level documentation](self).
+  pub fn elide_apps(&mut self) {
+    let idioms = self.nets.iter().filter_map(|(name, net)| Some((name.clone(), Idiom::of_def(net)?))).collect();
+    for net in self.nets.values_mut() {
+      net.elide_apps(&idioms);
+    }
+  }
+}
+
+impl Net {
+  /// Elides applications of the identity and constant functions in this net,
+  /// including references to the definitions in `idioms`.
+  fn elide_apps(&mut self, idioms: &Map<String, Idiom>) {
+    let mut i = 0;
+    while i < self.redexes.len() {
+      let (a, b) = &self.redexes[i];
+      let Some(links) = elide(a, b, idioms).or_else(|| elide(b, a, idioms)) else {
+        i += 1;
+        continue;
+      };
+      self.redexes.remove(i);
+      let redexes = self.link_all(links);
+      self.redexes.splice(i .. i, redexes);
+      // substituting variables may have created new applications
+      i = 0;
+    }
+  }
+
+  /// Connects each pair in `links`, substituting variables where possible, and
+  /// returns the pairs that must be kept as redexes.
+  fn link_all(&mut self, mut links: Vec<(Tree, Tree)>) -> Vec<(Tree, Tree)> {
+    let mut redexes = vec![];
+    while let Some((mut a, mut b)) = links.pop() {
+      if !matches!(a, Tree::Var { .. }) {
+        mem::swap(&mut a, &mut b);
+      }
+      let Tree::Var { nam } = &mut a else {
+        redexes.push((a, b));
+        continue;
+      };
+      if matches!(&b, Tree::Var { nam: n } if n == nam) {
+        continue;
+      }
+      let nam = mem::take(nam);
+      let mut val = Some(b);
+      for tree in self.trees_mut().chain(links.iter_mut().chain(&mut redexes).flat_map(|(a, b)| [a, b])) {
+        if substitute(tree, &nam, &mut val) {
+          break;
+        }
+      }
+      // the other occurrence of the variable is within `val` itself
+      if let Some(val) = val {
+        redexes.push((Tree::Var { nam }, val));
+      }
+    }
+    redexes.reverse();
+    redexes
+  }
+}
+
+impl Idiom {
+  /// Recognizes definitions that are exactly an identity or constant function.
+  fn of_def(net: &Net) -> Option<Self> {
+    if !net.redexes.is_empty() {
+      return None;
+    }
+    match Idiom::of_tree(&net.root, 0)? {
+      Idiom::Constant(val) if has_vars(&val) => None,
+      idiom => Some(idiom),
+    }
+  }
+
+  /// Recognizes `tree` as an identity or constant function with label `lab`.
+  fn of_tree(tree: &Tree, lab: Lab) -> Option<Self> {
+    match tree {
+      Tree::Ctr { lab: l, ports } if *l == lab => match &ports[..] {
+        [Tree::Var { nam: a }, Tree::Var { nam: b }] if a == b => Some(Idiom::Identity),
+        [Tree::Era, rest @ ..] if !rest.is_empty() => Some(Idiom::Constant(split_ctr(lab, rest))),
+        _ => None,
+      },
+      _ => None,
+    }
+  }
+}
+
+/// If `fun` is an identity or constant function and `app` is an application of
+/// it, returns the pairs that result from their annihilation.
+fn elide(fun: &Tree, app: &Tree, idioms: &Map<String, Idiom>) -> Option<Vec<(Tree, Tree)>> {
+  let Tree::Ctr { lab, ports } = app else { return None };
+  let [arg, rest @ ..] = &ports[..] else { return None };
+  if rest.is_empty() {
+    return None;
+  }
+  let idiom = match fun {
+    Tree::Ref { nam } if *lab == 0 => idioms.get(nam)?.clone(),
+    _ => Idiom::of_tree(fun, *lab)?,
+  };
+  let ret = split_ctr(*lab, rest);
+  Some(match idiom {
+    Idiom::Identity => vec![(arg.clone(), ret)],
+    Idiom::Constant(val) => vec![(arg.clone(), Tree::Era), (ret, val)],
+  })
+}
+
+/// The tree formed by the trailing ports of an n-ary constructor.
+fn split_ctr(lab: Lab, ports: &[Tree]) -> Tree {
+  match ports {
+    [port] => port.clone(),
+    _ => Tree::Ctr { lab, ports: ports.to_vec() },
+  }
+}
+
+/// Replaces the occurrence of the variable `nam` in `tree` with `val`,
+/// returning whether it was found.
+fn substitute(tree: &mut Tree, nam: &str, val: &mut Option<Tree>) -> bool {
+  maybe_grow(|| match tree {
+    Tree::Var { nam: n } if n == nam => {
+      *tree = val.take().unwrap();
+      true
+    }
+    _ => tree.children_mut().any(|child| substitute(child, nam, val)),
+  })
+}
+
+fn has_vars(tree: &Tree) -> bool {
+  maybe_grow(|| matches!(tree, Tree::Var { .. }) || tree.children().any(has_vars))
+}
@@ -55,7 +55,10 @@
 
 use crate::prelude::*;
 
-use crate::ast::{Net, Tree};
+use crate::{
+  ast::{Net, Tree},
+  ops::Ty,
+};
 use core::ops::RangeFrom;
 
 use ordered_float::OrderedFloat;
@@ -78,7 +81,7 @@ impl Net {
 enum NodeType {
   Ctr(u16),
   Var(isize),
-  Int(i64),
+  Int(i64, Option<Ty>),
   F32(OrderedFloat<f32>),
   Era,
   Other,
@@ -114,7 +117,7 @@ impl<'a> Phase1<'a> {
         }
       }
       Tree::Era => self.nodes.push(NodeType::Era),
-      Tree::Int { val } => self.nodes.push(NodeType::Int(*val)),
+      Tree::Int { val, ty } => self.nodes.push(NodeType::Int(*val, *ty)),
       Tree::F32 { val } => self.nodes.push(NodeType::F32(*val)),
       _ => {
         self.nodes.push(NodeType::Other);
@@ -145,7 +148,7 @@ impl Phase2 {
     if a == b {
       let reducible = match a {
         NodeType::Var(delta) => self.nodes[head_index.wrapping_add_signed(delta)] == NodeType::Ctr(lab),
-        NodeType::Era | NodeType::Int(_) | NodeType::F32(_) => true,
+        NodeType::Era | NodeType::Int(..) | NodeType::F32(_) => true,
         _ => false,
       };
       if reducible {
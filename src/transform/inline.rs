@@ -3,7 +3,7 @@ use crate::prelude::*;
 use super::TransformError;
 use crate::{
   ast::{Book, Net, Tree},
-  util::maybe_grow,
+  util::{create_var, maybe_grow},
 };
 use core::ops::BitOr;
 
@@ -23,6 +23,102 @@ impl Book {
     }
     Ok(all_changed)
   }
+
+  /// Replaces every reference to one of the defs named in `names` with a copy
+  /// of its net, returning the names of the defs that changed.
+  ///
+  /// The nets are copied as they were before inlining, so references within
+  /// them (including recursive ones) are left as-is.
+  pub fn inline_only(&mut self, names: &[String]) -> Result<Set<String>, TransformError> {
+    let mut inlinees = Map::new();
+    for name in names {
+      let net = self.nets.get(name).ok_or_else(|| TransformError::UndefinedInlinee(name.to_owned()))?;
+      inlinees.insert(name.to_owned(), net.clone());
+    }
+    let mut all_changed = Set::new();
+    for (name, net) in &mut self.nets {
+      if net.inline_nets(&inlinees) {
+        all_changed.insert(name.to_owned());
+      }
+    }
+    Ok(all_changed)
+  }
+}
+
+impl Net {
+  /// Replaces every reference to one of `inlinees` with a copy of that net,
+  /// renaming its variables apart from those of this net. Returns whether any
+  /// reference was replaced.
+  fn inline_nets(&mut self, inlinees: &Map<String, Net>) -> bool {
+    let mut vars = Set::new();
+    self.trees().for_each(|tree| collect_vars(tree, &mut vars));
+    let mut state = InlineNetsState { inlinees, vars, next_var: 0, redexes: vec![] };
+    let mut inlined = false;
+    for tree in self.trees_mut() {
+      inlined |= state.inline_into(tree);
+    }
+    self.redexes.append(&mut state.redexes);
+    inlined
+  }
+}
+
+struct InlineNetsState<'a> {
+  inlinees: &'a Map<String, Net>,
+  /// The variable names in use.
+  vars: Set<String>,
+  next_var: usize,
+  /// The redexes of the inlined nets.
+  redexes: Vec<(Tree, Tree)>,
+}
+
+impl InlineNetsState<'_> {
+  fn inline_into(&mut self, tree: &mut Tree) -> bool {
+    maybe_grow(|| {
+      let Tree::Ref { nam } = &*tree else {
+        return tree.children_mut().map(|t| self.inline_into(t)).fold(false, bool::bitor);
+      };
+      let Some(net) = self.inlinees.get(nam) else { return false };
+      let mut net = net.clone();
+      let mut renames = Map::new();
+      for tree in net.trees_mut() {
+        self.rename(tree, &mut renames);
+      }
+      *tree = mem::take(&mut net.root);
+      self.redexes.append(&mut net.redexes);
+      true
+    })
+  }
+
+  fn rename(&mut self, tree: &mut Tree, renames: &mut Map<String, String>) {
+    maybe_grow(|| {
+      if let Tree::Var { nam } = tree {
+        *nam = renames
+          .entry(mem::take(nam))
+          .or_insert_with(|| {
+            loop {
+              let var = create_var(self.next_var);
+              self.next_var += 1;
+              if self.vars.insert(var.clone()) {
+                break var;
+              }
+            }
+          })
+          .clone();
+      } else {
+        tree.children_mut().for_each(|tree| self.rename(tree, renames));
+      }
+    })
+  }
+}
+
+fn collect_vars(tree: &Tree, vars: &mut Set<String>) {
+  maybe_grow(|| {
+    if let Tree::Var { nam } = tree {
+      vars.insert(nam.clone());
+    } else {
+      tree.children().for_each(|tree| collect_vars(tree, vars));
+    }
+  })
 }
 
 #[derive(Debug, Default)]
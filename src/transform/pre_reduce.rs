@@ -4,7 +4,9 @@
 //! - Each definition is visited in topological order (dependencies before
 //!   dependents). In the case of cycles, one will be arbitrarily selected to be
 //!   first.
-//! - The definition is reduced in a [`run::Net`]
+//! - The definition is reduced in a [`run::Net`]. If an [`OnCycle`] policy is
+//!   given, and the definition is expanded again during its own reduction, the
+//!   reduction is abandoned, and the definition is skipped or reported.
 //! - The reduced [`run::Net`] is readback into an [`ast::Net`]
 //! - The [`ast::Net`] is encoded into a [`Vec<Instruction>`]
 //! - The [`ast::Net`] is stored in the [`State`], as it will be used later.
@@ -19,7 +21,7 @@ use crate::prelude::*;
 use crate::{
   ast::{Book, Net, Tree},
   host::{DefRef, Host},
-  run::{self, Def, Heap, InterpretedDef, LabSet, Rewrites},
+  run::{self, Def, Heap, Instruction, InterpretedDef, LabSet, Port, Rewrites, TrgId},
   stdlib::{AsHostedDef, HostedDef},
   util::maybe_grow,
 };
@@ -33,11 +35,16 @@ impl Book {
   /// Defs that are not in the book are treated as inert defs.
   ///
   /// `max_memory` is measured in bytes.
+  ///
+  /// If `on_cycle` is given, defs that are expanded again during their own
+  /// reduction (directly, or through other defs) are not pre-reduced, as their
+  /// reduction may never terminate; see [`OnCycle`].
   pub fn pre_reduce(
     &mut self,
     skip: &dyn Fn(&str) -> bool,
     max_memory: Option<usize>,
     max_rwts: u64,
+    on_cycle: Option<OnCycle>,
   ) -> PreReduceStats {
     let mut host = Host::default();
     let captured_redexes = Arc::new(Mutex::new(Vec::new()));
@@ -46,6 +53,9 @@ impl Book {
       HostedDef::new_hosted(LabSet::ALL, InertDef(captured_redexes.clone()))
     });
     let area = run::Heap::new(max_memory).expect("pre-reduce memory allocation failed");
+    let cycles = Arc::new(Mutex::new(Vec::new()));
+    let tripwire = unsafe { HostedDef::new_hosted(LabSet::ALL, InertDef(cycles.clone())) };
+    let tripwire: &Def = &tripwire;
 
     let mut state = State {
       book: self,
@@ -54,15 +64,19 @@ impl Book {
       max_rwts,
       host,
       area: &area,
+      on_cycle,
+      tripwire,
+      cycles,
       seen: Map::new(),
       rewrites: Rewrites::default(),
+      errors: vec![],
     };
 
     for nam in self.nets.keys() {
       state.pre_reduce(nam)
     }
 
-    let State { seen, rewrites, .. } = state;
+    let State { seen, rewrites, errors, .. } = state;
 
     let mut not_normal = vec![];
     for (nam, state) in seen {
@@ -70,20 +84,34 @@ impl Book {
         if !normal {
           not_normal.push(nam.clone());
         }
+        // the def has been rewritten, so its doc comment may no longer apply
+        self.docs.remove(&nam);
         self.nets.insert(nam, net);
       }
     }
 
-    PreReduceStats { rewrites, not_normal, errors: vec![] }
+    PreReduceStats { rewrites, not_normal, errors }
   }
 }
 
 pub struct PreReduceStats {
   pub rewrites: Rewrites,
   pub not_normal: Vec<String>,
+  /// The defs that were not pre-reduced because they are recursive, with
+  /// [`OnCycle::Error`].
   pub errors: Vec<String>,
 }
 
+/// What to do with a def that is expanded again during its own pre-reduction.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "cli", derive(clap::ValueEnum))]
+pub enum OnCycle {
+  /// Leave the def as it is.
+  Skip,
+  /// Leave the def as it is, and report it in [`PreReduceStats::errors`].
+  Error,
+}
+
 enum SeenState {
   Cycled,
   Reduced { net: Net, normal: bool },
@@ -112,7 +140,14 @@ struct State<'a> {
   skip: &'a dyn Fn(&str) -> bool,
   seen: Map<String, SeenState>,
 
+  on_cycle: Option<OnCycle>,
+  /// While a def is being reduced, it is replaced by a ref to this inert def,
+  /// which records the expansions of the def in `cycles`.
+  tripwire: &'a Def,
+  cycles: Arc<Mutex<Vec<(run::Port, run::Port)>>>,
+
   rewrites: Rewrites<u64>,
+  errors: Vec<String>,
 }
 
 impl<'a> State<'a> {
@@ -142,10 +177,35 @@ impl<'a> State<'a> {
 
     let mut rt = run::Net::<run::Strict>::new(self.area);
     rt.boot(self.host.defs.get(nam).expect("No function."));
+    let original = self.on_cycle.is_some().then(|| {
+      let port = Port::new_ref(self.tripwire);
+      let instr = vec![Instruction::LinkConst { trg: TrgId::new(0), port }];
+      self.set_def(nam, InterpretedDef { instr, trgs: 1, free: vec![] })
+    });
     let n_reduced = rt.reduce(self.max_rwts as usize);
 
     self.rewrites += rt.rwts;
 
+    if let Some(original) = original {
+      self.set_def(nam, original);
+      if !self.cycles.lock().is_empty() {
+        self.cycles.lock().clear();
+        self.captured_redexes.lock().clear();
+        if self.on_cycle == Some(OnCycle::Error) {
+          self.errors.push(nam.to_owned());
+        }
+        // the def stays in the "Cycled" state, so it is left as it is
+        return;
+      }
+    }
+
+    // An operation failed (e.g. an `ext` op, which the host of the book being
+    // transformed has no function for), so the def is left as it is
+    if rt.op_error.is_some() {
+      self.captured_redexes.lock().clear();
+      return;
+    }
+
     // Move interactions with inert defs back into the net redexes array
     self.captured_redexes.lock().drain(..).for_each(|r| rt.redux(r.0, r.1));
 
@@ -153,12 +213,17 @@ impl<'a> State<'a> {
 
     // Mutate the host in-place with the pre-reduced net.
     let instr = self.host.encode_def(&net);
-    if let DefRef::Owned(def_box) = self.host.defs.get_mut(nam).unwrap() {
-      let interpreted_def: &mut Def<HostedDef<InterpretedDef>> = def_box.downcast_mut().unwrap();
-      interpreted_def.data.0 = instr;
-    };
+    self.set_def(nam, instr);
 
     // Replace the "Cycled" state with the "Reduced" state
     *self.seen.get_mut(nam).unwrap() = SeenState::Reduced { net, normal: n_reduced.is_some() };
   }
+
+  /// Replaces the instructions of the def named `nam` in the host, returning
+  /// the old ones.
+  fn set_def(&mut self, nam: &str, instr: InterpretedDef) -> InterpretedDef {
+    let DefRef::Owned(def_box) = self.host.defs.get_mut(nam).unwrap() else { unreachable!() };
+    let interpreted_def: &mut Def<HostedDef<InterpretedDef>> = def_box.downcast_mut().unwrap();
+    mem::replace(&mut interpreted_def.data.0, instr)
+  }
 }
@@ -14,6 +14,7 @@ impl Book {
     let unvisited = state.unvisited;
     for name in unvisited {
       self.remove(&name);
+      self.docs.remove(&name);
     }
   }
 }
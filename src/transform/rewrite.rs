@@ -0,0 +1,255 @@
+//! Rewrites the trees of a book with user-supplied rules, as a peephole
+//! optimizer that can be extended without recompiling hvmc.
+//!
+//! A rule is written `pattern => replacement`, where both sides are trees. The
+//! variables of the pattern are metavariables:
+//! - one that appears once matches any subtree, which is moved to where it
+//!   appears in the replacement
+//! - one that appears twice matches a wire between the two positions, i.e. a
+//!   variable that appears at both of them, whatever its name
+//!
+//! For example, `<+ #0 x> => x` elides additions of zero (which, connected to
+//! a value `v`, compute `v + 0` into `x`), and `{2 (a a) (b b)} => (c c)`
+//! replaces a duplicated identity function with a single one.
+//!
+//! As the trees of a net are linear, a metavariable that matches a subtree must
+//! appear exactly once in the replacement, and one that matches a wire must
+//! appear either twice or not at all. Other variables of the replacement stand
+//! for new wires, and must appear twice; like the wires matched by the
+//! pattern, they are renamed apart from the other variables of the net.
+//!
+//! Rules are applied, in order, at every position of every tree of a net,
+//! until none of them matches anywhere. Since rules like `(a b) => (b a)` may
+//! never reach such a fixpoint, the number of rewrites done in each net is
+//! bounded.
+
+use crate::prelude::*;
+
+use core::{
+  hash::{Hash, Hasher},
+  str::FromStr,
+};
+
+use crate::{
+  ast::{Book, Net, ParseError, Tree},
+  util::maybe_grow,
+};
+
+/// A rule of the form `pattern => replacement`; see the
+/// [module-level documentation](self).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RewriteRule {
+  pattern: Tree,
+  replacement: Tree,
+  /// The metavariables that appear twice in the pattern.
+  wires: Set<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+#[cfg_attr(feature = "std", derive(Error))]
+pub enum RewriteRuleError {
+  #[cfg_attr(feature = "std", error("expected `=>` between the pattern and the replacement"))]
+  MissingArrow,
+  #[cfg_attr(feature = "std", error("invalid pattern: {0}"))]
+  Pattern(ParseError),
+  #[cfg_attr(feature = "std", error("invalid replacement: {0}"))]
+  Replacement(ParseError),
+  #[cfg_attr(feature = "std", error("`{0}` appears more than twice in the pattern"))]
+  RepeatedVar(String),
+  #[cfg_attr(feature = "std", error("`{0}` is not used linearly by the replacement"))]
+  NonLinearVar(String),
+}
+
+impl FromStr for RewriteRule {
+  type Err = RewriteRuleError;
+  fn from_str(str: &str) -> Result<Self, Self::Err> {
+    // no tree contains `=>`, as operators are always followed by a tree
+    let (pattern, replacement) = str.split_once("=>").ok_or(RewriteRuleError::MissingArrow)?;
+    let pattern: Tree = pattern.trim().parse().map_err(RewriteRuleError::Pattern)?;
+    let replacement: Tree = replacement.trim().parse().map_err(RewriteRuleError::Replacement)?;
+    RewriteRule::new(pattern, replacement)
+  }
+}
+
+impl fmt::Display for RewriteRule {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    write!(f, "{} => {}", self.pattern, self.replacement)
+  }
+}
+
+// `wires` is determined by the pattern
+impl Hash for RewriteRule {
+  fn hash<H: Hasher>(&self, state: &mut H) {
+    self.pattern.hash(state);
+    self.replacement.hash(state);
+  }
+}
+
+impl RewriteRule {
+  /// Creates a rule, checking that the replacement uses the metavariables of
+  /// the pattern linearly.
+  pub fn new(pattern: Tree, replacement: Tree) -> Result<Self, RewriteRuleError> {
+    let mut counts = Map::<String, (usize, usize)>::new();
+    count_vars(&pattern, &mut |nam| counts.entry(nam.to_owned()).or_default().0 += 1);
+    count_vars(&replacement, &mut |nam| counts.entry(nam.to_owned()).or_default().1 += 1);
+    let mut wires = Set::new();
+    for (nam, counts) in counts {
+      match counts {
+        (1, 1) | (0, 2) => {}
+        (2, 0 | 2) => {
+          wires.insert(nam);
+        }
+        (3 .., _) => Err(RewriteRuleError::RepeatedVar(nam))?,
+        _ => Err(RewriteRuleError::NonLinearVar(nam))?,
+      }
+    }
+    Ok(RewriteRule { pattern, replacement, wires })
+  }
+
+  /// If `tree` matches the pattern of this rule, returns the metavariables
+  /// that match a subtree, bound to the subtrees they match.
+  fn matches<'t>(&self, tree: &'t Tree) -> Option<Map<&str, &'t Tree>> {
+    let mut bindings = Map::new();
+    self.match_tree(&self.pattern, tree, &mut bindings).then_some(bindings)
+  }
+
+  fn match_tree<'p, 't>(&'p self, pattern: &'p Tree, tree: &'t Tree, bindings: &mut Map<&'p str, &'t Tree>) -> bool {
+    maybe_grow(|| match (pattern, tree) {
+      (Tree::Var { nam }, _) if !self.wires.contains(nam) => {
+        bindings.insert(nam, tree);
+        true
+      }
+      // both ends of a wire must be the same variable
+      (Tree::Var { nam }, Tree::Var { .. }) => match bindings.get(&**nam) {
+        Some(other) => *other == tree,
+        None => {
+          bindings.insert(nam, tree);
+          true
+        }
+      },
+      (Tree::Var { .. }, _) => false,
+      _ => {
+        same_node(pattern, tree)
+          && pattern.children().zip(tree.children()).all(|(p, t)| self.match_tree(p, t, bindings))
+      }
+    })
+  }
+
+  /// Builds the replacement for a tree that matched the pattern, given the
+  /// bindings of the match, and a function creating fresh variable names.
+  fn replace(&self, bindings: &Map<&str, &Tree>, fresh: &mut dyn FnMut() -> String) -> Tree {
+    let mut renames = Map::new();
+    let mut tree = self.replacement.clone();
+    substitute(&mut tree, &mut |nam| match bindings.get(&**nam) {
+      Some(bound) if !self.wires.contains(nam) => (*bound).clone(),
+      _ => Tree::Var { nam: renames.entry(mem::take(nam)).or_insert_with(&mut *fresh).clone() },
+    });
+    tree
+  }
+}
+
+/// Whether `a` and `b` are the same kind of node, with the same label (or
+/// operation) and number of ports, ignoring their children.
+fn same_node(a: &Tree, b: &Tree) -> bool {
+  match (a, b) {
+    (Tree::Ctr { lab: a, ports: x }, Tree::Ctr { lab: b, ports: y }) => a == b && x.len() == y.len(),
+    (Tree::Op { op: a, .. }, Tree::Op { op: b, .. }) => a == b,
+    (Tree::Mat { .. }, Tree::Mat { .. }) => true,
+    (Tree::Switch { lab: a, arms: x, .. }, Tree::Switch { lab: b, arms: y, .. }) => a == b && x.len() == y.len(),
+    (
+      Tree::Adt { lab: a, variant_index: i, variant_count: n, fields: x },
+      Tree::Adt { lab: b, variant_index: j, variant_count: m, fields: y },
+    ) => a == b && i == j && n == m && x.len() == y.len(),
+    (Tree::Era | Tree::Int { .. } | Tree::F32 { .. } | Tree::Ref { .. }, _) => a == b,
+    _ => false,
+  }
+}
+
+fn count_vars(tree: &Tree, f: &mut dyn FnMut(&str)) {
+  maybe_grow(|| match tree {
+    Tree::Var { nam } => f(nam),
+    _ => tree.children().for_each(|child| count_vars(child, f)),
+  })
+}
+
+fn substitute(tree: &mut Tree, f: &mut dyn FnMut(&mut String) -> Tree) {
+  maybe_grow(|| match tree {
+    Tree::Var { nam } => {
+      let new = f(nam);
+      *tree = new;
+    }
+    _ => tree.children_mut().for_each(|child| substitute(child, f)),
+  })
+}
+
+impl Book {
+  /// Rewrites every definition with `rules`, doing at most `limit` rewrites in
+  /// each; see the [module-level documentation](self).
+  ///
+  /// Returns the total number of rewrites done.
+  pub fn rewrite(&mut self, rules: &[RewriteRule], limit: usize) -> usize {
+    self.nets.values_mut().map(|net| net.rewrite(rules, limit)).sum()
+  }
+}
+
+impl Net {
+  /// Rewrites the trees of this net with `rules`, doing at most `limit`
+  /// rewrites; see the [module-level documentation](self).
+  ///
+  /// Returns the number of rewrites done.
+  pub fn rewrite(&mut self, rules: &[RewriteRule], limit: usize) -> usize {
+    if rules.is_empty() {
+      return 0;
+    }
+    let mut used = Set::new();
+    for tree in self.trees() {
+      count_vars(tree, &mut |nam| {
+        used.insert(nam.to_owned());
+      });
+    }
+    let mut next = 0;
+    let mut fresh = || loop {
+      let nam = format!("_rw{next}");
+      next += 1;
+      if !used.contains(&nam) {
+        break nam;
+      }
+    };
+    let mut rewrites = 0;
+    loop {
+      let before = rewrites;
+      for tree in self.trees_mut() {
+        rewrite_tree(tree, rules, limit, &mut rewrites, &mut fresh);
+      }
+      // a rewrite may let a rule match at an enclosing position
+      if rewrites == before || rewrites == limit {
+        return rewrites;
+      }
+    }
+  }
+}
+
+fn rewrite_tree(
+  tree: &mut Tree,
+  rules: &[RewriteRule],
+  limit: usize,
+  rewrites: &mut usize,
+  fresh: &mut dyn FnMut() -> String,
+) {
+  maybe_grow(|| {
+    'rewrite: while *rewrites < limit {
+      for rule in rules {
+        if let Some(new) = rule.matches(tree).map(|bindings| rule.replace(&bindings, fresh)) {
+          *tree = new;
+          *rewrites += 1;
+          continue 'rewrite;
+        }
+      }
+      break;
+    }
+    for child in tree.children_mut() {
+      rewrite_tree(child, rules, limit, rewrites, fresh);
+    }
+  })
+}
@@ -3,13 +3,16 @@ pub(crate) mod array_vec;
 mod bi_enum;
 mod create_var;
 mod deref;
+mod diff;
 mod maybe_grow;
 mod parse_abbrev_number;
 mod stats;
 
+pub use apply_tree::*;
 pub(crate) use bi_enum::*;
 pub(crate) use create_var::*;
 pub(crate) use deref::*;
+pub use diff::*;
 pub(crate) use maybe_grow::*;
 pub use parse_abbrev_number::*;
 pub use stats::*;
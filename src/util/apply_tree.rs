@@ -3,6 +3,18 @@ use crate::prelude::*;
 use super::{create_var, var_to_num};
 use crate::ast::{Net, Tree};
 
+/// The auxiliary port of the application node that an argument is attached to
+/// by [`Net::apply_tree_at`]; the result is attached to the other one.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "cli", derive(clap::ValueEnum))]
+pub enum ApplySide {
+  /// `(arg result)`, the standard λ-calculus convention.
+  #[default]
+  Left,
+  /// `(result arg)`.
+  Right,
+}
+
 impl Net {
   /// Transforms the net `x & ...` into `y & x ~ (arg y) & ...`
   ///
@@ -10,6 +22,13 @@ impl Net {
   /// if the net is a λ-calculus term, then this function will
   /// apply an argument to it.
   pub fn apply_tree(&mut self, arg: Tree) {
+    self.apply_tree_at(arg, ApplySide::Left)
+  }
+
+  /// Like [`Net::apply_tree`], but attaches `arg` to the given `side` of the
+  /// application node; with [`ApplySide::Right`], the net `x & ...` becomes
+  /// `y & x ~ (y arg) & ...`.
+  pub fn apply_tree_at(&mut self, arg: Tree, side: ApplySide) {
     let mut fresh = 0usize;
     self.ensure_no_conflicts(&mut fresh);
     arg.ensure_no_conflicts(&mut fresh);
@@ -17,7 +36,12 @@ impl Net {
     let fresh_str = create_var(fresh + 1);
 
     let fun = mem::take(&mut self.root);
-    let app = Tree::Ctr { lab: 0, ports: vec![arg, Tree::Var { nam: fresh_str.clone() }] };
+    let res = Tree::Var { nam: fresh_str.clone() };
+    let ports = match side {
+      ApplySide::Left => vec![arg, res],
+      ApplySide::Right => vec![res, arg],
+    };
+    let app = Tree::Ctr { lab: 0, ports };
     self.root = Tree::Var { nam: fresh_str };
     self.redexes.push((fun, app));
   }
@@ -0,0 +1,178 @@
+use crate::prelude::*;
+
+use crate::{
+  ast::{Book, DefName, Net, Tree},
+  util::maybe_grow,
+};
+
+/// The differences between two books; see [`Book::diff`].
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct BookDiff {
+  /// The defs that are only in the new book.
+  pub added: Vec<String>,
+  /// The defs that are only in the old book.
+  pub removed: Vec<String>,
+  /// The defs that are in both books, but with different nets, along with the
+  /// differences between them.
+  pub changed: Vec<(String, Vec<NodeDiff>)>,
+}
+
+/// A subtree that differs between two versions of a net; see [`Net::diff`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NodeDiff {
+  /// The location of the subtree: `root` or `redexes.{i}.lhs` or
+  /// `redexes.{i}.rhs`, followed by the index of each port on the way to it,
+  /// e.g. `root.1.0`.
+  pub path: String,
+  /// The subtree in the old net, if there is one at this location.
+  pub old: Option<Tree>,
+  /// The subtree in the new net, if there is one at this location.
+  pub new: Option<Tree>,
+}
+
+impl BookDiff {
+  pub fn is_empty(&self) -> bool {
+    self.added.is_empty() && self.removed.is_empty() && self.changed.is_empty()
+  }
+}
+
+impl Book {
+  /// Compares the defs of this book with those of `new`.
+  ///
+  /// Nets that only differ in the names of their variables are considered
+  /// equal.
+  pub fn diff(&self, new: &Book) -> BookDiff {
+    let mut diff = BookDiff::default();
+    for (name, old_net) in &self.nets {
+      match new.nets.get(name) {
+        None => diff.removed.push(name.clone()),
+        Some(new_net) => {
+          let nodes = old_net.diff(new_net);
+          if !nodes.is_empty() {
+            diff.changed.push((name.clone(), nodes));
+          }
+        }
+      }
+    }
+    diff.added = new.nets.keys().filter(|name| !self.nets.contains_key(*name)).cloned().collect();
+    diff
+  }
+}
+
+impl Net {
+  /// Compares this net with `new`, returning the outermost subtrees that
+  /// differ between them, in order.
+  ///
+  /// Variables are compared up to renaming: the first time a variable is
+  /// reached in both nets at the same location, the two names are paired, and
+  /// they must then be paired everywhere else. Thus, the nets are equal (and
+  /// this returns nothing) if they only differ in the names of their
+  /// variables.
+  pub fn diff(&self, new: &Net) -> Vec<NodeDiff> {
+    let mut state = DiffState::default();
+    state.diff_trees("root".to_owned(), &self.root, &new.root);
+    for i in 0 .. self.redexes.len().max(new.redexes.len()) {
+      let (old, new) = (self.redexes.get(i), new.redexes.get(i));
+      state.diff_redex_side(format!("redexes.{i}.lhs"), old.map(|x| &x.0), new.map(|x| &x.0));
+      state.diff_redex_side(format!("redexes.{i}.rhs"), old.map(|x| &x.1), new.map(|x| &x.1));
+    }
+    state.diffs
+  }
+}
+
+#[derive(Default)]
+struct DiffState<'a> {
+  /// The pairs of variable names that have been matched, in each direction.
+  old_to_new: Map<&'a str, &'a str>,
+  new_to_old: Map<&'a str, &'a str>,
+  diffs: Vec<NodeDiff>,
+}
+
+impl<'a> DiffState<'a> {
+  fn diff_trees(&mut self, path: String, old: &'a Tree, new: &'a Tree) {
+    maybe_grow(|| {
+      if !self.same_node(old, new) {
+        self.diffs.push(NodeDiff { path, old: Some(old.clone()), new: Some(new.clone()) });
+        return;
+      }
+      for (i, (old, new)) in old.children().zip(new.children()).enumerate() {
+        self.diff_trees(format!("{path}.{i}"), old, new);
+      }
+    })
+  }
+
+  /// Like [`DiffState::diff_trees`], but for one side of a redex that may
+  /// only be in one of the nets.
+  fn diff_redex_side(&mut self, path: String, old: Option<&'a Tree>, new: Option<&'a Tree>) {
+    match (old, new) {
+      (Some(old), Some(new)) => self.diff_trees(path, old, new),
+      (old, new) => self.diffs.push(NodeDiff { path, old: old.cloned(), new: new.cloned() }),
+    }
+  }
+
+  /// Whether `a` and `b` are the same node, with the same number of ports,
+  /// regardless of what their ports are connected to. Pairs the names of `a`
+  /// and `b` if they are both unpaired variables.
+  fn same_node(&mut self, a: &'a Tree, b: &'a Tree) -> bool {
+    if let (Tree::Var { nam: a }, Tree::Var { nam: b }) = (a, b) {
+      return match (self.old_to_new.get(&**a), self.new_to_old.get(&**b)) {
+        (None, None) => {
+          self.old_to_new.insert(a, b);
+          self.new_to_old.insert(b, a);
+          true
+        }
+        (x, _) => x == Some(&&**b),
+      };
+    }
+    same_node(a, b)
+  }
+}
+
+/// Whether `a` and `b` are the same node, with the same number of ports,
+/// regardless of what their ports are connected to. Variables are never the
+/// same node here; see [`DiffState::same_node`].
+fn same_node(a: &Tree, b: &Tree) -> bool {
+  match (a, b) {
+    (Tree::Era, Tree::Era) | (Tree::Mat { .. }, Tree::Mat { .. }) => true,
+    (Tree::Int { val: a, ty: a_ty }, Tree::Int { val: b, ty: b_ty }) => a == b && a_ty == b_ty,
+    (Tree::F32 { val: a }, Tree::F32 { val: b }) => a == b,
+    (Tree::Ref { nam: a }, Tree::Ref { nam: b }) => a == b,
+    (Tree::Ctr { lab: a, ports: a_ports }, Tree::Ctr { lab: b, ports: b_ports }) => {
+      a == b && a_ports.len() == b_ports.len()
+    }
+    (Tree::Op { op: a, .. }, Tree::Op { op: b, .. }) => a == b,
+    (Tree::Switch { lab: a, arms: a_arms, .. }, Tree::Switch { lab: b, arms: b_arms, .. }) => {
+      a == b && a_arms.len() == b_arms.len()
+    }
+    (
+      Tree::Adt { lab: a, variant_index: a_index, variant_count: a_count, fields: a_fields },
+      Tree::Adt { lab: b, variant_index: b_index, variant_count: b_count, fields: b_fields },
+    ) => a == b && a_index == b_index && a_count == b_count && a_fields.len() == b_fields.len(),
+    _ => false,
+  }
+}
+
+impl fmt::Display for BookDiff {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    for name in &self.added {
+      writeln!(f, "+ @{}", DefName(name))?;
+    }
+    for name in &self.removed {
+      writeln!(f, "- @{}", DefName(name))?;
+    }
+    for (name, nodes) in &self.changed {
+      writeln!(f, "~ @{}", DefName(name))?;
+      for node in nodes {
+        writeln!(f, "  {node}")?;
+      }
+    }
+    Ok(())
+  }
+}
+
+impl fmt::Display for NodeDiff {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    let show = |tree: &Option<Tree>| tree.as_ref().map_or("(none)".to_owned(), Tree::to_string);
+    write!(f, "{}: {} -> {}", self.path, show(&self.old), show(&self.new))
+  }
+}
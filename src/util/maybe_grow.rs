@@ -1,4 +1,7 @@
 /// Guard against stack overflows in recursive functions.
 pub(crate) fn maybe_grow<R>(f: impl FnOnce() -> R) -> R {
-  stacker::maybe_grow(1024 * 32, 1024 * 1024, f)
+  #[cfg(feature = "std")]
+  return stacker::maybe_grow(1024 * 32, 1024 * 1024, f);
+  #[cfg(not(feature = "std"))]
+  return f();
 }
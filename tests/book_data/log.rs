@@ -0,0 +1,6 @@
+// Generated by `hvmc compile --emit const`.
+
+pub static BOOK: &[hvmc::flat::DefData] = &[
+  hvmc::flat::DefData { name: "main", net: Some(hvmc::flat::NetData { root: 0x1, nodes: &[(0x13, 0xf), (0x23, 0x1)], redexes: &[(0x12, 0x7)] }) },
+  hvmc::flat::DefData { name: "HVM.log", net: None },
+];
@@ -2,7 +2,7 @@
 
 use std::{
   error::Error,
-  io::Read,
+  io::{Read, Write},
   path::PathBuf,
   process::{Command, ExitStatus, Stdio},
 };
@@ -50,11 +50,30 @@ fn test_cli_reduce() {
     execute_hvmc(&["reduce", "-m", "100M", "--", "a & #3 ~ <* #4 a>"]).unwrap().1,
     @"#12"
   );
+  // Test operator sections
+  assert_display_snapshot!(
+    execute_hvmc(&["reduce", "-m", "100M", "--", "a & <+ #1> ~ (#4 a)", "<+ #1>", "(<- a b> (a (<* #2> b)))"]).unwrap().1,
+    @"#5\n(<+ #1 a> a)\n(<- a b> (a ((<* #2 c> c) b)))"
+  );
+  assert_display_snapshot!(
+    execute_hvmc(&["reduce", "-m", "100M", "--sections", "--", "<+ #1>", "(<- a b> (a (<* #2> b)))"]).unwrap().1,
+    @"<+ #1>\n(<- a b> (a (<* #2> b)))"
+  );
+  // Test operator sections in nets using `_sec` variables
+  assert_display_snapshot!(
+    execute_hvmc(&["reduce", "-m", "100M", "--", "a & (_sec23 a) ~ <+ #1> & #4 ~ _sec23"]).unwrap().1,
+    @"#5"
+  );
   // Test multiple expressions
   assert_display_snapshot!(
     execute_hvmc(&["reduce", "-m", "100M", "--", "a & #3 ~ <* #4 a>", "a & #64 ~ </ #2 a>"]).unwrap().1,
     @"#12\n#32"
   );
+  // Test reducing multiple expressions concurrently
+  assert_display_snapshot!(
+    execute_hvmc(&["reduce", "-m", "100M", "-j", "2", "--", "a & #3 ~ <* #4 a>", "a & #64 ~ </ #2 a>", "a & #1 ~ <+ #2 a>"]).unwrap().1,
+    @"#12\n#32\n#3"
+  );
 
   // Test loading file and reducing expression
   let arithmetic_program = get_arithmetic_program_path();
@@ -75,7 +94,241 @@ fn test_cli_reduce() {
       "--", "a & @mul ~ (#3 (#4 a))", "a & @div ~ (#64 (#2 a))"
     ]).unwrap().1,
     @"#12\n#32"
-  )
+  );
+
+  // Test dumping the heap
+  let output = execute_hvmc(&["reduce", "-m", "100M", "-1", "--dump-heap", "--", "(a a)"]).unwrap().1;
+  let lines: Vec<_> = output.lines().collect();
+  assert_eq!(lines.len(), 4, "{output}");
+  assert_eq!(lines[0], "(a a)");
+  assert!(lines[1 .. 3].iter().all(|line| line.contains(": (")), "{output}");
+  assert!(lines[3].starts_with("root: "), "{output}");
+
+  // Test checking for leaks
+  assert_display_snapshot!(
+    execute_hvmc(&["reduce", "-m", "100M", "-1", "--verify", "--", "a & #3 ~ <* #4 a>"]).unwrap().1,
+    @r###"
+  #12
+  LEAKS  :               0
+  "###
+  );
+}
+
+#[test]
+fn test_cli_reduce_directives() {
+  // the expressions of the `#reduce` directives in the file are reduced, but
+  // not those of the files it imports
+  assert_display_snapshot!(
+    execute_hvmc(&["reduce", "tests/reduce/combined.hvmc"]).unwrap().1,
+    @r###"
+  #12
+  #20
+  "###
+  );
+  assert_display_snapshot!(execute_hvmc(&["reduce", "tests/reduce/lib.hvmc"]).unwrap().1, @"#42");
+  // expressions given on the command line replace them
+  assert_display_snapshot!(
+    execute_hvmc(&["reduce", "tests/reduce/combined.hvmc", "--", "a & @quad ~ (#1 a)"]).unwrap().1,
+    @"#4"
+  );
+  // the directives are kept by transformations
+  assert_display_snapshot!(
+    execute_hvmc(&["transform", "tests/reduce/combined.hvmc"]).unwrap().1,
+    @r###"
+  /// Doubles a number.
+  @double = (<* #2 _sec0> _sec0)
+
+  @quad = (a b)
+    & @double ~ (a c)
+    & @double ~ (c b)
+
+  #reduce a
+    & @quad ~ (#3 a)
+
+  #reduce a
+    & @quad ~ (#5 a)
+  "###
+  );
+}
+
+#[test]
+fn test_cli_result_separator() {
+  assert_eq!(
+    execute_hvmc(&["reduce", "-m", "100M", "--result-separator", "\\0", "--", "#1", "(a a)"]).unwrap().1,
+    "#1\0(a a)"
+  );
+  assert_eq!(
+    execute_hvmc(&["reduce", "-m", "100M", "--result-separator", ";\\t", "--trailing-separator", "--", "#1", "#2"])
+      .unwrap()
+      .1,
+    "#1;\t#2;\t"
+  );
+  assert_eq!(execute_hvmc(&["reduce", "-m", "100M", "--", "#1", "#2"]).unwrap().1, "#1\n#2\n");
+
+  let (status, output) = execute_hvmc(&["reduce", "--result-separator", "\\q", "--", "#1"]).unwrap();
+  assert!(!status.success());
+  assert!(output.contains("unknown escape sequence `\\q`"), "{output}");
+}
+
+#[test]
+fn test_cli_stats_per_expr() {
+  assert_eq!(
+    execute_hvmc(&[
+      "reduce",
+      "-m",
+      "100M",
+      "--stats-per-expr",
+      "--",
+      "#1",
+      "a & #1 ~ <+ #2 a>",
+      "a & (b b) ~ (c a) & #1 ~ <+ #2 c>"
+    ])
+    .unwrap()
+    .1,
+    "#1\t0\n#3\t1\n#3\t3\n"
+  );
+}
+
+#[test]
+fn test_cli_linear() {
+  // Linear programs reduce as usual
+  assert_display_snapshot!(
+    execute_hvmc(&["reduce", "-m", "100M", "--linear", "--", "a & (b b) ~ (c a) & #1 ~ <+ #2 c>"]).unwrap().1,
+    @"#3"
+  );
+
+  // Erasing a node
+  let (status, output) = execute_hvmc(&["reduce", "-m", "100M", "--linear", "--", "a & * ~ (#1 a)"]).unwrap();
+  assert!(!status.success());
+  assert_display_snapshot!(output, @"linearity violation: an eraser erased a Ctr node");
+
+  // Duplicating a number
+  let (status, output) =
+    execute_hvmc(&["reduce", "-m", "100M", "--linear", "--", "a & {1 x y} ~ #2 & (x y) ~ a"]).unwrap();
+  assert!(!status.success());
+  assert_display_snapshot!(output, @"linearity violation: a Ctr node duplicated an Int node");
+
+  // Duplicating a node
+  let (status, output) =
+    execute_hvmc(&["reduce", "-m", "100M", "-1", "--linear", "--", "a & {1 x y} ~ (b b) & (x y) ~ a"]).unwrap();
+  assert!(!status.success());
+  assert_display_snapshot!(output, @"linearity violation: a Ctr node and a Ctr node duplicated each other");
+}
+
+#[test]
+fn test_cli_ext_op() {
+  // the CLI registers no ext ops, so reduction stops at the first one
+  for threads in [&[][..], &["-1"]] {
+    let args = [&["reduce", "-m", "100M"], threads, &["--", "a & #1 ~ <ext:9 #2 a>"]].concat();
+    let (status, output) = execute_hvmc(&args).unwrap();
+    assert_eq!(status.code(), Some(4));
+    assert_eq!(output, "failed operation: no function is registered for `ext:9`\n");
+  }
+}
+
+#[test]
+fn test_cli_readback_numerals() {
+  let church_program = env!("CARGO_MANIFEST_DIR").to_owned() + "/examples/church_encoding/church.hvmc";
+
+  // Church numerals
+  assert_display_snapshot!(
+    execute_hvmc(&["run", "-m", "100M", "--readback", "numerals", "-e", "c3", &church_program]).unwrap().1,
+    @"#3"
+  );
+  assert_display_snapshot!(
+    execute_hvmc(&["run", "-m", "100M", "--readback", "numerals", "-e", "c4", &church_program]).unwrap().1,
+    @"#4"
+  );
+
+  // Scott numerals
+  assert_display_snapshot!(
+    execute_hvmc(&["reduce", "-m", "100M", "--readback", "numerals", "--", "(* (((* (((* (((z (* z)) a) a)) b) b)) c) c))"]).unwrap().1,
+    @"#3"
+  );
+
+  // Nets that don't exactly match a numeral are left alone
+  assert_display_snapshot!(
+    execute_hvmc(&["reduce", "-m", "100M", "--readback", "numerals", "--", "({1 (a b) (b c)} (c a))", "(* ((a b) (b a)))"]).unwrap().1,
+    @"([(a b) (b c)] (c a))\n(* ((a b) (b a)))"
+  );
+}
+
+#[test]
+fn test_cli_readback_refs() {
+  let program = PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join("readback_refs.hvmc");
+  std::fs::write(&program, "@true = (t (* t))\n@false = (* (f f))\n@main = (?<(@false (* @true)) a> a)\n").unwrap();
+  let program = program.to_str().unwrap();
+  assert_display_snapshot!(
+    execute_hvmc(&["run", "-m", "100M", program, "#1"]).unwrap().1,
+    @"(a (* a))"
+  );
+  assert_display_snapshot!(
+    execute_hvmc(&["run", "-m", "100M", "--readback", "refs", program, "#0"]).unwrap().1,
+    @"@false"
+  );
+  assert_display_snapshot!(
+    execute_hvmc(&["run", "-m", "100M", "--readback", "refs", program, "#1"]).unwrap().1,
+    @"@true"
+  );
+}
+
+#[test]
+fn test_cli_normalize_output() {
+  // a church numeral and the equivalent literal, which only print the same
+  // once their variables are renamed
+  let exprs = ["(({2 (a b) (b c)} (a c)) (x x))", "(#2 (x x))"];
+  assert_display_snapshot!(
+    execute_hvmc(&[&["reduce", "-m", "100M", "--readback", "numerals", "--"][..], &exprs].concat()).unwrap().1,
+    @"(#2 (d d))\n(#2 (a a))"
+  );
+  assert_display_snapshot!(
+    execute_hvmc(&[&["reduce", "-m", "100M", "--readback", "numerals", "--normalize-output", "--"][..], &exprs].concat()).unwrap().1,
+    @"(#2 (a a))\n(#2 (a a))"
+  );
+}
+
+#[test]
+fn test_cli_io() {
+  let io_program = env!("CARGO_MANIFEST_DIR").to_owned() + "/tests/programs/io.hvmc";
+
+  let mut child = Command::new(env!("CARGO_BIN_EXE_hvmc"))
+    .args(["run", "-m", "100M", "--io", &io_program])
+    .stdin(Stdio::piped())
+    .stdout(Stdio::piped())
+    .spawn()
+    .unwrap();
+  child.stdin.take().unwrap().write_all(b"#41\n").unwrap();
+  let output = child.wait_with_output().unwrap();
+  assert_eq!(output.status.code(), Some(2));
+  assert_display_snapshot!(String::from_utf8(output.stdout).unwrap(), @r###"
+  #72
+  #42
+  "###);
+
+  let (status, output) = execute_hvmc(&["reduce", "-m", "100M", "--io", "--", "(:1:3 #1 (:0:3 #0))", "#2"]).unwrap();
+  assert_eq!(status.code(), Some(4));
+  assert_display_snapshot!(output, @r###"
+  #1
+  Normal form is not an effect: #2
+  "###);
+
+  // exit codes that don't fit in an exit status are rejected, rather than
+  // truncated
+  for code in ["#256", "#-1"] {
+    let (status, output) = execute_hvmc(&["reduce", "--io", "--", &format!("(:0:3 {code})")]).unwrap();
+    assert_eq!(status.code(), Some(4));
+    assert!(output.contains(&format!("Exit code {} is not between 0 and 255", &code[1 ..])), "{output}");
+  }
+  assert_eq!(execute_hvmc(&["reduce", "--io", "--", "(:0:3 #255)"]).unwrap().0.code(), Some(255));
+
+  // effects are performed in order, so expressions can't be reduced concurrently
+  assert_display_snapshot!(execute_hvmc(&["reduce", "-j", "2", "--io", "--", "#1"]).unwrap().1, @r###"
+  error: the argument '--jobs <JOBS>' cannot be used with '--io'
+
+  Usage: hvmc reduce --jobs <JOBS> [FILES]... -- <EXPRS>...
+
+  For more information, try '--help'.
+  "###);
 }
 
 #[test]
@@ -111,6 +364,27 @@ fn test_cli_run_with_args() {
     ]).unwrap().1,
     @"[#21 #1]"
   );
+
+  // Test passing arguments on the right of application nodes.
+  let program = PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join("apply_side.hvmc");
+  std::fs::write(&program, "@main = ((r b) a) & a ~ <- b r>").unwrap();
+  let program = program.to_str().unwrap();
+  assert_display_snapshot!(
+    execute_hvmc(&["run", "-m", "100M", "--apply-side", "right", program, "#5", "#2"]).unwrap().1,
+    @"#3"
+  );
+}
+
+#[test]
+fn test_cli_bench() {
+  let arithmetic_program = get_arithmetic_program_path();
+
+  let (status, output) =
+    execute_hvmc(&["bench", "-m", "100M", "--runs", "3", "--warmup", "1", &arithmetic_program, "#64", "#3"]).unwrap();
+  assert!(status.success(), "{output}");
+  let rows: Vec<_> = output.lines().map(|line| line.split_whitespace().next().unwrap()).collect();
+  assert_eq!(rows, ["RUNS", "min", "TIME", "RPS"]);
+  assert!(output.starts_with("RUNS   : 3 (1 warmup)"), "{output}");
 }
 
 #[test]
@@ -178,6 +452,173 @@ fn test_cli_transform() {
   );
 }
 
+#[test]
+fn test_cli_transform_keeps_docs() {
+  let program = PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join("docs.hvmc");
+  std::fs::write(
+    &program,
+    "// not a doc comment\n/// The identity function,\n///\n/// eta-expanded.\n@id = (a b) & (c c) ~ (a b)\n@main = x & @id ~ (#1 x)",
+  )
+  .unwrap();
+  let program = program.to_str().unwrap();
+
+  let output = execute_hvmc(&["transform", "-Oeta-reduce", program]).unwrap().1;
+  assert_display_snapshot!(output, @r###"
+  /// The identity function,
+  ///
+  /// eta-expanded.
+  @id = a
+    & (c c) ~ a
+
+  @main = x
+    & @id ~ (#1 x)
+  "###);
+
+  // the output round-trips
+  let reparsed = PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join("docs_transformed.hvmc");
+  std::fs::write(&reparsed, &output).unwrap();
+  assert_eq!(execute_hvmc(&["transform", "-Oeta-reduce", reparsed.to_str().unwrap()]).unwrap().1, output);
+
+  // pre-reducing rewrites the def, dropping its doc comment
+  assert!(!execute_hvmc(&["transform", "-Opre-reduce", program]).unwrap().1.contains("///"));
+}
+
+#[test]
+fn test_cli_diff() {
+  let dir = PathBuf::from(env!("CARGO_TARGET_TMPDIR"));
+  let old = dir.join("diff_old.hvmc");
+  let new = dir.join("diff_new.hvmc");
+  std::fs::write(&old, "@id = ((x y) (x y))\n@k = (a (* a))\n@app = ((a b) (a b)) & @id ~ (c c)\n@old = *\n").unwrap();
+  let (old, new) = (old.to_str().unwrap(), new.to_str().unwrap());
+
+  let eta_reduced = execute_hvmc(&["transform", "-Oeta-reduce", old]).unwrap().1;
+  std::fs::write(new, eta_reduced.replace("@old = *\n", "") + "@new = #1\n").unwrap();
+  let (status, output) = execute_hvmc(&["diff", old, new]).unwrap();
+  assert_eq!(status.code(), Some(5));
+  assert_display_snapshot!(output, @r###"
+  + @new
+  - @old
+  ~ @app
+    root.0: (a b) -> a
+    root.1: (a b) -> a
+  ~ @id
+    root.0: (x y) -> x
+    root.1: (x y) -> x
+  "###);
+
+  // nets that only differ in the names of their variables are equal
+  let renamed = dir.join("diff_renamed.hvmc");
+  std::fs::write(&renamed, "@id = ((b a) (b a))\n@k = (x (* x))\n@app = ((p q) (p q)) & @id ~ (r r)\n@old = *\n")
+    .unwrap();
+  let (status, output) = execute_hvmc(&["diff", old, renamed.to_str().unwrap()]).unwrap();
+  assert!(status.success());
+  assert_eq!(output, "");
+
+  // but they must be renamed consistently
+  std::fs::write(&renamed, "@id = ((x y) (y x))\n@k = (a (* a))\n@app = ((a b) (a b)) & @id ~ (c c)\n@old = *\n")
+    .unwrap();
+  assert_display_snapshot!(execute_hvmc(&["diff", old, renamed.to_str().unwrap()]).unwrap().1, @r###"
+  ~ @id
+    root.1.0: x -> y
+    root.1.1: y -> x
+  "###);
+}
+
+#[test]
+fn test_cli_validate() {
+  let dir = PathBuf::from(env!("CARGO_TARGET_TMPDIR"));
+  let program = dir.join("validate.hvmc");
+  std::fs::write(&program, "@ok = (a a)\n@once = (a b)\n@thrice = (a (a a))\n#reduce x & @ok ~ (y x)\n").unwrap();
+  let program = program.to_str().unwrap();
+
+  // variables are only counted with `--strict`
+  let (status, output) = execute_hvmc(&["validate", program]).unwrap();
+  assert!(status.success());
+  assert_eq!(output, "");
+  let (status, output) = execute_hvmc(&["validate", "--strict", program]).unwrap();
+  assert_eq!(status.code(), Some(2));
+  assert_display_snapshot!(output, @r###"
+  @once: `a` appears once
+  @once: `b` appears once
+  @thrice: `a` appears 3 times
+  #reduce 1: `y` appears once
+  "###);
+
+  let (status, output) = execute_hvmc(&["validate", "--strict", &get_arithmetic_program_path()]).unwrap();
+  assert!(status.success());
+  assert_eq!(output, "");
+  let (status, output) = execute_hvmc(&["validate", "tests/imports/collision.hvmc"]).unwrap();
+  assert_eq!(status.code(), Some(2));
+  assert_display_snapshot!(output, @"@inc is defined in both tests/imports/lib/common.hvmc and tests/imports/collision.hvmc");
+}
+
+#[test]
+fn test_cli_print_passes() {
+  assert_display_snapshot!(
+    execute_hvmc(&[
+      "transform",
+      "-Oidentity",
+      "-Oeta",
+      "--print-passes",
+      &(env!("CARGO_MANIFEST_DIR").to_owned() + "/tests/programs/log.hvmc")
+    ]).unwrap().1,
+    @r###"
+  @main = a
+    & @HVM.log ~ (#1 (#2 a))
+  eta-reduce
+  identity
+  "###
+  );
+}
+
+#[test]
+fn test_cli_disable_passes() {
+  let program = PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join("disable_passes.hvmc");
+  std::fs::write(&program, "@main = ((a b) (a b))").unwrap();
+  let program = program.to_str().unwrap();
+  let transform = |passes: &[&str]| execute_hvmc(&[&["transform"], passes, &[program]].concat()).unwrap().1;
+
+  assert_display_snapshot!(transform(&["-Oeta-reduce"]), @"@main = (a a)");
+  // no pass runs by default, and later flags override earlier ones
+  assert_display_snapshot!(transform(&["-Ono-eta-reduce"]), @"@main = ((a b) (a b))");
+  assert_display_snapshot!(transform(&["-Oeta", "-Ono-eta-reduce"]), @"@main = ((a b) (a b))");
+  assert_display_snapshot!(transform(&["-Oall", "-Ono-eta-reduce", "-Ono-coalesce"]), @"@main = ((a b) (a b))");
+  assert_display_snapshot!(transform(&["-Ono-eta-reduce", "-Oeta"]), @"@main = (a a)");
+}
+
+#[test]
+fn test_cli_fold_on_parse() {
+  let program = PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join("fold_on_parse.hvmc");
+  std::fs::write(
+    &program,
+    "@main = (a (b (c d)))  & #2 ~ <+ #3 x>  & x ~ <* #4 a>  & #1.5 ~ <f32.* #2.0 b>  & #255u8 ~ <+ #1 c>  & @one ~ <- #1 d>  @one = #1",
+  )
+  .unwrap();
+  let program = program.to_str().unwrap();
+
+  assert_display_snapshot!(execute_hvmc(&["transform", "--fold-on-parse", program]).unwrap().1, @r###"
+  @main = (#20 (#3.0 (#0 d)))
+    & @one ~ <- #1 d>
+  
+  @one = #1
+  "###);
+  // the results are the same as those computed at runtime
+  assert_display_snapshot!(execute_hvmc(&["run", program]).unwrap().1, @"(#20 (#3.0 (#0 #0)))");
+  assert_display_snapshot!(execute_hvmc(&["run", "--fold-on-parse", program]).unwrap().1, @"(#20 (#3.0 (#0 #0)))");
+
+  // operations that would trap are not folded
+  let program = PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join("fold_on_parse_trap.hvmc");
+  std::fs::write(&program, "@main = a & #250u8 ~ <+c #10u8 a>").unwrap();
+  let program = program.to_str().unwrap();
+  assert_display_snapshot!(execute_hvmc(&["transform", "--fold-on-parse", program]).unwrap().1, @r###"
+  @main = a
+    & #250u8 ~ <u8.+c #10u8 a>
+  "###);
+  let (status, output) = execute_hvmc(&["run", "--fold-on-parse", program]).unwrap();
+  assert_eq!(status.code(), Some(4));
+  assert_display_snapshot!(output, @"failed operation: arithmetic overflow in checked operation `+c`");
+}
+
 #[test]
 fn test_cli_errors() {
   // Test passing all arguments.
@@ -199,6 +640,471 @@ fn test_cli_errors() {
   );
 }
 
+#[test]
+#[cfg(feature = "gzip")]
+fn test_cli_gzip() {
+  use flate2::{write::GzEncoder, Compression};
+  use hvmc::ast::Book;
+
+  let program = get_arithmetic_program_path();
+  let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+  encoder.write_all(&std::fs::read(&program).unwrap()).unwrap();
+  let compressed = PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join("arithmetic.hvmc.gz");
+  std::fs::write(&compressed, encoder.finish().unwrap()).unwrap();
+
+  // the compressed file is read back as the original one
+  let reduce = |file: &str| execute_hvmc(&["reduce", "-m", "100M", file, "--", "a & @mul ~ (#3 (#4 a))"]).unwrap().1;
+  assert_eq!(reduce(compressed.to_str().unwrap()), reduce(&program));
+  assert_display_snapshot!(reduce(compressed.to_str().unwrap()), @"#12");
+
+  // binary books can be compressed too
+  let book: Book = std::fs::read_to_string(&program).unwrap().parse().unwrap();
+  let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+  encoder.write_all(&book.to_flat().unwrap().to_bytes()).unwrap();
+  let compressed = PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join("arithmetic.hvmb.gz");
+  std::fs::write(&compressed, encoder.finish().unwrap()).unwrap();
+  assert_display_snapshot!(reduce(compressed.to_str().unwrap()), @"#12");
+}
+
+#[test]
+#[cfg(not(feature = "gzip"))]
+fn test_cli_gzip() {
+  // gzip-compressed files are detected by their magic number
+  let compressed = PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join("compressed.hvmc");
+  std::fs::write(&compressed, [0x1f, 0x8b, 0x08, 0x00]).unwrap();
+  let (status, output) = execute_hvmc(&["run", compressed.to_str().unwrap()]).unwrap();
+  assert_eq!(status.code(), Some(3));
+  assert!(output.contains("is gzip-compressed, which requires the `gzip` feature"), "{output}");
+}
+
+#[test]
+#[cfg(feature = "std")]
+fn test_cli_binary_book() {
+  use hvmc::ast::Book;
+
+  let program = get_arithmetic_program_path();
+  let book: Book = std::fs::read_to_string(&program).unwrap().parse().unwrap();
+  let binary = PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join("arithmetic.hvmb");
+  std::fs::write(&binary, book.to_flat().unwrap().to_bytes()).unwrap();
+
+  // binary books are detected by their magic number, and read back as the
+  // original book
+  let reduce = |file: &str| execute_hvmc(&["reduce", "-m", "100M", file, "--", "a & @mul ~ (#3 (#4 a))"]).unwrap().1;
+  assert_eq!(reduce(binary.to_str().unwrap()), reduce(&program));
+  assert_display_snapshot!(reduce(binary.to_str().unwrap()), @"#12");
+
+  let truncated = PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join("truncated.hvmb");
+  std::fs::write(&truncated, &book.to_flat().unwrap().to_bytes()[.. 12]).unwrap();
+  let (status, output) = execute_hvmc(&["run", truncated.to_str().unwrap()]).unwrap();
+  assert_eq!(status.code(), Some(2));
+  assert!(output.starts_with("Invalid binary book"), "{output}");
+}
+
+#[test]
+fn test_cli_imports() {
+  // `left` and `right` both import `lib/common`, which is only loaded once
+  assert_display_snapshot!(
+    execute_hvmc(&["run", "tests/imports/main.hvmc"]).unwrap().1,
+    @"(#2 #4)"
+  );
+  assert_display_snapshot!(
+    execute_hvmc(&["transform", "tests/imports/main.hvmc"]).unwrap().1,
+    @r###"
+  /// Adds one to a number.
+  @inc = (<+ #1 a> a)
+
+  @left = (a b)
+    & @inc ~ (a b)
+
+  @main = (a b)
+    & @left ~ (#1 a)
+    & @right ~ (#2 b)
+
+  @right = (a b)
+    & @inc ~ (c b)
+    & @inc ~ (a c)
+  "###
+  );
+  assert_display_snapshot!(
+    execute_hvmc(&["run", "tests/imports/cycle.hvmc"]).unwrap().1,
+    @"Import cycle: tests/imports/cycle.hvmc -> tests/imports/lib/cycle.hvmc -> tests/imports/lib/../cycle.hvmc"
+  );
+  assert_display_snapshot!(
+    execute_hvmc(&["run", "tests/imports/collision.hvmc"]).unwrap().1,
+    @"@inc is defined in both tests/imports/lib/common.hvmc and tests/imports/collision.hvmc"
+  );
+}
+
+#[test]
+fn test_cli_max_parse_depth() {
+  let program = env!("CARGO_MANIFEST_DIR").to_owned() + "/tests/programs/church_exp.hvmc";
+  let (status, output) = execute_hvmc(&["run", "--max-parse-depth", "3", &program]).unwrap();
+  assert_eq!(status.code(), Some(2));
+  assert!(output.contains("trees are nested too deeply (at byte 67)"), "{output}");
+  let (status, output) = execute_hvmc(&["run", "--max-parse-depth", "64", &program]).unwrap();
+  assert!(status.success(), "{output}");
+}
+
+#[test]
+fn test_cli_entry_points() {
+  let program = PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join("entry_points.hvmc");
+  std::fs::write(&program, "@fn_0 = (a a)  @fn_1 = (* (a a))  @fn_10 = (a b) & #1 ~ <+ a b>  @main = *").unwrap();
+  let program = program.to_str().unwrap();
+
+  assert_display_snapshot!(
+    execute_hvmc(&["run", "-m", "100M", "--entry-glob", "fn_*", program, "#2"]).unwrap().1,
+    @r###"
+  @fn_0: #2
+  @fn_1: (a a)
+  @fn_10: #3
+  "###
+  );
+  assert_display_snapshot!(
+    execute_hvmc(&["run", "-m", "100M", "--entry-glob", "fn_?", program]).unwrap().1,
+    @r###"
+  @fn_0: (a a)
+  @fn_1: (* (a a))
+  "###
+  );
+  assert_display_snapshot!(
+    execute_hvmc(&["run", "-m", "100M", "--entry-index", "2", program, "#2"]).unwrap().1,
+    @"#3"
+  );
+  assert_display_snapshot!(
+    execute_hvmc(&["run", "-m", "100M", "--entry-glob", "f?_*1", program]).unwrap().1,
+    @"@fn_1: (* (a a))"
+  );
+  assert_display_snapshot!(execute_hvmc(&["run", "--entry-glob", "gn_*", program]).unwrap().1, @"no definition matches `gn_*`");
+  assert_display_snapshot!(execute_hvmc(&["run", "--entry-index", "4", program]).unwrap().1, @"entry index 4 is out of range");
+  assert_display_snapshot!(execute_hvmc(&["run", "--io", "--entry-glob", "fn_*", program]).unwrap().1, @r###"
+  error: the argument '--io' cannot be used with '--entry-glob <ENTRY_GLOB>'
+
+  Usage: hvmc run --io <FILE> [ARGS]...
+
+  For more information, try '--help'.
+  "###);
+}
+
+#[test]
+fn test_cli_count_initial_redexes() {
+  let program = PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join("redexes.hvmc");
+  std::fs::write(&program, "@id = (x x)  @main = (a (b c)) & @id ~ (#1 a) & @id ~ (#2 b) & @id ~ (#3 c)").unwrap();
+  let program = program.to_str().unwrap();
+
+  assert_display_snapshot!(
+    execute_hvmc(&["run", "-m", "100M", "--count-initial-redexes", program]).unwrap().1,
+    @r###"
+  (#1 (#2 #3))
+  REDEXES:               3
+  "###
+  );
+  assert_display_snapshot!(
+    execute_hvmc(&["reduce", "-m", "100M", "--count-initial-redexes", program, "--", "@main", "(a b) & @id ~ (a b)"]).unwrap().1,
+    @r###"
+  (#1 (#2 #3))
+  (a a)
+  REDEXES:               3
+  REDEXES:               1
+  "###
+  );
+}
+
+#[test]
+fn test_cli_verbose_stats() {
+  let output = execute_hvmc(&["reduce", "-m", "100M", "-s", "-v", "--", "a & (x <* #4 a>) ~ ((b b) x)"]).unwrap().1;
+  // strip the timing information
+  let output =
+    output.lines().filter(|l| !l.starts_with("TIME") && !l.starts_with("RPS")).collect::<Vec<_>>().join("\n");
+  assert_display_snapshot!(output, @r###"
+  (a a)
+  RWTS   :               7
+  - ANNI :               2
+  - COMM :               2
+  - ERAS :               1
+  - DREF :               2
+  - OPER :               0
+  ALLOCS :               6
+  - FREE :               4
+  - BUMP :               2
+  HITRATE:           0.667
+  MAXFREE:               5
+  DEPTH  :               2
+  "###);
+
+  let (status, output) = execute_hvmc(&["reduce", "-v", "--", "#1"]).unwrap();
+  assert!(!status.success());
+  assert!(output.contains("--stats"), "{output}");
+}
+
+#[test]
+fn test_cli_normal_input() {
+  let program = PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join("normal_input.hvmc");
+  std::fs::write(&program, "@id = (x x)").unwrap();
+  let program = program.to_str().unwrap();
+  // expressions that are already in normal form are not reduced at all
+  let rwts = |expr: &str| {
+    let output = execute_hvmc(&["reduce", "-m", "100M", "-s", program, "--", expr]).unwrap().1;
+    output.lines().take(2).collect::<Vec<_>>().join("\n")
+  };
+  assert_display_snapshot!(rwts("#1"), @r###"
+  #1
+  RWTS   :               0
+  "###);
+  assert_display_snapshot!(rwts("(a ({2 b *} [a b]))"), @r###"
+  (a ({2 b *} [a b]))
+  RWTS   :               0
+  "###);
+  // refs are still expanded
+  assert_display_snapshot!(rwts("(@id *)"), @r###"
+  ((a a) *)
+  RWTS   :               6
+  "###);
+}
+
+#[test]
+fn test_cli_num_format() {
+  let program = PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join("num_format.hvmc");
+  std::fs::write(&program, "@main = (a (b c)) & #-5 ~ <* #1 a> & #255u8 ~ <+ #1u8 b> & #1000000 ~ <* #1000000 c>")
+    .unwrap();
+  let program = program.to_str().unwrap();
+  assert_display_snapshot!(execute_hvmc(&["run", program]).unwrap().1, @r###"
+  (#-5 (#0 #1000000000000))
+  "###);
+  assert_display_snapshot!(execute_hvmc(&["run", "--num-format", "hex", program]).unwrap().1, @r###"
+  (#-0x5 (#0x0 #0xe8d4a51000))
+  "###);
+}
+
+#[test]
+fn test_cli_steps() {
+  let program = PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join("steps.hvmc");
+  std::fs::write(&program, "@loop = (?<(#0 @loop) a> a)\n@main = a & @loop ~ (#200 a)").unwrap();
+  let program = program.to_str().unwrap();
+  let steps = |limit: &str, expr: &str| execute_hvmc(&["reduce", "-1", "--steps", limit, program, "--", expr]).unwrap();
+
+  // the countdown is stopped half-way, leaving its next iteration as a redex
+  let (status, output) = steps("100", "@main");
+  assert!(status.success(), "{output}");
+  assert_display_snapshot!(output, @r###"
+  a
+    & (#101 a) ~ @loop
+  STEPS  :             100
+  "###);
+
+  // which can be reduced the rest of the way
+  assert_display_snapshot!(steps("1K", "a & (#101 a) ~ @loop").1, @r###"
+  #0
+  STEPS  :             103
+  "###);
+
+  // a normal form is reached before the limit
+  assert_display_snapshot!(steps("1K", "@main").1, @r###"
+  #0
+  STEPS  :             203
+  "###);
+
+  // no interaction is performed with a limit of zero
+  assert_display_snapshot!(steps("0", "a & (x x) ~ (#1 a)").1, @r###"
+  a
+    & (b b) ~ (#1 a)
+  STEPS  :               0
+  "###);
+  assert_display_snapshot!(steps("1", "a & (x x) ~ (#1 a)").1, @r###"
+  a
+    & #1 ~ a
+  STEPS  :               1
+  "###);
+}
+
+#[test]
+fn test_cli_explain() {
+  assert_display_snapshot!(
+    execute_hvmc(&["reduce", "-m", "100M", "-1", "--explain", "--", "a & (x <* #4 a>) ~ (#3 x)"]).unwrap().1,
+    @r###"
+  #12
+  anni2      (_ _) ~ (#3 _)
+  op_num     <* #4 _> ~ #3
+  "###
+  );
+  assert_display_snapshot!(
+    execute_hvmc(&["run", "-m", "100M", "-1", "--explain", &get_arithmetic_program_path(), "#10", "#3"]).unwrap().1,
+    @r###"
+  [#3 #1]
+  call       @main ~ (#10 _)
+  comm02     #3 ~ {5 _ _}
+  comm02     #10 ~ {3 _ _}
+  call       @div ~ (#10 _)
+  op_num     #3 ~ </$ #10 _>
+  call       @mod ~ (#10 _)
+  op_num     #3 ~ <%$ #10 _>
+  "###
+  );
+  // `HVM.log` locks the host to read back refs while the reduction is explained
+  assert_display_snapshot!(
+    execute_hvmc(&["reduce", "-1", "--explain", "--", "a & @HVM.log ~ (@HVM.black_box a)"]).unwrap().1,
+    @r###"
+  @HVM.black_box
+  (a a)
+  call       @HVM.log ~ (@HVM.black_box _)
+  call       @HVM.black_box ~ _
+  "###
+  );
+}
+
+#[test]
+fn test_cli_coverage() {
+  let program = env!("CARGO_MANIFEST_DIR").to_owned() + "/tests/programs/coverage.hvmc";
+  assert_display_snapshot!(
+    execute_hvmc(&["run", "-1", "--no-skip", "--coverage", &program]).unwrap().1,
+    @r###"
+  (#1 ((#2 #3) (#13 (a (#8 (#11 (<+ #6 a> *)))))))
+  COVER  :           10/10
+  - VOID :              11
+  - DREF :              23
+  - ANNI :               5
+  - COMM :               1
+  - ERA2 :               5
+  - COPY :               2
+  - OP2N :               1
+  - OP1N :               1
+  - MTCH :               1
+  - SWIT :               1
+  "###
+  );
+  let output = execute_hvmc(&["run", "--coverage", &program]).unwrap().1;
+  assert!(output.contains("COVER  :            9/10"), "{output}");
+  assert!(output.contains("- VOID :               0 (missing)"), "{output}");
+}
+
+#[test]
+fn test_cli_lazy_fallback() {
+  let program = env!("CARGO_MANIFEST_DIR").to_owned() + "/tests/programs/church_exp.hvmc";
+  let expected = execute_hvmc(&["run", "-m", "64K", &program]).unwrap().1;
+  let (status, output) = execute_hvmc(&["run", "-m", "64K", "-s", "--lazy-fallback", "10", &program]).unwrap();
+  assert!(status.success(), "{output}");
+  assert!(output.starts_with(&expected), "{output}");
+  assert!(output.contains("MODE   :  strict -> lazy"), "{output}");
+
+  // nothing is reported if the limit is never reached
+  let output = execute_hvmc(&["run", "-m", "64K", "-s", "--lazy-fallback", "100", &program]).unwrap().1;
+  assert!(output.starts_with(&expected), "{output}");
+  assert!(!output.contains("MODE"), "{output}");
+}
+
+#[test]
+fn test_cli_checkpoints() {
+  let program = env!("CARGO_MANIFEST_DIR").to_owned() + "/tests/programs/church_exp.hvmc";
+  let dir = PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join("checkpoints");
+  let _ = std::fs::remove_dir_all(&dir);
+  let expected = execute_hvmc(&["run", "-m", "100M", &program]).unwrap().1;
+
+  let args = ["run", "-m", "100M", "-1", "--checkpoint-every", "500", "--checkpoint-path", dir.to_str().unwrap()];
+  let (status, output) = execute_hvmc(&[&args[..], &["--checkpoint-keep", &program]].concat()).unwrap();
+  assert!(status.success(), "{output}");
+  assert_eq!(output, expected);
+  let mut names: Vec<_> = std::fs::read_dir(&dir).unwrap().map(|e| e.unwrap().file_name()).collect();
+  names.sort();
+  assert_eq!(names, ["checkpoint-1.hvmc", "checkpoint-2.hvmc", "checkpoint-3.hvmc"]);
+
+  // a reduction interrupted after any checkpoint resumes to the same result
+  for name in &names {
+    let checkpoint = std::fs::read_to_string(dir.join(name)).unwrap();
+    let (status, output) = execute_hvmc(&["reduce", "-m", "100M", &program, "--", &checkpoint]).unwrap();
+    assert!(status.success(), "{output}");
+    assert_eq!(output, expected, "{name:?}");
+  }
+
+  // without `--checkpoint-keep`, only the last checkpoint is kept
+  std::fs::remove_dir_all(&dir).unwrap();
+  execute_hvmc(&[&args[..], &[&program]].concat()).unwrap();
+  let last = std::fs::read_to_string(dir.join("checkpoint.hvmc")).unwrap();
+  assert_eq!(std::fs::read_dir(&dir).unwrap().count(), 1);
+  let output = execute_hvmc(&["reduce", "-m", "100M", &program, "--", &last]).unwrap().1;
+  assert_eq!(output, expected);
+}
+
+#[test]
+fn test_cli_replay_log() {
+  let log = PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join("replay.log");
+  let log = log.to_str().unwrap();
+  let program = get_arithmetic_program_path();
+  let exprs = ["a & @mul ~ (#3 (#4 a))", "a & @div ~ (#12 (#4 a))"];
+  let reduce = |opt: &str| execute_hvmc(&[&["reduce", "-1", opt, log, &program, "--"][..], &exprs].concat()).unwrap();
+
+  let (status, recorded) = reduce("--record-log");
+  assert!(status.success(), "{recorded}");
+  assert_display_snapshot!(std::fs::read_to_string(log).unwrap(), @r###"
+  call @mul 1
+  op_num #4 0
+
+  call @div 1
+  op_num #4 0
+
+  "###);
+  let (status, replayed) = reduce("--replay-log");
+  assert!(status.success(), "{replayed}");
+  assert_eq!(replayed, recorded);
+
+  // the replay stops at the first interaction that differs from the log
+  let recorded = std::fs::read_to_string(log).unwrap();
+  std::fs::write(log, recorded.replacen("call @div", "call @mul", 1)).unwrap();
+  let (status, output) = reduce("--replay-log");
+  assert_eq!(status.code(), Some(4));
+  assert_display_snapshot!(output, @r###"
+  #12
+  replay diverged at interaction 1: expected `call @mul 1`, got `call @div 1`
+  "###);
+  std::fs::write(log, recorded.replacen("op_num #4 0\n\ncall", "op_num #4 0\nop_num #4 0\n\ncall", 1)).unwrap();
+  let (status, output) = reduce("--replay-log");
+  assert_eq!(status.code(), Some(4));
+  assert_display_snapshot!(output, @"replay diverged: the reduction finished after 2 interactions, but the log has 3");
+
+  // `HVM.log` locks the host to read back refs while the reduction is recorded
+  let expr = "a & @HVM.log ~ (@HVM.black_box a)";
+  let (status, recorded) = execute_hvmc(&["reduce", "-1", "--record-log", log, "--", expr]).unwrap();
+  assert!(status.success(), "{recorded}");
+  assert_display_snapshot!(std::fs::read_to_string(log).unwrap(), @r###"
+  call @HVM.log 1
+  call @HVM.black_box _
+
+  "###);
+  let (status, replayed) = execute_hvmc(&["reduce", "-1", "--replay-log", log, "--", expr]).unwrap();
+  assert!(status.success(), "{replayed}");
+  assert_eq!(replayed, recorded);
+
+  // the interactions of concurrent reductions would be interleaved
+  for opt in ["--record-log", "--replay-log"] {
+    let (status, output) = execute_hvmc(&["reduce", "-1", "-j", "2", opt, log, "--", "#1"]).unwrap();
+    assert_eq!(status.code(), Some(2));
+    assert!(output.contains("cannot be used with"), "{output}");
+  }
+}
+
+#[test]
+fn test_cli_quiet() {
+  let malformed = PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join("malformed.hvmc");
+  std::fs::write(&malformed, "@main = (a").unwrap();
+  let malformed = malformed.to_str().unwrap();
+
+  for (args, code) in [
+    (&["run", malformed][..], 2),
+    (&["reduce", "--", "(a"], 2),
+    (&["run", "this-file-does-not-exist.hvmc"], 3),
+    (&["reduce", "-m", "100M", "--linear", "--", "a & * ~ (#1 a)"], 4),
+  ] {
+    let (status, output) = execute_hvmc(args).unwrap();
+    assert_eq!(status.code(), Some(code), "{args:?}");
+    assert!(!output.is_empty(), "{args:?}");
+
+    let (status, output) = execute_hvmc(&[&["-q"], args].concat()).unwrap();
+    assert_eq!(status.code(), Some(code), "{args:?}");
+    assert_eq!(output, "", "{args:?}");
+  }
+
+  // Results are still printed
+  assert_eq!(execute_hvmc(&["reduce", "-q", "-m", "100M", "--", "#1"]).unwrap().1, "#1\n");
+}
+
 #[test]
 fn test_apply_tree() {
   use hvmc::run;
@@ -248,6 +1154,122 @@ fn test_apply_tree() {
   );
 }
 
+#[test]
+fn test_cli_compile_build_dir() {
+  // Directories not created by `compile` are never deleted
+  let build_dir = PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join("user_dir");
+  std::fs::create_dir_all(&build_dir).unwrap();
+  std::fs::write(build_dir.join("data.txt"), "important").unwrap();
+  let (status, output) = execute_hvmc(&[
+    "compile",
+    "--build-dir",
+    build_dir.to_str().unwrap(),
+    "-o",
+    build_dir.join("out").to_str().unwrap(),
+    &get_arithmetic_program_path(),
+  ])
+  .unwrap();
+  assert!(!status.success());
+  assert!(output.contains("is not empty, and was not created by `hvmc compile`"), "{output}");
+  assert_eq!(std::fs::read_to_string(build_dir.join("data.txt")).unwrap(), "important");
+}
+
+#[test]
+fn test_cli_emit_const() {
+  let (status, output) = execute_hvmc(&[
+    "compile",
+    "--emit",
+    "const",
+    &(env!("CARGO_MANIFEST_DIR").to_owned() + "/tests/programs/log.hvmc"),
+  ])
+  .unwrap();
+  assert!(status.success(), "{output}");
+  let expected = std::fs::read_to_string(env!("CARGO_MANIFEST_DIR").to_owned() + "/tests/book_data/log.rs").unwrap();
+  assert_eq!(output, expected);
+
+  // `--output` is written to, rather than standard output
+  let path = PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join("book_data.rs");
+  let (status, output) = execute_hvmc(&[
+    "compile",
+    "--emit",
+    "const",
+    "-o",
+    path.to_str().unwrap(),
+    &(env!("CARGO_MANIFEST_DIR").to_owned() + "/tests/programs/log.hvmc"),
+  ])
+  .unwrap();
+  assert!(status.success(), "{output}");
+  assert_eq!(output, "");
+  assert_eq!(std::fs::read_to_string(path).unwrap(), expected);
+
+  // the options for building executables don't apply
+  for option in
+    [&["--build-dir", "dir"][..], &["--profile", "debug"], &["--dry-run"], &["--report"], &["--bake-args", "#1"]]
+  {
+    let program = get_arithmetic_program_path();
+    let args = [&["compile", &program, "--emit", "const"], option].concat();
+    let (status, output) = execute_hvmc(&args).unwrap();
+    assert_eq!(status.code(), Some(1), "{option:?}");
+    assert!(output.contains("can't be used with `--emit const`"), "{output}");
+  }
+}
+
+#[test]
+fn test_cli_compile_dry_run() {
+  let build_dir = PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join("dry_run");
+  let output_path = build_dir.with_extension("out");
+  let (status, output) = execute_hvmc(&[
+    "compile",
+    "--dry-run",
+    "--build-dir",
+    build_dir.to_str().unwrap(),
+    "-o",
+    output_path.to_str().unwrap(),
+    &get_arithmetic_program_path(),
+  ])
+  .unwrap();
+  assert!(status.success(), "{output}");
+  assert_eq!(output.trim(), build_dir.join("src/gen.rs").to_str().unwrap());
+  assert!(std::fs::read_to_string(build_dir.join("src/gen.rs")).unwrap().contains("host.insert_def(\"main\""));
+  assert!(build_dir.join("Cargo.toml").exists());
+  assert!(!build_dir.join("target").exists());
+  assert!(!output_path.exists());
+}
+
+#[test]
+fn test_cli_compile_report() {
+  let dir = PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join("report");
+  let program = dir.with_extension("hvmc");
+  std::fs::write(&program, "@one = #1\n@id = (x x)\n@two = a & @one ~ <+ @one a>\n@main = a & @id ~ (@two a)\n")
+    .unwrap();
+  let (status, output) = execute_hvmc(&[
+    "compile",
+    "--report",
+    "--dry-run",
+    "-O",
+    "inline",
+    "--build-dir",
+    dir.to_str().unwrap(),
+    "-o",
+    dir.with_extension("out").to_str().unwrap(),
+    program.to_str().unwrap(),
+  ])
+  .unwrap();
+  assert!(status.success(), "{output}");
+  // the path of the generated source is printed to stdout first
+  let report = output.split_once('\n').unwrap().1;
+  assert_display_snapshot!(report, @r###"
+  DEF      LINES   ALLOCS     REFS
+  @id         12        1        0
+  @main       15        1        2
+  @one        11        0        0
+  @two        13        1        0
+  DEFS   :               4
+  LINES  :              68
+  INLINED: @one
+  "###);
+}
+
 #[test]
 fn test_cli_compile() {
   // Test normal-form expressions
@@ -280,3 +1302,39 @@ fn test_cli_compile() {
 
   std::fs::remove_file(&output_path).unwrap();
 }
+
+#[test]
+fn test_cli_compile_bake_args() {
+  let dir = PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join("bake_args");
+  let output_path = dir.with_extension("out");
+  let (status, output) = execute_hvmc(&[
+    "compile",
+    "--profile",
+    "debug",
+    "--build-dir",
+    dir.to_str().unwrap(),
+    "-o",
+    output_path.to_str().unwrap(),
+    &get_arithmetic_program_path(),
+    "--bake-args",
+    "#40",
+    "#3",
+  ])
+  .unwrap();
+  assert!(status.success(), "{output}");
+
+  let output = Command::new(&output_path).output().unwrap();
+  assert_display_snapshot!(String::from_utf8(output.stdout).unwrap(), @r###"
+  [#13 #1]
+  "###);
+  // arguments passed at runtime replace the baked ones
+  let output = Command::new(&output_path).args(["#7", "#2"]).output().unwrap();
+  assert_display_snapshot!(String::from_utf8(output.stdout).unwrap(), @r###"
+  [#3 #1]
+  "###);
+
+  // invalid arguments are rejected at compile time
+  let (status, output) =
+    execute_hvmc(&["compile", "--dry-run", &get_arithmetic_program_path(), "--bake-args", "(a"]).unwrap();
+  assert_eq!(status.code(), Some(2), "{output}");
+}
@@ -1,6 +1,6 @@
 #![allow(dead_code)]
 
-use hvmc::{ast::*, run, stdlib::create_host};
+use hvmc::{ast::*, ops::OpError, run, stdlib::create_host};
 use std::fs;
 
 pub fn load_file(file: &str) -> String {
@@ -37,3 +37,30 @@ pub fn normal_with(book: Book, mem: Option<usize>, entry_point: &str) -> (hvmc::
 pub fn normal(book: Book, mem: Option<usize>) -> (hvmc::run::Rewrites, Net) {
   normal_with(book, mem, "main")
 }
+
+// Reduces the `main` def of a book, returning the operation that stopped the
+// reduction, if any
+pub fn op_error(book: Book) -> Option<OpError> {
+  let area = run::Heap::new(Some(128)).unwrap();
+  let host = create_host(&book);
+
+  let mut rnet = run::Net::<run::Strict>::new(&area);
+  rnet.boot(&host.lock().defs["main"]);
+  rnet.normal();
+  rnet.op_error
+}
+
+// Reduces the `main` def of a book, returning its normal form, or `None` if
+// that takes more than `budget` interactions
+pub fn normal_with_budget(book: &Book, budget: usize) -> Option<Net> {
+  let area = run::Heap::new(None).unwrap();
+  let host = create_host(book);
+
+  let mut rnet = run::Net::<run::Strict>::new(&area);
+  rnet.boot(&host.lock().defs["main"]);
+  rnet.expand();
+  rnet.reduce(budget)?;
+
+  let net = host.lock().readback(&rnet);
+  Some(net)
+}
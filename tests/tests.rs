@@ -1,20 +1,33 @@
 #![cfg(feature = "std")]
+#![feature(allocator_api, noop_waker)]
 
+use core::{
+  alloc::{AllocError, Allocator, GlobalAlloc, Layout},
+  cell::RefCell,
+  panic::AssertUnwindSafe,
+  ptr::NonNull,
+  sync::atomic::{AtomicUsize, Ordering},
+};
 use parking_lot::Mutex;
 use std::{
-  fs,
+  alloc::System,
+  collections::{BTreeMap, BTreeSet},
+  env, fs,
   io::{self, Write},
+  panic,
   path::{Path, PathBuf},
   str::FromStr,
   sync::Arc,
+  thread,
   time::Instant,
 };
 
 use hvmc::{
   ast::{self, Book, Net},
-  host::Host,
-  run::{self, Strict},
-  util::show_rewrites,
+  host::{Host, InstantiateError, ReplaceDefError},
+  ops::{ExtOps, OpError},
+  run::{self, Lazy, Strict, Trg},
+  util::{show_rewrites, ApplySide},
 };
 use insta::{assert_debug_snapshot, assert_snapshot};
 use loaders::*;
@@ -63,6 +76,1647 @@ fn test_bool_and() {
   assert_debug_snapshot!(rwts.total(), @"14");
 }
 
+#[test]
+fn test_rewrites_sum() {
+  let stats: Vec<run::Rewrites> = ["@main = * & * ~ *", "@main = (* *) & * ~ *", "@main = root & (x x) ~ [* root]"]
+    .into_iter()
+    .map(|code| normal(parse_core(code), Some(128)).0)
+    .collect();
+  let total: run::Rewrites = stats.iter().sum();
+  assert_eq!(total.total(), stats.iter().map(|rwts| rwts.total()).sum::<u64>());
+  assert_eq!(total.total(), stats.into_iter().sum::<run::Rewrites>().total());
+  let mut acc = run::Rewrites::default();
+  acc += &total;
+  acc += &total;
+  assert_debug_snapshot!(acc.total(), @"30");
+}
+
+#[test]
+fn test_wide_comparisons() {
+  use hvmc::ops::TypedOp;
+
+  let cmp = |op: &str, a: u64, b: u64| op.parse::<TypedOp>().unwrap().op(a, b, &ExtOps::new()).unwrap();
+  assert_eq!(cmp("==", 0x1_000_001, 0x1_000_001), 1);
+  assert_eq!(cmp("==", 0x1_000_000, 0x2_000_000), 0);
+  assert_eq!(cmp("<", 0x1_000_000, 0x1_000_001), 1);
+  assert_eq!(cmp(">=", 0xFFF_FFFF_FFFF_FFFF, 0xFFF_FFFF_FFFF_FFFE), 1);
+  assert_eq!(cmp("u32.==", 0x1_0000_0000, 0), 1);
+  assert_eq!(cmp("i32.<", 0xFFFF_FFFF, 0), 1);
+
+  let net = parse_core("@main = a & #16777217 ~ <== #16777217 b> & #16777216 ~ << #33554432 c> & c ~ <+ b a>");
+  let (_, net) = normal(net, Some(128));
+  assert_snapshot!(Net::to_string(&net), @"#2");
+}
+
+#[test]
+fn test_wide_arithmetic() {
+  use hvmc::ops::TypedOp;
+
+  // results are only truncated to the width of their type, which is 60 bits
+  // for untyped operations
+  let op = |op: &str, a: u64, b: u64| op.parse::<TypedOp>().unwrap().op(a, b, &ExtOps::new()).unwrap();
+  assert_eq!(op("+", 0xFF_FFFF, 1), 0x100_0000);
+  assert_eq!(op("*", 0x1000, 0x1000), 0x100_0000);
+  assert_eq!(op("-", 0x100_0000, 1), 0xFF_FFFF);
+  assert_eq!(op("u32.+", 0xFF_FFFF, 1), 0x100_0000);
+  assert_eq!(op("+", 0xFFFF_FFFF, 1), 0x1_0000_0000);
+  assert_eq!(op("<<", 1, 32), 0x1_0000_0000);
+  assert_eq!(op("u32.+", 0xFFFF_FFFF, 1), 0);
+  assert_eq!(op("+", 0xFFF_FFFF_FFFF_FFFE, 1), 0xFFF_FFFF_FFFF_FFFF);
+  assert_eq!(op("+", 0xFFF_FFFF_FFFF_FFFF, 1), 0);
+  assert_eq!(op("*", 1 << 30, 1 << 29), 1 << 59);
+
+  let run = |src: &str| {
+    let (_, net) = normal(parse_core(src), Some(128));
+    Net::to_string(&net)
+  };
+  assert_snapshot!(run("@main = a & #16777215 ~ <+ #1 a>"), @"#16777216");
+  assert_snapshot!(run("@main = a & #4294967295 ~ <+ #1 a>"), @"#4294967296");
+  assert_snapshot!(run("@main = a & #4294967295u32 ~ <+ #1u32 a>"), @"#0");
+  assert_snapshot!(run("@main = a & #536870912 ~ <* #536870912 a>"), @"#288230376151711744");
+  // ints are read back as signed 60-bit numbers
+  assert_snapshot!(run("@main = a & #1073741824 ~ <* #536870912 a>"), @"#-576460752303423488");
+}
+
+#[test]
+fn test_overflow_modifiers() {
+  use hvmc::ops::Op;
+
+  let run = |src: &str| {
+    let (_, net) = normal(parse_core(src), Some(128));
+    Net::to_string(&net)
+  };
+  // wrapping is the default, and can be written explicitly
+  assert_snapshot!(run("@main = a & #250u8 ~ <+ #10u8 a>"), @"#4");
+  assert_snapshot!(run("@main = a & #250u8 ~ <+. #10u8 a>"), @"#4");
+  assert_snapshot!(run("@main = a & #250u8 ~ <+s #10u8 a>"), @"#255");
+  assert_snapshot!(run("@main = a & #-100i8 ~ <i8.+s #-100i8 a>"), @"#-128");
+  assert_snapshot!(run("@main = a & #1152921504606846970 ~ <+s #10 a>"), @"#-1");
+  assert_snapshot!(run("@main = a & #3 ~ <-s #5 a>"), @"#0");
+  assert_snapshot!(run("@main = a & #250u8 ~ <+c #5u8 a>"), @"#255");
+  for overflowing in ["@main = a & #250u8 ~ <+c #10u8 a>", "@main = a & #1152921504606846970 ~ <+c #10 a>"] {
+    assert_eq!(op_error(parse_core(overflowing)), Some(OpError::Overflow(Op::Add)));
+    // operations that would trap are not folded
+    let mut net = parse_core(overflowing).nets["main"].clone();
+    net.fold_literal_ops();
+    assert_eq!(net.redexes.len(), 1);
+  }
+  assert_eq!(OpError::Overflow(Op::Add).to_string(), "arithmetic overflow in checked operation `+c`");
+
+  // checked shifts trap when shifting by at least the width of the type, which
+  // is 60 bits for the default type
+  assert_snapshot!(run("@main = a & #1 ~ <<<c #58 a>"), @"#288230376151711744");
+  assert_snapshot!(run("@main = a & #1u32 ~ <u32.<<c #31 a>"), @"#2147483648");
+  for (overflowing, op) in [
+    ("@main = a & #1 ~ <<<c #60 a>", Op::Shl),
+    ("@main = a & #1 ~ <<<c #63 a>", Op::Shl),
+    ("@main = a & #60 ~ <<<$c #1 a>", Op::ShlS),
+    ("@main = a & #1 ~ <>>c #60 a>", Op::Shr),
+    ("@main = a & #1u32 ~ <u32.<<c #40 a>", Op::Shl),
+  ] {
+    assert_eq!(op_error(parse_core(overflowing)), Some(OpError::Overflow(op)), "{overflowing}");
+  }
+
+  // the modifier is printed after the operator, and kept by swapped operators
+  let tree: ast::Tree = "<i32.-s #1 <u8.*c #2 <+. #3 x>>>".parse().unwrap();
+  assert_snapshot!(tree.to_string(), @"<i32.-s #1 <u8.*c #2 <+ #3 x>>>");
+  assert!("<ext:1s a b>".parse::<ast::Tree>().is_err());
+  assert_snapshot!(run("@main = a & <-s #5 a> ~ #3"), @"#0");
+}
+
+#[test]
+fn test_pow_sqrt() {
+  use hvmc::ops::{Op, TypedOp};
+
+  let run = |src: &str| {
+    let (_, net) = normal(parse_core(src), Some(128));
+    Net::to_string(&net)
+  };
+  assert_snapshot!(run("@main = a & #2 ~ <pow #10 a>"), @"#1024");
+  assert_snapshot!(run("@main = a & #144 ~ <sqrt #0 a>"), @"#12");
+  assert_snapshot!(run("@main = a & #10 ~ <pow$ #2 a>"), @"#1024");
+  // the second operand of `sqrt` is erased without waiting for it
+  assert_snapshot!(run("@main = a & #144 ~ <sqrt b a> & @loop ~ b  @loop = @loop"), @"#12");
+  assert_snapshot!(run("@main = (b a) & #145 ~ <sqrt b a>"), @"(* #12)");
+  assert!("<sqrt$ a b>".parse::<ast::Tree>().is_err());
+
+  let op = |op: &str, a: u64, b: u64| op.parse::<TypedOp>().unwrap().op(a, b, &ExtOps::new()).unwrap();
+  assert_eq!(op("pow", 3, 0), 1);
+  assert_eq!(op("pow", 0, 0), 1);
+  assert_eq!(op("i8.pow", (-2i8) as u64, 7), (-128i8) as u64);
+  assert_eq!(op("i8.pow", 2, (-1i8) as u64), 0);
+  assert_eq!(op("sqrt", 0xFFF_FFFF_FFFF_FFFF, 0), 0x3FFF_FFFF);
+  assert_eq!(op("sqrt", 15, 0), 3);
+  assert_eq!(op("i32.sqrt", (-4i32) as u64, 0), 0);
+  assert_eq!(op("f32.pow", 2f32.to_bits() as u64, 2f32.to_bits() as u64), 0);
+
+  // large exponents follow the overflow mode
+  assert_eq!(op("pow", 3, 1 << 59), 3u64.wrapping_pow(1 << 31).wrapping_pow(1 << 28) & 0xFFF_FFFF_FFFF_FFFF);
+  assert_eq!(op("pow", 1, 1 << 59), 1);
+  assert_eq!(op("u8.pow", 2, 8), 0);
+  assert_eq!(op("u8.pows", 2, 8), 255);
+  assert_eq!(op("i8.pows", (-2i8) as u64, 9), (-128i8) as u64);
+  assert_eq!(op("i8.pows", (-2i8) as u64, 10), 127);
+  assert_eq!(op("pows", 2, 60), 0xFFF_FFFF_FFFF_FFFF);
+  assert_eq!(op("pows", 2, 1 << 40), 0xFFF_FFFF_FFFF_FFFF);
+  assert_eq!(op("u8.powc", 2, 7), 128);
+  for (overflowing, op) in [
+    ("@main = a & #2u8 ~ <powc #8u8 a>", Op::Pow),
+    ("@main = a & #2 ~ <powc #60 a>", Op::Pow),
+    ("@main = a & #-1i8 ~ <i8.sqrtc #0 a>", Op::Sqrt),
+  ] {
+    assert_eq!(op_error(parse_core(overflowing)), Some(OpError::Overflow(op)));
+  }
+}
+
+#[test]
+fn test_divhi_remhi() {
+  use hvmc::ops::{Op, TypedOp};
+
+  let op = |op: &str, a: u64, b: u64| op.parse::<TypedOp>().unwrap().op(a, b, &ExtOps::new()).unwrap();
+  assert_eq!(op("/hi", 1, 3), ((1u128 << 60) / 3) as u64);
+  assert_eq!(op("%hi", 1, 3), 1);
+  assert_eq!(op("i32./hi$", 3, (-1i32) as u64), (-1431655765i32) as u64);
+  // the quotient wraps when the first operand isn't smaller than the second
+  assert_eq!(op("u8./hi", 3, 2), 0x80);
+  assert_eq!(op("u8./hic", 2, 3), 170);
+  for (overflowing, op) in [
+    ("@main = a & #3u8 ~ <u8./hic #2u8 a>", Op::DivHi),
+    ("@main = a & #3 ~ </hic #3 a>", Op::DivHi),
+    ("@main = a & #1 ~ <%hic #0 a>", Op::RemHi),
+  ] {
+    assert_eq!(op_error(parse_core(overflowing)), Some(OpError::Overflow(op)));
+  }
+}
+
+#[test]
+fn test_float_canonical() {
+  use hvmc::ops::TypedOp;
+
+  let op = |op: &str, a: f32, b: f32| {
+    op.parse::<TypedOp>().unwrap().op(a.to_bits() as u64, b.to_bits() as u64, &ExtOps::new()).unwrap()
+  };
+  // every NaN result has the same bits, whatever the platform produces
+  assert_eq!(op("f32./", 0.0, 0.0), 0x7fc0_0000);
+  assert_eq!(op("f32.-", f32::INFINITY, f32::INFINITY), 0x7fc0_0000);
+  assert_eq!(op("f32.*", -f32::NAN, 1.0), 0x7fc0_0000);
+  assert_eq!(op("f32.%", 1.0, 0.0), 0x7fc0_0000);
+  // subnormal results are not flushed to zero
+  assert_eq!(op("f32./", f32::MIN_POSITIVE, 2.0), 0x0040_0000);
+  assert_eq!(op("f32.*", f32::MIN_POSITIVE, -0.25), 0x8020_0000);
+  assert_eq!(op("f32.-", 1.5e-45, 0.0), 0x0000_0001);
+  // and are rounded to nearest, ties to even
+  assert_eq!(op("f32./", 1.5e-45, 2.0), 0x0000_0000);
+  assert_eq!(op("f32./", 4.5e-45, 2.0), 0x0000_0002);
+
+  assert_eq!(run::Port::new_float(-f32::NAN).float().to_bits(), 0x7fc0_0000);
+  assert_eq!(run::Port::new_num(run::Tag::F32, 0xffc0_0001).float().to_bits(), 0x7fc0_0000);
+  let (_, net) = normal(parse_core("@main = a & #0.0 ~ <f32./ #0.0 a>"), Some(128));
+  assert_snapshot!(Net::to_string(&net), @"#NaN");
+}
+
+#[test]
+fn test_parse_errors() {
+  use ast::{ParseError, ParseErrorKind};
+  let err = |src: &str| src.parse::<Net>().unwrap_err();
+  assert_eq!(err("(a <+ #1 a)"), ParseError { offset: 10, kind: ParseErrorKind::Expected(">") });
+  assert_eq!(err("#1u9"), ParseError { offset: 2, kind: ParseErrorKind::InvalidType });
+  assert_eq!(err("(a a) b"), ParseError { offset: 6, kind: ParseErrorKind::TrailingInput });
+  assert_eq!(err("(a a) b").to_string(), "unable to parse the whole input; is this not an hvmc file? (at byte 6)");
+  // errors are cheap to construct, as they are only rendered when displayed
+  assert!(!std::mem::needs_drop::<ParseError>());
+}
+
+#[test]
+fn test_parse_trailing_comment() {
+  // a comment may end the input, without a newline after it
+  assert_eq!("(a a) // end".parse::<Net>().unwrap(), "(a a)".parse().unwrap());
+  assert_eq!(parse_core("@main = * // end")["main"], "*".parse().unwrap());
+}
+
+#[test]
+fn test_compact_display() {
+  let net: Net = "(a b) & (a c) ~ {2 c d} & @foo ~ (d b)".parse().unwrap();
+  assert_snapshot!(format!("{net}"), @r###"
+  (a b)
+    & (a c) ~ {2 c d}
+    & @foo ~ (d b)
+  "###);
+  assert_snapshot!(format!("{net:#}"), @"(a b) & (a c) ~ {2 c d} & @foo ~ (d b)");
+  // both forms parse back to the same net
+  assert_eq!(format!("{net:#}").parse::<Net>().unwrap(), net);
+  assert_eq!(format!("{net}").parse::<Net>().unwrap(), net);
+}
+
+#[test]
+fn test_redex_shorthand() {
+  let canonical: Net = "(a b) & (a c) ~ {2 c d} & @foo ~ (d b) & #1 ~ <+ e f>".parse().unwrap();
+  // the `~` may be omitted, and redexes after one `&` separated by commas
+  for src in [
+    "(a b) & (a c) {2 c d} & @foo (d b) & #1 <+ e f>",
+    "(a b) & (a c) ~ {2 c d}, @foo ~ (d b), #1 ~ <+ e f>",
+    "(a b) & (a c) {2 c d}, @foo (d b) & #1 <+ e f>",
+  ] {
+    let net: Net = src.parse().unwrap();
+    assert_eq!(net, canonical);
+    // the printer only emits the canonical form
+    assert_eq!(format!("{net:#}"), "(a b) & (a c) ~ {2 c d} & @foo ~ (d b) & #1 ~ <+ e f>");
+  }
+  let book = parse_core("@main = a & @foo (#1 a)  @foo = (b b)");
+  assert_eq!(book["main"], "a & @foo ~ (#1 a)".parse().unwrap());
+  assert!("a & b".parse::<Net>().is_err());
+  assert!("a & b ~ c,".parse::<Net>().is_err());
+}
+
+#[test]
+fn test_erase_redex() {
+  // `erase` is shorthand for a redex with an eraser
+  let net: Net = "(a a) & erase (b {2 c d}), erase ~ e & e ~ (c d)".parse().unwrap();
+  assert_eq!(net, "(a a) & * ~ (b {2 c d}) & erase ~ e & e ~ (c d)".parse().unwrap());
+  assert!("a & erase".parse::<Net>().is_err());
+
+  let book = parse_core("@main = (a a) & erase ((b b) {2 (c c) [#1 #2]})");
+  let host = hvmc::stdlib::create_host(&book);
+  let heap = run::Heap::new(Some(1 << 12)).unwrap();
+  let mut net = run::Net::<Strict>::new(&heap);
+  net.boot(&host.lock().defs["main"]);
+  net.normal();
+  assert_eq!(host.lock().readback(&net).to_string(), "(a a)");
+  // erasing a binary node commutes it with the eraser, which is copied to its
+  // auxiliary ports, until only nilary agents remain
+  assert_debug_snapshot!(net.rwts, @r###"
+  Rewrites {
+      anni: 0,
+      comm: 5,
+      eras: 4,
+      dref: 3,
+      oper: 0,
+  }
+  "###);
+  assert_eq!(net.count_leaks(), 0);
+}
+
+#[test]
+fn test_typed_literals() {
+  let run = |src: &str| {
+    let (_, net) = normal(parse_core(src), Some(128));
+    Net::to_string(&net)
+  };
+  assert_snapshot!(run("@main = a & #200u8 ~ <+ #100u8 a>"), @"#44");
+  assert_snapshot!(run("@main = a & #200u16 ~ <+ #100u16 a>"), @"#300");
+  assert_snapshot!(run("@main = a & #200u8 ~ <+ b a> & #100 ~ b"), @"#44");
+  assert_snapshot!(run("@main = a & #-1i16 ~ << #0i16 a>"), @"#1");
+  assert_snapshot!(run("@main = a & #-1 ~ << #0 a>"), @"#0");
+  // the type isn't carried through variables or definitions
+  assert_snapshot!(run("@main = a & #200 ~ <+ b a> & #100u8 ~ b"), @"#300");
+  assert_snapshot!(run("@main = a & @x ~ <+ #200 a>  @x = #100u8"), @"#300");
+
+  let tree: ast::Tree = "<+ #255u8 #-1i16>".parse().unwrap();
+  assert_snapshot!(tree.to_string(), @"<u8.+ #255u8 #-1i16>");
+  assert!("#256u8".parse::<ast::Tree>().is_err());
+  assert!("#-1u32".parse::<ast::Tree>().is_err());
+  assert!("#1f32".parse::<ast::Tree>().is_err());
+
+  // eta-reduction keeps ints of different types apart
+  let mut net: Net = "(#1u8 #1)".parse().unwrap();
+  net.eta_reduce();
+  assert_snapshot!(net.to_string(), @"(#1u8 #1)");
+}
+
+#[test]
+fn test_num_format() {
+  use ast::{LabelAliases, NumFormat};
+
+  let book =
+    "@main = (a (b (c d))) & #-5 ~ <* #1 a> & #576460752303423487 ~ <+ #0 b> & #255u8 ~ <+ #1u8 c> & #3 ~ <- #5 d>";
+  // ints are read back as signed 60-bit numbers
+  let (_, res) = normal(parse_core(book), Some(128));
+  assert_snapshot!(res.to_string(), @"(#-5 (#576460752303423487 (#0 #-2)))");
+
+  let mut hex = LabelAliases::default();
+  hex.num_format(NumFormat::Hex);
+  let hex_res = res.display_with(&hex).to_string();
+  assert_snapshot!(hex_res, @"(#-0x5 (#0x7ffffffffffffff (#0x0 #-0x2)))");
+
+  // both formats parse back to the same net, including typed and extreme ints
+  assert_eq!(hex_res.parse::<Net>().unwrap(), res);
+  for src in ["(#-576460752303423488 #576460752303423487)", "(#-128i8 (#255u8 #4294967295u32))"] {
+    let net: Net = src.parse().unwrap();
+    assert_eq!(net.display_with(&hex).to_string().parse::<Net>().unwrap(), net);
+  }
+  let tree: ast::Tree = "(#-128i8 #255u8)".parse().unwrap();
+  assert_eq!(tree.display_with(&hex).to_string(), "(#-0x80i8 #0xffu8)");
+}
+
+#[test]
+fn test_op_encoding() {
+  use hvmc::ops::{Op, Overflow, Ty, TypedOp};
+  // the type is in the low byte, and the operation in the high byte
+  let wrapping = Overflow::Wrapping;
+  assert_eq!(u16::from(TypedOp { ty: Ty::U60, op: Op::Add, overflow: wrapping }), 0x0003);
+  assert_eq!(u16::from(TypedOp { ty: Ty::I32, op: Op::Sub, overflow: wrapping }), 0x0106);
+  assert_eq!(u16::from(TypedOp { ty: Ty::F32, op: Op::Ge, overflow: wrapping }), 0x1407);
+  // the overflow behavior is in the top bits of the low byte
+  assert_eq!(u16::from(TypedOp { ty: Ty::U8, op: Op::Add, overflow: Overflow::Saturating }), 0x0040);
+  assert_eq!(u16::from(TypedOp { ty: Ty::I32, op: Op::Mul, overflow: Overflow::Checked }), 0x0386);
+  assert_eq!(TypedOp::try_from(0x00C0), Err(()));
+  assert_eq!(TypedOp::try_from(0x0008), Err(()));
+  assert_eq!(TypedOp::try_from(0x1D00), Err(()));
+  for bits in 0 ..= u16::MAX {
+    if let Ok(op) = TypedOp::try_from(bits) {
+      assert_eq!(u16::from(op), bits);
+      assert_eq!(unsafe { TypedOp::from_unchecked(bits) }, op);
+    }
+  }
+  // numeric values are in the top 60 bits, above the tag
+  assert_eq!(run::Port::new_int(5).0, 0x53);
+  assert_eq!(run::Port::new_int(-1).0, 0xFFFF_FFFF_FFFF_FFF3);
+  assert_eq!(run::Port::new_float(1.0).0, 0x0003_F800_0004);
+}
+
+#[test]
+fn test_ext_op() {
+  fn gcd(a: i64, b: i64) -> i64 {
+    if b == 0 { a.abs() } else { gcd(b, a % b) }
+  }
+  let book = parse_core(
+    "
+    @main = [a [b *]]
+    & #12 ~ <ext:7 #18 a>
+    & @f ~ (#1 (#2 b))
+    @f = (<ext:8 x r> (x r))
+    @missing = a & #1 ~ <ext:9 #2 a>
+    @typed = [a b] & #30 ~ <u8.ext:8 #5 a> & #255 ~ <i8.ext:8 #0 b>
+  ",
+  );
+  assert_snapshot!(book.to_string(), @r###"
+  @f = (<ext:8 x r> (x r))
+
+  @main = [a [b *]]
+    & #12 ~ <ext:7 #18 a>
+    & @f ~ (#1 (#2 b))
+
+  @missing = a
+    & #1 ~ <ext:9 #2 a>
+
+  @typed = [a b]
+    & #30 ~ <u8.ext:8 #5 a>
+    & #255 ~ <i8.ext:8 #0 b>
+  "###);
+  let host = hvmc::stdlib::create_host(&book);
+  host.lock().register_op(7, Box::new(gcd));
+  host.lock().register_op(8, Box::new(|a, b| a * 10 + b));
+  let reduce = |name: &str, ext_ops: &Arc<ExtOps>| {
+    let host = host.lock();
+    let heap = run::Heap::new(Some(1 << 16)).unwrap();
+    let mut net = run::Net::<Strict>::new(&heap);
+    net.ext_ops = ext_ops.clone();
+    net.boot(&host.defs[name]);
+    net.normal();
+    (net.op_error, host.readback(&net).to_string())
+  };
+  let ext_ops = host.lock().ext_ops().clone();
+  assert_eq!(reduce("main", &ext_ops), (None, "[#6 [#12 *]]".to_owned()));
+  // typed ext ops are passed the values of their operands, and truncate their
+  // results: `305 as u8` is `49`, and `255 as i8` is `-1`
+  assert_eq!(reduce("typed", &ext_ops), (None, "[#49 #-10]".to_owned()));
+  // `ext:9` is not registered
+  assert_eq!(reduce("missing", &ext_ops).0, Some(OpError::Unregistered(9)));
+  // the ops are registered in the host, not in every net
+  assert_eq!(reduce("main", &Default::default()).0, Some(OpError::Unregistered(7)));
+  // ext ops are not folded without a host
+  let mut net = book.nets["main"].clone();
+  net.fold_literal_ops();
+  assert_eq!(net.redexes.len(), 2);
+  assert!("<ext:64 a b>".parse::<ast::Tree>().is_err());
+  assert!("<f32.ext:1 a b>".parse::<ast::Tree>().is_err());
+}
+
+#[test]
+fn test_switch() {
+  let book = parse_core(
+    "
+    @op = (?{2 (<+ a b> (a b)) (<* a b> (a b)) r} r)
+    @main = (r0 (r1 (r2 r3)))
+    & {2 #1 #2} ~ ?{2 (<+ a b> (a b)) (<* a b> (a b)) r0}
+    & @op ~ ({3 #4 #5} r1)
+    & @op ~ ({5 #4 #5} r2)
+    & {1 {2 #7 #0} {3 #8 #0}} ~ ?{2 (a (* a)) (* (b b)) r3}
+  ",
+  );
+  let (_, net) = normal(book, Some(1 << 16));
+  assert_snapshot!(Net::to_string(&net), @"(#3 (#20 (* [#7 #0])))");
+
+  // switches commute with everything they don't match on, and only annihilate
+  // with switches of the same label
+  let book = parse_core(
+    "
+    @main = (r0 (r1 (r2 r3)))
+    & (#1 #2) ~ ?{1 (a (* a)) r0}
+    & #3 ~ ?{1 * r1}
+    & ?{1 * r2} ~ ?{1 * #4}
+    & ?{1 * r3} ~ ?{2 * #5}
+  ",
+  );
+  let (_, net) = normal(book, Some(1 << 16));
+  assert_snapshot!(Net::to_string(&net), @"((#1 #2) (#3 (#4 ?{2 * #5})))");
+
+  // combinators with labels past the last arm are erased, without allocating
+  // a node for each label in between
+  let book = parse_core("@main = (r0 r1) & {3 #1 #2} ~ ?{1 * #3 r0} & {60000 #1 #2} ~ ?{1 * #3 r1}");
+  let (_, net) = normal(book, Some(1 << 12));
+  assert_snapshot!(Net::to_string(&net), @"(* *)");
+
+  let labs = &hvmc::host::calc_labels(&parse_core("@f = (?{3 * a} a)"), |_| unreachable!())["f"];
+  assert!(!labs.has(2) && labs.has(3) && labs.has(u16::MAX - 1));
+
+  let tree: ast::Tree = "?{3 * (a a) b}".parse().unwrap();
+  assert_snapshot!(tree.to_string(), @"?{3 * (a a) b}");
+  assert!("?{0 * a}".parse::<ast::Tree>().is_err());
+}
+
+#[test]
+fn test_large_def() {
+  // defs with more wires than `run::DEFAULT_TRGS`; calling `@bigger` after
+  // `@big` grows the net's targets a second time
+  let n = run::DEFAULT_TRGS + 10;
+  let chain = |name: &str, len: usize| {
+    let mut code = format!("@{name} = (x0 x{len})");
+    for i in 0 .. len {
+      code += &format!(" & #1 ~ <+ x{i} x{}>", i + 1);
+    }
+    code
+  };
+  let code = format!("{} {} @main = (a b) & @big ~ (#0 a) & @bigger ~ (#0 b)", chain("big", n), chain("bigger", 2 * n));
+  let host = hvmc::stdlib::create_host(&parse_core(&code));
+  let heap = run::Heap::new(None).unwrap();
+  for (redexes, trgs) in [(0, run::DEFAULT_TRGS), (1 << 10, 16)] {
+    let mut net = run::Net::<Strict>::with_capacity(&heap, redexes, trgs);
+    net.boot(&host.lock().defs["main"]);
+    net.normal();
+    assert_eq!(host.lock().readback(&net).to_string(), format!("(#{n} #{})", 2 * n));
+  }
+}
+
+#[test]
+fn test_quoted_def_names() {
+  let book = parse_core(r#"@"a b/c" = (x x) @main = @"a b/c" @"q\"\\" = *"#);
+  assert!(book.contains_key("a b/c"));
+  assert!(book.contains_key("q\"\\"));
+  let printed = book.to_string();
+  assert_snapshot!(printed, @r###"
+  @"a b/c" = (x x)
+
+  @main = @"a b/c"
+
+  @"q\"\\" = *
+  "###);
+  assert_eq!(printed.parse::<Book>().unwrap().to_string(), printed);
+}
+
+#[test]
+fn test_parse_imports() {
+  let book = parse_core(r#"import "lib.hvmc" @main = @lib  import "dir/\"q\".hvmc""#);
+  assert_eq!(book.imports, ["lib.hvmc", r#"dir/"q".hvmc"#]);
+  let printed = book.to_string();
+  assert_snapshot!(printed, @r###"
+  import "lib.hvmc"
+  import "dir/\"q\".hvmc"
+
+  @main = @lib
+  "###);
+  assert_eq!(printed.parse::<Book>().unwrap().to_string(), printed);
+}
+
+#[test]
+fn test_named_labels() {
+  let book = parse_core(
+    "
+    label Pair = 3
+    label Tag = 5
+    @main = ({Pair a b} ?{Pair (a b) r}) & {Tag :0:2 r} ~ *
+    @pair = {3 #1 {4 #2 #3}}
+  ",
+  );
+  assert_eq!(book["pair"], parse_core("@pair = {3 #1 {4 #2 #3}}")["pair"]);
+  let printed = book.to_string();
+  assert_snapshot!(printed, @r###"
+  label Pair = 3
+  label Tag = 5
+
+  @main = ({Pair a b} ?{Pair (a b) r})
+    & {Tag:0:2 r} ~ *
+
+  @pair = {Pair #1 {4 #2 #3}}
+  "###);
+  assert_eq!(printed.parse::<Book>().unwrap(), book);
+
+  // names must be declared before they are used
+  let err = "@main = {Pair a b}  label Pair = 3".parse::<Book>().unwrap_err();
+  assert_eq!(err.kind, ast::ParseErrorKind::UnknownLabel);
+}
+
+#[test]
+fn test_consts() {
+  let book = parse_core(
+    "
+    const ZERO = #0
+    const ID = (a a)
+    const PAIR = ($ZERO @ID)
+    @main = ($PAIR (@ZERO x)) & $ID ~ (x <+ #1 *>)
+  ",
+  );
+  assert_snapshot!(book.to_string(), @r###"
+  @main = ((#0 (_sec0 _sec0)) (#0 x))
+    & (_sec1 _sec1) ~ (x <+ #1 *>)
+  "###);
+
+  // each use gets its own variables, apart from those of the net
+  let book = parse_core("const DUP = {2 a a}  @main = ($DUP ($DUP _sec0)) & _sec0 ~ *");
+  assert_snapshot!(book.to_string(), @r###"
+  @main = ({2 _sec1 _sec1} ({2 _sec2 _sec2} _sec0))
+    & _sec0 ~ *
+  "###);
+
+  // names must be declared before they are used
+  let err = "@main = $ZERO  const ZERO = #0".parse::<Book>().unwrap_err();
+  assert_eq!(err, ast::ParseError { offset: 8, kind: ast::ParseErrorKind::UnknownConst });
+  // without a declaration, `@NAME` is an ordinary reference
+  assert_eq!(parse_core("@main = @ZERO")["main"].root, ast::Tree::Ref { nam: "ZERO".to_owned() });
+  // a constant can't have the name of a def, declared before or after it
+  let err = "const ZERO = #0  @ZERO = #1".parse::<Book>().unwrap_err();
+  assert_eq!(err, ast::ParseError { offset: 18, kind: ast::ParseErrorKind::ConstNamesDef });
+  let err = "@ZERO = #1  const ZERO = #0".parse::<Book>().unwrap_err();
+  assert_eq!(err, ast::ParseError { offset: 18, kind: ast::ParseErrorKind::ConstNamesDef });
+}
+
+#[test]
+fn test_check_linearity() {
+  let check = |src: &str| src.parse::<Net>().unwrap().check_linearity();
+  assert_eq!(check("(a b) & (b <+ #1>) ~ (c a) & c ~ *"), Ok(()));
+  assert_eq!(check("(a b)"), Err(vec!["`a` appears once".to_owned(), "`b` appears once".to_owned()]));
+  assert_eq!(check("(a (a a))"), Err(vec!["`a` appears 3 times".to_owned()]));
+  assert_eq!(
+    check("(a b) & (a a) ~ (b c)"),
+    Err(vec!["`a` appears 3 times".to_owned(), "`c` appears once".to_owned()])
+  );
+}
+
+#[test]
+fn test_max_parse_depth() {
+  use ast::{ParseError, ParseErrorKind};
+  // `depth` nested nodes, plus the leaves at the bottom
+  let deep = |depth| format!("@main = {}*{}", "(* ".repeat(depth), ")".repeat(depth));
+  assert!(Book::parse_with_max_depth(&deep(9), 10).is_ok());
+  let err = Book::parse_with_max_depth(&deep(10), 10).unwrap_err();
+  assert_eq!(err, ParseError { offset: 36, kind: ParseErrorKind::TooDeep });
+  let err = Book::parse_with_max_depth(&deep(1_000_000), 1000).unwrap_err();
+  assert_eq!(err.kind, ParseErrorKind::TooDeep);
+}
+
+#[test]
+fn test_apply_tree_at() {
+  let apply = |def: &str, side| {
+    let mut book = parse_core("@sub = (a (b r)) & a ~ <- b r>  @bus = ((r b) a) & a ~ <- b r>");
+    let mut net = Net { root: ast::Tree::Ref { nam: def.to_owned() }, redexes: vec![] };
+    net.apply_tree_at("#5".parse().unwrap(), side);
+    net.apply_tree_at("#2".parse().unwrap(), side);
+    let applied = net.to_string();
+    book.insert("main".to_owned(), net);
+    (applied, normal(book, None).1.to_string())
+  };
+  assert_debug_snapshot!(apply("sub", ApplySide::Left), @r###"
+  (
+      "c\n  & @sub ~ (#5 b)\n  & b ~ (#2 c)",
+      "#3",
+  )
+  "###);
+  assert_debug_snapshot!(apply("bus", ApplySide::Right), @r###"
+  (
+      "c\n  & @bus ~ (b #5)\n  & b ~ (c #2)",
+      "#3",
+  )
+  "###);
+}
+
+#[test]
+fn test_opaque_refs() {
+  let book = parse_core(
+    "
+    @true = (t (* t))
+    @false = (* (f f))
+    @choose = (?<(@false (* @true)) a> a)
+    @main = (a b)
+    & @choose ~ (#0 a)
+    & @choose ~ (#1 b)
+  ",
+  );
+  let host = hvmc::stdlib::create_host(&book);
+  let heap = run::Heap::new(Some(1 << 16)).unwrap();
+  let run = |opaque_refs: Option<Arc<_>>| {
+    let mut net = run::Net::<Strict>::new(&heap);
+    net.opaque_refs = opaque_refs;
+    net.boot(&host.lock().defs["main"]);
+    net.normal();
+    host.lock().readback(&net).to_string()
+  };
+  assert_snapshot!(run(None), @"((* (a a)) (b (* b)))");
+  let back = host.lock().back.keys().copied().collect();
+  assert_snapshot!(run(Some(Arc::new(back))), @"(@false @true)");
+}
+
+#[test]
+fn test_replace_def() {
+  let host = hvmc::stdlib::create_host(&parse_core("@foo = {1 #1 #2}  @main = a & @foo ~ {1 a *}"));
+  let (heap, stale_heap) = (run::Heap::new(Some(1 << 12)).unwrap(), run::Heap::new(Some(1 << 12)).unwrap());
+  let run = |host: &Host, net: &mut run::Net<Strict>| {
+    net.normal();
+    host.readback(net).to_string()
+  };
+
+  let mut net = run::Net::<Strict>::new(&heap);
+  net.boot(&host.lock().defs["main"]);
+  let mut stale = run::Net::<Strict>::new(&stale_heap);
+  stale.boot(&host.lock().defs["main"]);
+  assert_eq!(run(&host.lock(), &mut net), "#1");
+
+  // Existing refs to `@foo`, such as the one in `stale`, see the new definition
+  unsafe { host.lock().replace_def("foo", &"{1 #3 #4}".parse().unwrap()).unwrap() };
+  assert_eq!(run(&host.lock(), &mut stale), "#3");
+
+  // Refs to `@foo` commute with `{2 ...}` without expanding it, so `@foo` can't
+  // start using label 2
+  let new_labels = unsafe { host.lock().replace_def("foo", &"{2 #5 #6}".parse().unwrap()) };
+  assert_eq!(new_labels, Err(ReplaceDefError::NewLabels("foo".to_owned())));
+  let mut net = run::Net::<Strict>::new(&heap);
+  net.boot(&host.lock().defs["main"]);
+  assert_eq!(run(&host.lock(), &mut net), "#3");
+  // ...but it can stop using label 1
+  unsafe { host.lock().replace_def("foo", &"#7".parse().unwrap()).unwrap() };
+  assert!(host.lock().defs["foo"].labs.has(1));
+
+  let mut lock = host.lock();
+  let removed = lock.remove_def("foo").unwrap();
+  assert!(!lock.defs.contains_key("foo"));
+  assert!(!lock.back.values().any(|name| name == "foo"));
+  assert!(lock.remove_def("foo").is_none());
+  drop(removed);
+}
+
+#[test]
+fn test_count_leaks() {
+  for file in [
+    "examples/arithmetic.hvmc",
+    "examples/church_encoding/church.hvmc",
+    "tests/programs/list_put_got.hvmc",
+    "tests/programs/log.hvmc",
+    "tests/programs/queue.hvmc",
+  ] {
+    let book = parse_core(&fs::read_to_string(manifest_relative(file)).unwrap());
+    let host = hvmc::stdlib::create_host(&book);
+    let heap = run::Heap::new(Some(1 << 24)).unwrap();
+    let mut net = run::Net::<Strict>::new(&heap);
+    net.boot(&host.lock().defs["main"]);
+    net.normal();
+    assert_eq!(net.count_leaks(), 0, "{file}");
+  }
+
+  let heap = run::Heap::new(Some(1 << 12)).unwrap();
+  let mut net = run::Net::<Strict>::new(&heap);
+  let _ = net.alloc();
+  assert_eq!(net.count_leaks(), 1);
+}
+
+#[test]
+fn test_reduce_bounded() {
+  let book = parse_core(&load_file("church_exp.hvmc"));
+  let host = hvmc::stdlib::create_host(&book);
+
+  let heap = run::Heap::new_exact(1 << 8).unwrap();
+  let mut net = run::Net::<Strict>::new(&heap);
+  net.boot(&host.lock().defs["main"]);
+  assert_eq!(net.reduce_bounded(usize::MAX), Err(run::OutOfMemory));
+  // the pair that didn't fit is left in the queue
+  assert!(!net.redexes.is_empty());
+
+  let heap = run::Heap::new(Some(1 << 24)).unwrap();
+  let mut net = run::Net::<Strict>::new(&heap);
+  net.boot(&host.lock().defs["main"]);
+  assert!(net.reduce_bounded(usize::MAX).unwrap().is_some());
+  net.normal();
+  assert_eq!(host.lock().readback(&net), normal(book, None).1);
+}
+
+#[test]
+fn test_readback_partial() {
+  let book = parse_core("@id = (x x)  @main = a & @id ~ (b a) & @id ~ (c b) & @id ~ (#1 c)");
+  let host = hvmc::stdlib::create_host(&book);
+  let host = host.lock();
+  let heap = run::Heap::new(None).unwrap();
+  let mut net = run::Net::<Strict>::new(&heap);
+  net.boot(&host.defs["main"]);
+  assert_eq!(net.reduce(1), None);
+  // the redexes left by the stopped reduction are read back as well
+  let partial = host.readback(&net);
+  assert_snapshot!(partial.to_string(), @r###"
+  a
+    & @id ~ (b a)
+    & @id ~ (#1 b)
+  "###);
+  assert_eq!(partial.to_string().parse::<Net>().unwrap(), partial);
+
+  // and resuming from the readback gives the same result as not stopping
+  let book = parse_core(&load_file("church_mul.hvmc"));
+  let host = hvmc::stdlib::create_host(&book);
+  let host = host.lock();
+  let mut net = run::Net::<Strict>::new(&heap);
+  net.boot(&host.defs["main"]);
+  assert_eq!(net.reduce(20), None);
+  let partial = host.readback(&net);
+  assert!(!partial.redexes.is_empty());
+  let mut resumed = book.clone();
+  resumed.nets.insert("main".to_owned(), partial.to_string().parse().unwrap());
+  assert_eq!(normal(resumed, None).1, normal(book, None).1);
+}
+
+#[test]
+fn test_heap_new_in() {
+  /// Counts the bytes currently allocated through it.
+  #[derive(Default)]
+  struct CountBytes(AtomicUsize);
+
+  unsafe impl Allocator for CountBytes {
+    fn allocate(&self, layout: Layout) -> Result<NonNull<[u8]>, AllocError> {
+      self.0.fetch_add(layout.size(), Ordering::Relaxed);
+      System.allocate(layout)
+    }
+
+    unsafe fn deallocate(&self, ptr: NonNull<u8>, layout: Layout) {
+      self.0.fetch_sub(layout.size(), Ordering::Relaxed);
+      System.deallocate(ptr, layout)
+    }
+  }
+
+  let book = parse_core(&load_file("church_exp.hvmc"));
+  let host = hvmc::stdlib::create_host(&book);
+  let alloc = CountBytes::default();
+  let heap = run::Heap::new_in(Some(1 << 24), &alloc).unwrap();
+  assert_eq!(alloc.0.load(Ordering::Relaxed), 1 << 24);
+  let mut net = run::Net::<Strict>::new(&heap);
+  net.boot(&host.lock().defs["main"]);
+  net.normal();
+  assert_eq!(host.lock().readback(&net), normal(book, None).1);
+  drop(net);
+  drop(heap);
+  assert_eq!(alloc.0.load(Ordering::Relaxed), 0);
+
+  assert!(run::Heap::new_exact_in(1, &alloc).is_none());
+}
+
+#[test]
+fn test_causal_depth() {
+  // returns the total rewrites and the causal depth of reducing `@main`
+  let depth = |book: &str| {
+    let host = hvmc::stdlib::create_host(&parse_core(book));
+    let heap = run::Heap::new(Some(1 << 24)).unwrap();
+    let mut net = run::Net::<Strict>::new(&heap);
+    net.depth = Some(Default::default());
+    net.boot(&host.lock().defs["main"]);
+    net.normal();
+    (net.rwts.total(), net.depth.as_ref().unwrap().max)
+  };
+
+  // each iteration of the loop depends on the previous one
+  let sequential = depth(
+    "
+    @loop = (?<(#0 @loop$S) a> a)
+    @loop$S = (p r) & @loop ~ (p r)
+    @main = a & @loop ~ (#4096 a)
+  ",
+  );
+  // the two halves of each tree can be built independently
+  let parallel = depth(
+    "
+    @tree = (?<(#1 @tree$S) a> a)
+    @tree$S = ({2 p q} r) & @tree ~ (p a) & @tree ~ (q b) & a ~ <+ b r>
+    @main = a & @tree ~ (#10 a)
+  ",
+  );
+  assert_eq!(sequential, (28_679, 8_193));
+  assert_eq!(parallel, (14_329, 21));
+}
+
+#[test]
+fn test_alloc_stats() {
+  // returns the allocation statistics of reducing `@main` with `threads`
+  let alloc_stats = |book: &str, threads: usize| {
+    let host = hvmc::stdlib::create_host(&parse_core(book));
+    let heap = run::Heap::new(Some(1 << 24)).unwrap();
+    let mut net = run::Net::<Strict>::new(&heap);
+    net.boot(&host.lock().defs["main"]);
+    let start = net.allocs;
+    net.alloc_stats = Some(Default::default());
+    if threads == 1 {
+      net.normal();
+    } else {
+      net.parallel_normal_with_threads(threads);
+    }
+    let stats = net.alloc_stats.take().unwrap();
+    assert_eq!(stats.hits + stats.fallbacks, net.allocs - start);
+    stats
+  };
+
+  // a loop frees the nodes of each iteration before the next one allocates
+  let churn = "
+    @loop = (?<(#0 @loop) a> a)
+    @main = a & @loop ~ (#4096 a)
+  ";
+  // while a tree that is built and kept can't reuse its nodes
+  let growth = "
+    @tree = (?<(* @tree$S) a> a)
+    @tree$S = ({2 p q} (a b)) & @tree ~ (p a) & @tree ~ (q b)
+    @main = a & @tree ~ (#10 a)
+  ";
+  for threads in [1, 4] {
+    let (churn, growth) = (alloc_stats(churn, threads), alloc_stats(growth, threads));
+    assert!(churn.hit_rate() > 0.99, "{churn:?}");
+    assert!(growth.hit_rate() < 0.95, "{growth:?}");
+    // the 1023 nodes of the tree are never freed
+    assert!(growth.fallbacks > 1023, "{growth:?}");
+  }
+}
+
+#[test]
+fn test_step() {
+  let book = parse_core(
+    "
+    @main = (r0 (r1 r2))
+      & {2 r0 r1} ~ (#1 #2)
+      & ?{1 * r2} ~ {2 #3 #4}
+  ",
+  );
+  let host = hvmc::stdlib::create_host(&book);
+  let heap = run::Heap::new(Some(1 << 12)).unwrap();
+  let mut net = run::Net::<Strict>::new(&heap);
+  // skipped pairs are counted as rewrites, but aren't steps
+  net.skip_pairs = false;
+  let root = Trg::port(run::Port::new_var(net.root.addr()));
+  host.lock().encode_net(&mut net, root, &book["main"]);
+  let mut steps = 0;
+  while net.step() {
+    steps += 1;
+  }
+  assert!(!net.step());
+  assert_eq!(steps, 12);
+  assert_eq!(steps, net.rwts.total());
+  assert_eq!(host.lock().readback(&net), normal(book, None).1);
+}
+
+#[test]
+fn test_encode_round_trip() {
+  // agents of each kind, and a redex between nodes (those of nilary agents
+  // may be reduced while encoding)
+  let book = parse_core(
+    "
+    @id = (a a)
+    @main = (a {3 b (<+ #2.5 c> [?<#0 @id d> [c d]])})
+      & (#-1 a) ~ <* #6 b>
+  ",
+  );
+  let host = hvmc::stdlib::create_host(&book);
+  let heap = run::Heap::new(Some(1 << 12)).unwrap();
+  let mut net = run::Net::<Strict>::new(&heap);
+  let root = Trg::port(run::Port::new_var(net.root.addr()));
+  host.lock().encode_net(&mut net, root, &book["main"]);
+  let mut encoded = host.lock().readback(&net);
+  let mut expected = book["main"].clone();
+  encoded.canonicalize();
+  expected.canonicalize();
+  assert_eq!(encoded, expected);
+  net.normal();
+  assert_eq!(host.lock().readback(&net), normal(book, None).1);
+}
+
+#[test]
+fn test_expand_where() {
+  let book = parse_core("@id = (a a)  @k = (a (* a))  @main = (@id {2 @k @id})");
+  let host = hvmc::stdlib::create_host(&book);
+  let expand = |pred: &dyn Fn(&str) -> bool| {
+    let heap = run::Heap::new(Some(1 << 16)).unwrap();
+    let mut net = run::Net::<Strict>::new(&heap);
+    net.boot(&host.lock().defs["main"]);
+    net.expand_where(&host.lock(), pred);
+    net.reduce(usize::MAX);
+    host.lock().readback(&net).to_string()
+  };
+  assert_snapshot!(expand(&|_| true), @"((a a) {2 (b (* b)) (c c)})");
+  assert_snapshot!(expand(&|name| name != "k"), @"((a a) {2 @k (b b)})");
+}
+
+#[test]
+fn test_memory_limit() {
+  let book = parse_core(&load_file("church_exp.hvmc"));
+  let host = hvmc::stdlib::create_host(&book);
+
+  let heap = run::Heap::new_exact(1 << 12).unwrap();
+  let mut net = run::Net::<Strict>::new(&heap);
+  net.memory_limit = Some(0.1);
+  net.boot(&host.lock().defs["main"]);
+  net.normal();
+  assert!(net.memory_exceeded);
+  assert!(!net.redexes.is_empty());
+
+  // the partially reduced net can be read back and finished in lazy mode
+  let partial = host.lock().readback(&net);
+  let mut net = run::Net::<Lazy>::new(&heap);
+  let root = Trg::port(run::Port::new_var(net.root.addr()));
+  host.lock().encode_net(&mut net, root, &partial);
+  net.normal();
+  assert_eq!(host.lock().readback(&net), normal(book, None).1);
+}
+
+#[test]
+fn test_def_ids() {
+  let book = parse_core("@foo = (a a)  @bar = (* @foo)  @main = a & @bar ~ (#1 a)");
+  let ids = |host: &Host| book.keys().map(|name| host.def_id(name).unwrap()).collect::<Vec<_>>();
+  let mut host = Host::new(&book);
+  assert_eq!(ids(&host), [0, 1, 2]);
+  assert_eq!(ids(&Host::new(&book)), ids(&host));
+  assert_eq!(host.def_name(1), Some("foo"));
+  assert_eq!(host.def_name(3), None);
+
+  host.remove_def("foo");
+  assert_eq!(host.def_id("foo"), None);
+  assert_eq!(host.def_name(1), None);
+  host.insert_book(&parse_core("@baz = *  @foo = *"));
+  assert_eq!((host.def_id("baz"), host.def_id("foo")), (Some(3), Some(4)));
+
+  host.clear();
+  host.insert_book(&book);
+  assert_eq!(ids(&host), [0, 1, 2]);
+}
+
+#[test]
+fn test_host_clear() {
+  let mut host = Host::new(&parse_core("@foo = #1  @main = a & @foo ~ <+ #10 a>"));
+  host.clear();
+  assert!(host.defs.is_empty() && host.back.is_empty());
+
+  host.insert_book(&parse_core("@bar = #2  @main = a & @bar ~ <* #10 a>"));
+  assert!(!host.defs.contains_key("foo"));
+  assert_eq!(host.back.len(), 2);
+  let heap = run::Heap::new(Some(1 << 12)).unwrap();
+  let mut net = run::Net::<Strict>::new(&heap);
+  net.boot(&host.defs["main"]);
+  net.normal();
+  assert_eq!(host.readback(&net).to_string(), "#20");
+}
+
+#[test]
+fn test_native_def() {
+  use hvmc::{dispatch_dyn_net, run::Port, stdlib::NativeDef};
+  let mut host = Host::default();
+  host.insert_def("clamp", unsafe {
+    NativeDef::new(3, |_, args| {
+      let [val, min, max] = [0, 1, 2].map(|i| args[i].int());
+      Port::new_int(val.clamp(min, max))
+    })
+  });
+  host.insert_def("fst", unsafe {
+    NativeDef::new(2, |net, args| {
+      dispatch_dyn_net!(net => net.link_port_port(args[1].clone(), Port::ERA));
+      args[0].clone()
+    })
+  });
+  host.insert_book(&parse_core(
+    "
+    @main = (x y)
+    & @clamp ~ (a (#0 (#10 x)))
+    & #7 ~ <* #2 a>
+    & @fst ~ ((b c) ((* *) y))
+    & @clamp ~ (#-3 (#0 (#10 (b c))))
+  ",
+  ));
+  let heap = run::Heap::new(Some(1 << 12)).unwrap();
+  let mut net = run::Net::<Strict>::new(&heap);
+  net.boot(&host.defs["main"]);
+  net.normal();
+  assert_snapshot!(host.readback(&net).to_string(), @"(#10 (#0 #0))");
+  assert_eq!(net.count_leaks(), 0);
+}
+
+#[test]
+fn test_oracle() {
+  let mut host = Host::default();
+  let calls = Arc::new(Mutex::new(0));
+  let counter = calls.clone();
+  host.set_oracle(
+    "ask",
+    Box::new(move |arg| {
+      *counter.lock() += 1;
+      let root = match arg.root {
+        ast::Tree::Int { val, ty } => ast::Tree::Int { val: val * 2, ty },
+        ref tree => {
+          ast::Tree::Ctr { lab: 0, ports: vec![tree.clone(), ast::Tree::Int { val: *counter.lock(), ty: None }] }
+        }
+      };
+      Net { root, redexes: vec![] }
+    }),
+  );
+  host.insert_book(&parse_core(
+    "
+    @main = (a b)
+    & @ask ~ (#21 a)
+    & @ask ~ ((#1 #2) b)
+  ",
+  ));
+  // the oracle doesn't lock the host, which is usually locked while reducing
+  let host = Mutex::new(host);
+  let host = host.lock();
+  let heap = run::Heap::new(Some(1 << 12)).unwrap();
+  let mut net = run::Net::<Strict>::new(&heap);
+  net.boot(&host.defs["main"]);
+  net.normal();
+  assert_snapshot!(host.readback(&net).to_string(), @"(#42 ((#1 #2) #1))");
+  assert_eq!(*calls.lock(), 2);
+}
+
+#[test]
+fn test_no_skip() {
+  let book = parse_core(
+    "
+    @foo = (a a)
+    @main = * & @foo ~ * & @foo ~ #1 & * ~ * & #2 ~ *
+  ",
+  );
+  let host = hvmc::stdlib::create_host(&book);
+  let heap = run::Heap::new(Some(1 << 12)).unwrap();
+  let mut rwts = vec![];
+  for skip_pairs in [true, false] {
+    let mut net = run::Net::<Strict>::new(&heap);
+    net.skip_pairs = skip_pairs;
+    net.boot(&host.lock().defs["main"]);
+    net.normal();
+    assert_eq!(host.lock().readback(&net).to_string(), "*");
+    rwts.push(show_rewrites(&net.rwts));
+  }
+  assert_snapshot!(format!("skip:\n{}no skip:\n{}", rwts[0], rwts[1]), @r###"
+  skip:
+  RWTS   :               6
+  - ANNI :               0
+  - COMM :               0
+  - ERAS :               4
+  - DREF :               2
+  - OPER :               0
+  no skip:
+  RWTS   :              10
+  - ANNI :               0
+  - COMM :               2
+  - ERAS :               4
+  - DREF :               4
+  - OPER :               0
+
+  "###);
+}
+
+#[test]
+fn test_skip_policy() {
+  // skips everything but pairs of two references other than erasers
+  fn skip_unless_ref_ref(a: &run::Port, b: &run::Port) -> bool {
+    let is_ref = |p: &run::Port| p.tag() == run::Tag::Ref && *p != run::Port::ERA;
+    !(is_ref(a) && is_ref(b))
+  }
+
+  let book = parse_core(
+    "
+    @foo = (a a)
+    @bar = *
+    @main = * & @foo ~ @bar & @foo ~ #1 & * ~ *
+  ",
+  );
+  let host = hvmc::stdlib::create_host(&book);
+  let heap = run::Heap::new(Some(1 << 12)).unwrap();
+  let mut rwts = vec![];
+  for skip_policy in [None, Some(skip_unless_ref_ref as run::SkipPolicy)] {
+    let mut net = run::Net::<Strict>::new(&heap);
+    net.skip_policy = skip_policy;
+    net.boot(&host.lock().defs["main"]);
+    net.normal();
+    assert_eq!(host.lock().readback(&net).to_string(), "*");
+    rwts.push(show_rewrites(&net.rwts));
+  }
+  assert_snapshot!(format!("skip all:\n{}skip unless ref-ref:\n{}", rwts[0], rwts[1]), @r###"
+  skip all:
+  RWTS   :               5
+  - ANNI :               0
+  - COMM :               0
+  - ERAS :               3
+  - DREF :               2
+  - OPER :               0
+  skip unless ref-ref:
+  RWTS   :               8
+  - ANNI :               0
+  - COMM :               1
+  - ERAS :               3
+  - DREF :               4
+  - OPER :               0
+
+  "###);
+}
+
+#[test]
+fn test_coverage() {
+  let book = parse_core(&load_file("coverage.hvmc"));
+  let host = hvmc::stdlib::create_host(&book);
+  let heap = run::Heap::new(Some(1 << 16)).unwrap();
+  for parallel in [false, true] {
+    let mut net = run::Net::<Strict>::new(&heap);
+    net.skip_pairs = false;
+    net.boot(&host.lock().defs["main"]);
+    if parallel {
+      net.parallel_normal();
+    } else {
+      net.normal();
+    }
+    let missing = net.coverage.missing().collect::<Vec<_>>();
+    assert!(net.coverage.is_full(), "missing {missing:?} (parallel: {parallel})");
+  }
+
+  // pairs of nilary agents are skipped by default
+  let mut net = run::Net::<Strict>::new(&heap);
+  net.boot(&host.lock().defs["main"]);
+  net.normal();
+  assert_eq!(net.coverage.missing().collect::<Vec<_>>(), [run::Interaction::Void]);
+}
+
+#[test]
+fn test_calc_labels() {
+  let book = parse_core(&fs::read_to_string(manifest_relative("examples/arithmetic.hvmc")).unwrap());
+  let labels = hvmc::host::calc_labels(&book, |nam| panic!("unexpected ref to {nam}"));
+  let labels: BTreeMap<_, Vec<_>> =
+    labels.into_iter().map(|(nam, labs)| (nam, (0 .. 8).filter(|&l| labs.has(l)).collect())).collect();
+  assert_snapshot!(format!("{labels:?}"), @r###"{"add": [0], "div": [0], "main": [0, 1, 3, 5], "mod": [0], "mul": [0], "sub": [0]}"###);
+}
+
+/// Records the addresses allocated by a thread while its `ALLOCS` is set, so
+/// that tests can check the order in which things are allocated.
+struct RecordAllocs;
+
+#[global_allocator]
+static RECORD_ALLOCS: RecordAllocs = RecordAllocs;
+
+thread_local! {
+  static ALLOCS: RefCell<Option<Vec<usize>>> = const { RefCell::new(None) };
+}
+
+unsafe impl GlobalAlloc for RecordAllocs {
+  unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+    let ptr = System.alloc(layout);
+    // the vec is never grown here, as that would allocate
+    let _ = ALLOCS.try_with(|allocs| match allocs.try_borrow_mut().as_deref_mut() {
+      Ok(Some(allocs)) if allocs.len() < allocs.capacity() => allocs.push(ptr as usize),
+      _ => {}
+    });
+    ptr
+  }
+
+  unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+    System.dealloc(ptr, layout)
+  }
+}
+
+#[test]
+fn test_insert_book_order() {
+  let book = parse_core("@c = (@z @a)  @b = (@y @c)  @a = (@x @b)");
+  // returns the names of the defs in the order they were allocated, and the
+  // names passed to `default_def`
+  let insert = || {
+    let mut host = Host::default();
+    let mut defaults = vec![];
+    ALLOCS.with(|allocs| *allocs.borrow_mut() = Some(Vec::with_capacity(1 << 12)));
+    host.insert_book_with_default(&book, &mut |nam| {
+      defaults.push(nam.to_owned());
+      unsafe { hvmc::stdlib::HostedDef::<run::InterpretedDef>::new(run::LabSet::NONE) }
+    });
+    let allocs = ALLOCS.with(|allocs| allocs.borrow_mut().take().unwrap());
+    let mut order: Vec<_> =
+      host.back.iter().map(|(addr, nam)| (allocs.iter().rposition(|&x| x == addr.0), nam)).collect();
+    order.sort();
+    assert!(order.iter().all(|(index, _)| index.is_some()));
+    (order.into_iter().map(|(_, nam)| nam.clone()).collect::<Vec<_>>(), defaults)
+  };
+  let (order, defaults) = insert();
+  assert_eq!(order, ["x", "y", "z", "a", "b", "c"]);
+  assert_eq!(defaults, ["x", "y", "z"]);
+  for _ in 0 .. 8 {
+    assert_eq!(insert(), (order.clone(), defaults.clone()));
+  }
+}
+
+#[test]
+fn test_flat() {
+  use hvmc::flat::{FlatBook, FlatPort};
+
+  let book =
+    parse_core("@main = (a (b a)) & @foo ~ <+ #1 b>  @foo = (:1:2 @ext #2.5)  @bar = ?<(* x) x y> & @main ~ y");
+  let flat = book.to_flat().unwrap();
+  assert_eq!(flat.names, ["bar", "foo", "main", "ext"]);
+  assert_eq!(FlatBook::from_bytes(&flat.to_bytes()).unwrap(), flat);
+  assert_snapshot!(flat.to_book().unwrap().to_string(), @r###"
+  @bar = ?<(* a) a b>
+    & @main ~ b
+
+  @foo = (* ((@ext (#2.5 a)) a))
+
+  @main = (a (b a))
+    & @foo ~ <+ #1 b>
+  "###);
+
+  for file in ["examples/arithmetic.hvmc", "tests/programs/f32.hvmc", "tests/programs/log.hvmc"] {
+    let book = parse_core(&fs::read_to_string(manifest_relative(file)).unwrap());
+    let flat = book.to_flat().unwrap();
+    assert_eq!(FlatBook::from_bytes(&flat.to_bytes()).unwrap(), flat);
+    assert_eq!(flat.to_book().unwrap().to_flat().unwrap(), flat);
+  }
+
+  // malformed input is rejected
+  assert_eq!(parse_core("@main = (a b)").to_flat(), Err("in main: unbound variable `a`".to_owned()));
+  let mut flat = parse_core("@main = *").to_flat().unwrap();
+  flat.nets[0].root = FlatPort::new(run::Tag::Ref, 1, 0);
+  assert_eq!(flat.to_book(), Err("in main: invalid eraser port".to_owned()));
+  let mut bytes = flat.to_bytes();
+  // the nodes of `main` are at offset `u64::MAX`
+  bytes[32 .. 40].copy_from_slice(&u64::MAX.to_le_bytes());
+  bytes[40 .. 48].copy_from_slice(&1u64.to_le_bytes());
+  assert_eq!(FlatBook::from_bytes(&bytes), Err("offset out of range".to_owned()));
+}
+
+#[test]
+fn test_emit_const() {
+  use hvmc::flat::{DefData, FlatBook};
+
+  // generated by `hvmc compile --emit const tests/programs/log.hvmc`
+  mod log {
+    include!("book_data/log.rs");
+  }
+
+  let book = parse_core(&load_file("log.hvmc"));
+  let flat = FlatBook::from_defs(log::BOOK).unwrap();
+  assert_eq!(flat, book.to_flat().unwrap());
+  assert_eq!(flat.to_rust(), fs::read_to_string(manifest_relative("tests/book_data/log.rs")).unwrap());
+
+  // the loaded book produces the same host as the parsed one
+  let loaded = hvmc::stdlib::create_host(&flat.to_book().unwrap());
+  let parsed = hvmc::stdlib::create_host(&book);
+  let (loaded, parsed) = (loaded.lock(), parsed.lock());
+  assert_eq!(loaded.defs.keys().collect::<BTreeSet<_>>(), parsed.defs.keys().collect::<BTreeSet<_>>());
+  let heap = run::Heap::new(None).unwrap();
+  let readback = |host: &hvmc::host::Host| {
+    let mut net = run::Net::<Strict>::new(&heap);
+    net.boot(&host.defs["main"]);
+    host.readback(&net)
+  };
+  assert_eq!(readback(&loaded), readback(&parsed));
+
+  // nets must come before the definitions they reference
+  let defs = [DefData { name: "ext", net: None }, log::BOOK[0]];
+  assert_eq!(FlatBook::from_defs(&defs), Err("the net of main comes after an undefined def".to_owned()));
+}
+
+#[test]
+fn test_snapshot() {
+  let book = parse_core(&load_file("church_mul.hvmc"));
+  let host = hvmc::stdlib::create_host(&book);
+  let host = host.lock();
+  let heap = run::Heap::new(None).unwrap();
+  let mut net = run::Net::<Strict>::new(&heap);
+  net.boot(&host.defs["main"]);
+  net.expand();
+  assert_eq!(net.reduce(100), None);
+
+  let copy_heap = run::Heap::new(Some(1 << 20)).unwrap();
+  let mut copy = net.snapshot_into(&copy_heap);
+  assert_eq!(copy.redexes.len(), net.redexes.len());
+  // the nets were already expanded, so reduce them without `normal`
+  copy.reduce(usize::MAX);
+  assert_eq!(copy.count_leaks(), 0);
+  // the original net is unaffected by the reduction of the copy
+  let pending = net.redexes.len();
+  assert!(pending > 0);
+  net.reduce(usize::MAX);
+  assert_eq!(host.readback(&copy).to_string(), host.readback(&net).to_string());
+  assert_eq!(copy.rwts.total(), net.rwts.total());
+  assert_snapshot!(host.readback(&copy).to_string(), @"({3 (a {3 b {3 c {3 d {3 e {3 f {3 g {3 h {3 i {3 j {3 k {3 l {3 m {3 n {3 o {3 p {3 q {3 r {3 s {3 t u}}}}}}}}}}}}}}}}}}}) {3 (v a) {3 (w v) {3 (x w) {3 (y x) {3 (z y) {3 (aa z) {3 (ab aa) {3 (ac ab) {3 (ad ac) {3 (ae ad) {3 (af ae) {3 (ag af) {3 (ah ag) {3 (ai ah) {3 (aj ai) {3 (ak aj) {3 (al ak) {3 (am al) ({3 c {3 d {3 e {3 f {3 g {3 h {3 i {3 j {3 k {3 l {3 m {3 n {3 o {3 p {3 q {3 r {3 s {3 t {3 u an}}}}}}}}}}}}}}}}}}} am)}}}}}}}}}}}}}}}}}}} (an b))");
+}
+
+#[test]
+fn test_canonical_image() {
+  let book = parse_core(&load_file("church_mul.hvmc"));
+  let host = hvmc::stdlib::create_host(&book);
+  let host = host.lock();
+  let heap = run::Heap::new(None).unwrap();
+  let mut net = run::Net::<Strict>::new(&heap);
+  net.boot(&host.defs["main"]);
+  net.normal();
+  let expected = net.canonical_image(&host);
+  // the root, no redexes, and the two words of each node
+  assert_eq!(expected.len() % 16, 0);
+  assert_eq!(u64::from_le_bytes(expected[8 .. 16].try_into().unwrap()), 0);
+  for threads in [2, 4, 8] {
+    for _ in 0 .. 4 {
+      let heap = run::Heap::new(None).unwrap();
+      let mut net = run::Net::<Strict>::new(&heap);
+      net.boot(&host.defs["main"]);
+      net.parallel_normal_with_threads(threads);
+      assert_eq!(net.canonical_image(&host), expected, "{threads} threads");
+    }
+  }
+
+  // pending redexes and refs are encoded as well
+  let book = parse_core("@main = a & @id ~ (#1 a)  @id = (b b)");
+  let host = hvmc::stdlib::create_host(&book);
+  let host = host.lock();
+  let heap = run::Heap::new(None).unwrap();
+  let mut net = run::Net::<Strict>::new(&heap);
+  net.boot(&host.defs["main"]);
+  let image = net.canonical_image(&host);
+  let words: Vec<_> = image.chunks(8).map(|word| u64::from_le_bytes(word.try_into().unwrap())).collect();
+  let id = host.def_id("id").unwrap() as u64;
+  assert_eq!(words[1], 1);
+  assert_eq!(run::Port(words[2]).addr().0 as u64, (id + 1) * 8);
+}
+
+#[test]
+fn test_parallel_progress() {
+  let book = parse_core(&load_file("church_mul.hvmc"));
+  let host = hvmc::stdlib::create_host(&book);
+  let heap = run::Heap::new(None).unwrap();
+  let mut net = run::Net::<Strict>::new(&heap);
+  net.boot(&host.lock().defs["main"]);
+  let reports = Mutex::new(vec![]);
+  net.parallel_normal_with_progress(&|redexes, rwts| reports.lock().push((redexes, rwts.total())));
+  let reports = reports.into_inner();
+  assert!(reports.windows(2).all(|w| w[0].1 <= w[1].1));
+  assert_eq!(reports.last(), Some(&(0, net.rwts.total())));
+}
+
+#[test]
+fn test_parallel_termination() {
+  // `sum` repeatedly drains the redex bag and refills it by expanding a ref,
+  // so every round of termination detection sees some threads go idle.
+  let book = parse_core(
+    "
+    @main = a
+    & @sum ~ (#1000 (#0 a))
+    @sum = (?<(a b) d> d)
+    & (a b) ~ ((c c) @sum$S0)
+    @sum$S0 = ({3 a <+ b c>} (b d))
+    & @sum ~ (a (c d))
+  ",
+  );
+  let host = hvmc::stdlib::create_host(&book);
+  let host = host.lock();
+  let heap = run::Heap::new(None).unwrap();
+  let mut net = run::Net::<Strict>::new(&heap);
+  net.boot(&host.defs["main"]);
+  net.normal();
+  let expected = (host.readback(&net).to_string(), net.rwts.total());
+  for threads in [1, 2, 3, 8, 16, 64] {
+    for _ in 0 .. 8 {
+      let heap = run::Heap::new(None).unwrap();
+      let mut net = run::Net::<Strict>::new(&heap);
+      net.boot(&host.defs["main"]);
+      net.parallel_normal_with_threads(threads);
+      assert_eq!((host.readback(&net).to_string(), net.rwts.total()), expected, "{threads} threads");
+    }
+  }
+  assert_snapshot!(expected.0, @"#499500");
+}
+
+#[test]
+fn test_parallel_linear() {
+  // the violation and the (linear) loop are reduced on different threads
+  let book = parse_core(
+    "
+    @main = a
+    & @loop ~ (#1000000 a)
+    & * ~ (b b)
+    @loop = (?<(#0 @loop) a> a)
+  ",
+  );
+  let host = hvmc::stdlib::create_host(&book);
+  let heap = run::Heap::new(None).unwrap();
+  let mut net = run::Net::<Strict>::new(&heap);
+  net.linear = true;
+  net.boot(&host.lock().defs["main"]);
+  net.parallel_normal_with_threads(4);
+  assert_eq!(net.violation, Some(run::LinearityViolation::Erase(run::Tag::Ctr)));
+  // every thread stops at the next synchronization, long before the loop ends
+  // (which takes 5_000_000 rewrites)
+  assert!(net.rwts.total() < 2_000_000, "{}", net.rwts.total());
+}
+
+#[test]
+fn test_parallel_region() {
+  // the first stage is connected to `a` through the call to `@id`, the second
+  // one is only connected to `b`
+  let book = parse_core(
+    "
+    @main = (a b)
+    & @loop ~ (#10000 c)
+    & @id ~ (c a)
+    & @loop ~ (#30 b)
+    @loop = (?<(#0 @loop) a> a)
+    @id = (x x)
+  ",
+  );
+  let host = hvmc::stdlib::create_host(&book);
+  let host = host.lock();
+  let heap = run::Heap::new(None).unwrap();
+  let mut net = run::Net::<Strict>::new(&heap);
+  net.boot(&host.defs["main"]);
+  assert_eq!(net.redexes.len(), 3);
+  assert_eq!(net.parallel_normal_region(4, &[0]), 2);
+  assert_snapshot!(host.readback(&net).to_string(), @r###"
+  (#0 a)
+    & @loop ~ (#30 a)
+  "###);
+  // only the targeted redexes were reduced
+  let rwts = net.rwts.total();
+  let stage = hvmc::stdlib::create_host(&parse_core(
+    "@main = a & @loop ~ (#10000 c) & @id ~ (c a)  @loop = (?<(#0 @loop) a> a)  @id = (x x)",
+  ));
+  let expected_heap = run::Heap::new(None).unwrap();
+  let mut expected = run::Net::<Strict>::new(&expected_heap);
+  expected.boot(&stage.lock().defs["main"]);
+  expected.reduce(usize::MAX);
+  assert_eq!(rwts, expected.rwts.total());
+
+  // the rest of the net can then be reduced as usual
+  assert_eq!(net.parallel_normal_region(4, &[1]), 1);
+  assert_snapshot!(host.readback(&net).to_string(), @"(#0 #0)");
+  assert_eq!(net.parallel_normal_region(4, &[]), 0);
+}
+
+#[test]
+fn test_thread_pool() {
+  let book = parse_core(
+    "
+    @main = a
+    & @sum ~ (#100 (#0 a))
+    @sum = (?<(a b) d> d)
+    & (a b) ~ ((c c) @sum$S0)
+    @sum$S0 = ({3 a <+ b c>} (b d))
+    & @sum ~ (a (c d))
+    @panic = * & #1.5 ~ ?<(a a) *>
+  ",
+  );
+  let host = hvmc::stdlib::create_host(&book);
+  let host = host.lock();
+  let pool = run::ThreadPool::new(4);
+  let heap = run::Heap::new(Some(1 << 20)).unwrap();
+  for _ in 0 .. 100 {
+    let mut net = run::Net::<Strict>::new(&heap);
+    net.boot(&host.defs["main"]);
+    net.parallel_normal_on(&pool);
+    assert_eq!(host.readback(&net).to_string(), "#4950");
+  }
+
+  // a panic on one thread reaches the caller, rather than leaving the other
+  // threads waiting for it, and the pool can still be used afterwards
+  let mut net = run::Net::<Strict>::new(&heap);
+  net.boot(&host.defs["panic"]);
+  assert!(panic::catch_unwind(AssertUnwindSafe(|| net.parallel_normal_on(&pool))).is_err());
+  let mut net = run::Net::<Strict>::new(&heap);
+  net.boot(&host.defs["main"]);
+  net.parallel_normal_on(&pool);
+  assert_eq!(host.readback(&net).to_string(), "#4950");
+}
+
+#[test]
+fn test_pin_threads() {
+  let book = parse_core(&load_file("church_exp.hvmc"));
+  let host = hvmc::stdlib::create_host(&book);
+  let host = host.lock();
+  let expected = normal(book, None).1;
+  let pool = run::ThreadPool::new(4);
+  let heap = run::Heap::new(Some(1 << 24)).unwrap();
+
+  // pinning more threads than there are cores wraps around
+  let mut net = run::Net::<Strict>::new(&heap);
+  net.pin_threads = true;
+  net.boot(&host.defs["main"]);
+  net.parallel_normal_with_threads(2 * thread::available_parallelism().unwrap().get());
+  assert_eq!(host.readback(&net), expected);
+
+  for _ in 0 .. 2 {
+    let mut net = run::Net::<Strict>::new(&heap);
+    net.pin_threads = true;
+    net.boot(&host.defs["main"]);
+    net.parallel_normal_on(&pool);
+    assert_eq!(host.readback(&net), expected);
+  }
+}
+
+#[test]
+fn test_instantiate() {
+  let host = hvmc::stdlib::create_host(&parse_core("@two = #2"));
+  let mut host = host.lock();
+  host.insert_open_def("open", &"(x (y @two))".parse().unwrap());
+  let heap = run::Heap::new(None).unwrap();
+  let bindings = [("x", "#1"), ("y", "a & @two ~ <* #3 a>")];
+  let bindings = bindings.into_iter().map(|(nam, net)| (nam.to_owned(), net.parse().unwrap())).collect();
+
+  let mut net = run::Net::<Strict>::new(&heap);
+  host.instantiate(&mut net, "open", &bindings).unwrap();
+  net.normal();
+  assert_eq!(host.readback(&net).to_string(), "(#1 (#6 #2))");
+
+  // referencing an open def erases its free variables
+  let mut net = run::Net::<Strict>::new(&heap);
+  net.boot(&host.defs["open"]);
+  net.normal();
+  assert_eq!(host.readback(&net).to_string(), "(* (* #2))");
+
+  let mut partial = bindings.clone();
+  partial.remove("y");
+  let mut net = run::Net::<Strict>::new(&heap);
+  assert_eq!(host.instantiate(&mut net, "open", &partial), Err(InstantiateError::MissingBinding("y".to_owned())));
+  let mut extra = bindings.clone();
+  extra.insert("z".to_owned(), "*".parse().unwrap());
+  assert_eq!(host.instantiate(&mut net, "open", &extra), Err(InstantiateError::UnknownBinding("z".to_owned())));
+
+  // only defs inserted as open defs may have free variables
+  let book = parse_core("@open = (x (y *))");
+  assert!(panic::catch_unwind(AssertUnwindSafe(|| hvmc::stdlib::create_host(&book))).is_err());
+}
+
+#[test]
+fn test_reduction_handle() {
+  let book = parse_core(
+    "
+    @main = a
+    & @sum ~ (#100000 (#0 a))
+    @sum = (?<(a b) d> d)
+    & (a b) ~ ((c c) @sum$S0)
+    @sum$S0 = ({3 a <+ b c>} (b d))
+    & @sum ~ (a (c d))
+  ",
+  );
+  let host = hvmc::stdlib::create_host(&book);
+  let host = host.lock();
+  let heap = run::Heap::new(Some(1 << 24)).unwrap();
+  let mut net = run::Net::<Strict>::new(&heap);
+  net.boot(&host.defs["main"]);
+  let rwts = thread::scope(|s| {
+    let handle = net.spawn_parallel_normal(s);
+    let mut last = 0;
+    while !handle.is_finished() {
+      let total = handle.rewrites().total();
+      assert!(total >= last);
+      last = total;
+      thread::yield_now();
+    }
+    assert_eq!(handle.redexes(), 0);
+    let rwts = handle.rewrites();
+    handle.join();
+    rwts
+  });
+  assert_eq!(rwts.total(), net.rwts.total());
+  assert_eq!(host.readback(&net).to_string(), "#4999950000");
+}
+
+#[test]
+#[cfg(feature = "async")]
+fn test_normal_stream() {
+  use core::{
+    pin::pin,
+    task::{Context, Poll, Waker},
+  };
+  use futures_core::Stream;
+  use hvmc::host::OwnedNet;
+
+  let book = parse_core(&load_file("church_exp.hvmc"));
+  let mut net = OwnedNet::new(hvmc::stdlib::create_host(&book));
+  net.boot("main");
+  let mut snapshots = vec![];
+  {
+    let mut stream = pin!(net.stream(500));
+    let mut cx = Context::from_waker(Waker::noop());
+    while let Poll::Ready(Some(snapshot)) = stream.as_mut().poll_next(&mut cx) {
+      snapshots.push(snapshot);
+    }
+    assert!(matches!(stream.poll_next(&mut cx), Poll::Ready(None)));
+  }
+  // a snapshot is taken every 500 interactions, and the last one is the
+  // normal form
+  assert_eq!(snapshots.len(), 4);
+  assert!(snapshots.windows(2).all(|w| w[0] != w[1]));
+  let (rwts, normal_form) = normal(book, None);
+  assert_eq!(snapshots.last(), Some(&normal_form));
+  assert_eq!(net.rewrites().total(), rwts.total());
+}
+
 fn execute_host(host: Arc<Mutex<Host>>) -> Option<(run::Rewrites, Net)> {
   let heap = run::Heap::new(None).unwrap();
   let mut net = run::Net::<Strict>::new(&heap);
@@ -97,7 +1751,7 @@ fn test_pre_reduce_run(path: &str, mut book: Book) {
   io::stdout().flush().unwrap();
 
   let start = Instant::now();
-  let pre_stats = book.pre_reduce(&|x| x == "main", None, u64::MAX);
+  let pre_stats = book.pre_reduce(&|x| x == "main", None, u64::MAX, None);
   print!(" {:.3?}...", start.elapsed());
   io::stdout().flush().unwrap();
 
@@ -135,6 +1789,36 @@ fn manifest_relative(sub: &str) -> PathBuf {
   format!("{}/{}", env!("CARGO_MANIFEST_DIR"), sub).into()
 }
 
+#[test]
+#[serial]
+#[cfg(feature = "trace")]
+fn test_trace_defs() {
+  use hvmc::trace;
+
+  let host = hvmc::stdlib::create_host(&parse_core("@main = a & @foo ~ (#1 a)  @foo = (a b) & #2 ~ <+ a b>"));
+  let host = host.lock();
+  let traces = |filter: &[&str]| {
+    unsafe { trace::_reset_traces() };
+    trace::set_def_filter(Some(filter.iter().map(|name| run::Port::new_ref(&host.defs[*name]).addr()).collect()));
+    let heap = run::Heap::new(Some(1 << 12)).unwrap();
+    let mut net = run::Net::<Strict>::new(&heap);
+    net.boot(&host.defs["main"]);
+    net.normal();
+    trace::set_def_filter(None);
+    trace::read_traces(usize::MAX)
+  };
+
+  // the call to `@foo` is traced, and so are the interactions of its nodes
+  let out = traces(&["foo"]);
+  assert!(out.contains("call"), "{out}");
+  assert!(out.contains("op_num"), "{out}");
+  // the op node belongs to `@foo`, not to `@main`
+  let out = traces(&["main"]);
+  assert!(out.contains("call"), "{out}");
+  assert!(!out.contains("op_num"), "{out}");
+  assert_eq!(traces(&[]), "");
+}
+
 #[test]
 #[serial]
 fn test_programs() {
@@ -146,3 +1830,44 @@ fn test_programs() {
 fn test_examples() {
   test_dir(&manifest_relative("examples/"), |_| true);
 }
+
+/// Checks that every example reduces, within a small budget, to the net in the
+/// `.expected` file next to it (or to `budget exceeded`). Run with
+/// `UPDATE_EXPECTED=1` to write the `.expected` files instead.
+#[test]
+fn test_examples_expected() {
+  const BUDGET: usize = 1 << 16;
+
+  fn hvmc_files(dir: &Path, files: &mut Vec<PathBuf>) {
+    for entry in fs::read_dir(dir).unwrap() {
+      let path = entry.unwrap().path();
+      if path.is_dir() {
+        hvmc_files(&path, files);
+      } else if path.extension().is_some_and(|ext| ext == "hvmc") {
+        files.push(path);
+      }
+    }
+  }
+
+  let update = env::var_os("UPDATE_EXPECTED").is_some();
+  let mut files = vec![];
+  hvmc_files(&manifest_relative("examples/"), &mut files);
+  files.sort();
+  assert!(!files.is_empty());
+
+  let mut failures = vec![];
+  for path in files {
+    let book = parse_core(&fs::read_to_string(&path).unwrap());
+    let output = match normal_with_budget(&book, BUDGET) {
+      Some(net) => format!("{net}\n"),
+      None => "budget exceeded\n".to_owned(),
+    };
+    let expected_path = path.with_extension("expected");
+    if update {
+      fs::write(&expected_path, output).unwrap();
+    } else if fs::read_to_string(&expected_path).ok().as_ref() != Some(&output) {
+      failures.push(expected_path.strip_prefix(env!("CARGO_MANIFEST_DIR")).unwrap().display().to_string());
+    }
+  }
+  assert!(failures.is_empty(), "unexpected output for: {failures:?}");
+}
@@ -14,9 +14,9 @@ pub fn test_fast_pre_reduce() {
   let book = parse_core(&load_file("heavy_pre_reduction.hvmc"));
   let (mut book_1, mut book_2) = (book.clone(), book);
 
-  let rwts_1 = book_1.pre_reduce(&|x| !["expensive", "main_fast"].contains(&x), None, u64::MAX).rewrites;
+  let rwts_1 = book_1.pre_reduce(&|x| !["expensive", "main_fast"].contains(&x), None, u64::MAX, None).rewrites;
   let rwts_2 =
-    book_2.pre_reduce(&|x| !["expensive_1", "expensive_2", "main_slow"].contains(&x), None, u64::MAX).rewrites;
+    book_2.pre_reduce(&|x| !["expensive_1", "expensive_2", "main_slow"].contains(&x), None, u64::MAX, None).rewrites;
 
   let rwts_1 = show_rewrites(&(rwts_1 + normal_with(book_1, None, "main_fast").0));
   let rwts_2 = show_rewrites(&(rwts_2 + normal_with(book_2, None, "main_slow").0));
@@ -39,6 +39,46 @@ pub fn test_fast_pre_reduce() {
   "###)
 }
 
+#[test]
+pub fn test_pre_reduce_on_cycle() {
+  use hvmc::{ast::Book, transform::pre_reduce::OnCycle};
+  use std::str::FromStr;
+  let book = Book::from_str(
+    "
+    @loop = a & @loop ~ (#1 a)
+    @ping = a & @pong ~ (#1 a)
+    @pong = a & @ping ~ (#1 a)
+    @sum = (?<(#0 @sum) a> a)
+    @two = a & #1 ~ <+ #1 a>
+  ",
+  )
+  .unwrap();
+
+  // recursive defs are left as they are, unless they are never expanded
+  let mut skipped = book.clone();
+  let stats = skipped.pre_reduce(&|_| false, None, u64::MAX, Some(OnCycle::Skip));
+  assert!(stats.errors.is_empty());
+  assert_display_snapshot!(skipped, @r###"
+  @loop = a
+    & @loop ~ (#1 a)
+
+  @ping = a
+    & @pong ~ (#1 a)
+
+  @pong = a
+    & @ping ~ (#1 a)
+
+  @sum = (?<#0 @sum a> a)
+
+  @two = #2
+  "###);
+
+  let mut reported = book.clone();
+  let stats = reported.pre_reduce(&|_| false, None, u64::MAX, Some(OnCycle::Error));
+  assert_eq!(stats.errors, ["loop", "pong", "ping"]);
+  assert_eq!(reported, skipped);
+}
+
 #[test]
 pub fn test_adt_encoding() {
   use hvmc::ast::{Net, Tree};
@@ -147,6 +187,98 @@ pub fn test_inline() {
   }
 }
 
+#[test]
+pub fn test_inline_only() {
+  use hvmc::ast::Book;
+  use std::str::FromStr;
+  pub fn parse_and_inline_only(net: &str, names: &[&str]) -> Result<String, TransformError> {
+    let mut net = Book::from_str(net).unwrap();
+    let names: Vec<_> = names.iter().map(|&x| x.to_owned()).collect();
+    net.inline_only(&names).map(|_| format!("{net}"))
+  }
+  let book = "
+    @add = (a (b c)) & a ~ <+ b c>
+    @mul = (a (b c)) & a ~ <* b c>
+    @loop = (a b) & @loop ~ (a b)
+    @main = (a b) & @add ~ (#1 (#2 a)) & @mul ~ (@add (#3 b))
+  ";
+  assert_display_snapshot!(parse_and_inline_only(book, &["add", "loop"]).unwrap(), @r###"
+  @add = (a (b c))
+    & a ~ <+ b c>
+
+  @loop = (a b)
+    & (c d) ~ (a b)
+    & @loop ~ (c d)
+
+  @main = (a b)
+    & (c (d e)) ~ (#1 (#2 a))
+    & @mul ~ ((f (g h)) (#3 b))
+    & c ~ <+ d e>
+    & f ~ <+ g h>
+
+  @mul = (a (b c))
+    & a ~ <* b c>
+  "###);
+  assert!(matches!(parse_and_inline_only(book, &["sub"]), Err(TransformError::UndefinedInlinee(_))));
+}
+
+#[test]
+pub fn test_elide_apps() {
+  let book = "
+    @id = (x x)
+    @k = (* @id)
+    @loop = (?<(#0 @loop$S) a> a)
+    @loop$S = (p r)
+      & @id ~ (p q)
+      & @k ~ (#7 s)
+      & s ~ (@loop f)
+      & f ~ (q r)
+    @lab = (a b) & {2 x x} ~ {2 a b}
+    @const = r & (* #1) ~ (#2 r)
+    @main = a & @loop ~ (#1000 a)
+  ";
+  let mut elided = parse_core(book);
+  elided.elide_apps();
+  assert_display_snapshot!(elided, @r###"
+  @const = #1
+    & * ~ #2
+
+  @id = (x x)
+
+  @k = (* @id)
+
+  @lab = (b b)
+
+  @loop = (?<#0 @loop$S a> a)
+
+  @loop$S = (q r)
+    & * ~ #7
+    & @loop ~ (q r)
+
+  @main = a
+    & @loop ~ (#1000 a)
+  "###);
+
+  let rwts_before = show_rewrites(&normal(parse_core(book), None).0);
+  let rwts_after = show_rewrites(&normal(elided, None).0);
+  assert_snapshot!(format!("Before:\n{rwts_before}After:\n{rwts_after}"), @r###"
+  Before:
+  RWTS   :          14_007
+  - ANNI :           6_002
+  - COMM :               0
+  - ERAS :           2_001
+  - DREF :           5_003
+  - OPER :           1_001
+  After:
+  RWTS   :           8_007
+  - ANNI :           3_002
+  - COMM :               0
+  - ERAS :           2_001
+  - DREF :           2_003
+  - OPER :           1_001
+  "###);
+}
+
 #[test]
 pub fn test_prune() {
   use hvmc::ast::Book;
@@ -176,3 +308,87 @@ pub fn test_prune() {
   @main = (@main @a @b)
   "###);
 }
+
+#[test]
+pub fn test_rewrite() {
+  use hvmc::transform::rewrite::{RewriteRule, RewriteRuleError};
+  let rules = |rules: &[&str]| rules.iter().map(|rule| rule.parse().unwrap()).collect::<Vec<RewriteRule>>();
+  let book = "
+    @main = a
+      & @add_zero ~ (#5 a)
+    @add_zero = (a b) & a ~ <+ #0 <+ #0 b>>
+    @pair = (c ({2 (a a) (b b)} <+ #1 c>))
+  ";
+
+  // `x + 0` is `x`
+  let mut rewritten = parse_core(book);
+  assert_eq!(rewritten.rewrite(&rules(&["<+ #0 x> => x"]), usize::MAX), 2);
+  assert_display_snapshot!(rewritten, @r###"
+  @add_zero = (a b)
+    & a ~ b
+
+  @main = a
+    & @add_zero ~ (#5 a)
+
+  @pair = (c ({2 (a a) (b b)} <+ #1 c>))
+  "###);
+  assert_eq!(normal(rewritten, None).1.to_string(), "#5");
+
+  // wires match whatever their names, and are renamed apart
+  let mut rewritten = parse_core(book);
+  assert_eq!(rewritten.rewrite(&rules(&["{2 (a a) (b b)} => (c c)"]), usize::MAX), 1);
+  assert_eq!(rewritten["pair"].to_string(), "(c ((_rw0 _rw0) <+ #1 c>))");
+  assert_eq!(parse_core(book).rewrite(&rules(&["{2 (a b) (b a)} => (c c)"]), usize::MAX), 0);
+
+  // rules that never reach a fixpoint are bounded
+  let mut rewritten = parse_core(book);
+  assert_eq!(rewritten.rewrite(&rules(&["(x y) => (y x)"]), 101), 303);
+
+  // the replacement must use the metavariables linearly
+  let err = |rule: &str| rule.parse::<RewriteRule>().unwrap_err();
+  assert_eq!(err("(a b) => a"), RewriteRuleError::NonLinearVar("b".to_owned()));
+  assert_eq!(err("(a b) => (a (b b))"), RewriteRuleError::NonLinearVar("b".to_owned()));
+  assert_eq!(err("(a a) => a"), RewriteRuleError::NonLinearVar("a".to_owned()));
+  assert_eq!(err("(a (a a)) => *"), RewriteRuleError::RepeatedVar("a".to_owned()));
+  assert_eq!(err("(a b)"), RewriteRuleError::MissingArrow);
+}
+
+#[test]
+pub fn test_book_cache() {
+  use hvmc::transform::{
+    cache::{BookCache, LoadError},
+    TransformOpts, TransformPasses,
+  };
+  use std::sync::Arc;
+
+  let src = "@id = (x x)  @main = a & @id ~ (#1 a)";
+  let (passes, opts) = (TransformPasses::ALL, TransformOpts::default());
+  let cache = BookCache::new();
+  let book = cache.load(src, passes, &opts).unwrap();
+  assert_eq!(cache.parses(), 1);
+  assert_display_snapshot!(book, @"@main = #1\n");
+
+  // a hit returns the same book without parsing the source again
+  assert!(Arc::ptr_eq(&cache.load(src, passes, &opts).unwrap(), &book));
+  assert_eq!(cache.parses(), 1);
+
+  // changing the source, the passes, or the options is a miss
+  cache.load("@main = *", passes, &opts).unwrap();
+  assert_eq!(cache.parses(), 2);
+  assert_eq!(cache.load(src, TransformPasses::NONE, &opts).unwrap().len(), 2);
+  assert_eq!(cache.parses(), 3);
+  let mut skip_main = TransformOpts::default();
+  skip_main.add_entrypoint("main");
+  cache.load(src, passes, &skip_main).unwrap();
+  assert_eq!(cache.parses(), 4);
+  assert_eq!(cache.len(), 4);
+
+  // failures are not cached
+  assert!(matches!(cache.load("@main = (", passes, &opts), Err(LoadError::Parse(_))));
+  assert!(matches!(cache.load("@main = (", passes, &opts), Err(LoadError::Parse(_))));
+  assert_eq!(cache.parses(), 6);
+  assert_eq!(cache.len(), 4);
+
+  cache.clear();
+  assert!(cache.is_empty());
+}